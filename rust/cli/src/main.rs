@@ -1,21 +1,33 @@
 use anyhow::{Context, Result, bail};
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
 use protocol::{
     Frame, TransferDirection, build_file_search_request, build_get_shared_files_in_folder_request,
     build_login_request, build_transfer_request, build_transfer_response,
 };
 use soul_core::{
-    Credentials, DownloadPlan, ManualUploadDecision, PrivateEvent, RoomEvent, SearchMode,
-    SearchResultSource, SearchSelectDownloadRequest, SessionClient, UploadAgent,
-    UploadDecisionKind, download_single_file, probe_login_versions,
+    BuddyEvent, BuddyList, CandidateDownloadRequest, ChatDirection, ChatMessage, ChatStore,
+    Credentials, DEFAULT_UPLOAD_SLOTS, DownloadPlan, ManualUploadDecision, PrivateEvent, RoomEvent,
+    RoomLog, RoomLogFormat, SearchCandidate, SearchMode, SearchResultSource, SearchScope,
+    SearchSelectDownloadRequest, SessionClient, SharedDir, UploadAgent, UploadDecisionKind,
+    UploadScheduler, UserProfile, credentials_file_path, download_single_file, local_download_path,
+    open_credential_store_with, probe_login_versions,
 };
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use verify::{
-    CaptureRunReport, ComparisonMode, FrameComparison, compare_capture_run_with_mode,
-    compare_fixture_to_frame, write_capture_report, write_report,
+    AlignmentOptions, CaptureAlignmentReport, CaptureRunReport, ComparisonMode, FrameComparison,
+    FrameFraming, IgnoreRule, InspectOptions, ProtocolCoverageReport, ReportFormat,
+    SchemaConformance, align_capture_run, check_schema_conformance,
+    compare_capture_sequences_with_mode, compare_fixture_to_frame, inspect_stream, load_hex_lines,
+    load_message_schema, looks_like_hex_text, parse_hex_input, protocol_coverage,
+    render_protocol_coverage_markdown, write_alignment_report, write_capture_report,
+    write_protocol_coverage_report, write_rendered_capture_reports, write_report,
+    write_schema_conformance_report,
 };
 
 #[derive(Debug, Parser)]
@@ -26,6 +38,27 @@ struct Cli {
     command: Commands,
 }
 
+// Server and login flags shared by every command that talks to the server.
+// A plain comment: clap would turn a doc comment into the about text of every
+// subcommand that flattens these flags.
+#[derive(Debug, Args)]
+struct ConnectionArgs {
+    #[arg(long)]
+    server: Option<String>,
+    #[arg(long)]
+    username: Option<String>,
+    #[arg(long)]
+    password: Option<String>,
+    #[arg(long, hide = true)]
+    password_md5: Option<String>,
+}
+
+/// Server address and login, resolved once from [`ConnectionArgs`].
+struct Connection {
+    server: String,
+    credentials: Credentials,
+}
+
 #[derive(Debug, Subcommand)]
 enum Commands {
     BuildLogin {
@@ -66,29 +99,26 @@ enum Commands {
         #[arg(long)]
         directory: String,
     },
-    RunLogin {
-        #[arg(long)]
-        server: Option<String>,
+    Decode {
+        hex: Option<String>,
         #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        file: Option<PathBuf>,
+        #[arg(long, value_enum, default_value_t = FramingArg::Auto)]
+        framing: FramingArg,
+        #[arg(long, default_value_t = false)]
+        lenient: bool,
+    },
+    RunLogin {
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long, default_value_t = 157)]
         client_version: u32,
         #[arg(long, default_value_t = 19)]
         minor_version: u32,
     },
     RunSearch {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         token: u32,
         #[arg(long)]
@@ -132,6 +162,14 @@ enum Commands {
         #[command(subcommand)]
         command: DiscoverCommand,
     },
+    Buddy {
+        #[command(subcommand)]
+        command: BuddyCommand,
+    },
+    Chat {
+        #[command(subcommand)]
+        command: ChatCommand,
+    },
     Verify {
         #[command(subcommand)]
         command: VerifyCommand,
@@ -141,28 +179,16 @@ enum Commands {
 #[derive(Debug, Subcommand)]
 enum SessionCommand {
     Login {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long, default_value_t = 157)]
         client_version: u32,
         #[arg(long, default_value_t = 19)]
         minor_version: u32,
     },
     Search {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         token: u32,
         #[arg(long)]
@@ -182,19 +208,72 @@ enum SessionCommand {
         #[arg(long, default_value = "P")]
         connection_type: String,
     },
-    DownloadAuto {
+    SearchRoom {
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
+        room: String,
+        /// Token for the search requests sent to peers when falling back to
+        /// distributed results; the server-side room search frame has no token.
+        #[arg(long, default_value_t = 0)]
+        token: u32,
         #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        query: String,
+        #[arg(long, default_value_t = 5)]
+        timeout_secs: u64,
+        #[arg(long, default_value_t = 10)]
+        max_messages: usize,
+        #[arg(long, default_value_t = 157)]
+        client_version: u32,
+        #[arg(long, default_value_t = 19)]
+        minor_version: u32,
+        #[arg(long, value_enum, default_value_t = SearchModeArg::Auto)]
+        search_mode: SearchModeArg,
         #[arg(long)]
+        strict_track: Option<String>,
+        #[arg(long, default_value = "P")]
+        connection_type: String,
+    },
+    SearchUser {
+        #[command(flatten)]
+        connection: ConnectionArgs,
+        #[arg(long = "target-user", required = true)]
+        target_users: Vec<String>,
+        /// Token for the search requests sent to peers when falling back to
+        /// distributed results; the server-side user search frame has no token.
+        #[arg(long, default_value_t = 0)]
         token: u32,
         #[arg(long)]
         query: String,
+        #[arg(long, default_value_t = 5)]
+        timeout_secs: u64,
+        #[arg(long, default_value_t = 10)]
+        max_messages: usize,
+        #[arg(long, default_value_t = 157)]
+        client_version: u32,
+        #[arg(long, default_value_t = 19)]
+        minor_version: u32,
+        #[arg(long, value_enum, default_value_t = SearchModeArg::Auto)]
+        search_mode: SearchModeArg,
+        #[arg(long)]
+        strict_track: Option<String>,
+        #[arg(long, default_value = "P")]
+        connection_type: String,
+    },
+    DownloadAuto {
+        #[command(flatten)]
+        connection: ConnectionArgs,
+        #[arg(long, required_unless_present = "peer_user")]
+        token: Option<u32>,
+        #[arg(long, required_unless_present = "peer_user")]
+        query: Option<String>,
+        /// Download `--file-path` from this user directly instead of searching.
+        #[arg(long, requires = "file_path")]
+        peer_user: Option<String>,
+        #[arg(long, requires = "peer_user")]
+        file_path: Option<String>,
+        #[arg(long, default_value_t = 0)]
+        file_size: u64,
         #[arg(long)]
         output: PathBuf,
         #[arg(long)]
@@ -229,20 +308,16 @@ enum SessionCommand {
         verbose: bool,
     },
     Message {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         target_user: String,
         #[arg(long)]
         message: String,
         #[arg(long, default_value_t = false)]
         wait_ack: bool,
+        #[arg(long)]
+        history: Option<PathBuf>,
         #[arg(long, default_value_t = 5)]
         timeout_secs: u64,
         #[arg(long, default_value_t = 160)]
@@ -253,14 +328,8 @@ enum SessionCommand {
         verbose: bool,
     },
     MessageUsers {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long, value_delimiter = ',')]
         targets: Vec<String>,
         #[arg(long)]
@@ -271,14 +340,8 @@ enum SessionCommand {
         minor_version: u32,
     },
     Status {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         target_user: String,
         #[arg(long, default_value_t = 5)]
@@ -291,17 +354,29 @@ enum SessionCommand {
         verbose: bool,
     },
     Stats {
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
+        target_user: String,
+        #[arg(long, default_value_t = 5)]
+        timeout_secs: u64,
+        #[arg(long, default_value_t = 160)]
+        client_version: u32,
+        #[arg(long, default_value_t = 1)]
+        minor_version: u32,
         #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        verbose: bool,
+    },
+    UserInfo {
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         target_user: String,
-        #[arg(long, default_value_t = 5)]
+        #[arg(long, default_value_t = 0)]
+        token: u32,
+        #[arg(long)]
+        save_picture: Option<PathBuf>,
+        #[arg(long, default_value_t = 10)]
         timeout_secs: u64,
         #[arg(long, default_value_t = 160)]
         client_version: u32,
@@ -310,15 +385,40 @@ enum SessionCommand {
         #[arg(long)]
         verbose: bool,
     },
-    PeerAddress {
+    /// Lists a user's shares as a tree and optionally downloads a part of it.
+    Browse {
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
-        server: Option<String>,
+        target_user: String,
+        /// Only show (and download) this folder of the share.
         #[arg(long)]
-        username: Option<String>,
+        path: Option<String>,
+        /// Folder levels to print below `--path`; files are listed only at
+        /// the last level shown.
         #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        depth: Option<usize>,
+        /// Download every file under `--path`, keeping the folder layout.
+        #[arg(long, requires = "output_dir")]
+        download: bool,
+        #[arg(long)]
+        output_dir: Option<PathBuf>,
+        #[arg(long, default_value_t = 0)]
+        token: u32,
+        #[arg(long, default_value_t = 555)]
+        transfer_token: u32,
+        #[arg(long)]
+        wait_port: Option<u16>,
+        #[arg(long, default_value_t = 10)]
+        timeout_secs: u64,
+        #[arg(long, default_value_t = 160)]
+        client_version: u32,
+        #[arg(long, default_value_t = 1)]
+        minor_version: u32,
+    },
+    PeerAddress {
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         target_user: String,
         #[arg(long, default_value_t = 5)]
@@ -331,14 +431,8 @@ enum SessionCommand {
         verbose: bool,
     },
     ConnectPeer {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         target_user: String,
         #[arg(long)]
@@ -351,18 +445,14 @@ enum SessionCommand {
         minor_version: u32,
     },
     WatchPrivate {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long, default_value_t = 15)]
         timeout_secs: u64,
         #[arg(long, default_value_t = 128)]
         max_events: usize,
+        #[arg(long)]
+        history: Option<PathBuf>,
         #[arg(long, default_value_t = 160)]
         client_version: u32,
         #[arg(long, default_value_t = 1)]
@@ -371,14 +461,8 @@ enum SessionCommand {
         verbose: bool,
     },
     IgnoreUser {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         target_user: String,
         #[arg(long, default_value_t = 160)]
@@ -387,14 +471,8 @@ enum SessionCommand {
         minor_version: u32,
     },
     UnignoreUser {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         target_user: String,
         #[arg(long, default_value_t = 160)]
@@ -403,14 +481,8 @@ enum SessionCommand {
         minor_version: u32,
     },
     BanUser {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         target_user: String,
         #[arg(long, default_value_t = 160)]
@@ -419,14 +491,8 @@ enum SessionCommand {
         minor_version: u32,
     },
     PrivilegedList {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long, default_value_t = 5)]
         timeout_secs: u64,
         #[arg(long, default_value_t = 160)]
@@ -437,14 +503,8 @@ enum SessionCommand {
         verbose: bool,
     },
     OwnPrivileges {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long, default_value_t = 5)]
         timeout_secs: u64,
         #[arg(long, default_value_t = 160)]
@@ -455,14 +515,8 @@ enum SessionCommand {
         verbose: bool,
     },
     UserPrivileges {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         target_user: String,
         #[arg(long, default_value_t = 5)]
@@ -475,14 +529,8 @@ enum SessionCommand {
         verbose: bool,
     },
     UploadSpeed {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         bytes_per_sec: u32,
         #[arg(long, default_value_t = 160)]
@@ -491,14 +539,8 @@ enum SessionCommand {
         minor_version: u32,
     },
     GivePrivilege {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         target_user: String,
         #[arg(long)]
@@ -509,14 +551,8 @@ enum SessionCommand {
         minor_version: u32,
     },
     InformPrivileges {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         token: u32,
         #[arg(long)]
@@ -527,14 +563,8 @@ enum SessionCommand {
         minor_version: u32,
     },
     InformPrivilegesAck {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         token: u32,
         #[arg(long, default_value_t = 160)]
@@ -543,14 +573,8 @@ enum SessionCommand {
         minor_version: u32,
     },
     ProbeLoginVersion {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
     },
 }
 
@@ -579,20 +603,20 @@ enum TransferCommand {
         reason: String,
         #[arg(long)]
         source_file: Option<PathBuf>,
+        #[arg(long, default_value = "")]
+        description: String,
+        #[arg(long)]
+        picture: Option<PathBuf>,
+        #[arg(long, default_value_t = DEFAULT_UPLOAD_SLOTS)]
+        upload_slots: u32,
     },
 }
 
 #[derive(Debug, Subcommand)]
 enum RoomCommand {
     List {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long, default_value_t = 160)]
         client_version: u32,
         #[arg(long, default_value_t = 1)]
@@ -601,14 +625,8 @@ enum RoomCommand {
         verbose: bool,
     },
     Join {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         room: String,
         #[arg(long, default_value_t = 160)]
@@ -619,14 +637,8 @@ enum RoomCommand {
         verbose: bool,
     },
     Leave {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         room: String,
         #[arg(long, default_value_t = 160)]
@@ -637,14 +649,8 @@ enum RoomCommand {
         verbose: bool,
     },
     Add {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         room: String,
         #[arg(long, default_value_t = 160)]
@@ -655,14 +661,8 @@ enum RoomCommand {
         verbose: bool,
     },
     Members {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         room: String,
         #[arg(long, default_value_t = 6)]
@@ -675,14 +675,8 @@ enum RoomCommand {
         verbose: bool,
     },
     Ticker {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         room: String,
         #[arg(long, default_value_t = 6)]
@@ -695,18 +689,14 @@ enum RoomCommand {
         verbose: bool,
     },
     Watch {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         room: String,
         #[arg(long, default_value_t = 15)]
         timeout_secs: u64,
+        #[arg(long)]
+        log_dir: Option<PathBuf>,
         #[arg(long, default_value_t = 160)]
         client_version: u32,
         #[arg(long, default_value_t = 1)]
@@ -714,15 +704,19 @@ enum RoomCommand {
         #[arg(long)]
         verbose: bool,
     },
-    AddMember {
-        #[arg(long)]
-        server: Option<String>,
+    Export {
+        #[arg(long, default_value = "room-logs")]
+        log_dir: PathBuf,
         #[arg(long)]
-        username: Option<String>,
+        room: String,
+        #[arg(long, value_enum, default_value_t = RoomLogFormatArg::Text)]
+        format: RoomLogFormatArg,
         #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        output: Option<PathBuf>,
+    },
+    AddMember {
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         room: String,
         #[arg(long)]
@@ -735,14 +729,8 @@ enum RoomCommand {
         verbose: bool,
     },
     RemoveMember {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         room: String,
         #[arg(long)]
@@ -755,14 +743,8 @@ enum RoomCommand {
         verbose: bool,
     },
     AddOperator {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         room: String,
         #[arg(long)]
@@ -775,14 +757,8 @@ enum RoomCommand {
         verbose: bool,
     },
     RemoveOperator {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         room: String,
         #[arg(long)]
@@ -799,14 +775,8 @@ enum RoomCommand {
 #[derive(Debug, Subcommand)]
 enum DiscoverCommand {
     Recommendations {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long, default_value_t = 160)]
         client_version: u32,
         #[arg(long, default_value_t = 1)]
@@ -817,14 +787,8 @@ enum DiscoverCommand {
         verbose: bool,
     },
     Global {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long, default_value_t = 160)]
         client_version: u32,
         #[arg(long, default_value_t = 1)]
@@ -835,14 +799,8 @@ enum DiscoverCommand {
         verbose: bool,
     },
     Mine {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long, default_value_t = 160)]
         client_version: u32,
         #[arg(long, default_value_t = 1)]
@@ -853,14 +811,8 @@ enum DiscoverCommand {
         verbose: bool,
     },
     User {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         target_user: String,
         #[arg(long, default_value_t = 160)]
@@ -873,14 +825,8 @@ enum DiscoverCommand {
         verbose: bool,
     },
     SimilarTerms {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         term: String,
         #[arg(long, default_value_t = 160)]
@@ -893,14 +839,8 @@ enum DiscoverCommand {
         verbose: bool,
     },
     AddLikeTerm {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         term: String,
         #[arg(long, default_value_t = 160)]
@@ -911,14 +851,8 @@ enum DiscoverCommand {
         verbose: bool,
     },
     RemoveLikeTerm {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         term: String,
         #[arg(long, default_value_t = 160)]
@@ -929,14 +863,8 @@ enum DiscoverCommand {
         verbose: bool,
     },
     RecommendedUsers {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long, default_value_t = 160)]
         client_version: u32,
         #[arg(long, default_value_t = 1)]
@@ -947,14 +875,8 @@ enum DiscoverCommand {
         verbose: bool,
     },
     TermRecommendations {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         term: String,
         #[arg(long, default_value_t = 160)]
@@ -967,14 +889,8 @@ enum DiscoverCommand {
         verbose: bool,
     },
     RecommendationUsers {
-        #[arg(long)]
-        server: Option<String>,
-        #[arg(long)]
-        username: Option<String>,
-        #[arg(long)]
-        password: Option<String>,
-        #[arg(long, hide = true)]
-        password_md5: Option<String>,
+        #[command(flatten)]
+        connection: ConnectionArgs,
         #[arg(long)]
         term: String,
         #[arg(long, default_value_t = 160)]
@@ -988,6 +904,70 @@ enum DiscoverCommand {
     },
 }
 
+#[derive(Debug, Subcommand)]
+enum ChatCommand {
+    Threads {
+        #[arg(long, default_value = "chats.json")]
+        file: PathBuf,
+    },
+    Show {
+        #[arg(long, default_value = "chats.json")]
+        file: PathBuf,
+        #[arg(long)]
+        user: String,
+        #[arg(long, default_value_t = false)]
+        mark_read: bool,
+    },
+    Search {
+        #[arg(long, default_value = "chats.json")]
+        file: PathBuf,
+        #[arg(long)]
+        query: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum BuddyCommand {
+    Add {
+        #[arg(long, default_value = "buddies.json")]
+        file: PathBuf,
+        #[arg(long)]
+        user: String,
+        #[arg(long)]
+        note: Option<String>,
+        #[arg(long)]
+        group: Option<String>,
+    },
+    Remove {
+        #[arg(long, default_value = "buddies.json")]
+        file: PathBuf,
+        #[arg(long)]
+        user: String,
+    },
+    List {
+        #[arg(long, default_value = "buddies.json")]
+        file: PathBuf,
+    },
+    Watch {
+        #[arg(long, default_value = "buddies.json")]
+        file: PathBuf,
+        #[command(flatten)]
+        connection: ConnectionArgs,
+        #[arg(long, default_value_t = 30)]
+        timeout_secs: u64,
+        #[arg(long, default_value_t = 160)]
+        client_version: u32,
+        #[arg(long, default_value_t = 1)]
+        minor_version: u32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum RoomLogFormatArg {
+    Text,
+    Json,
+}
+
 #[derive(Debug, Clone, Copy, ValueEnum)]
 enum ManualDecisionArg {
     Accept,
@@ -1009,6 +989,33 @@ enum VerifyCommand {
         base_dir: PathBuf,
         #[arg(long, value_enum, default_value_t = VerifyModeArg::Semantic)]
         mode: VerifyModeArg,
+        #[arg(long = "render", value_enum)]
+        render_formats: Vec<ReportFormatArg>,
+    },
+    Align {
+        #[arg(long)]
+        run: String,
+        #[arg(long, default_value = "captures/redacted")]
+        base_dir: PathBuf,
+        /// Field to ignore, or `code:field` to ignore it for one message code only.
+        #[arg(long = "ignore-field", value_parser = IgnoreRule::parse)]
+        ignore_fields: Vec<IgnoreRule>,
+        #[arg(long, default_value_t = false)]
+        ignore_volatile: bool,
+    },
+    Coverage {
+        #[arg(long, default_value = "captures/redacted")]
+        captures_dir: PathBuf,
+        #[arg(long, default_value = "docs/state/protocol-coverage.json")]
+        out_json: PathBuf,
+        #[arg(long, default_value = "docs/state/protocol-coverage.md")]
+        out_md: PathBuf,
+    },
+    Schema {
+        #[arg(long, default_value = "analysis/protocol/message_schema.json")]
+        schema: PathBuf,
+        #[arg(long)]
+        report: Option<PathBuf>,
     },
 }
 
@@ -1018,6 +1025,20 @@ enum VerifyModeArg {
     Semantic,
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum FramingArg {
+    Auto,
+    Server,
+    Peer,
+    PeerInit,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ReportFormatArg {
+    Html,
+    Markdown,
+}
+
 #[derive(Debug, Clone, Copy, ValueEnum)]
 enum SearchModeArg {
     Auto,
@@ -1071,41 +1092,31 @@ async fn main() -> Result<()> {
             let frame = build_get_shared_files_in_folder_request(&directory);
             println!("{}", hex_string(&frame));
         }
+        Commands::Decode {
+            hex,
+            file,
+            framing,
+            lenient,
+        } => {
+            run_decode(hex.as_deref(), file.as_deref(), framing, lenient)?;
+        }
         Commands::RunLogin {
-            server,
-            username,
-            password,
-            password_md5,
+            connection,
             client_version,
             minor_version,
-        } => {
-            run_login(
-                runtime_server(server.as_deref())?.as_str(),
-                runtime_username(username.as_deref())?.as_str(),
-                runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                client_version,
-                minor_version,
-            )
-            .await?
-        }
+        } => run_login(&connection.resolve(client_version, minor_version)?).await?,
         Commands::RunSearch {
-            server,
-            username,
-            password,
-            password_md5,
+            connection,
             token,
             query,
             client_version,
             minor_version,
         } => {
             run_search(
-                runtime_server(server.as_deref())?.as_str(),
-                runtime_username(username.as_deref())?.as_str(),
-                runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
+                &connection.resolve(client_version, minor_version)?,
                 token,
+                &SearchScope::Global,
                 &query,
-                client_version,
-                minor_version,
                 5,
                 10,
                 SearchMode::Auto,
@@ -1129,27 +1140,64 @@ async fn main() -> Result<()> {
         }
         Commands::Session { command } => match command {
             SessionCommand::Login {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 client_version,
                 minor_version,
+            } => run_login(&connection.resolve(client_version, minor_version)?).await?,
+            SessionCommand::Search {
+                connection,
+                token,
+                query,
+                timeout_secs,
+                max_messages,
+                client_version,
+                minor_version,
+                search_mode,
+                strict_track,
+                connection_type,
             } => {
-                run_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
+                run_search(
+                    &connection.resolve(client_version, minor_version)?,
+                    token,
+                    &SearchScope::Global,
+                    &query,
+                    timeout_secs,
+                    max_messages,
+                    search_mode.into(),
+                    strict_track.as_deref(),
+                    &connection_type,
                 )
                 .await?
             }
-            SessionCommand::Search {
-                server,
-                username,
-                password,
-                password_md5,
+            SessionCommand::SearchRoom {
+                connection,
+                room,
+                token,
+                query,
+                timeout_secs,
+                max_messages,
+                client_version,
+                minor_version,
+                search_mode,
+                strict_track,
+                connection_type,
+            } => {
+                run_search(
+                    &connection.resolve(client_version, minor_version)?,
+                    token,
+                    &SearchScope::Room(room),
+                    &query,
+                    timeout_secs,
+                    max_messages,
+                    search_mode.into(),
+                    strict_track.as_deref(),
+                    &connection_type,
+                )
+                .await?
+            }
+            SessionCommand::SearchUser {
+                connection,
+                target_users,
                 token,
                 query,
                 timeout_secs,
@@ -1161,13 +1209,10 @@ async fn main() -> Result<()> {
                 connection_type,
             } => {
                 run_search(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
+                    &connection.resolve(client_version, minor_version)?,
                     token,
+                    &user_search_scope(target_users),
                     &query,
-                    client_version,
-                    minor_version,
                     timeout_secs,
                     max_messages,
                     search_mode.into(),
@@ -1177,12 +1222,12 @@ async fn main() -> Result<()> {
                 .await?
             }
             SessionCommand::DownloadAuto {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 token,
                 query,
+                peer_user,
+                file_path,
+                file_size,
                 output,
                 transfer_token,
                 result_index,
@@ -1200,18 +1245,25 @@ async fn main() -> Result<()> {
                 strict_track,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
+                let exact =
+                    peer_user
+                        .zip(file_path)
+                        .map(|(peer_user, file_path)| SearchCandidate {
+                            username: peer_user,
+                            file_path,
+                            file_size,
+                            peer_addr: peer.clone(),
+                            connect_token: None,
+                            bitrate: None,
+                            source: SearchResultSource::ServerSummary,
+                        });
                 run_download_auto(
                     &mut client,
-                    token,
-                    &query,
+                    token.unwrap_or_default(),
+                    query.as_deref().unwrap_or_default(),
+                    exact,
                     output,
                     transfer_token,
                     result_index,
@@ -1230,369 +1282,268 @@ async fn main() -> Result<()> {
                 .await?;
             }
             SessionCommand::Message {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 target_user,
                 message,
                 wait_ack,
+                history,
                 timeout_secs,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_message(
                     &mut client,
                     &target_user,
                     &message,
                     wait_ack,
+                    history.as_deref(),
                     timeout_secs,
                     verbose,
                 )
                 .await?;
             }
             SessionCommand::MessageUsers {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 targets,
                 message,
                 client_version,
                 minor_version,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_message_users(&mut client, &targets, &message).await?;
             }
             SessionCommand::Status {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 target_user,
                 timeout_secs,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_user_status(&mut client, &target_user, timeout_secs, verbose).await?;
             }
             SessionCommand::Stats {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 target_user,
                 timeout_secs,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_user_stats(&mut client, &target_user, timeout_secs, verbose).await?;
             }
-            SessionCommand::PeerAddress {
-                server,
-                username,
-                password,
-                password_md5,
+            SessionCommand::UserInfo {
+                connection,
                 target_user,
+                token,
+                save_picture,
                 timeout_secs,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
+                run_user_info(
+                    &mut client,
+                    &target_user,
+                    token,
+                    save_picture.as_deref(),
+                    timeout_secs,
+                    verbose,
                 )
                 .await?;
+            }
+            SessionCommand::Browse {
+                connection,
+                target_user,
+                path,
+                depth,
+                download,
+                output_dir,
+                token,
+                transfer_token,
+                wait_port,
+                timeout_secs,
+                client_version,
+                minor_version,
+            } => {
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
+                let download_to = output_dir.filter(|_| download);
+                run_browse(
+                    &mut client,
+                    &target_user,
+                    path.as_deref().unwrap_or_default(),
+                    depth,
+                    download_to.as_deref(),
+                    token,
+                    transfer_token,
+                    wait_port,
+                    timeout_secs,
+                )
+                .await?;
+            }
+            SessionCommand::PeerAddress {
+                connection,
+                target_user,
+                timeout_secs,
+                client_version,
+                minor_version,
+                verbose,
+            } => {
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_peer_address(&mut client, &target_user, timeout_secs, verbose).await?;
             }
             SessionCommand::ConnectPeer {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 target_user,
                 token,
                 connection_type,
                 client_version,
                 minor_version,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_connect_peer(&mut client, &target_user, token, &connection_type).await?;
             }
             SessionCommand::WatchPrivate {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 timeout_secs,
                 max_events,
+                history,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
+                run_watch_private(
+                    &mut client,
+                    timeout_secs,
+                    max_events,
+                    history.as_deref(),
+                    verbose,
                 )
                 .await?;
-                run_watch_private(&mut client, timeout_secs, max_events, verbose).await?;
             }
             SessionCommand::IgnoreUser {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 target_user,
                 client_version,
                 minor_version,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_ignore_user(&mut client, &target_user).await?;
             }
             SessionCommand::UnignoreUser {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 target_user,
                 client_version,
                 minor_version,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_unignore_user(&mut client, &target_user).await?;
             }
             SessionCommand::BanUser {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 target_user,
                 client_version,
                 minor_version,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_ban_user(&mut client, &target_user).await?;
             }
             SessionCommand::PrivilegedList {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 timeout_secs,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_privileged_list(&mut client, timeout_secs, verbose).await?;
             }
             SessionCommand::OwnPrivileges {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 timeout_secs,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_own_privileges(&mut client, timeout_secs, verbose).await?;
             }
             SessionCommand::UserPrivileges {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 target_user,
                 timeout_secs,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_user_privileges(&mut client, &target_user, timeout_secs, verbose).await?;
             }
             SessionCommand::UploadSpeed {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 bytes_per_sec,
                 client_version,
                 minor_version,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_upload_speed(&mut client, bytes_per_sec).await?;
             }
             SessionCommand::GivePrivilege {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 target_user,
                 days,
                 client_version,
                 minor_version,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_give_privilege(&mut client, &target_user, days).await?;
             }
             SessionCommand::InformPrivileges {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 token,
                 target_user,
                 client_version,
                 minor_version,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_inform_privileges(&mut client, token, &target_user).await?;
             }
             SessionCommand::InformPrivilegesAck {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 token,
                 client_version,
                 minor_version,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_inform_privileges_ack(&mut client, token).await?;
             }
-            SessionCommand::ProbeLoginVersion {
-                server,
-                username,
-                password,
-                password_md5,
-            } => {
-                run_probe_login_version(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                )
-                .await?;
+            SessionCommand::ProbeLoginVersion { connection } => {
+                run_probe_login_version(&connection.resolve(0, 0)?).await?;
             }
         },
         Commands::Transfer { command } => match command {
@@ -1609,449 +1560,397 @@ async fn main() -> Result<()> {
                 decision,
                 reason,
                 source_file,
+                description,
+                picture,
+                upload_slots,
             } => {
                 if !manual {
                     bail!("stage2 supports manual upload policy only, use --manual");
                 }
-                run_serve_upload(&bind, decision, reason, source_file).await?;
+                let profile = UserProfile::load(description, picture.as_deref())?;
+                run_serve_upload(&bind, decision, reason, source_file, profile, upload_slots)
+                    .await?;
             }
         },
         Commands::Room { command } => match command {
             RoomCommand::List {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_room_list(&mut client, verbose).await?;
             }
             RoomCommand::Join {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 room,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_room_join(&mut client, &room, verbose).await?;
             }
             RoomCommand::Leave {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 room,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_room_leave(&mut client, &room, verbose).await?;
             }
             RoomCommand::Add {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 room,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_room_add(&mut client, &room, verbose).await?;
             }
             RoomCommand::Members {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 room,
                 timeout_secs,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_room_members(&mut client, &room, timeout_secs, verbose).await?;
             }
             RoomCommand::Ticker {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 room,
                 timeout_secs,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_room_ticker(&mut client, &room, timeout_secs, verbose).await?;
             }
             RoomCommand::Watch {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 room,
                 timeout_secs,
+                log_dir,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
-                run_room_watch(&mut client, &room, timeout_secs, verbose).await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
+                let log = log_dir.map(RoomLog::new);
+                run_room_watch(&mut client, &room, timeout_secs, log.as_ref(), verbose).await?;
+            }
+            RoomCommand::Export {
+                log_dir,
+                room,
+                format,
+                output,
+            } => {
+                let format = match format {
+                    RoomLogFormatArg::Text => RoomLogFormat::Text,
+                    RoomLogFormatArg::Json => RoomLogFormat::Json,
+                };
+                let exported = RoomLog::new(&log_dir).export(&room, format)?;
+                match output {
+                    Some(path) => {
+                        fs::write(&path, &exported)
+                            .with_context(|| format!("write export: {}", path.display()))?;
+                        println!(
+                            "room.export ok room={} bytes={} output={}",
+                            room,
+                            exported.len(),
+                            path.display()
+                        );
+                    }
+                    None => print!("{exported}"),
+                }
             }
             RoomCommand::AddMember {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 room,
                 target_user,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_room_add_member(&mut client, &room, &target_user, verbose).await?;
             }
             RoomCommand::RemoveMember {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 room,
                 target_user,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_room_remove_member(&mut client, &room, &target_user, verbose).await?;
             }
             RoomCommand::AddOperator {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 room,
                 target_user,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_room_add_operator(&mut client, &room, &target_user, verbose).await?;
             }
             RoomCommand::RemoveOperator {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 room,
                 target_user,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_room_remove_operator(&mut client, &room, &target_user, verbose).await?;
             }
         },
         Commands::Discover { command } => match command {
             DiscoverCommand::Recommendations {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 client_version,
                 minor_version,
                 timeout_secs,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_discover_recommendations(&mut client, timeout_secs, verbose).await?;
             }
-            DiscoverCommand::Global {
-                server,
-                username,
-                password,
-                password_md5,
+            DiscoverCommand::Global {
+                connection,
                 client_version,
                 minor_version,
                 timeout_secs,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_discover_global(&mut client, timeout_secs, verbose).await?;
             }
             DiscoverCommand::Mine {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 client_version,
                 minor_version,
                 timeout_secs,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_discover_mine(&mut client, timeout_secs, verbose).await?;
             }
             DiscoverCommand::User {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 target_user,
                 client_version,
                 minor_version,
                 timeout_secs,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_discover_user(&mut client, &target_user, timeout_secs, verbose).await?;
             }
             DiscoverCommand::SimilarTerms {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 term,
                 client_version,
                 minor_version,
                 timeout_secs,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_discover_similar_terms(&mut client, &term, timeout_secs, verbose).await?;
             }
             DiscoverCommand::AddLikeTerm {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 term,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_discover_add_like_term(&mut client, &term, verbose).await?;
             }
             DiscoverCommand::RemoveLikeTerm {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 term,
                 client_version,
                 minor_version,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_discover_remove_like_term(&mut client, &term, verbose).await?;
             }
             DiscoverCommand::RecommendedUsers {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 client_version,
                 minor_version,
                 timeout_secs,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_discover_recommended_users(&mut client, timeout_secs, verbose).await?;
             }
             DiscoverCommand::TermRecommendations {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 term,
                 client_version,
                 minor_version,
                 timeout_secs,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_discover_term_recommendations(&mut client, &term, timeout_secs, verbose)
                     .await?;
             }
             DiscoverCommand::RecommendationUsers {
-                server,
-                username,
-                password,
-                password_md5,
+                connection,
                 term,
                 client_version,
                 minor_version,
                 timeout_secs,
                 verbose,
             } => {
-                let mut client = connect_and_login(
-                    runtime_server(server.as_deref())?.as_str(),
-                    runtime_username(username.as_deref())?.as_str(),
-                    runtime_password(password.as_deref(), password_md5.as_deref())?.as_str(),
-                    client_version,
-                    minor_version,
-                )
-                .await?;
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
                 run_discover_recommendation_users(&mut client, &term, timeout_secs, verbose)
                     .await?;
             }
         },
+        Commands::Buddy { command } => match command {
+            BuddyCommand::Add {
+                file,
+                user,
+                note,
+                group,
+            } => {
+                let mut buddies = BuddyList::load(&file)?;
+                let added = buddies.upsert(&user, note.as_deref(), group.as_deref());
+                buddies.save(&file)?;
+                println!(
+                    "buddy.add ok user={} new={} total={}",
+                    user,
+                    added,
+                    buddies.len()
+                );
+            }
+            BuddyCommand::Remove { file, user } => {
+                let mut buddies = BuddyList::load(&file)?;
+                if !buddies.remove(&user) {
+                    bail!("{user} is not on the buddy list");
+                }
+                buddies.save(&file)?;
+                println!("buddy.remove ok user={} total={}", user, buddies.len());
+            }
+            BuddyCommand::List { file } => {
+                let buddies = BuddyList::load(&file)?;
+                println!(
+                    "buddy.list ok total={} groups={}",
+                    buddies.len(),
+                    buddies.groups().join(",")
+                );
+                for (idx, buddy) in buddies.buddies().iter().enumerate() {
+                    println!(
+                        "[{idx}] user={} group={} note={}",
+                        buddy.username,
+                        buddy.group.as_deref().unwrap_or("-"),
+                        buddy.note
+                    );
+                }
+            }
+            BuddyCommand::Watch {
+                file,
+                connection,
+                timeout_secs,
+                client_version,
+                minor_version,
+            } => {
+                let mut buddies = BuddyList::load(&file)?;
+                if buddies.is_empty() {
+                    bail!("buddy list {} is empty", file.display());
+                }
+                let mut client =
+                    connect_and_login(&connection.resolve(client_version, minor_version)?).await?;
+                run_buddy_watch(&mut client, &mut buddies, timeout_secs).await?;
+            }
+        },
+        Commands::Chat { command } => match command {
+            ChatCommand::Threads { file } => {
+                let store = ChatStore::load(&file)?;
+                println!(
+                    "chat.threads ok total={} unread={}",
+                    store.threads().len(),
+                    store.total_unread()
+                );
+                for (idx, thread) in store.threads().iter().enumerate() {
+                    println!(
+                        "[{idx}] user={} messages={} unread={}",
+                        thread.username,
+                        thread.messages.len(),
+                        thread.unread
+                    );
+                }
+            }
+            ChatCommand::Show {
+                file,
+                user,
+                mark_read,
+            } => {
+                let mut store = ChatStore::load(&file)?;
+                let Some(thread) = store.thread(&user) else {
+                    bail!("no conversation with {user}");
+                };
+                println!(
+                    "chat.show ok user={} messages={} unread={}",
+                    user,
+                    thread.messages.len(),
+                    thread.unread
+                );
+                for message in &thread.messages {
+                    println!("{}", chat_line(&user, message));
+                }
+                if mark_read && store.mark_read(&user) > 0 {
+                    store.save(&file)?;
+                }
+            }
+            ChatCommand::Search { file, query } => {
+                let store = ChatStore::load(&file)?;
+                let hits = store.search(&query);
+                println!("chat.search ok query={} hits={}", query, hits.len());
+                for hit in hits {
+                    println!("{}", chat_line(hit.username, hit.message));
+                }
+            }
+        },
         Commands::Verify { command } => match command {
             VerifyCommand::Fixtures {
                 fixtures_dir,
@@ -2063,8 +1962,29 @@ async fn main() -> Result<()> {
                 run,
                 base_dir,
                 mode,
+                render_formats,
+            } => {
+                let render_formats: Vec<ReportFormat> =
+                    render_formats.into_iter().map(to_report_format).collect();
+                run_verify_capture_run(&run, &base_dir, to_comparison_mode(mode), &render_formats)?;
+            }
+            VerifyCommand::Align {
+                run,
+                base_dir,
+                ignore_fields,
+                ignore_volatile,
+            } => {
+                run_verify_capture_alignment(&run, &base_dir, &ignore_fields, ignore_volatile)?;
+            }
+            VerifyCommand::Coverage {
+                captures_dir,
+                out_json,
+                out_md,
             } => {
-                run_verify_capture_run(&run, &base_dir, to_comparison_mode(mode))?;
+                run_verify_coverage(&captures_dir, &out_json, &out_md)?;
+            }
+            VerifyCommand::Schema { schema, report } => {
+                run_verify_schema(&schema, report.as_deref())?;
             }
         },
     }
@@ -2151,17 +2071,94 @@ fn env_key_to_arg(env_key: &str) -> &'static str {
     }
 }
 
-fn runtime_server(arg: Option<&str>) -> Result<String> {
-    env_or_arg(arg, "NSS_TEST_SERVER")
+impl ConnectionArgs {
+    /// Each flag falls back to its `NSS_TEST_*` variable; the password then
+    /// falls back to the one the TUI saved for this server and user in the
+    /// credential store.
+    fn resolve(self, client_version: u32, minor_version: u32) -> Result<Connection> {
+        let server = env_or_arg(self.server.as_deref(), "NSS_TEST_SERVER")?;
+        let username = env_or_arg(self.username.as_deref(), "NSS_TEST_USERNAME")?;
+        reject_password_md5(self.password_md5.as_deref())?;
+        let password = match env_or_arg(self.password.as_deref(), "NSS_TEST_PASSWORD") {
+            Ok(password) => password,
+            Err(err) => stored_password(&server, &username)?.ok_or(err)?,
+        };
+        Ok(Connection {
+            server,
+            credentials: Credentials {
+                username,
+                password,
+                client_version,
+                minor_version,
+            },
+        })
+    }
+}
+
+fn stored_password(server: &str, username: &str) -> Result<Option<String>> {
+    let Some(store) =
+        open_credential_store_with(prompt_store_passphrase).context("open credential store")?
+    else {
+        return Ok(None);
+    };
+    store.get(server, username)
+}
+
+/// Asks for the credential store passphrase on the terminal without echoing
+/// it. Skipped when stdin is not a terminal or there is no store file yet.
+fn prompt_store_passphrase() -> Result<Option<String>> {
+    if !std::io::stdin().is_terminal() || !credentials_file_path()?.exists() {
+        return Ok(None);
+    }
+    eprint!("Credential store passphrase: ");
+    std::io::stderr()
+        .flush()
+        .context("flush passphrase prompt")?;
+    terminal::enable_raw_mode().context("enable raw terminal mode")?;
+    let passphrase = read_hidden_line();
+    let _ = terminal::disable_raw_mode();
+    eprintln!();
+    Ok(Some(passphrase?).filter(|passphrase| !passphrase.is_empty()))
+}
+
+fn read_hidden_line() -> Result<String> {
+    let mut line = String::new();
+    loop {
+        let Event::Key(key) = event::read().context("read passphrase")? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Enter => return Ok(line),
+            KeyCode::Esc => bail!("passphrase prompt cancelled"),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                bail!("passphrase prompt cancelled")
+            }
+            KeyCode::Backspace => {
+                line.pop();
+            }
+            KeyCode::Char(ch) => line.push(ch),
+            _ => {}
+        }
+    }
 }
 
-fn runtime_username(arg: Option<&str>) -> Result<String> {
-    env_or_arg(arg, "NSS_TEST_USERNAME")
+fn to_frame_framing(framing: FramingArg) -> Option<FrameFraming> {
+    match framing {
+        FramingArg::Auto => None,
+        FramingArg::Server => Some(FrameFraming::Server),
+        FramingArg::Peer => Some(FrameFraming::Peer),
+        FramingArg::PeerInit => Some(FrameFraming::PeerInit),
+    }
 }
 
-fn runtime_password(password: Option<&str>, password_md5: Option<&str>) -> Result<String> {
-    reject_password_md5(password_md5)?;
-    env_or_arg(password, "NSS_TEST_PASSWORD")
+fn to_report_format(format: ReportFormatArg) -> ReportFormat {
+    match format {
+        ReportFormatArg::Html => ReportFormat::Html,
+        ReportFormatArg::Markdown => ReportFormat::Markdown,
+    }
 }
 
 fn to_comparison_mode(mode: VerifyModeArg) -> ComparisonMode {
@@ -2171,6 +2168,23 @@ fn to_comparison_mode(mode: VerifyModeArg) -> ComparisonMode {
     }
 }
 
+fn user_search_scope(mut usernames: Vec<String>) -> SearchScope {
+    if usernames.len() == 1 {
+        SearchScope::User(usernames.remove(0))
+    } else {
+        SearchScope::Users(usernames)
+    }
+}
+
+fn search_scope_label(scope: &SearchScope) -> String {
+    match scope {
+        SearchScope::Global => "global".to_string(),
+        SearchScope::Room(room) => format!("room:{room}"),
+        SearchScope::User(username) => format!("user:{username}"),
+        SearchScope::Users(usernames) => format!("users:{}", usernames.join(",")),
+    }
+}
+
 fn search_source_label(source: SearchResultSource) -> &'static str {
     match source {
         SearchResultSource::ServerSummary => "server_summary",
@@ -2178,63 +2192,40 @@ fn search_source_label(source: SearchResultSource) -> &'static str {
     }
 }
 
-async fn connect_and_login(
-    server: &str,
-    username: &str,
-    password: &str,
-    client_version: u32,
-    minor_version: u32,
-) -> Result<SessionClient> {
-    let mut client = SessionClient::connect(server).await?;
-    client
-        .login(&Credentials {
-            username: username.to_owned(),
-            password: password.to_owned(),
-            client_version,
-            minor_version,
-        })
-        .await?;
+async fn connect_and_login(connection: &Connection) -> Result<SessionClient> {
+    let mut client = SessionClient::connect(&connection.server).await?;
+    client.login(&connection.credentials).await?;
     Ok(client)
 }
 
-async fn run_login(
-    server: &str,
-    username: &str,
-    password: &str,
-    client_version: u32,
-    minor_version: u32,
-) -> Result<()> {
-    let client =
-        connect_and_login(server, username, password, client_version, minor_version).await?;
+async fn run_login(connection: &Connection) -> Result<()> {
+    let client = connect_and_login(connection).await?;
     println!(
         "session.login ok state={:?} server={}",
         client.state(),
-        server
+        connection.server
     );
     Ok(())
 }
 
 #[allow(clippy::too_many_arguments)]
 async fn run_search(
-    server: &str,
-    username: &str,
-    password: &str,
+    connection: &Connection,
     token: u32,
+    scope: &SearchScope,
     query: &str,
-    client_version: u32,
-    minor_version: u32,
     timeout_secs: u64,
     max_messages: usize,
     search_mode: SearchMode,
     strict_track: Option<&str>,
     connection_type: &str,
 ) -> Result<()> {
-    let mut client =
-        connect_and_login(server, username, password, client_version, minor_version).await?;
+    let mut client = connect_and_login(connection).await?;
 
     let candidates = client
-        .search_collect_candidates(
+        .search_collect_scoped_candidates(
             token,
+            scope,
             query,
             Duration::from_secs(timeout_secs),
             max_messages,
@@ -2249,8 +2240,9 @@ async fn run_search(
         .map(|candidate| candidate.source)
         .unwrap_or(SearchResultSource::ServerSummary);
     println!(
-        "session.search ok token={} query={} rows={} source={}",
+        "session.search ok token={} scope={} query={} rows={} source={}",
         token,
+        search_scope_label(scope),
         query,
         candidates.len(),
         search_source_label(source)
@@ -2275,6 +2267,7 @@ async fn run_download_auto(
     client: &mut SessionClient,
     token: u32,
     query: &str,
+    exact: Option<SearchCandidate>,
     output: PathBuf,
     transfer_token: u32,
     result_index: usize,
@@ -2305,10 +2298,18 @@ async fn run_download_auto(
         wait_port,
         skip_connect_probe,
         search_mode,
+        search_scope: SearchScope::Global,
         strict_track: strict_track.map(ToOwned::to_owned),
     };
 
-    let result = client.search_select_and_download(&request).await?;
+    let result = match exact {
+        Some(candidate) => {
+            client
+                .download_candidate(&candidate, &request.candidate_download())
+                .await?
+        }
+        None => client.search_select_and_download(&request).await?,
+    };
     println!(
         "session.download-auto ok user={} path={} size={} peer={} token={} bytes={} output={} source={}",
         result.selected_username,
@@ -2331,10 +2332,16 @@ async fn run_message(
     target_user: &str,
     message: &str,
     wait_ack: bool,
+    history: Option<&Path>,
     timeout_secs: u64,
     verbose: bool,
 ) -> Result<()> {
     client.send_private_message(target_user, message).await?;
+    if let Some(path) = history {
+        let mut store = ChatStore::load(path)?;
+        store.record_outgoing(target_user, message, unix_now_secs());
+        store.save(path)?;
+    }
     println!(
         "session.message sent target_user={} message_len={} wait_ack={}",
         target_user,
@@ -2409,6 +2416,135 @@ async fn run_user_stats(
     Ok(())
 }
 
+async fn run_user_info(
+    client: &mut SessionClient,
+    target_user: &str,
+    token: u32,
+    save_picture: Option<&Path>,
+    timeout_secs: u64,
+    verbose: bool,
+) -> Result<()> {
+    let payload = client
+        .get_user_info(target_user, token, Duration::from_secs(timeout_secs))
+        .await?;
+    println!(
+        "session.user-info ok target_user={} uploads={} queue={} slots_free={} picture_bytes={}",
+        target_user,
+        payload.total_uploads,
+        payload.queue_size,
+        payload.slots_free,
+        payload.picture.len()
+    );
+    for line in payload.description.lines() {
+        println!("session.user-info description {line}");
+    }
+    if let Some(path) = save_picture
+        && payload.has_picture
+    {
+        fs::write(path, &payload.picture)
+            .with_context(|| format!("write picture: {}", path.display()))?;
+        println!("session.user-info picture saved={}", path.display());
+    }
+    if verbose {
+        println!("{payload:#?}");
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_browse(
+    client: &mut SessionClient,
+    target_user: &str,
+    path: &str,
+    depth: Option<usize>,
+    download_to: Option<&Path>,
+    token: u32,
+    transfer_token: u32,
+    wait_port: Option<u16>,
+    timeout_secs: u64,
+) -> Result<()> {
+    let timeout = Duration::from_secs(timeout_secs);
+    let tree = client
+        .browse_user(target_user, token, timeout, false)
+        .await?;
+    let dir = tree
+        .root
+        .find(path)
+        .with_context(|| format!("{target_user} shares no folder named {path}"))?;
+    println!(
+        "session.browse ok target_user={} path={} files={} size={}",
+        target_user,
+        dir.path,
+        dir.file_count(),
+        dir.total_size()
+    );
+    print_share_dir(dir, 0, depth);
+
+    let Some(output_dir) = download_to else {
+        return Ok(());
+    };
+    let files = dir.walk_files();
+    let mut failed = 0;
+    for (idx, file) in files.iter().enumerate() {
+        let request = CandidateDownloadRequest {
+            transfer_token: transfer_token.wrapping_add(idx as u32),
+            output_path: local_download_path(output_dir, &file.path),
+            peer_addr_override: None,
+            peer_lookup_timeout: timeout,
+            connection_type: "P".to_owned(),
+            wait_port,
+            skip_connect_probe: false,
+        };
+        match client
+            .download_candidate(&tree.candidate(file), &request)
+            .await
+        {
+            Ok(result) => println!(
+                "session.browse download ok [{}/{}] path={} bytes={} output={}",
+                idx + 1,
+                files.len(),
+                file.path,
+                result.bytes_written,
+                result.output_path.display()
+            ),
+            Err(err) => {
+                failed += 1;
+                println!(
+                    "session.browse download failed [{}/{}] path={} error={err}",
+                    idx + 1,
+                    files.len(),
+                    file.path
+                );
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{failed} of {} downloads failed", files.len());
+    }
+    Ok(())
+}
+
+fn print_share_dir(dir: &SharedDir, level: usize, depth: Option<usize>) {
+    let indent = "  ".repeat(level);
+    let expand = depth.is_none_or(|depth| level < depth);
+    for child in &dir.dirs {
+        println!(
+            "{indent}{}/ files={} size={}",
+            child.name,
+            child.file_count(),
+            child.total_size()
+        );
+        if expand {
+            print_share_dir(child, level + 1, depth);
+        }
+    }
+    if expand {
+        for file in &dir.files {
+            println!("{indent}{} size={}", file.name, file.size);
+        }
+    }
+}
+
 async fn run_peer_address(
     client: &mut SessionClient,
     target_user: &str,
@@ -2452,11 +2588,29 @@ async fn run_watch_private(
     client: &mut SessionClient,
     timeout_secs: u64,
     max_events: usize,
+    history: Option<&Path>,
     verbose: bool,
 ) -> Result<()> {
     let events = client
         .collect_private_events(Duration::from_secs(timeout_secs), max_events)
         .await?;
+    // Incoming messages are acked by the session as they are read; the
+    // history only needs the buffered copies.
+    let received = client.take_private_messages();
+    if let Some(path) = history {
+        let mut store = ChatStore::load(path)?;
+        let stored = received
+            .iter()
+            .filter(|payload| store.record_incoming(payload))
+            .count();
+        store.save(path)?;
+        println!(
+            "session.watch-private history={} stored={} unread={}",
+            path.display(),
+            stored,
+            store.total_unread()
+        );
+    }
     let messages = events
         .iter()
         .filter(|event| matches!(event, PrivateEvent::Message(_)))
@@ -2608,14 +2762,23 @@ async fn run_inform_privileges_ack(client: &mut SessionClient, token: u32) -> Re
 
 async fn run_room_list(client: &mut SessionClient, verbose: bool) -> Result<()> {
     let payload = client.list_rooms(Duration::from_secs(6)).await?;
+    let mut rooms: Vec<(&String, Option<u32>)> = payload
+        .rooms
+        .iter()
+        .enumerate()
+        .map(|(idx, room)| (room, payload.user_counts.get(idx).copied()))
+        .collect();
+    rooms.sort_by_key(|(_, users)| std::cmp::Reverse(*users));
     println!(
         "room.list ok rooms={} sample={}",
         payload.room_count,
-        payload
-            .rooms
+        rooms
             .iter()
             .take(5)
-            .cloned()
+            .map(|(room, users)| match users {
+                Some(users) => format!("{room} ({users})"),
+                None => room.to_string(),
+            })
             .collect::<Vec<_>>()
             .join(", ")
     );
@@ -2748,18 +2911,99 @@ async fn run_room_ticker(
     Ok(())
 }
 
+fn chat_line(username: &str, message: &ChatMessage) -> String {
+    let arrow = match message.direction {
+        ChatDirection::Incoming => "<",
+        ChatDirection::Outgoing => ">",
+    };
+    format!(
+        "ts={} {} user={}{} text={}",
+        message.timestamp,
+        arrow,
+        username,
+        if message.offline { " offline" } else { "" },
+        message.text
+    )
+}
+
+fn unix_now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+async fn run_buddy_watch(
+    client: &mut SessionClient,
+    buddies: &mut BuddyList,
+    timeout_secs: u64,
+) -> Result<()> {
+    client.watch_buddies(buddies).await?;
+    let events = client
+        .collect_buddy_events(buddies, Duration::from_secs(timeout_secs), 1024)
+        .await?;
+
+    println!(
+        "buddy.watch ok watched={} timeout_secs={} events={}",
+        buddies.len(),
+        timeout_secs,
+        events.len()
+    );
+    for (idx, event) in events.iter().enumerate() {
+        match event {
+            BuddyEvent::NotFound { username } => {
+                println!("[{idx}] not_found user={username}");
+            }
+            BuddyEvent::StatusChanged {
+                username,
+                previous,
+                presence,
+                privileged,
+            } => {
+                println!(
+                    "[{idx}] status user={} from={} to={} privileged={}",
+                    username,
+                    previous.map_or("-", |presence| presence.label()),
+                    presence.label(),
+                    privileged
+                );
+            }
+            BuddyEvent::StatsChanged { username, stats } => {
+                println!(
+                    "[{idx}] stats user={} avg_speed={} files={} dirs={}",
+                    username, stats.avg_speed, stats.files, stats.dirs
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
 async fn run_room_watch(
     client: &mut SessionClient,
     room: &str,
     timeout_secs: u64,
+    log: Option<&RoomLog>,
     verbose: bool,
 ) -> Result<()> {
     client.join_room(room).await?;
     client.request_room_members(room).await?;
     client.request_room_operators(room).await?;
-    let events = client
-        .collect_room_events(Duration::from_secs(timeout_secs), 512)
-        .await?;
+    let events = match log {
+        Some(log) => {
+            client
+                .collect_room_events_logged(log, Duration::from_secs(timeout_secs), 512)
+                .await?
+        }
+        None => {
+            client
+                .collect_room_events(Duration::from_secs(timeout_secs), 512)
+                .await?
+        }
+    };
+    if let Some(log) = log {
+        println!("room.watch log_dir={}", log.dir().display());
+    }
 
     let joined = events
         .iter()
@@ -2775,7 +3019,14 @@ async fn run_room_watch(
         .count();
     let tickers = events
         .iter()
-        .filter(|event| matches!(event, RoomEvent::TickerSnapshot(_)))
+        .filter(|event| {
+            matches!(
+                event,
+                RoomEvent::TickerSnapshot(_)
+                    | RoomEvent::TickerSet { .. }
+                    | RoomEvent::TickerRemoved { .. }
+            )
+        })
         .count();
 
     println!(
@@ -3075,8 +3326,16 @@ async fn run_discover_recommendation_users(
     Ok(())
 }
 
-async fn run_probe_login_version(server: &str, username: &str, password: &str) -> Result<()> {
-    let attempts = probe_login_versions(server, username, password).await?;
+/// Tries the known client versions in turn, so the versions in `connection`
+/// are not used.
+async fn run_probe_login_version(connection: &Connection) -> Result<()> {
+    let credentials = &connection.credentials;
+    let attempts = probe_login_versions(
+        &connection.server,
+        &credentials.username,
+        &credentials.password,
+    )
+    .await?;
     let rendered: Vec<serde_json::Value> = attempts
         .into_iter()
         .map(|attempt| {
@@ -3122,8 +3381,13 @@ async fn run_serve_upload(
     decision: ManualDecisionArg,
     reason: String,
     source_file: Option<PathBuf>,
+    profile: UserProfile,
+    upload_slots: u32,
 ) -> Result<()> {
-    let agent = UploadAgent::bind_manual(bind).await?;
+    let agent = UploadAgent::bind_manual(bind)
+        .await?
+        .with_profile(profile)
+        .with_scheduler(UploadScheduler::new(upload_slots));
     let addr = agent.local_addr()?;
 
     println!("transfer.serve-upload waiting bind={} policy=manual", addr);
@@ -3137,13 +3401,13 @@ async fn run_serve_upload(
         .await?;
 
     println!(
-        "transfer.serve-upload handled peer={} decision={:?} bytes_sent={}",
-        result.peer_addr, result.decision, result.bytes_sent
+        "transfer.serve-upload handled peer={} decision={:?} bytes_sent={} user_info_served={}",
+        result.peer_addr, result.decision, result.bytes_sent, result.user_info_served
     );
     Ok(())
 }
 
-fn run_verify_fixtures(fixtures_dir: &PathBuf, report: &PathBuf) -> Result<()> {
+fn run_verify_fixtures(fixtures_dir: &Path, report: &Path) -> Result<()> {
     let comparisons = verify_fixtures(fixtures_dir)?;
     write_report(report, &comparisons)
         .with_context(|| format!("write report: {}", report.display()))?;
@@ -3162,14 +3426,36 @@ fn run_verify_fixtures(fixtures_dir: &PathBuf, report: &PathBuf) -> Result<()> {
     Ok(())
 }
 
-fn run_verify_capture_run(run: &str, base_dir: &PathBuf, mode: ComparisonMode) -> Result<()> {
+fn run_verify_capture_run(
+    run: &str,
+    base_dir: &Path,
+    mode: ComparisonMode,
+    render_formats: &[ReportFormat],
+) -> Result<()> {
     let run_dir = base_dir.join(run);
     let report_path = run_dir.join("verify-captures-report.json");
 
-    let report = compare_capture_run_with_mode(&run_dir, mode)
-        .with_context(|| format!("compare capture run: {}", run_dir.display()))?;
+    let official = load_hex_lines(run_dir.join("official_frames.hex"))?;
+    let neo = load_hex_lines(run_dir.join("neo_frames.hex"))?;
+    let run_id = run_dir
+        .file_name()
+        .map(|v| v.to_string_lossy().to_string())
+        .unwrap_or_else(|| run.to_string());
+    let report = compare_capture_sequences_with_mode(&run_id, &official, &neo, mode);
     write_capture_report(&report_path, &report)?;
 
+    let rendered = write_rendered_capture_reports(
+        run_dir.join("verify-captures-report"),
+        &report,
+        &official,
+        &neo,
+        render_formats,
+    )
+    .with_context(|| format!("render capture run: {}", run_dir.display()))?;
+    for path in rendered {
+        println!("rendered={}", path.display());
+    }
+
     print_capture_report_summary(&report);
 
     if report.mismatched_pairs != 0 || report.official_only != 0 || report.neo_only != 0 {
@@ -3192,7 +3478,165 @@ fn print_capture_report_summary(report: &CaptureRunReport) {
     );
 }
 
-fn verify_fixtures(fixtures_dir: &PathBuf) -> Result<Vec<FrameComparison>> {
+fn run_verify_capture_alignment(
+    run: &str,
+    base_dir: &Path,
+    ignore_fields: &[IgnoreRule],
+    ignore_volatile: bool,
+) -> Result<()> {
+    let run_dir = base_dir.join(run);
+    let report_path = run_dir.join("verify-captures-alignment.json");
+
+    let mut ignore_rules = if ignore_volatile {
+        IgnoreRule::volatile_defaults()
+    } else {
+        Vec::new()
+    };
+    ignore_rules.extend_from_slice(ignore_fields);
+    let options = AlignmentOptions { ignore_rules };
+
+    let report = align_capture_run(&run_dir, &options)
+        .with_context(|| format!("align capture run: {}", run_dir.display()))?;
+    write_alignment_report(&report_path, &report)?;
+
+    print_alignment_report_summary(&report);
+
+    if !report.is_clean() {
+        bail!("capture alignment failed for run {}", run);
+    }
+
+    Ok(())
+}
+
+fn print_alignment_report_summary(report: &CaptureAlignmentReport) {
+    for connection in &report.connections {
+        println!(
+            "run={} connection={} official={} neo={} matched={} modified={} reordered={} inserted={} deleted={}",
+            report.run_id,
+            connection.connection,
+            connection.official_frames,
+            connection.neo_frames,
+            connection.matched,
+            connection.modified,
+            connection.reordered,
+            connection.inserted,
+            connection.deleted
+        );
+    }
+}
+
+fn run_decode(
+    hex: Option<&str>,
+    file: Option<&Path>,
+    framing: FramingArg,
+    lenient: bool,
+) -> Result<()> {
+    let bytes = match (hex, file) {
+        (Some(_), Some(_)) => bail!("pass either a hex string or --file, not both"),
+        (Some(hex), None) => parse_hex_input(hex).context("parse hex input")?,
+        (None, Some(path)) => {
+            let raw = fs::read(path).with_context(|| format!("read {}", path.display()))?;
+            decode_input_bytes(raw)?
+        }
+        (None, None) => {
+            let mut raw = Vec::new();
+            std::io::stdin()
+                .read_to_end(&mut raw)
+                .context("read stdin")?;
+            decode_input_bytes(raw)?
+        }
+    };
+
+    let options = InspectOptions {
+        framing: to_frame_framing(framing),
+        lenient,
+    };
+    let frames = inspect_stream(&bytes, &options)?;
+    println!("{}", serde_json::to_string_pretty(&frames)?);
+    Ok(())
+}
+
+fn decode_input_bytes(raw: Vec<u8>) -> Result<Vec<u8>> {
+    if looks_like_hex_text(&raw) {
+        let text = String::from_utf8(raw).context("hex input is not utf-8")?;
+        return parse_hex_input(&text).context("parse hex input");
+    }
+    Ok(raw)
+}
+
+fn run_verify_schema(schema_path: &Path, report_path: Option<&Path>) -> Result<()> {
+    let schema = load_message_schema(schema_path)?;
+    let report = check_schema_conformance(&schema);
+    if let Some(path) = report_path {
+        write_schema_conformance_report(path, &report)?;
+    }
+
+    for case in report.cases.iter().filter(|case| case.is_disagreement()) {
+        let detail = match &case.result {
+            SchemaConformance::DecodeFailed { error } => format!("decode_failed error={error}"),
+            SchemaConformance::ReencodeMismatch {
+                first_diff_offset,
+                synthesized_len,
+                reencoded_len,
+            } => format!(
+                "reencode_mismatch first_diff_offset={first_diff_offset:?} synthesized_len={synthesized_len} reencoded_len={reencoded_len}"
+            ),
+            SchemaConformance::FieldMismatch {
+                field,
+                expected,
+                actual,
+            } => format!("field_mismatch field={field} expected={expected} actual={actual}"),
+            SchemaConformance::Conforms | SchemaConformance::Skipped { .. } => continue,
+        };
+        println!(
+            "schema.disagreement scope={} code={} name={} {}",
+            case.scope, case.code, case.name, detail
+        );
+    }
+    println!(
+        "schema version={} total={} conforms={} skipped={} disagreements={}",
+        report.schema_version, report.total, report.conforms, report.skipped, report.disagreements
+    );
+
+    if report.disagreements != 0 {
+        bail!(
+            "schema conformance found {} disagreements",
+            report.disagreements
+        );
+    }
+    Ok(())
+}
+
+fn run_verify_coverage(captures_dir: &Path, out_json: &Path, out_md: &Path) -> Result<()> {
+    let report = protocol_coverage(captures_dir)
+        .with_context(|| format!("protocol coverage: {}", captures_dir.display()))?;
+    write_protocol_coverage_report(out_json, &report)?;
+    fs::write(out_md, render_protocol_coverage_markdown(&report))
+        .with_context(|| format!("write report: {}", out_md.display()))?;
+
+    print_protocol_coverage_summary(&report);
+    Ok(())
+}
+
+fn print_protocol_coverage_summary(report: &ProtocolCoverageReport) {
+    let summary = &report.summary;
+    println!(
+        "coverage messages={} server={} peer={} distributed={} peer_init={} typed_decode={} opaque_decode={} missing_decode={} encode={} roundtrip_test={} capture_evidence={}",
+        summary.total_messages,
+        summary.server_messages,
+        summary.peer_messages,
+        summary.distributed_messages,
+        summary.peer_init_messages,
+        summary.typed_decode,
+        summary.opaque_decode,
+        summary.missing_decode,
+        summary.encode,
+        summary.roundtrip_test,
+        summary.capture_evidence
+    );
+}
+
+fn verify_fixtures(fixtures_dir: &Path) -> Result<Vec<FrameComparison>> {
     let login_fixture = fixtures_dir.join("server_login_request.hex");
     let search_fixture = fixtures_dir.join("server_file_search_request.hex");
     let transfer_req_fixture = fixtures_dir.join("peer_transfer_request.hex");
@@ -0,0 +1,287 @@
+use anyhow::{Context, Result, bail};
+use protocol::ServerMessage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserPresence {
+    Offline,
+    Away,
+    Online,
+}
+
+impl UserPresence {
+    pub fn from_status(status: u32) -> Option<Self> {
+        match status {
+            0 => Some(Self::Offline),
+            1 => Some(Self::Away),
+            2 => Some(Self::Online),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> u32 {
+        match self {
+            Self::Offline => 0,
+            Self::Away => 1,
+            Self::Online => 2,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Offline => "offline",
+            Self::Away => "away",
+            Self::Online => "online",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Buddy {
+    pub username: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub note: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuddyStats {
+    pub avg_speed: u32,
+    pub files: u32,
+    pub dirs: u32,
+}
+
+/// Live state learned from the server; never persisted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuddyStatus {
+    pub exists: Option<bool>,
+    pub presence: Option<UserPresence>,
+    pub privileged: bool,
+    pub stats: Option<BuddyStats>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuddyEvent {
+    NotFound {
+        username: String,
+    },
+    StatusChanged {
+        username: String,
+        previous: Option<UserPresence>,
+        presence: UserPresence,
+        privileged: bool,
+    },
+    StatsChanged {
+        username: String,
+        stats: BuddyStats,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BuddyList {
+    buddies: Vec<Buddy>,
+    #[serde(skip)]
+    status: HashMap<String, BuddyStatus>,
+}
+
+impl BuddyList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("read buddy list: {}", path.display()))?;
+        serde_json::from_str(&raw).with_context(|| format!("parse buddy list: {}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent).with_context(|| format!("create {}", parent.display()))?;
+        }
+        let json = serde_json::to_string_pretty(self).context("serialize buddy list")?;
+        fs::write(path, json).with_context(|| format!("write buddy list: {}", path.display()))
+    }
+
+    pub fn buddies(&self) -> &[Buddy] {
+        &self.buddies
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buddies.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buddies.len()
+    }
+
+    pub fn usernames(&self) -> Vec<String> {
+        self.buddies
+            .iter()
+            .map(|buddy| buddy.username.clone())
+            .collect()
+    }
+
+    pub fn get(&self, username: &str) -> Option<&Buddy> {
+        self.buddies.iter().find(|buddy| buddy.username == username)
+    }
+
+    pub fn contains(&self, username: &str) -> bool {
+        self.get(username).is_some()
+    }
+
+    /// Adds a buddy, or updates the note and group of an existing one.
+    /// Returns `true` when the user was not on the list before.
+    pub fn upsert(&mut self, username: &str, note: Option<&str>, group: Option<&str>) -> bool {
+        let group = group.map(str::trim).filter(|group| !group.is_empty());
+        if let Some(buddy) = self
+            .buddies
+            .iter_mut()
+            .find(|buddy| buddy.username == username)
+        {
+            if let Some(note) = note {
+                buddy.note = note.to_string();
+            }
+            if let Some(group) = group {
+                buddy.group = Some(group.to_string());
+            }
+            return false;
+        }
+        self.buddies.push(Buddy {
+            username: username.to_string(),
+            note: note.unwrap_or_default().to_string(),
+            group: group.map(str::to_string),
+        });
+        true
+    }
+
+    pub fn remove(&mut self, username: &str) -> bool {
+        let before = self.buddies.len();
+        self.buddies.retain(|buddy| buddy.username != username);
+        self.status.remove(username);
+        self.buddies.len() != before
+    }
+
+    pub fn set_group(&mut self, username: &str, group: Option<&str>) -> Result<()> {
+        let Some(buddy) = self
+            .buddies
+            .iter_mut()
+            .find(|buddy| buddy.username == username)
+        else {
+            bail!("{username} is not on the buddy list");
+        };
+        buddy.group = group
+            .map(str::trim)
+            .filter(|group| !group.is_empty())
+            .map(str::to_string);
+        Ok(())
+    }
+
+    pub fn groups(&self) -> Vec<String> {
+        let mut groups: Vec<String> = self
+            .buddies
+            .iter()
+            .filter_map(|buddy| buddy.group.clone())
+            .collect();
+        groups.sort();
+        groups.dedup();
+        groups
+    }
+
+    pub fn status(&self, username: &str) -> Option<&BuddyStatus> {
+        self.status.get(username)
+    }
+
+    /// Folds a server message into the live status of a listed buddy and
+    /// reports what changed. Messages about other users are ignored.
+    pub fn apply(&mut self, message: &ServerMessage) -> Vec<BuddyEvent> {
+        let mut events = Vec::new();
+        match message {
+            ServerMessage::AddUserResponse(payload) if self.contains(&payload.username) => {
+                let username = payload.username.clone();
+                self.status.entry(username.clone()).or_default().exists = Some(payload.exists);
+                if !payload.exists {
+                    events.push(BuddyEvent::NotFound { username });
+                    return events;
+                }
+                if let Some(stats) = &payload.stats {
+                    let privileged = self.status(&username).is_some_and(|s| s.privileged);
+                    events.extend(self.update_presence(&username, stats.status, privileged));
+                    events.extend(self.update_stats(
+                        &username,
+                        BuddyStats {
+                            avg_speed: stats.avg_speed,
+                            files: stats.files,
+                            dirs: stats.dirs,
+                        },
+                    ));
+                }
+            }
+            ServerMessage::GetUserStatusResponse(payload) if self.contains(&payload.username) => {
+                events.extend(self.update_presence(
+                    &payload.username,
+                    payload.status,
+                    payload.privileged,
+                ));
+            }
+            ServerMessage::GetUserStatsResponse(payload) if self.contains(&payload.username) => {
+                events.extend(self.update_stats(
+                    &payload.username,
+                    BuddyStats {
+                        avg_speed: payload.avg_speed,
+                        files: payload.files,
+                        dirs: payload.dirs,
+                    },
+                ));
+            }
+            _ => {}
+        }
+        events
+    }
+
+    fn update_presence(
+        &mut self,
+        username: &str,
+        status: u32,
+        privileged: bool,
+    ) -> Option<BuddyEvent> {
+        let presence = UserPresence::from_status(status)?;
+        let entry = self.status.entry(username.to_string()).or_default();
+        entry.exists = Some(true);
+        if entry.presence == Some(presence) && entry.privileged == privileged {
+            return None;
+        }
+        let previous = entry.presence.replace(presence);
+        entry.privileged = privileged;
+        Some(BuddyEvent::StatusChanged {
+            username: username.to_string(),
+            previous,
+            presence,
+            privileged,
+        })
+    }
+
+    fn update_stats(&mut self, username: &str, stats: BuddyStats) -> Option<BuddyEvent> {
+        let entry = self.status.entry(username.to_string()).or_default();
+        if entry.stats == Some(stats) {
+            return None;
+        }
+        entry.stats = Some(stats);
+        Some(BuddyEvent::StatsChanged {
+            username: username.to_string(),
+            stats,
+        })
+    }
+}
@@ -0,0 +1,184 @@
+use anyhow::{Context, Result};
+use protocol::MessageUserIncomingPayload;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatDirection {
+    Incoming,
+    Outgoing,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub timestamp: u64,
+    pub direction: ChatDirection,
+    pub text: String,
+    /// Server id of an incoming message, used to drop redeliveries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<u32>,
+    /// Set for messages the server held while we were offline.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub offline: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatThread {
+    pub username: String,
+    pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub unread: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatSearchHit<'a> {
+    pub username: &'a str,
+    pub message: &'a ChatMessage,
+}
+
+/// Private conversations keyed by peer, most recently active thread first.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ChatStore {
+    threads: Vec<ChatThread>,
+}
+
+impl ChatStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("read chat history: {}", path.display()))?;
+        serde_json::from_str(&raw)
+            .with_context(|| format!("parse chat history: {}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent).with_context(|| format!("create {}", parent.display()))?;
+        }
+        let json = serde_json::to_string_pretty(self).context("serialize chat history")?;
+        fs::write(path, json).with_context(|| format!("write chat history: {}", path.display()))
+    }
+
+    pub fn threads(&self) -> &[ChatThread] {
+        &self.threads
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.threads.is_empty()
+    }
+
+    pub fn thread(&self, username: &str) -> Option<&ChatThread> {
+        self.threads
+            .iter()
+            .find(|thread| thread.username == username)
+    }
+
+    pub fn unread(&self, username: &str) -> usize {
+        self.thread(username).map_or(0, |thread| thread.unread)
+    }
+
+    pub fn total_unread(&self) -> usize {
+        self.threads.iter().map(|thread| thread.unread).sum()
+    }
+
+    /// Stores an incoming message and counts it as unread. Returns `false`
+    /// when the same server message id was already stored for that user.
+    pub fn record_incoming(&mut self, payload: &MessageUserIncomingPayload) -> bool {
+        let duplicate = self.thread(&payload.username).is_some_and(|thread| {
+            thread
+                .messages
+                .iter()
+                .any(|message| message.message_id == Some(payload.message_id))
+        });
+        if duplicate {
+            return false;
+        }
+        let thread = self.touch(&payload.username);
+        thread.messages.push(ChatMessage {
+            timestamp: u64::from(payload.timestamp),
+            direction: ChatDirection::Incoming,
+            text: payload.message.clone(),
+            message_id: Some(payload.message_id),
+            offline: !payload.is_new,
+        });
+        thread.unread += 1;
+        true
+    }
+
+    pub fn record_outgoing(&mut self, username: &str, text: &str, timestamp: u64) {
+        let thread = self.touch(username);
+        thread.messages.push(ChatMessage {
+            timestamp,
+            direction: ChatDirection::Outgoing,
+            text: text.to_string(),
+            message_id: None,
+            offline: false,
+        });
+    }
+
+    /// Clears the unread count of a thread and returns what it was.
+    pub fn mark_read(&mut self, username: &str) -> usize {
+        self.threads
+            .iter_mut()
+            .find(|thread| thread.username == username)
+            .map_or(0, |thread| std::mem::take(&mut thread.unread))
+    }
+
+    pub fn remove(&mut self, username: &str) -> bool {
+        let before = self.threads.len();
+        self.threads.retain(|thread| thread.username != username);
+        self.threads.len() != before
+    }
+
+    /// Case-insensitive text search across every thread, oldest match first
+    /// within each thread.
+    pub fn search(&self, query: &str) -> Vec<ChatSearchHit<'_>> {
+        let needle = query.trim().to_lowercase();
+        if needle.is_empty() {
+            return Vec::new();
+        }
+        self.threads
+            .iter()
+            .flat_map(|thread| {
+                thread
+                    .messages
+                    .iter()
+                    .filter(|message| message.text.to_lowercase().contains(&needle))
+                    .map(|message| ChatSearchHit {
+                        username: &thread.username,
+                        message,
+                    })
+            })
+            .collect()
+    }
+
+    fn touch(&mut self, username: &str) -> &mut ChatThread {
+        let thread = match self
+            .threads
+            .iter()
+            .position(|thread| thread.username == username)
+        {
+            Some(idx) => self.threads.remove(idx),
+            None => ChatThread {
+                username: username.to_string(),
+                messages: Vec::new(),
+                unread: 0,
+            },
+        };
+        self.threads.insert(0, thread);
+        &mut self.threads[0]
+    }
+}
@@ -0,0 +1,508 @@
+use anyhow::{Context, Result, anyhow, bail};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const CREDENTIALS_FILE_NAME: &str = "credentials.enc.json";
+const ENCRYPTED_FILE_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+
+/// Saved login passwords, keyed by server address and username.
+pub trait CredentialStore: Send {
+    /// Short name shown to the user, e.g. `encrypted-file`.
+    fn backend(&self) -> &'static str;
+    fn get(&self, server: &str, username: &str) -> Result<Option<String>>;
+    fn set(&mut self, server: &str, username: &str, password: &str) -> Result<()>;
+    /// Returns whether an entry was removed.
+    fn remove(&mut self, server: &str, username: &str) -> Result<bool>;
+}
+
+/// Opens the store selected by `NSS_CREDENTIALS_BACKEND` (`file` by default,
+/// or `secret-service`). The file backend needs `NSS_CREDENTIALS_PASSPHRASE`;
+/// without it no store is available and `None` is returned.
+pub fn open_credential_store() -> Result<Option<Box<dyn CredentialStore>>> {
+    open_credential_store_with(|| Ok(None))
+}
+
+/// Like [`open_credential_store`], but asks `prompt` for the file backend's
+/// passphrase when `NSS_CREDENTIALS_PASSPHRASE` is unset. The variable still
+/// wins when present, and a prompt that yields nothing leaves no store.
+pub fn open_credential_store_with(
+    prompt: impl FnOnce() -> Result<Option<String>>,
+) -> Result<Option<Box<dyn CredentialStore>>> {
+    let backend = std::env::var("NSS_CREDENTIALS_BACKEND").unwrap_or_default();
+    match backend.trim() {
+        "" | "file" => {
+            let passphrase = match std::env::var("NSS_CREDENTIALS_PASSPHRASE") {
+                Ok(passphrase) if passphrase.is_empty() => {
+                    bail!("NSS_CREDENTIALS_PASSPHRASE is set but empty")
+                }
+                Ok(passphrase) => passphrase,
+                Err(_) => match prompt()? {
+                    Some(passphrase) if !passphrase.is_empty() => passphrase,
+                    _ => return Ok(None),
+                },
+            };
+            let store = EncryptedFileStore::open(credentials_file_path()?, &passphrase)?;
+            Ok(Some(Box::new(store)))
+        }
+        #[cfg(feature = "secret-service")]
+        "secret-service" => Ok(Some(Box::new(SecretServiceStore::connect()?))),
+        #[cfg(not(feature = "secret-service"))]
+        "secret-service" => bail!("built without the secret-service feature"),
+        other => bail!("unknown credential backend {other:?}; use file or secret-service"),
+    }
+}
+
+/// Whether the selected backend is the encrypted file, so a missing store can
+/// be unlocked by asking the user for its passphrase.
+pub fn credential_store_uses_passphrase() -> bool {
+    matches!(
+        std::env::var("NSS_CREDENTIALS_BACKEND")
+            .unwrap_or_default()
+            .trim(),
+        "" | "file"
+    )
+}
+
+/// Where the encrypted file backend keeps its data, shared by the CLI and TUI.
+/// `NSS_CREDENTIALS_FILE` overrides the location.
+pub fn credentials_file_path() -> Result<PathBuf> {
+    if let Ok(override_path) = std::env::var("NSS_CREDENTIALS_FILE") {
+        let trimmed = override_path.trim();
+        if trimmed.is_empty() {
+            bail!("NSS_CREDENTIALS_FILE is set but empty");
+        }
+        return Ok(PathBuf::from(trimmed));
+    }
+    let project_dirs = ProjectDirs::from("org", "NeoSoulSeek", "NeoSoulSeek")
+        .context("resolve project directories")?;
+    Ok(project_dirs.data_local_dir().join(CREDENTIALS_FILE_NAME))
+}
+
+/// Argon2id cost settings used to turn the passphrase into a key. They are
+/// written next to the ciphertext so a file stays readable if defaults change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct StoredCredential {
+    server: String,
+    username: String,
+    password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedFile {
+    version: u8,
+    kdf: KdfParams,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Credentials encrypted with XChaCha20-Poly1305 under a key derived from a
+/// passphrase. The whole entry list is re-encrypted with a fresh salt and
+/// nonce on every change.
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    passphrase: String,
+    kdf: KdfParams,
+    entries: Vec<StoredCredential>,
+}
+
+impl std::fmt::Debug for EncryptedFileStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptedFileStore")
+            .field("path", &self.path)
+            .field("entries", &self.entries.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl EncryptedFileStore {
+    pub fn open(path: impl Into<PathBuf>, passphrase: &str) -> Result<Self> {
+        Self::open_with_params(path, passphrase, KdfParams::default())
+    }
+
+    /// Like `open`, with the key derivation cost used for the next write.
+    pub fn open_with_params(
+        path: impl Into<PathBuf>,
+        passphrase: &str,
+        kdf: KdfParams,
+    ) -> Result<Self> {
+        let path = path.into();
+        let entries = if path.exists() {
+            read_encrypted(&path, passphrase)?
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            path,
+            passphrase: passphrase.to_owned(),
+            kdf,
+            entries,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn save(&self) -> Result<()> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let cipher = cipher_for(&self.passphrase, &salt, self.kdf)?;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let plaintext = serde_json::to_vec(&self.entries).context("serialize credentials")?;
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext.as_slice())
+            .map_err(|_| anyhow!("encrypt credentials"))?;
+        let file = EncryptedFile {
+            version: ENCRYPTED_FILE_VERSION,
+            kdf: self.kdf,
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        };
+
+        if let Some(parent) = self.path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent).with_context(|| format!("create {}", parent.display()))?;
+        }
+        let json = serde_json::to_string_pretty(&file).context("serialize credentials file")?;
+
+        // Write beside the store and rename over it, so a crash mid-write
+        // leaves the previous file intact instead of a truncated one.
+        let mut tmp_name = self.path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = self.path.with_file_name(tmp_name);
+        if let Err(err) = write_private_file(&tmp_path, json.as_bytes()) {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(err);
+        }
+        fs::rename(&tmp_path, &self.path).with_context(|| {
+            let _ = fs::remove_file(&tmp_path);
+            format!("replace credentials: {}", self.path.display())
+        })
+    }
+
+    fn position(&self, server: &str, username: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| entry.server == server && entry.username == username)
+    }
+}
+
+impl CredentialStore for EncryptedFileStore {
+    fn backend(&self) -> &'static str {
+        "encrypted-file"
+    }
+
+    fn get(&self, server: &str, username: &str) -> Result<Option<String>> {
+        Ok(self
+            .position(server, username)
+            .map(|idx| self.entries[idx].password.clone()))
+    }
+
+    fn set(&mut self, server: &str, username: &str, password: &str) -> Result<()> {
+        match self.position(server, username) {
+            Some(idx) if self.entries[idx].password == password => return Ok(()),
+            Some(idx) => self.entries[idx].password = password.to_owned(),
+            None => self.entries.push(StoredCredential {
+                server: server.to_owned(),
+                username: username.to_owned(),
+                password: password.to_owned(),
+            }),
+        }
+        self.save()
+    }
+
+    fn remove(&mut self, server: &str, username: &str) -> Result<bool> {
+        let Some(idx) = self.position(server, username) else {
+            return Ok(false);
+        };
+        self.entries.remove(idx);
+        self.save()?;
+        Ok(true)
+    }
+}
+
+/// Writes `contents` to a file only the owner can read, setting the mode
+/// before any secret bytes land on disk.
+fn write_private_file(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("write credentials: {}", path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("set permissions on {}", path.display()))?;
+    }
+    file.write_all(contents)
+        .and_then(|()| file.sync_all())
+        .with_context(|| format!("write credentials: {}", path.display()))
+}
+
+fn read_encrypted(path: &Path, passphrase: &str) -> Result<Vec<StoredCredential>> {
+    let raw = fs::read_to_string(path)
+        .with_context(|| format!("read credentials: {}", path.display()))?;
+    let file: EncryptedFile = serde_json::from_str(&raw)
+        .with_context(|| format!("parse credentials: {}", path.display()))?;
+    if file.version != ENCRYPTED_FILE_VERSION {
+        bail!(
+            "unsupported credentials file version {} in {}",
+            file.version,
+            path.display()
+        );
+    }
+    let salt = hex::decode(&file.salt).context("decode credentials salt")?;
+    let nonce = hex::decode(&file.nonce).context("decode credentials nonce")?;
+    let ciphertext = hex::decode(&file.ciphertext).context("decode credentials ciphertext")?;
+    if nonce.len() != 24 {
+        bail!("credentials nonce has {} bytes, expected 24", nonce.len());
+    }
+    let cipher = cipher_for(passphrase, &salt, file.kdf)?;
+    let plaintext = cipher
+        .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| {
+            anyhow!(
+                "decrypt {}: wrong passphrase or damaged file",
+                path.display()
+            )
+        })?;
+    serde_json::from_slice(&plaintext).context("parse decrypted credentials")
+}
+
+fn cipher_for(passphrase: &str, salt: &[u8], kdf: KdfParams) -> Result<XChaCha20Poly1305> {
+    let params = Params::new(kdf.memory_kib, kdf.iterations, kdf.parallelism, Some(32))
+        .map_err(|err| anyhow!("invalid key derivation parameters: {err}"))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| anyhow!("derive credentials key: {err}"))?;
+    Ok(XChaCha20Poly1305::new(&key.into()))
+}
+
+/// Passwords kept in the desktop keyring over the freedesktop Secret Service
+/// API (GNOME Keyring, KWallet). Each call talks to the service on its own
+/// thread since the blocking client must not run inside the tokio runtime.
+#[cfg(feature = "secret-service")]
+#[derive(Debug, Default)]
+pub struct SecretServiceStore;
+
+#[cfg(feature = "secret-service")]
+impl SecretServiceStore {
+    const APPLICATION: &'static str = "neosoulseek";
+
+    pub fn connect() -> Result<Self> {
+        off_runtime(|| {
+            secret_service::blocking::SecretService::connect(secret_service::EncryptionType::Dh)
+                .map(|_| ())
+        })
+        .context("connect to the secret service")?;
+        Ok(Self)
+    }
+
+    fn attributes<'a>(
+        server: &'a str,
+        username: &'a str,
+    ) -> std::collections::HashMap<&'a str, &'a str> {
+        std::collections::HashMap::from([
+            ("application", Self::APPLICATION),
+            ("server", server),
+            ("username", username),
+        ])
+    }
+}
+
+#[cfg(feature = "secret-service")]
+impl CredentialStore for SecretServiceStore {
+    fn backend(&self) -> &'static str {
+        "secret-service"
+    }
+
+    fn get(&self, server: &str, username: &str) -> Result<Option<String>> {
+        let secret = off_runtime(|| {
+            let service = secret_service::blocking::SecretService::connect(
+                secret_service::EncryptionType::Dh,
+            )?;
+            let found = service.search_items(Self::attributes(server, username))?;
+            if let Some(item) = found.unlocked.first() {
+                return item.get_secret().map(Some);
+            }
+            match found.locked.first() {
+                Some(item) => {
+                    item.unlock()?;
+                    item.get_secret().map(Some)
+                }
+                None => Ok(None),
+            }
+        })
+        .context("read password from the secret service")?;
+        secret
+            .map(|bytes| String::from_utf8(bytes).context("stored password is not UTF-8"))
+            .transpose()
+    }
+
+    fn set(&mut self, server: &str, username: &str, password: &str) -> Result<()> {
+        off_runtime(|| {
+            let service = secret_service::blocking::SecretService::connect(
+                secret_service::EncryptionType::Dh,
+            )?;
+            let collection = service.get_default_collection()?;
+            collection.ensure_unlocked()?;
+            collection.create_item(
+                &format!("NeoSoulSeek {username}@{server}"),
+                Self::attributes(server, username),
+                password.as_bytes(),
+                true,
+                "text/plain",
+            )?;
+            Ok(())
+        })
+        .context("store password in the secret service")
+    }
+
+    fn remove(&mut self, server: &str, username: &str) -> Result<bool> {
+        off_runtime(|| {
+            let service = secret_service::blocking::SecretService::connect(
+                secret_service::EncryptionType::Dh,
+            )?;
+            let found = service.search_items(Self::attributes(server, username))?;
+            let mut removed = false;
+            for item in found.unlocked.iter().chain(&found.locked) {
+                item.delete()?;
+                removed = true;
+            }
+            Ok(removed)
+        })
+        .context("remove password from the secret service")
+    }
+}
+
+#[cfg(feature = "secret-service")]
+fn off_runtime<T: Send>(
+    call: impl FnOnce() -> Result<T, secret_service::Error> + Send,
+) -> Result<T, secret_service::Error> {
+    std::thread::scope(|scope| {
+        scope
+            .spawn(call)
+            .join()
+            .expect("secret service thread panicked")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const CHEAP: KdfParams = KdfParams {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    fn unique_path() -> PathBuf {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("clock before unix epoch")
+            .as_nanos();
+        std::env::temp_dir().join(format!("nss-credentials-test-{now}.json"))
+    }
+
+    #[test]
+    fn encrypted_file_roundtrips_without_plaintext_on_disk() {
+        let path = unique_path();
+        let mut store =
+            EncryptedFileStore::open_with_params(&path, "correct horse", CHEAP).expect("open");
+        assert_eq!(store.get("srv:2416", "alice").expect("get"), None);
+        store.set("srv:2416", "alice", "hunter2").expect("set");
+        store.set("other:2416", "alice", "second").expect("set");
+
+        let raw = fs::read_to_string(&path).expect("read file");
+        assert!(!raw.contains("hunter2") && !raw.contains("alice"));
+
+        let mut reopened =
+            EncryptedFileStore::open_with_params(&path, "correct horse", CHEAP).expect("reopen");
+        assert_eq!(
+            reopened.get("srv:2416", "alice").expect("get").as_deref(),
+            Some("hunter2")
+        );
+        assert!(reopened.remove("srv:2416", "alice").expect("remove"));
+        assert!(!reopened.remove("srv:2416", "alice").expect("remove again"));
+
+        let reopened = EncryptedFileStore::open(&path, "correct horse").expect("reopen");
+        assert_eq!(reopened.get("srv:2416", "alice").expect("get"), None);
+        assert_eq!(
+            reopened.get("other:2416", "alice").expect("get").as_deref(),
+            Some("second")
+        );
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn save_replaces_the_file_without_leaving_a_temp_copy() {
+        let path = unique_path();
+        let mut store = EncryptedFileStore::open_with_params(&path, "right", CHEAP).expect("open");
+        store.set("srv:2416", "alice", "hunter2").expect("set");
+        store
+            .set("srv:2416", "alice", "changed")
+            .expect("overwrite");
+
+        let mut tmp_name = path.file_name().expect("file name").to_os_string();
+        tmp_name.push(".tmp");
+        assert!(!path.with_file_name(tmp_name).exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).expect("metadata").permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let path = unique_path();
+        let mut store = EncryptedFileStore::open_with_params(&path, "right", CHEAP).expect("open");
+        store.set("srv:2416", "alice", "hunter2").expect("set");
+
+        let err = EncryptedFileStore::open(&path, "wrong").expect_err("wrong passphrase");
+        assert!(err.to_string().contains("wrong passphrase"), "{err}");
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -1,49 +1,86 @@
 use anyhow::{Context, Result, anyhow, bail};
+use progress::{ProgressReporter, report_progress};
 use protocol::{
     CODE_PM_FILE_SEARCH_RESULT, CODE_PM_QUEUE_UPLOAD, CODE_PM_TRANSFER_REQUEST,
-    CODE_PM_UPLOAD_DENIED, CODE_PM_UPLOAD_FAILED, CODE_PM_UPLOAD_PLACE_IN_LINE,
+    CODE_PM_SHARED_FILES_IN_FOLDER, CODE_PM_UPLOAD_DENIED, CODE_PM_UPLOAD_FAILED,
+    CODE_PM_UPLOAD_PLACE_IN_LINE,
     CODE_SM_GET_OWN_PRIVILEGES_STATUS, CODE_SM_GET_PEER_ADDRESS, CODE_SM_GET_RECOMMENDATION_USERS,
     CODE_SM_GET_RECOMMENDED_USERS, CODE_SM_GET_ROOM_TICKER, CODE_SM_GET_TERM_RECOMMENDATIONS,
     CODE_SM_GET_USER_PRIVILEGES_STATUS, CODE_SM_GET_USER_STATS, CODE_SM_GET_USER_STATUS,
-    CODE_SM_LOGIN, CODE_SM_MESSAGE_ACKED, CODE_SM_PRIVILEGED_LIST, CODE_SM_ROOM_LIST,
+    CODE_SM_LOGIN, CODE_SM_MESSAGE_ACKED, CODE_SM_MESSAGE_USER, CODE_SM_PRIVILEGED_LIST,
+    CODE_SM_ROOM_LIST, CODE_SM_WISHLIST_WAIT,
     ConnectToPeerResponsePayload, FileSearchRequestPayload, Frame, LoginFailureReason,
     LoginResponsePayload, MessageAckedPayload, MessageUserIncomingPayload,
-    OwnPrivilegesStatusPayload, PayloadReader, PayloadWriter, PeerAddressResponsePayload,
-    PeerMessage, PrivilegedListPayload, ProtocolMessage, RecommendationUsersPayload,
+    OwnPrivilegesStatusPayload, PayloadWriter, PeerAddressResponsePayload,
+    PeerInitMessage, PeerInitPayload, PeerMessage, PierceFirewallPayload, PrivilegedListPayload,
+    ProtocolMessage, RecommendationUsersPayload,
     QueueUploadPayload, RecommendationsPayload, RecommendedUsersPayload, RoomListPayload, RoomMembersPayload,
     RoomOperatorsPayload, RoomTickerPayload, SearchResponseSummary, ServerMessage,
-    SetWaitPortPayload, SimilarTermsPayload, TermRecommendationsPayload, TransferDirection,
-    TransferRequestPayload, TransferResponsePayload, UserPrivilegesStatusPayload,
+    SetWaitPortPayload, SharedFileEntry, SharedFilesInFolderListingFormat, SimilarTermsPayload,
+    TermRecommendationsPayload, TransferDirection,
+    TransferRequestPayload, TransferResponsePayload, UserInfoReplyPayload,
+    UserPrivilegesStatusPayload,
     UserRecommendationsPayload, UserStatsResponsePayload, UserStatusResponsePayload,
     build_add_chatroom_request, build_add_like_term_request, build_add_room_member_request,
-    build_add_room_operator_request, build_ban_user_request, build_connect_to_peer_request,
+    build_add_room_operator_request, build_add_user_request, build_ban_user_request,
+    build_connect_to_peer_request,
     build_file_search_request, build_get_global_recommendations_request,
     build_get_my_recommendations_request, build_get_own_privileges_status_request,
     build_get_peer_address_request, build_get_recommendation_users_request,
+    build_get_shared_file_list_request, build_get_shared_files_in_folder_request,
     build_get_recommendations_request, build_get_recommended_users_request,
     build_get_room_ticker_request, build_get_similar_terms_request,
     build_get_term_recommendations_request, build_get_user_privileges_status_request,
     build_get_user_recommendations_request, build_get_user_stats_request,
     build_get_user_status_request, build_give_privilege_request, build_ignore_user_request,
+    decode_peer_init_message, encode_peer_init_message,
     build_inform_user_of_privileges_ack_request, build_inform_user_of_privileges_request,
     build_join_room_request, build_leave_room_request, build_login_request,
     build_message_user_request, build_message_users_request, build_privileged_list_request,
     build_remove_like_term_request, build_remove_room_member_request,
-    build_remove_room_operator_request, build_room_list_request, build_room_members_request,
-    build_room_operators_request, build_say_chatroom, build_send_connect_token,
+    build_remove_room_operator_request, build_remove_user_request, build_room_list_request,
+    build_room_members_request,
+    build_room_operators_request, build_say_chatroom, build_search_room_request,
+    build_search_user_files_request, build_send_connect_token, build_set_status_request,
     build_transfer_request, build_unignore_user_request, build_upload_speed_request,
+    build_user_info_request, build_wishlist_search_request,
     decode_peer_message, decode_server_message, encode_peer_message, encode_server_message,
-    split_first_frame,
+    parse_shared_files_in_folder_payload_decompressed, split_first_frame,
 };
 use std::collections::HashSet;
+use std::fs as std_fs;
 use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 use tokio::fs;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::time::error::Elapsed;
 use tokio::time::{Duration, Instant};
 
+mod buddies;
+mod chat;
+mod credentials;
+mod progress;
+mod room_log;
+mod shares;
+
+pub use buddies::{Buddy, BuddyEvent, BuddyList, BuddyStats, BuddyStatus, UserPresence};
+pub use chat::{ChatDirection, ChatMessage, ChatSearchHit, ChatStore, ChatThread};
+#[cfg(feature = "secret-service")]
+pub use credentials::SecretServiceStore;
+pub use credentials::{
+    CredentialStore, EncryptedFileStore, KdfParams, credential_store_uses_passphrase,
+    credentials_file_path, open_credential_store, open_credential_store_with,
+};
+pub use progress::{TransferMeter, TransferProgress, with_transfer_progress};
+pub use room_log::{
+    DEFAULT_ROOM_LOG_KEEP, DEFAULT_ROOM_LOG_MAX_BYTES, RoomLog, RoomLogEntry, RoomLogFormat,
+    RoomLogKind,
+};
+pub use shares::{ShareCache, ShareTree, SharedDir, SharedFile};
+
 #[derive(Debug, Clone)]
 pub struct Credentials {
     pub username: String,
@@ -83,9 +120,74 @@ pub struct SearchSelectDownloadRequest {
     pub wait_port: Option<u16>,
     pub skip_connect_probe: bool,
     pub search_mode: SearchMode,
+    pub search_scope: SearchScope,
     pub strict_track: Option<String>,
 }
 
+impl SearchSelectDownloadRequest {
+    pub fn candidate_download(&self) -> CandidateDownloadRequest {
+        CandidateDownloadRequest {
+            transfer_token: self.transfer_token,
+            output_path: self.output_path.clone(),
+            peer_addr_override: self.peer_addr_override.clone(),
+            peer_lookup_timeout: self.peer_lookup_timeout,
+            connection_type: self.connection_type.clone(),
+            wait_port: self.wait_port,
+            skip_connect_probe: self.skip_connect_probe,
+        }
+    }
+}
+
+/// Transfer settings for downloading one already-chosen `SearchCandidate`.
+#[derive(Debug, Clone)]
+pub struct CandidateDownloadRequest {
+    pub transfer_token: u32,
+    pub output_path: PathBuf,
+    pub peer_addr_override: Option<String>,
+    pub peer_lookup_timeout: Duration,
+    pub connection_type: String,
+    pub wait_port: Option<u16>,
+    pub skip_connect_probe: bool,
+}
+
+/// A candidate download whose server-side steps are done, returned by
+/// [`SessionClient::prepare_candidate_download`].
+pub struct PeerDownload {
+    candidate: SearchCandidate,
+    request: CandidateDownloadRequest,
+    peer_addr: String,
+    wire_token: u32,
+    login_username: String,
+    transfer_connection_type: String,
+    wait_port: Option<u16>,
+    inbound: Option<InboundPeer>,
+    /// Failures of the steps already tried, reported if every flow fails.
+    errors: Vec<String>,
+    flows: PeerFlows,
+}
+
+/// Which peer transfer flows a [`PeerDownload`] tries, in order.
+enum PeerFlows {
+    /// A plain download from a server-summary peer.
+    Direct,
+    /// Inbound, queue-upload and peer-init flows for a distributed peer whose
+    /// address came from an override or a lookup.
+    Queued,
+    /// Every flow, with the F address and token the probes returned.
+    Distributed {
+        file_peer_addr: String,
+        file_connect_token: u32,
+    },
+}
+
+/// A P connection the peer opened to our wait port, with the listener its F
+/// connection will arrive on.
+struct InboundPeer {
+    listener: TcpListener,
+    p_stream: TcpStream,
+    wait_port: u16,
+}
+
 #[derive(Debug, Clone)]
 pub struct SearchSelectDownloadResult {
     pub selected_username: String,
@@ -103,6 +205,8 @@ pub struct SearchCandidate {
     pub username: String,
     pub file_path: String,
     pub file_size: u64,
+    /// Bitrate in kbps when the result reported one.
+    pub bitrate: Option<u32>,
     pub peer_addr: Option<String>,
     pub connect_token: Option<u32>,
     pub source: SearchResultSource,
@@ -115,6 +219,15 @@ pub enum SearchMode {
     Distributed,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum SearchScope {
+    #[default]
+    Global,
+    Room(String),
+    User(String),
+    Users(Vec<String>),
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SearchResultSource {
     ServerSummary,
@@ -146,6 +259,24 @@ pub enum RoomEvent {
     MembersSnapshot(RoomMembersPayload),
     OperatorsSnapshot(RoomOperatorsPayload),
     TickerSnapshot(RoomTickerPayload),
+    TickerSet {
+        room: String,
+        username: String,
+        ticker: String,
+    },
+    TickerRemoved {
+        room: String,
+        username: String,
+    },
+}
+
+/// Everything a long-running client reacts to between its own requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEvent {
+    Buddy(BuddyEvent),
+    Room(RoomEvent),
+    /// Server-summarized results of a search sent earlier on this session.
+    Search(SearchResponseSummary),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -154,25 +285,89 @@ pub enum PrivateEvent {
     Ack(MessageAckedPayload),
 }
 
+/// Read half of a server session. Bytes are gathered in a buffer the reader
+/// keeps, so a read cancelled by a timeout or a `select!` loses nothing and
+/// the next read resumes the same frame.
+#[derive(Debug)]
+pub struct SessionReader {
+    stream: OwnedReadHalf,
+    buffered: Vec<u8>,
+}
+
+impl SessionReader {
+    fn new(stream: OwnedReadHalf) -> Self {
+        Self {
+            stream,
+            buffered: Vec::new(),
+        }
+    }
+
+    /// Reads the next complete frame. Cancel-safe.
+    pub async fn read_frame(&mut self) -> Result<Frame> {
+        loop {
+            if let Some(frame) = self.take_buffered_frame()? {
+                return Ok(frame);
+            }
+            self.buffered.reserve(4096);
+            let read = self
+                .stream
+                .read_buf(&mut self.buffered)
+                .await
+                .context("read frame")?;
+            if read == 0 {
+                bail!("read frame: early eof");
+            }
+        }
+    }
+
+    fn take_buffered_frame(&mut self) -> Result<Option<Frame>> {
+        let Some(len_bytes) = self.buffered.first_chunk::<4>() else {
+            return Ok(None);
+        };
+        let frame_len = 4 + u32::from_le_bytes(*len_bytes) as usize;
+        if self.buffered.len() < frame_len {
+            return Ok(None);
+        }
+        let rest = self.buffered.split_off(frame_len);
+        let frame_bytes = std::mem::replace(&mut self.buffered, rest);
+        Frame::decode(&frame_bytes).map(Some)
+    }
+}
+
 #[derive(Debug)]
 pub struct SessionClient {
-    stream: Option<TcpStream>,
+    stream: Option<OwnedWriteHalf>,
+    /// `None` while the read half is lent out with [`Self::take_reader`].
+    reader: Option<SessionReader>,
     state: SessionState,
     login_response_timeout: Duration,
     logged_username: Option<String>,
+    wishlist_interval: Option<Duration>,
+    status: UserPresence,
+    private_inbox: Vec<MessageUserIncomingPayload>,
+    /// Ids of received private messages whose ack is not written yet.
+    pending_acks: Vec<u32>,
+    shares: ShareCache,
 }
 
 pub type SoulClient = SessionClient;
 
 impl SessionClient {
     const DEFAULT_LOGIN_RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+    const SHARE_CACHE_TTL: Duration = Duration::from_secs(600);
 
     pub fn new_disconnected() -> Self {
         Self {
             stream: None,
+            reader: None,
             state: SessionState::Disconnected,
             login_response_timeout: Self::DEFAULT_LOGIN_RESPONSE_TIMEOUT,
             logged_username: None,
+            wishlist_interval: None,
+            status: UserPresence::Online,
+            private_inbox: Vec::new(),
+            pending_acks: Vec::new(),
+            shares: ShareCache::new(Self::SHARE_CACHE_TTL),
         }
     }
 
@@ -181,11 +376,18 @@ impl SessionClient {
             .await
             .with_context(|| format!("connect failed: {server_addr}"))?;
 
+        let (reader, writer) = stream.into_split();
         Ok(Self {
-            stream: Some(stream),
+            stream: Some(writer),
+            reader: Some(SessionReader::new(reader)),
             state: SessionState::Connected,
             login_response_timeout: Self::DEFAULT_LOGIN_RESPONSE_TIMEOUT,
             logged_username: None,
+            wishlist_interval: None,
+            status: UserPresence::Online,
+            private_inbox: Vec::new(),
+            pending_acks: Vec::new(),
+            shares: ShareCache::new(Self::SHARE_CACHE_TTL),
         })
     }
 
@@ -197,6 +399,57 @@ impl SessionClient {
         self.login_response_timeout = timeout;
     }
 
+    /// Lends the read half out, e.g. to a task that forwards server pushes
+    /// while the session idles. Reads on the session fail until
+    /// [`Self::restore_reader`] hands it back; writes keep working.
+    pub fn take_reader(&mut self) -> Option<SessionReader> {
+        self.reader.take()
+    }
+
+    pub fn restore_reader(&mut self, reader: SessionReader) {
+        self.reader = Some(reader);
+    }
+
+    /// Last wishlist interval announced by the server, if one was seen yet.
+    pub fn wishlist_interval(&self) -> Option<Duration> {
+        self.wishlist_interval
+    }
+
+    /// Status this session presents to other users; re-sent after every login.
+    pub fn status(&self) -> UserPresence {
+        self.status
+    }
+
+    pub async fn set_status(&mut self, status: UserPresence) -> Result<()> {
+        if status == UserPresence::Offline {
+            bail!("offline is not a settable status");
+        }
+        self.ensure_logged_in()?;
+        let frame = build_set_status_request(status.code());
+        write_frame(self.stream_mut()?, &frame).await?;
+        self.status = status;
+        Ok(())
+    }
+
+    /// Opens a fresh server connection and logs in again, keeping the status
+    /// that was set on the previous connection.
+    pub async fn reconnect(
+        &mut self,
+        server_addr: &str,
+        credentials: &Credentials,
+    ) -> std::result::Result<(), AuthError> {
+        let stream = TcpStream::connect(server_addr).await.map_err(|err| {
+            AuthError::ProtocolDecode(format!("connect failed: {server_addr}: {err}"))
+        })?;
+        let (reader, writer) = stream.into_split();
+        self.stream = Some(writer);
+        self.reader = Some(SessionReader::new(reader));
+        self.state = SessionState::Connected;
+        self.logged_username = None;
+        self.wishlist_interval = None;
+        self.login(credentials).await
+    }
+
     pub async fn login(&mut self, credentials: &Credentials) -> std::result::Result<(), AuthError> {
         self.ensure_connected()
             .map_err(|err| AuthError::ProtocolDecode(err.to_string()))?;
@@ -213,7 +466,11 @@ impl SessionClient {
             .await
             .map_err(|err| AuthError::ProtocolDecode(format!("write login frame: {err}")))?;
 
-        let response_frame = tokio::time::timeout(self.login_response_timeout, read_frame(stream))
+        let reader = self
+            .reader
+            .as_mut()
+            .ok_or_else(|| AuthError::ProtocolDecode("session reader is lent out".to_string()))?;
+        let response_frame = tokio::time::timeout(self.login_response_timeout, reader.read_frame())
             .await
             .map_err(|_| AuthError::Timeout)?
             .map_err(|err| {
@@ -246,6 +503,12 @@ impl SessionClient {
             LoginResponsePayload::Success(_) => {
                 self.state = SessionState::LoggedIn;
                 self.logged_username = Some(credentials.username.clone());
+                if self.status != UserPresence::Online {
+                    let frame = build_set_status_request(self.status.code());
+                    write_frame(stream, &frame).await.map_err(|err| {
+                        AuthError::ProtocolDecode(format!("restore status: {err}"))
+                    })?;
+                }
                 Ok(())
             }
             LoginResponsePayload::Failure(failure) => {
@@ -264,6 +527,43 @@ impl SessionClient {
         write_frame(self.stream_mut()?, &frame).await
     }
 
+    pub async fn search_room(&mut self, room: &str, search_text: &str) -> Result<()> {
+        self.ensure_logged_in()?;
+        let frame = build_search_room_request(room, search_text);
+        write_frame(self.stream_mut()?, &frame).await
+    }
+
+    pub async fn search_user_files(&mut self, username: &str, search_text: &str) -> Result<()> {
+        self.ensure_logged_in()?;
+        let frame = build_search_user_files_request(username, search_text);
+        write_frame(self.stream_mut()?, &frame).await
+    }
+
+    /// Sends a search for `scope`. Only the global search frame carries
+    /// `token`; room and user searches are keyed by room or username on the
+    /// wire, so `token` only matters for the peer requests made afterwards.
+    pub async fn search_scoped(
+        &mut self,
+        token: u32,
+        scope: &SearchScope,
+        search_text: &str,
+    ) -> Result<()> {
+        match scope {
+            SearchScope::Global => self.search(token, search_text).await,
+            SearchScope::Room(room) => self.search_room(room, search_text).await,
+            SearchScope::User(username) => self.search_user_files(username, search_text).await,
+            SearchScope::Users(usernames) => {
+                if usernames.is_empty() {
+                    bail!("user search scope has no users");
+                }
+                for username in usernames {
+                    self.search_user_files(username, search_text).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
     pub async fn list_rooms(&mut self, timeout: Duration) -> Result<RoomListPayload> {
         self.ensure_logged_in()?;
         let frame = build_room_list_request();
@@ -272,7 +572,7 @@ impl SessionClient {
         let deadline = Instant::now() + timeout;
         while Instant::now() < deadline {
             let remaining = deadline.saturating_duration_since(Instant::now());
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(response)) => {
                     if response.code != CODE_SM_ROOM_LIST {
                         continue;
@@ -338,7 +638,7 @@ impl SessionClient {
         let deadline = Instant::now() + timeout;
         while Instant::now() < deadline {
             let remaining = deadline.saturating_duration_since(Instant::now());
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(response)) => {
                     if response.code != CODE_SM_GET_ROOM_TICKER {
                         continue;
@@ -387,6 +687,101 @@ impl SessionClient {
         write_frame(self.stream_mut()?, &frame).await
     }
 
+    /// Asks the server to push status and stats changes for `username`.
+    pub async fn watch_user(&mut self, username: &str) -> Result<()> {
+        self.ensure_logged_in()?;
+        let frame = build_add_user_request(username);
+        write_frame(self.stream_mut()?, &frame).await
+    }
+
+    pub async fn unwatch_user(&mut self, username: &str) -> Result<()> {
+        self.ensure_logged_in()?;
+        let frame = build_remove_user_request(username);
+        write_frame(self.stream_mut()?, &frame).await
+    }
+
+    pub async fn watch_buddies(&mut self, buddies: &BuddyList) -> Result<()> {
+        for buddy in buddies.buddies() {
+            self.watch_user(&buddy.username).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn collect_buddy_events(
+        &mut self,
+        buddies: &mut BuddyList,
+        timeout: Duration,
+        max_events: usize,
+    ) -> Result<Vec<BuddyEvent>> {
+        self.ensure_logged_in()?;
+        let mut events = Vec::new();
+        let deadline = Instant::now() + timeout;
+
+        while events.len() < max_events {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+
+            let remaining = deadline.saturating_duration_since(now);
+            match self.read_frame_within(remaining).await {
+                Ok(Ok(frame)) => {
+                    let Ok(msg) = decode_server_message(frame.code, &frame.payload) else {
+                        continue;
+                    };
+                    events.extend(buddies.apply(&msg));
+                }
+                Ok(Err(err)) => {
+                    if is_connection_eof(&err) {
+                        break;
+                    }
+                    return Err(err);
+                }
+                Err(_) => break,
+            }
+        }
+
+        Ok(events)
+    }
+
+    /// Collects buddy, room and search result events together, so no kind is
+    /// dropped by a collector that only understands the others.
+    pub async fn collect_session_events(
+        &mut self,
+        buddies: &mut BuddyList,
+        timeout: Duration,
+        max_events: usize,
+    ) -> Result<Vec<SessionEvent>> {
+        self.ensure_logged_in()?;
+        let mut events = Vec::new();
+        let deadline = Instant::now() + timeout;
+
+        while events.len() < max_events {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+
+            let remaining = deadline.saturating_duration_since(now);
+            match self.read_frame_within(remaining).await {
+                Ok(Ok(frame)) => {
+                    if let Ok(msg) = decode_server_message(frame.code, &frame.payload) {
+                        events.extend(session_events(msg, buddies));
+                    }
+                }
+                Ok(Err(err)) => {
+                    if is_connection_eof(&err) {
+                        break;
+                    }
+                    return Err(err);
+                }
+                Err(_) => break,
+            }
+        }
+
+        Ok(events)
+    }
+
     pub async fn ignore_user(&mut self, username: &str) -> Result<()> {
         self.ensure_logged_in()?;
         let frame = build_ignore_user_request(username);
@@ -422,7 +817,7 @@ impl SessionClient {
         let deadline = Instant::now() + timeout;
         while Instant::now() < deadline {
             let remaining = deadline.saturating_duration_since(Instant::now());
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(response)) => {
                     if response.code != CODE_SM_GET_OWN_PRIVILEGES_STATUS {
                         continue;
@@ -460,7 +855,7 @@ impl SessionClient {
         let deadline = Instant::now() + timeout;
         while Instant::now() < deadline {
             let remaining = deadline.saturating_duration_since(Instant::now());
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(response)) => {
                     if response.code != CODE_SM_GET_USER_PRIVILEGES_STATUS {
                         continue;
@@ -522,12 +917,73 @@ impl SessionClient {
         write_frame(self.stream_mut()?, &frame).await
     }
 
+    pub async fn ack_private_message(&mut self, message_id: u32) -> Result<()> {
+        self.ensure_logged_in()?;
+        let frame = encode_server_message(&ServerMessage::MessageAcked(MessageAckedPayload {
+            message_id,
+        }));
+        write_frame(self.stream_mut()?, &frame).await
+    }
+
+    /// Private messages seen by any read on this session, already acked,
+    /// in arrival order. Offline messages sent right after login land here
+    /// even when another collector consumed their frames.
+    pub fn take_private_messages(&mut self) -> Vec<MessageUserIncomingPayload> {
+        std::mem::take(&mut self.private_inbox)
+    }
+
+    /// Reads until the timeout or `max_messages` new messages, storing every
+    /// private message (including ones buffered by earlier reads) in `store`.
+    /// Returns the messages that were not already stored.
+    pub async fn collect_private_messages(
+        &mut self,
+        store: &mut ChatStore,
+        timeout: Duration,
+        max_messages: usize,
+    ) -> Result<Vec<MessageUserIncomingPayload>> {
+        self.ensure_logged_in()?;
+        let mut stored = Vec::new();
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            for payload in self.take_private_messages() {
+                if store.record_incoming(&payload) {
+                    stored.push(payload);
+                }
+            }
+            let now = Instant::now();
+            if stored.len() >= max_messages || now >= deadline {
+                break;
+            }
+
+            let remaining = deadline.saturating_duration_since(now);
+            match self.read_frame_within(remaining).await {
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => {
+                    if is_connection_eof(&err) {
+                        break;
+                    }
+                    return Err(err);
+                }
+                Err(_) => break,
+            }
+        }
+
+        self.flush_message_acks().await?;
+        for payload in self.take_private_messages() {
+            if store.record_incoming(&payload) {
+                stored.push(payload);
+            }
+        }
+        Ok(stored)
+    }
+
     pub async fn wait_message_ack(&mut self, timeout: Duration) -> Result<MessageAckedPayload> {
         self.ensure_logged_in()?;
         let deadline = Instant::now() + timeout;
         while Instant::now() < deadline {
             let remaining = deadline.saturating_duration_since(Instant::now());
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(response)) => {
                     if response.code != CODE_SM_MESSAGE_ACKED {
                         continue;
@@ -564,7 +1020,7 @@ impl SessionClient {
         let deadline = Instant::now() + timeout;
         while Instant::now() < deadline {
             let remaining = deadline.saturating_duration_since(Instant::now());
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(response)) => {
                     if response.code != CODE_SM_GET_USER_STATUS {
                         continue;
@@ -601,7 +1057,7 @@ impl SessionClient {
         let deadline = Instant::now() + timeout;
         while Instant::now() < deadline {
             let remaining = deadline.saturating_duration_since(Instant::now());
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(response)) => {
                     if response.code != CODE_SM_GET_USER_STATS {
                         continue;
@@ -638,7 +1094,7 @@ impl SessionClient {
         let deadline = Instant::now() + timeout;
         while Instant::now() < deadline {
             let remaining = deadline.saturating_duration_since(Instant::now());
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(response)) => {
                     if response.code != CODE_SM_GET_PEER_ADDRESS {
                         continue;
@@ -663,6 +1119,89 @@ impl SessionClient {
         bail!("timed out waiting for peer address response")
     }
 
+    /// Looks up the peer address of `username` and asks the peer for its profile.
+    pub async fn get_user_info(
+        &mut self,
+        username: &str,
+        token: u32,
+        timeout: Duration,
+    ) -> Result<UserInfoReplyPayload> {
+        let login_username = self
+            .logged_username
+            .clone()
+            .context("user info requires a logged in session")?;
+        let address = self.get_peer_address(username, timeout).await?;
+        if address.ip_address == "0.0.0.0" || address.port == 0 {
+            bail!("user {username} is offline or has no reachable address");
+        }
+        let peer_addr = format!("{}:{}", address.ip_address, address.port);
+        request_user_info(&peer_addr, &login_username, token, timeout).await
+    }
+
+    /// Fetches the full shared file list of `username` as a directory tree.
+    /// Trees are cached for a while; `refresh` asks the peer again.
+    pub async fn browse_user(
+        &mut self,
+        username: &str,
+        token: u32,
+        timeout: Duration,
+        refresh: bool,
+    ) -> Result<ShareTree> {
+        if refresh {
+            self.shares.forget(username);
+        } else if let Some(tree) = self.shares.get(username, Instant::now()) {
+            return Ok(tree.clone());
+        }
+        let login_username = self
+            .logged_username
+            .clone()
+            .context("browsing requires a logged in session")?;
+        let address = self.get_peer_address(username, timeout).await?;
+        if address.ip_address == "0.0.0.0" || address.port == 0 {
+            bail!("user {username} is offline or has no reachable address");
+        }
+        let peer_addr = format!("{}:{}", address.ip_address, address.port);
+        let entries = request_shared_file_list(&peer_addr, &login_username, token, timeout).await?;
+        let tree = ShareTree::from_entries(username, &entries);
+        self.shares.insert(tree.clone(), Instant::now());
+        Ok(tree)
+    }
+
+    /// Looks up the peer address of `username` and asks the peer for the files
+    /// in `directory`. The returned candidates carry the peer address, so they
+    /// download without another lookup.
+    pub async fn get_folder_contents(
+        &mut self,
+        username: &str,
+        directory: &str,
+        token: u32,
+        timeout: Duration,
+    ) -> Result<Vec<SearchCandidate>> {
+        let login_username = self
+            .logged_username
+            .clone()
+            .context("folder listing requires a logged in session")?;
+        let address = self.get_peer_address(username, timeout).await?;
+        if address.ip_address == "0.0.0.0" || address.port == 0 {
+            bail!("user {username} is offline or has no reachable address");
+        }
+        let peer_addr = format!("{}:{}", address.ip_address, address.port);
+        let entries =
+            request_folder_contents(&peer_addr, &login_username, token, directory, timeout).await?;
+        Ok(entries
+            .into_iter()
+            .map(|entry| SearchCandidate {
+                username: username.to_owned(),
+                file_path: folder_entry_path(directory, &entry.virtual_path),
+                file_size: entry.size,
+                peer_addr: Some(peer_addr.clone()),
+                connect_token: None,
+                bitrate: None,
+                source: SearchResultSource::ServerSummary,
+            })
+            .collect())
+    }
+
     pub async fn connect_to_peer(
         &mut self,
         username: &str,
@@ -691,7 +1230,7 @@ impl SessionClient {
         let deadline = Instant::now() + timeout;
         while Instant::now() < deadline {
             let remaining = deadline.saturating_duration_since(Instant::now());
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(response)) => {
                     if response.code != protocol::CODE_SM_CONNECT_TO_PEER {
                         continue;
@@ -748,15 +1287,17 @@ impl SessionClient {
         bail!("timed out waiting for connect-to-peer response");
     }
 
-    async fn download_single_file_via_inbound_wait_port(
+    /// Asks `peer_username` to connect to our wait port and accepts its P
+    /// connection. This is the only part of the inbound wait-port flow that
+    /// needs the server; [`download_via_inbound_peer`] finishes it.
+    async fn accept_inbound_peer(
         &mut self,
-        plan: &DownloadPlan,
         login_username: &str,
         peer_username: &str,
         connect_token: u32,
         wait_port: u16,
         connection_type: &str,
-    ) -> Result<DownloadResult> {
+    ) -> Result<InboundPeer> {
         let bind_addr = format!("0.0.0.0:{wait_port}");
         transfer_debug(format!(
             "inbound wait-port flow start wait_port={} peer_user={} connect_token={}",
@@ -796,51 +1337,54 @@ impl SessionClient {
             "inbound wait-port flow",
         )
         .await?;
+        Ok(InboundPeer {
+            listener,
+            p_stream,
+            wait_port,
+        })
+    }
 
-        let transfer_request = send_queue_upload_and_wait_transfer_request(
-            &mut p_stream,
-            login_username,
-            &plan.virtual_path,
-            resolve_transfer_flow_timeout(),
+    /// Runs the inbound wait-port handshake under its flow timeout and keeps
+    /// the failure in `errors` when the peer never connected back.
+    #[allow(clippy::too_many_arguments)]
+    async fn try_accept_inbound_peer(
+        &mut self,
+        login_username: &str,
+        peer_username: &str,
+        connect_token: u32,
+        wait_port: u16,
+        connection_type: &str,
+        errors: &mut Vec<String>,
+    ) -> Option<InboundPeer> {
+        match tokio::time::timeout(
+            resolve_inbound_wait_port_flow_timeout(),
+            self.accept_inbound_peer(
+                login_username,
+                peer_username,
+                connect_token,
+                wait_port,
+                connection_type,
+            ),
         )
-        .await?;
-        transfer_debug(format!(
-            "inbound wait-port received transfer request token={} direction={:?} size={}",
-            transfer_request.token, transfer_request.direction, transfer_request.file_size
-        ));
-        write_transfer_allow(&mut p_stream, transfer_request.token).await?;
-
-        let (mut f_stream, maybe_f_init, addr) =
-            accept_peer_file_socket(&listener, Duration::from_secs(45))
-                .await
-                .context("accept inbound F connection")?;
-        if let Some(f_init) = maybe_f_init {
-            transfer_debug(format!(
-                "accepted inbound F connection with init token={} user={} from={}",
-                f_init.token, f_init.username, addr
-            ));
-        } else {
-            transfer_debug(format!(
-                "accepted inbound F connection without init from={addr}"
-            ));
+        .await
+        {
+            Ok(Ok(inbound)) => Some(inbound),
+            Ok(Err(err)) => {
+                let rendered = format_error_chain(&err);
+                transfer_debug(format!("inbound wait-port flow failed: {rendered}"));
+                errors.push(format!(
+                    "inbound wait-port flow failed (port {wait_port}): {rendered}"
+                ));
+                None
+            }
+            Err(_) => {
+                transfer_debug("inbound wait-port flow timed out");
+                errors.push(format!(
+                    "inbound wait-port flow timed out (port {wait_port})"
+                ));
+                None
+            }
         }
-        ensure_parent_dir(&plan.output_path).await?;
-        let expected_size = if transfer_request.file_size == 0 {
-            plan.file_size
-        } else {
-            transfer_request.file_size
-        };
-        let content = read_file_transfer_content(&mut f_stream, expected_size, transfer_request.token)
-            .await?;
-        validate_transfer_content(&content, expected_size)?;
-        fs::write(&plan.output_path, &content)
-            .await
-            .with_context(|| format!("write output file: {}", plan.output_path.display()))?;
-
-        Ok(DownloadResult {
-            output_path: plan.output_path.clone(),
-            bytes_written: content.len() as u64,
-        })
     }
 
     pub async fn collect_private_events(
@@ -859,7 +1403,7 @@ impl SessionClient {
             }
 
             let remaining = deadline.saturating_duration_since(now);
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(frame)) => {
                     let Ok(msg) = decode_server_message(frame.code, &frame.payload) else {
                         continue;
@@ -908,7 +1452,7 @@ impl SessionClient {
         let deadline = Instant::now() + timeout;
         while Instant::now() < deadline {
             let remaining = deadline.saturating_duration_since(Instant::now());
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(response)) => {
                     let Ok(message) = decode_server_message(response.code, &response.payload)
                     else {
@@ -956,7 +1500,7 @@ impl SessionClient {
         let deadline = Instant::now() + timeout;
         while Instant::now() < deadline {
             let remaining = deadline.saturating_duration_since(Instant::now());
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(response)) => {
                     let Ok(message) = decode_server_message(response.code, &response.payload)
                     else {
@@ -991,7 +1535,7 @@ impl SessionClient {
         let deadline = Instant::now() + timeout;
         while Instant::now() < deadline {
             let remaining = deadline.saturating_duration_since(Instant::now());
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(response)) => {
                     let Ok(message) = decode_server_message(response.code, &response.payload)
                     else {
@@ -1037,7 +1581,7 @@ impl SessionClient {
         let deadline = Instant::now() + timeout;
         while Instant::now() < deadline {
             let remaining = deadline.saturating_duration_since(Instant::now());
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(response)) => {
                     if response.code != CODE_SM_PRIVILEGED_LIST {
                         continue;
@@ -1074,7 +1618,7 @@ impl SessionClient {
         let deadline = Instant::now() + timeout;
         while Instant::now() < deadline {
             let remaining = deadline.saturating_duration_since(Instant::now());
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(response)) => {
                     if response.code != CODE_SM_GET_RECOMMENDED_USERS {
                         continue;
@@ -1112,7 +1656,7 @@ impl SessionClient {
         let deadline = Instant::now() + timeout;
         while Instant::now() < deadline {
             let remaining = deadline.saturating_duration_since(Instant::now());
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(response)) => {
                     if response.code != CODE_SM_GET_TERM_RECOMMENDATIONS {
                         continue;
@@ -1162,7 +1706,7 @@ impl SessionClient {
         let deadline = Instant::now() + timeout;
         while Instant::now() < deadline {
             let remaining = deadline.saturating_duration_since(Instant::now());
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(response)) => {
                     if response.code != CODE_SM_GET_RECOMMENDATION_USERS {
                         continue;
@@ -1211,9 +1755,65 @@ impl SessionClient {
         write_frame(self.stream_mut()?, &frame).await
     }
 
+    /// Reads one frame and never writes, so it is safe to wrap in a timeout.
+    /// Private messages it sees are buffered and their acks queued for
+    /// [`Self::flush_message_acks`].
     pub async fn read_next_frame(&mut self) -> Result<Frame> {
         self.ensure_connected()?;
-        read_frame(self.stream_mut()?).await
+        let frame = self
+            .reader
+            .as_mut()
+            .ok_or_else(|| anyhow!("session reader is lent out"))?
+            .read_frame()
+            .await?;
+        if frame.code == CODE_SM_WISHLIST_WAIT
+            && let Ok(ServerMessage::WishlistWait(payload)) =
+                decode_server_message(frame.code, &frame.payload)
+        {
+            self.wishlist_interval = Some(Duration::from_secs(u64::from(payload.interval_seconds)));
+        }
+        if frame.code == CODE_SM_MESSAGE_USER
+            && let Ok(ServerMessage::MessageUserIncoming(payload)) =
+                decode_server_message(frame.code, &frame.payload)
+        {
+            // The server keeps re-sending unacked messages at every login, so
+            // ack as soon as one is read.
+            self.pending_acks.push(payload.message_id);
+            self.private_inbox.push(payload);
+        }
+        Ok(frame)
+    }
+
+    /// Reads the next frame within `wait`, then writes the acks the read
+    /// queued. The acks go out after the timeout resolved, so a write is
+    /// never cancelled halfway through a frame.
+    async fn read_frame_within(&mut self, wait: Duration) -> Result<Result<Frame>, Elapsed> {
+        let read = tokio::time::timeout(wait, self.read_next_frame()).await;
+        if let Err(err) = self.flush_message_acks().await {
+            return Ok(Err(err));
+        }
+        read
+    }
+
+    /// Queues the ack for a private message read through a lent-out reader.
+    pub fn queue_message_ack(&mut self, message_id: u32) {
+        self.pending_acks.push(message_id);
+    }
+
+    /// Writes the acks queued by earlier reads. An id leaves the queue only
+    /// after its ack is written; call this outside any timeout, since a write
+    /// cancelled partway leaves a partial frame on the wire.
+    pub async fn flush_message_acks(&mut self) -> Result<()> {
+        while let Some(&message_id) = self.pending_acks.first() {
+            let ack = encode_server_message(&ServerMessage::MessageAcked(MessageAckedPayload {
+                message_id,
+            }));
+            write_frame(self.stream_mut()?, &ack)
+                .await
+                .with_context(|| format!("ack private message {message_id}"))?;
+            self.pending_acks.remove(0);
+        }
+        Ok(())
     }
 
     pub async fn read_next_message(&mut self) -> Result<ProtocolMessage> {
@@ -1229,8 +1829,68 @@ impl SessionClient {
         timeout: Duration,
         max_messages: usize,
     ) -> Result<Vec<ServerMessage>> {
-        self.search(token, query).await?;
+        self.search_and_collect_scoped(token, &SearchScope::Global, query, timeout, max_messages)
+            .await
+    }
+
+    pub async fn search_and_collect_scoped(
+        &mut self,
+        token: u32,
+        scope: &SearchScope,
+        query: &str,
+        timeout: Duration,
+        max_messages: usize,
+    ) -> Result<Vec<ServerMessage>> {
+        self.search_scoped(token, scope, query).await?;
+        self.collect_server_messages(timeout, max_messages).await
+    }
+
+    pub async fn wishlist_search(&mut self, token: u32, search_text: &str) -> Result<()> {
+        self.ensure_logged_in()?;
+        let frame = build_wishlist_search_request(token, search_text);
+        write_frame(self.stream_mut()?, &frame).await
+    }
+
+    /// Runs one low-priority wishlist search and returns only the results that
+    /// pass the item's filters and were not reported by an earlier run.
+    pub async fn run_wishlist_search(
+        &mut self,
+        scheduler: &mut WishlistScheduler,
+        item: &WishlistItem,
+        token: u32,
+        timeout: Duration,
+        max_messages: usize,
+    ) -> Result<Vec<SearchCandidate>> {
+        let candidates = self
+            .collect_wishlist_candidates(token, &item.query, timeout, max_messages)
+            .await?;
+        if let Some(interval) = self.wishlist_interval {
+            scheduler.set_interval(interval);
+        }
+        Ok(scheduler.accept_results(item, &candidates))
+    }
+
+    /// Sends one wishlist search and returns every summarized result, before
+    /// any wishlist filtering.
+    pub async fn collect_wishlist_candidates(
+        &mut self,
+        token: u32,
+        query: &str,
+        timeout: Duration,
+        max_messages: usize,
+    ) -> Result<Vec<SearchCandidate>> {
+        self.wishlist_search(token, query).await?;
+        let collected = self.collect_server_messages(timeout, max_messages).await?;
+        Ok(flatten_summary_candidates(&collect_search_summaries(
+            &collected,
+        )))
+    }
 
+    async fn collect_server_messages(
+        &mut self,
+        timeout: Duration,
+        max_messages: usize,
+    ) -> Result<Vec<ServerMessage>> {
         let mut collected = Vec::new();
         let deadline = Instant::now() + timeout;
 
@@ -1241,7 +1901,7 @@ impl SessionClient {
             }
 
             let remaining = deadline.saturating_duration_since(now);
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(frame)) => {
                     if let Ok(msg) = decode_server_message(frame.code, &frame.payload) {
                         collected.push(msg);
@@ -1267,8 +1927,9 @@ impl SessionClient {
         self.ensure_logged_in()
             .map_err(|err| SearchSelectDownloadError::Session(err.to_string()))?;
         let candidates = self
-            .search_collect_candidates(
+            .search_collect_scoped_candidates(
                 request.search_token,
+                &request.search_scope,
                 &request.query,
                 request.search_timeout,
                 request.max_messages,
@@ -1306,12 +1967,13 @@ impl SessionClient {
             };
         }
 
-        let selected = candidates.get(request.result_index).ok_or_else(|| {
+        let selected = candidates.get(request.result_index).ok_or(
             SearchSelectDownloadError::InvalidSearchResultIndex {
                 index: request.result_index,
                 available: candidates.len(),
-            }
-        })?;
+            },
+        )?;
+        let download = request.candidate_download();
 
         if selected.source == SearchResultSource::DistributedPeer
             && request.peer_addr_override.is_none()
@@ -1322,25 +1984,8 @@ impl SessionClient {
                 candidates.len(),
                 request.result_index
             ));
-            let transfer_connection_type = if request.connection_type.eq_ignore_ascii_case("P") {
-                "F".to_string()
-            } else {
-                request.connection_type.clone()
-            };
-            let wait_port = resolve_wait_port(request.wait_port);
-            let login_username = self
-                .logged_username
-                .clone()
-                .unwrap_or_else(|| "neosoulseek".to_string());
-            if let Some(configured_wait_port) = wait_port
-                && let Err(err) = self.set_wait_port(configured_wait_port).await
-            {
-                transfer_debug(format!(
-                    "set-wait-port preflight failed on {}: {}",
-                    configured_wait_port,
-                    format_error_chain(&err)
-                ));
-            }
+            let (wait_port, login_username, transfer_connection_type) =
+                self.prepare_distributed_download(&download).await;
             let mut last_error = None::<String>;
             for (attempt_index, candidate) in candidates
                 .iter()
@@ -1355,286 +2000,22 @@ impl SessionClient {
                 let Some(candidate_peer_addr) = candidate.peer_addr.clone() else {
                     continue;
                 };
-                transfer_debug(format!(
-                    "candidate user={} peer={} path={} token={}",
-                    candidate.username,
-                    candidate_peer_addr,
-                    candidate.file_path,
-                    candidate.connect_token.unwrap_or(request.transfer_token)
-                ));
-                let mut peer_addr = candidate_peer_addr;
-                let mut wire_token = candidate.connect_token.unwrap_or(request.transfer_token);
-                let mut attempt_errors = Vec::new();
-
-                if !request.skip_connect_probe
-                    && let Some(inbound_wait_port) = wait_port
-                {
-                    let inbound_plan = DownloadPlan {
-                        peer_addr: peer_addr.clone(),
-                        token: request.transfer_token,
-                        virtual_path: candidate.file_path.clone(),
-                        file_size: candidate.file_size,
-                        output_path: request.output_path.clone(),
-                    };
-                match tokio::time::timeout(
-                    resolve_inbound_wait_port_flow_timeout(),
-                    self.download_single_file_via_inbound_wait_port(
-                        &inbound_plan,
-                        &login_username,
-                            &candidate.username,
-                            wire_token,
-                            inbound_wait_port,
-                            &request.connection_type,
-                        ),
+                match self
+                    .prepare_distributed_candidate(
+                        candidate,
+                        candidate_peer_addr,
+                        &download,
+                        attempt_index,
+                        wait_port,
+                        login_username.clone(),
+                        transfer_connection_type.clone(),
                     )
                     .await
-                    {
-                        Ok(Ok(download_result)) => {
-                            transfer_debug("inbound wait-port flow succeeded");
-                            return Ok(SearchSelectDownloadResult {
-                                selected_username: candidate.username.clone(),
-                                selected_virtual_path: candidate.file_path.clone(),
-                                selected_file_size: candidate.file_size,
-                                peer_addr,
-                                transfer_token: request.transfer_token,
-                                output_path: download_result.output_path,
-                                bytes_written: download_result.bytes_written,
-                                search_source: candidate.source,
-                            });
-                        }
-                        Ok(Err(err)) => {
-                            let rendered = format_error_chain(&err);
-                            transfer_debug(format!("inbound wait-port flow failed: {rendered}"));
-                            attempt_errors.push(format!(
-                                "inbound wait-port flow failed (port {inbound_wait_port}): {rendered}"
-                            ));
-                        }
-                        Err(_) => {
-                            transfer_debug("inbound wait-port flow timed out");
-                            attempt_errors.push(format!(
-                                "inbound wait-port flow timed out (port {inbound_wait_port})"
-                            ));
-                        }
-                    }
-                }
-
-                if !request.skip_connect_probe {
-                    let probe_token_p = request
-                        .transfer_token
-                        .wrapping_add((attempt_index as u32).wrapping_mul(17))
-                        .wrapping_add(7);
-                    if let Err(err) = self
-                        .connect_to_peer(&candidate.username, probe_token_p, &request.connection_type)
-                        .await
-                    {
-                        let rendered = format_error_chain(&err);
-                        transfer_debug(format!(
-                            "connect-to-peer probe failed, keeping candidate address: {rendered}"
-                        ));
-                        attempt_errors.push(format!(
-                            "connect-to-peer probe failed: {rendered}; using candidate address"
-                        ));
-                    } else {
-                        match self
-                            .wait_connect_to_peer_response(
-                                &candidate.username,
-                                Some(probe_token_p),
-                                resolve_connect_to_peer_wait_timeout(),
-                            )
-                            .await
-                        {
-                            Ok(response) => {
-                                peer_addr = format!("{}:{}", response.ip_address, response.port);
-                                wire_token = response.token;
-                                transfer_debug(format!(
-                                    "connect-to-peer response accepted: peer={} token={}",
-                                    peer_addr, wire_token
-                                ));
-                            }
-                            Err(err) => {
-                                let rendered = format_error_chain(&err);
-                                transfer_debug(format!(
-                                    "connect-to-peer response wait failed, keeping candidate address: {rendered}"
-                                ));
-                                attempt_errors.push(format!(
-                                    "connect-to-peer response wait failed: {rendered}; using candidate address"
-                                ));
-                            }
-                        }
-                    }
-                    tokio::time::sleep(Duration::from_millis(120)).await;
-                }
-                let mut file_peer_addr = peer_addr.clone();
-                let mut file_connect_token = wire_token;
-                if !request.skip_connect_probe {
-                    let probe_token_f = request
-                        .transfer_token
-                        .wrapping_add((attempt_index as u32).wrapping_mul(17))
-                        .wrapping_add(13);
-                    if let Err(err) = self
-                        .connect_to_peer(&candidate.username, probe_token_f, "F")
-                        .await
-                    {
-                        transfer_debug(format!(
-                            "connect-to-peer F probe failed, keeping control peer address: {}",
-                            format_error_chain(&err)
-                        ));
-                    } else {
-                        match self
-                            .wait_connect_to_peer_response(
-                                &candidate.username,
-                                Some(probe_token_f),
-                                resolve_connect_to_peer_wait_timeout(),
-                            )
-                            .await
-                        {
-                            Ok(response) => {
-                                file_peer_addr =
-                                    format!("{}:{}", response.ip_address, response.port);
-                                file_connect_token = response.token;
-                                transfer_debug(format!(
-                                    "connect-to-peer F response accepted: peer={} token={}",
-                                    file_peer_addr, file_connect_token
-                                ));
-                            }
-                            Err(err) => {
-                                transfer_debug(format!(
-                                    "connect-to-peer F response wait failed, using control peer address: {}",
-                                    format_error_chain(&err)
-                                ));
-                            }
-                        }
-                    }
-                }
-                let plan = DownloadPlan {
-                    peer_addr: peer_addr.clone(),
-                    token: request.transfer_token,
-                    virtual_path: candidate.file_path.clone(),
-                    file_size: candidate.file_size,
-                    output_path: request.output_path.clone(),
-                };
-                if !resolve_skip_transfer_request_flow() {
-                    let transfer_flow_timeout = resolve_transfer_flow_timeout();
-                    let transfer_request_flow = tokio::time::timeout(
-                        transfer_flow_timeout,
-                        download_single_file_via_transfer_request(
-                            &plan,
-                            &login_username,
-                            wire_token,
-                            wait_port,
-                            Some(&file_peer_addr),
-                            Some(file_connect_token),
-                        ),
-                    )
-                    .await;
-                    match transfer_request_flow {
-                        Ok(Ok(download_result)) => {
-                            transfer_debug("transfer-request flow succeeded");
-                            return Ok(SearchSelectDownloadResult {
-                                selected_username: candidate.username.clone(),
-                                selected_virtual_path: candidate.file_path.clone(),
-                                selected_file_size: candidate.file_size,
-                                peer_addr,
-                                transfer_token: request.transfer_token,
-                                output_path: download_result.output_path,
-                                bytes_written: download_result.bytes_written,
-                                search_source: candidate.source,
-                            });
-                        }
-                        Ok(Err(err)) => {
-                            let rendered = err.to_string();
-                            transfer_debug(format!("transfer-request flow failed: {rendered}"));
-                            attempt_errors.push(format!("transfer-request flow failed: {rendered}"));
-                        }
-                        Err(_) => {
-                            transfer_debug("transfer-request flow timed out");
-                            attempt_errors.push("transfer-request flow timed out".to_string());
-                        }
-                    }
-                } else {
-                    transfer_debug("transfer-request flow skipped by NSS_SKIP_TRANSFER_REQUEST_FLOW");
-                }
-                let queue_flow_timeout = resolve_transfer_flow_timeout();
-                let modern_queue = tokio::time::timeout(
-                    queue_flow_timeout,
-                    download_single_file_via_queue_upload(
-                        &plan,
-                        &login_username,
-                        &candidate.username,
-                        wire_token,
-                        wait_port,
-                        Some(&file_peer_addr),
-                        Some(file_connect_token),
-                    ),
-                )
-                .await;
-                match modern_queue {
-                    Ok(Ok(download_result)) => {
-                        transfer_debug("queue-upload flow succeeded");
-                        return Ok(SearchSelectDownloadResult {
-                            selected_username: candidate.username.clone(),
-                            selected_virtual_path: candidate.file_path.clone(),
-                            selected_file_size: candidate.file_size,
-                            peer_addr,
-                            transfer_token: request.transfer_token,
-                            output_path: download_result.output_path,
-                            bytes_written: download_result.bytes_written,
-                            search_source: candidate.source,
-                        });
-                    }
-                    Ok(Err(err)) => {
-                        let rendered = format_error_chain(&err);
-                        transfer_debug(format!("queue-upload flow failed: {rendered}"));
-                        attempt_errors.push(format!("queue-upload flow failed: {rendered}"));
-                    }
-                    Err(_) => {
-                        transfer_debug("queue-upload flow timed out");
-                        attempt_errors.push("queue-upload flow timed out".to_string());
-                    }
-                }
-
-                match tokio::time::timeout(
-                    resolve_direct_transfer_flow_timeout(),
-                    download_single_file_with_peer_init(
-                        &DownloadPlan {
-                            peer_addr: file_peer_addr.clone(),
-                            token: plan.token,
-                            virtual_path: plan.virtual_path.clone(),
-                            file_size: plan.file_size,
-                            output_path: plan.output_path.clone(),
-                        },
-                        &login_username,
-                        &transfer_connection_type,
-                        file_connect_token,
-                    ),
-                )
-                .await
+                    .run_distributed_flows()
+                    .await
                 {
-                    Ok(Ok(download_result)) => {
-                        transfer_debug("direct transfer flow succeeded");
-                        return Ok(SearchSelectDownloadResult {
-                            selected_username: candidate.username.clone(),
-                            selected_virtual_path: candidate.file_path.clone(),
-                            selected_file_size: candidate.file_size,
-                            peer_addr,
-                            transfer_token: request.transfer_token,
-                            output_path: download_result.output_path,
-                            bytes_written: download_result.bytes_written,
-                            search_source: candidate.source,
-                        });
-                    }
-                    Ok(Err(err)) => {
-                        let rendered = format_error_chain(&err);
-                        transfer_debug(format!("direct flow failed: {rendered}"));
-                        attempt_errors.push(format!("direct flow failed: {rendered}"));
-                    }
-                    Err(_) => {
-                        transfer_debug("direct flow timed out");
-                        attempt_errors.push("direct flow timed out".to_string());
-                    }
-                }
-                if !attempt_errors.is_empty() {
-                    last_error = Some(attempt_errors.join(" | "));
+                    Ok(result) => return Ok(result),
+                    Err(errors) => last_error = Some(errors),
                 }
             }
 
@@ -1644,256 +2025,348 @@ impl SessionClient {
             )));
         }
 
+        self.download_candidate(selected, &download).await
+    }
+
+    /// Downloads exactly `candidate`, without searching again. Distributed
+    /// candidates are tried once; there is no fallback to other results.
+    pub async fn download_candidate(
+        &mut self,
+        candidate: &SearchCandidate,
+        request: &CandidateDownloadRequest,
+    ) -> std::result::Result<SearchSelectDownloadResult, SearchSelectDownloadError> {
+        self.prepare_candidate_download(candidate, request)
+            .await?
+            .run()
+            .await
+    }
+
+    /// Does the server-side part of downloading `candidate`: peer lookup,
+    /// ConnectToPeer probes and the wait-port handshake. The returned
+    /// [`PeerDownload`] only talks to the peer, so the session can serve other
+    /// requests while the file transfers.
+    pub async fn prepare_candidate_download(
+        &mut self,
+        candidate: &SearchCandidate,
+        request: &CandidateDownloadRequest,
+    ) -> std::result::Result<PeerDownload, SearchSelectDownloadError> {
+        self.ensure_logged_in()
+            .map_err(|err| SearchSelectDownloadError::Session(err.to_string()))?;
+        if let Some(parent) = request.output_path.parent() {
+            fs::create_dir_all(parent).await.map_err(|err| {
+                SearchSelectDownloadError::Download(format!(
+                    "create output directory {}: {err}",
+                    parent.display()
+                ))
+            })?;
+        }
+
+        if candidate.source == SearchResultSource::DistributedPeer
+            && request.peer_addr_override.is_none()
+            && let Some(candidate_peer_addr) = candidate.peer_addr.clone()
+        {
+            let (wait_port, login_username, transfer_connection_type) =
+                self.prepare_distributed_download(request).await;
+            return Ok(self
+                .prepare_distributed_candidate(
+                    candidate,
+                    candidate_peer_addr,
+                    request,
+                    0,
+                    wait_port,
+                    login_username,
+                    transfer_connection_type,
+                )
+                .await);
+        }
+
         let peer_addr = if let Some(override_addr) = request.peer_addr_override.clone() {
             override_addr
-        } else if let Some(candidate_addr) = selected.peer_addr.clone() {
+        } else if let Some(candidate_addr) = candidate.peer_addr.clone() {
             candidate_addr
         } else {
             let payload = self
-                .get_peer_address(&selected.username, request.peer_lookup_timeout)
+                .get_peer_address(&candidate.username, request.peer_lookup_timeout)
                 .await
                 .map_err(|err| SearchSelectDownloadError::PeerLookup(err.to_string()))?;
             format!("{}:{}", payload.ip_address, payload.port)
         };
-        let wire_token = selected.connect_token.unwrap_or(request.transfer_token);
+        let wire_token = candidate.connect_token.unwrap_or(request.transfer_token);
         let transfer_connection_type = if request.connection_type.eq_ignore_ascii_case("P") {
             "F".to_string()
         } else {
             request.connection_type.clone()
         };
 
-        if selected.source == SearchResultSource::ServerSummary && !request.skip_connect_probe {
+        if candidate.source == SearchResultSource::ServerSummary && !request.skip_connect_probe {
             self.connect_to_peer(
-                &selected.username,
+                &candidate.username,
                 request.transfer_token,
                 &request.connection_type,
             )
             .await
             .map_err(|err| SearchSelectDownloadError::ConnectToPeer(err.to_string()))?;
-        } else if selected.source == SearchResultSource::DistributedPeer
+        } else if candidate.source == SearchResultSource::DistributedPeer
             && !request.skip_connect_probe
         {
-            self.connect_to_peer(&selected.username, wire_token, &request.connection_type)
+            self.connect_to_peer(&candidate.username, wire_token, &request.connection_type)
                 .await
                 .map_err(|err| SearchSelectDownloadError::ConnectToPeer(err.to_string()))?;
             tokio::time::sleep(Duration::from_millis(120)).await;
         }
 
-        let plan = DownloadPlan {
-            peer_addr: peer_addr.clone(),
-            token: request.transfer_token,
-            virtual_path: selected.file_path.clone(),
-            file_size: selected.file_size,
-            output_path: request.output_path.clone(),
-        };
-        let download_result = if selected.source == SearchResultSource::DistributedPeer {
-            let login_username = self
-                .logged_username
-                .clone()
-                .unwrap_or_else(|| "neosoulseek".to_string());
-            let mut inbound_error = None::<String>;
-            if !request.skip_connect_probe
-                && let Some(wait_port) = resolve_wait_port(request.wait_port)
-            {
-                match tokio::time::timeout(
-                    resolve_inbound_wait_port_flow_timeout(),
-                    self.download_single_file_via_inbound_wait_port(
-                        &plan,
+        let login_username = self
+            .logged_username
+            .clone()
+            .unwrap_or_else(|| "neosoulseek".to_string());
+        let wait_port = resolve_wait_port(request.wait_port);
+        let mut errors = Vec::new();
+        let (flows, inbound) = if candidate.source == SearchResultSource::DistributedPeer {
+            let inbound = match wait_port {
+                Some(port) if !request.skip_connect_probe => {
+                    self.try_accept_inbound_peer(
                         &login_username,
-                        &selected.username,
+                        &candidate.username,
                         wire_token,
-                        wait_port,
+                        port,
                         &request.connection_type,
-                    ),
-                )
-                .await
-                {
-                    Ok(Ok(download_result)) => download_result,
-                    Ok(Err(err)) => {
-                        inbound_error = Some(format!(
-                            "inbound wait-port flow failed (port {wait_port}): {err}"
-                        ));
-                        match tokio::time::timeout(
-                            Duration::from_secs(45),
-                            download_single_file_via_queue_upload(
-                                &plan,
-                                &login_username,
-                                &selected.username,
-                                wire_token,
-                                resolve_wait_port(request.wait_port),
-                                None,
-                                None,
-                            ),
-                        )
-                        .await
-                        {
-                            Ok(Ok(download_result)) => download_result,
-                            Ok(Err(queue_err)) => {
-                                download_single_file_with_peer_init(
-                                    &plan,
-                                    &login_username,
-                                    &transfer_connection_type,
-                                    wire_token,
-                                )
-                                .await
-                                .map_err(|err| {
-                                    SearchSelectDownloadError::Download(format!(
-                                        "{}; queue-upload flow failed: {queue_err}; direct flow failed: {err}",
-                                        inbound_error
-                                            .clone()
-                                            .unwrap_or_else(|| "inbound flow failed".to_string())
-                                    ))
-                                })?
-                            }
-                            Err(_) => {
-                                download_single_file_with_peer_init(
-                                    &plan,
-                                    &login_username,
-                                    &transfer_connection_type,
-                                    wire_token,
-                                )
-                                .await
-                                .map_err(|err| {
-                                    SearchSelectDownloadError::Download(format!(
-                                        "{}; queue-upload flow timed out; direct flow failed: {err}",
-                                        inbound_error
-                                            .clone()
-                                            .unwrap_or_else(|| "inbound flow failed".to_string())
-                                    ))
-                                })?
-                            }
-                        }
-                    }
-                    Err(_) => {
-                        inbound_error = Some(format!(
-                            "inbound wait-port flow timed out (port {wait_port})"
-                        ));
-                        match tokio::time::timeout(
-                            Duration::from_secs(45),
-                            download_single_file_via_queue_upload(
-                                &plan,
-                                &login_username,
-                                &selected.username,
-                                wire_token,
-                                resolve_wait_port(request.wait_port),
-                                None,
-                                None,
-                            ),
-                        )
-                        .await
-                        {
-                            Ok(Ok(download_result)) => download_result,
-                            Ok(Err(queue_err)) => {
-                                download_single_file_with_peer_init(
-                                    &plan,
-                                    &login_username,
-                                    &transfer_connection_type,
-                                    wire_token,
-                                )
-                                .await
-                                .map_err(|err| {
-                                    SearchSelectDownloadError::Download(format!(
-                                        "{}; queue-upload flow failed: {queue_err}; direct flow failed: {err}",
-                                        inbound_error
-                                            .clone()
-                                            .unwrap_or_else(|| "inbound flow timed out".to_string())
-                                    ))
-                                })?
-                            }
-                            Err(_) => {
-                                download_single_file_with_peer_init(
-                                    &plan,
-                                    &login_username,
-                                    &transfer_connection_type,
-                                    wire_token,
-                                )
-                                .await
-                                .map_err(|err| {
-                                    SearchSelectDownloadError::Download(format!(
-                                        "{}; queue-upload flow timed out; direct flow failed: {err}",
-                                        inbound_error
-                                            .clone()
-                                            .unwrap_or_else(|| "inbound flow timed out".to_string())
-                                    ))
-                                })?
-                            }
-                        }
-                    }
-                }
-            } else {
-                match tokio::time::timeout(
-                    Duration::from_secs(45),
-                    download_single_file_via_queue_upload(
-                        &plan,
-                        &login_username,
-                        &selected.username,
-                        wire_token,
-                        resolve_wait_port(request.wait_port),
-                        None,
-                        None,
-                    ),
-                )
-                .await
-                {
-                    Ok(Ok(download_result)) => download_result,
-                    Ok(Err(queue_err)) => download_single_file_with_peer_init(
-                        &plan,
-                        &login_username,
-                        &transfer_connection_type,
-                        wire_token,
-                    )
-                    .await
-                    .map_err(|err| {
-                        SearchSelectDownloadError::Download(format!(
-                            "queue-upload flow failed: {queue_err}; direct flow failed: {err}"
-                        ))
-                    })?,
-                    Err(_) => download_single_file_with_peer_init(
-                        &plan,
-                        &login_username,
-                        &transfer_connection_type,
-                        wire_token,
+                        &mut errors,
                     )
                     .await
-                    .map_err(|err| {
-                        SearchSelectDownloadError::Download(format!(
-                            "queue-upload flow timed out; direct flow failed: {err}"
-                        ))
-                    })?,
                 }
-            }
+                _ => None,
+            };
+            (PeerFlows::Queued, inbound)
         } else {
-            download_single_file(&plan)
-                .await
-                .map_err(|err| SearchSelectDownloadError::Download(err.to_string()))?
+            (PeerFlows::Direct, None)
         };
 
-        Ok(SearchSelectDownloadResult {
-            selected_username: selected.username.clone(),
-            selected_virtual_path: selected.file_path.clone(),
-            selected_file_size: selected.file_size,
+        Ok(PeerDownload {
+            candidate: candidate.clone(),
+            request: request.clone(),
             peer_addr,
-            transfer_token: request.transfer_token,
-            output_path: download_result.output_path,
-            bytes_written: download_result.bytes_written,
-            search_source: selected.source,
+            wire_token,
+            login_username,
+            transfer_connection_type,
+            wait_port,
+            inbound,
+            errors,
+            flows,
         })
     }
 
+    async fn prepare_distributed_download(
+        &mut self,
+        request: &CandidateDownloadRequest,
+    ) -> (Option<u16>, String, String) {
+        let transfer_connection_type = if request.connection_type.eq_ignore_ascii_case("P") {
+            "F".to_string()
+        } else {
+            request.connection_type.clone()
+        };
+        let wait_port = resolve_wait_port(request.wait_port);
+        let login_username = self
+            .logged_username
+            .clone()
+            .unwrap_or_else(|| "neosoulseek".to_string());
+        if let Some(configured_wait_port) = wait_port
+            && let Err(err) = self.set_wait_port(configured_wait_port).await
+        {
+            transfer_debug(format!(
+                "set-wait-port preflight failed on {}: {}",
+                configured_wait_port,
+                format_error_chain(&err)
+            ));
+        }
+        (wait_port, login_username, transfer_connection_type)
+    }
+
+    /// Runs the server-side steps for one distributed candidate. The inbound
+    /// wait-port handshake goes first; the ConnectToPeer probes only run when
+    /// it failed, since a probe would make the peer connect to the listener
+    /// the inbound transfer still waits on.
     #[allow(clippy::too_many_arguments)]
-    pub async fn search_collect_candidates(
+    async fn prepare_distributed_candidate(
         &mut self,
-        token: u32,
-        query: &str,
-        timeout: Duration,
-        max_messages: usize,
-        mode: SearchMode,
-        strict_track: Option<&str>,
-        connection_type: &str,
+        candidate: &SearchCandidate,
+        candidate_peer_addr: String,
+        request: &CandidateDownloadRequest,
+        attempt_index: usize,
+        wait_port: Option<u16>,
+        login_username: String,
+        transfer_connection_type: String,
+    ) -> PeerDownload {
+        transfer_debug(format!(
+            "candidate user={} peer={} path={} token={}",
+            candidate.username,
+            candidate_peer_addr,
+            candidate.file_path,
+            candidate.connect_token.unwrap_or(request.transfer_token)
+        ));
+        let mut peer_addr = candidate_peer_addr;
+        let mut wire_token = candidate.connect_token.unwrap_or(request.transfer_token);
+        let mut errors = Vec::new();
+
+        let inbound = match wait_port {
+            Some(port) if !request.skip_connect_probe => {
+                self.try_accept_inbound_peer(
+                    &login_username,
+                    &candidate.username,
+                    wire_token,
+                    port,
+                    &request.connection_type,
+                    &mut errors,
+                )
+                .await
+            }
+            _ => None,
+        };
+
+        let probe = !request.skip_connect_probe && inbound.is_none();
+        if probe {
+            let probe_token_p = request
+                .transfer_token
+                .wrapping_add((attempt_index as u32).wrapping_mul(17))
+                .wrapping_add(7);
+            if let Err(err) = self
+                .connect_to_peer(&candidate.username, probe_token_p, &request.connection_type)
+                .await
+            {
+                let rendered = format_error_chain(&err);
+                transfer_debug(format!(
+                    "connect-to-peer probe failed, keeping candidate address: {rendered}"
+                ));
+                errors.push(format!(
+                    "connect-to-peer probe failed: {rendered}; using candidate address"
+                ));
+            } else {
+                match self
+                    .wait_connect_to_peer_response(
+                        &candidate.username,
+                        Some(probe_token_p),
+                        resolve_connect_to_peer_wait_timeout(),
+                    )
+                    .await
+                {
+                    Ok(response) => {
+                        peer_addr = format!("{}:{}", response.ip_address, response.port);
+                        wire_token = response.token;
+                        transfer_debug(format!(
+                            "connect-to-peer response accepted: peer={} token={}",
+                            peer_addr, wire_token
+                        ));
+                    }
+                    Err(err) => {
+                        let rendered = format_error_chain(&err);
+                        transfer_debug(format!(
+                            "connect-to-peer response wait failed, keeping candidate address: {rendered}"
+                        ));
+                        errors.push(format!(
+                            "connect-to-peer response wait failed: {rendered}; using candidate address"
+                        ));
+                    }
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(120)).await;
+        }
+        let mut file_peer_addr = peer_addr.clone();
+        let mut file_connect_token = wire_token;
+        if probe {
+            let probe_token_f = request
+                .transfer_token
+                .wrapping_add((attempt_index as u32).wrapping_mul(17))
+                .wrapping_add(13);
+            if let Err(err) = self
+                .connect_to_peer(&candidate.username, probe_token_f, "F")
+                .await
+            {
+                transfer_debug(format!(
+                    "connect-to-peer F probe failed, keeping control peer address: {}",
+                    format_error_chain(&err)
+                ));
+            } else {
+                match self
+                    .wait_connect_to_peer_response(
+                        &candidate.username,
+                        Some(probe_token_f),
+                        resolve_connect_to_peer_wait_timeout(),
+                    )
+                    .await
+                {
+                    Ok(response) => {
+                        file_peer_addr = format!("{}:{}", response.ip_address, response.port);
+                        file_connect_token = response.token;
+                        transfer_debug(format!(
+                            "connect-to-peer F response accepted: peer={} token={}",
+                            file_peer_addr, file_connect_token
+                        ));
+                    }
+                    Err(err) => {
+                        transfer_debug(format!(
+                            "connect-to-peer F response wait failed, using control peer address: {}",
+                            format_error_chain(&err)
+                        ));
+                    }
+                }
+            }
+        }
+
+        PeerDownload {
+            candidate: candidate.clone(),
+            request: request.clone(),
+            peer_addr,
+            wire_token,
+            login_username,
+            transfer_connection_type,
+            wait_port,
+            inbound,
+            errors,
+            flows: PeerFlows::Distributed {
+                file_peer_addr,
+                file_connect_token,
+            },
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn search_collect_candidates(
+        &mut self,
+        token: u32,
+        query: &str,
+        timeout: Duration,
+        max_messages: usize,
+        mode: SearchMode,
+        strict_track: Option<&str>,
+        connection_type: &str,
+    ) -> Result<Vec<SearchCandidate>> {
+        self.search_collect_scoped_candidates(
+            token,
+            &SearchScope::Global,
+            query,
+            timeout,
+            max_messages,
+            mode,
+            strict_track,
+            connection_type,
+        )
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn search_collect_scoped_candidates(
+        &mut self,
+        token: u32,
+        scope: &SearchScope,
+        query: &str,
+        timeout: Duration,
+        max_messages: usize,
+        mode: SearchMode,
+        strict_track: Option<&str>,
+        connection_type: &str,
     ) -> Result<Vec<SearchCandidate>> {
         self.ensure_logged_in()?;
         let collected = self
-            .search_and_collect(token, query, timeout, max_messages)
+            .search_and_collect_scoped(token, scope, query, timeout, max_messages)
             .await?;
         let summary_candidates = flatten_summary_candidates(&collect_search_summaries(&collected));
 
@@ -1954,6 +2427,7 @@ impl SessionClient {
                 file_size: hit.file_size,
                 peer_addr: Some(hit.peer_addr),
                 connect_token: Some(hit.connect_token),
+                bitrate: hit.bitrate,
                 source: SearchResultSource::DistributedPeer,
             })
             .collect::<Vec<_>>();
@@ -1977,6 +2451,28 @@ impl SessionClient {
         &mut self,
         timeout: Duration,
         max_events: usize,
+    ) -> Result<Vec<RoomEvent>> {
+        self.collect_room_events_into(None, timeout, max_events)
+            .await
+    }
+
+    /// Like `collect_room_events`, also appending each event to `log` with
+    /// the time it arrived.
+    pub async fn collect_room_events_logged(
+        &mut self,
+        log: &RoomLog,
+        timeout: Duration,
+        max_events: usize,
+    ) -> Result<Vec<RoomEvent>> {
+        self.collect_room_events_into(Some(log), timeout, max_events)
+            .await
+    }
+
+    async fn collect_room_events_into(
+        &mut self,
+        log: Option<&RoomLog>,
+        timeout: Duration,
+        max_events: usize,
     ) -> Result<Vec<RoomEvent>> {
         self.ensure_logged_in()?;
         let mut events = Vec::new();
@@ -1989,50 +2485,18 @@ impl SessionClient {
             }
 
             let remaining = deadline.saturating_duration_since(now);
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(frame)) => {
                     let Ok(msg) = decode_server_message(frame.code, &frame.payload) else {
                         continue;
                     };
-                    match msg {
-                        ServerMessage::UserJoinedRoom(payload) => {
-                            events.push(RoomEvent::UserJoined {
-                                room: payload.room,
-                                username: payload.username,
-                            });
-                        }
-                        ServerMessage::UserLeftRoom(payload) => {
-                            events.push(RoomEvent::UserLeft {
-                                room: payload.room,
-                                username: payload.username,
-                            });
-                        }
-                        ServerMessage::SayChatRoom(payload) => {
-                            events.push(RoomEvent::RoomMessage {
-                                room: payload.room,
-                                username: payload.username,
-                                message: payload.message,
-                            });
-                        }
-                        ServerMessage::RoomMembers(payload) => {
-                            events.push(RoomEvent::MembersSnapshot(payload));
-                        }
-                        ServerMessage::RoomOperators(payload) => {
-                            events.push(RoomEvent::OperatorsSnapshot(payload));
-                        }
-                        ServerMessage::RoomTicker(payload) => {
-                            events.push(RoomEvent::TickerSnapshot(payload));
-                        }
-                        ServerMessage::JoinRoom(payload) => {
-                            if !payload.users.is_empty() {
-                                events.push(RoomEvent::MembersSnapshot(RoomMembersPayload {
-                                    room: payload.room,
-                                    users: payload.users,
-                                }));
-                            }
-                        }
-                        _ => {}
+                    let Some(event) = room_event_from_message(msg) else {
+                        continue;
+                    };
+                    if let Some(log) = log {
+                        log.record_event(&event, room_log::unix_now_secs())?;
                     }
+                    events.push(event);
                 }
                 Ok(Err(err)) => {
                     if is_connection_eof(&err) {
@@ -2061,7 +2525,7 @@ impl SessionClient {
         Ok(())
     }
 
-    fn stream_mut(&mut self) -> Result<&mut TcpStream> {
+    fn stream_mut(&mut self) -> Result<&mut OwnedWriteHalf> {
         self.stream
             .as_mut()
             .ok_or_else(|| anyhow!("session stream is unavailable"))
@@ -2075,7 +2539,7 @@ impl SessionClient {
         let deadline = Instant::now() + timeout;
         while Instant::now() < deadline {
             let remaining = deadline.saturating_duration_since(Instant::now());
-            match tokio::time::timeout(remaining, self.read_next_frame()).await {
+            match self.read_frame_within(remaining).await {
                 Ok(Ok(response)) => {
                     let Ok(message) = decode_server_message(response.code, &response.payload)
                     else {
@@ -2118,6 +2582,179 @@ enum RecommendationKind {
     Global,
 }
 
+pub const DEFAULT_WISHLIST_INTERVAL: Duration = Duration::from_secs(720);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WishlistFilter {
+    pub min_file_size: Option<u64>,
+    pub max_file_size: Option<u64>,
+    pub extensions: Vec<String>,
+    pub excluded_terms: Vec<String>,
+}
+
+impl WishlistFilter {
+    pub fn matches(&self, candidate: &SearchCandidate) -> bool {
+        if self
+            .min_file_size
+            .is_some_and(|min| candidate.file_size < min)
+            || self
+                .max_file_size
+                .is_some_and(|max| candidate.file_size > max)
+        {
+            return false;
+        }
+
+        let path = candidate.file_path.to_ascii_lowercase();
+        if !self.extensions.is_empty() {
+            let extension = path.rsplit_once('.').map_or("", |(_, ext)| ext);
+            if !self.extensions.iter().any(|wanted| {
+                wanted
+                    .trim_start_matches('.')
+                    .eq_ignore_ascii_case(extension)
+            }) {
+                return false;
+            }
+        }
+        !self
+            .excluded_terms
+            .iter()
+            .any(|term| path.contains(&term.to_ascii_lowercase()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WishlistItem {
+    pub query: String,
+    pub filter: WishlistFilter,
+    pub auto_download: bool,
+}
+
+/// Issues one wishlist search per server-provided interval, cycling through
+/// the items, and remembers which results were already reported.
+#[derive(Debug, Clone)]
+pub struct WishlistScheduler {
+    interval: Duration,
+    next_due: Instant,
+    cursor: usize,
+    seen: HashSet<(String, String)>,
+}
+
+impl WishlistScheduler {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            interval: DEFAULT_WISHLIST_INTERVAL,
+            next_due: now,
+            cursor: 0,
+            seen: HashSet::new(),
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn next_due(&self) -> Instant {
+        self.next_due
+    }
+
+    pub fn set_interval(&mut self, interval: Duration) {
+        if interval.is_zero() || interval == self.interval {
+            return;
+        }
+        if let Some(last_run) = self.next_due.checked_sub(self.interval) {
+            self.next_due = last_run + interval;
+        }
+        self.interval = interval;
+    }
+
+    /// Returns the index of the item to search now, or `None` when the
+    /// interval has not elapsed yet.
+    pub fn take_due(&mut self, items: &[WishlistItem], now: Instant) -> Option<usize> {
+        if items.is_empty() || now < self.next_due {
+            return None;
+        }
+        let index = self.cursor % items.len();
+        self.cursor = index + 1;
+        self.next_due = now + self.interval;
+        Some(index)
+    }
+
+    /// Marks a file as already handled, e.g. one restored from download history.
+    pub fn remember(&mut self, username: &str, file_path: &str) {
+        self.seen
+            .insert((username.to_string(), file_path.to_string()));
+    }
+
+    pub fn accept_results(
+        &mut self,
+        item: &WishlistItem,
+        candidates: &[SearchCandidate],
+    ) -> Vec<SearchCandidate> {
+        candidates
+            .iter()
+            .filter(|candidate| item.filter.matches(candidate))
+            .filter(|candidate| {
+                self.seen
+                    .insert((candidate.username.clone(), candidate.file_path.clone()))
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+pub const DEFAULT_AUTO_AWAY_AFTER: Duration = Duration::from_secs(15 * 60);
+
+/// Switches to away after a period without user input and back to online on
+/// the next input. An away status set by hand is left alone.
+#[derive(Debug, Clone)]
+pub struct AutoAway {
+    idle_after: Option<Duration>,
+    last_activity: Instant,
+    engaged: bool,
+}
+
+impl AutoAway {
+    pub fn new(idle_after: Option<Duration>, now: Instant) -> Self {
+        Self {
+            idle_after: idle_after.filter(|idle| !idle.is_zero()),
+            last_activity: now,
+            engaged: false,
+        }
+    }
+
+    pub fn idle_after(&self) -> Option<Duration> {
+        self.idle_after
+    }
+
+    pub fn is_engaged(&self) -> bool {
+        self.engaged
+    }
+
+    /// Records user input; returns `Online` when auto-away has to be lifted.
+    pub fn record_activity(&mut self, now: Instant) -> Option<UserPresence> {
+        self.last_activity = now;
+        if self.engaged {
+            self.engaged = false;
+            Some(UserPresence::Online)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `Away` once the idle period has elapsed while `current` is online.
+    pub fn poll(&mut self, current: UserPresence, now: Instant) -> Option<UserPresence> {
+        let idle_after = self.idle_after?;
+        if self.engaged
+            || current != UserPresence::Online
+            || now.saturating_duration_since(self.last_activity) < idle_after
+        {
+            return None;
+        }
+        self.engaged = true;
+        Some(UserPresence::Away)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Error)]
 pub enum AuthError {
     #[error("login rejected: INVALIDVERSION")]
@@ -2184,6 +2821,59 @@ pub enum SearchSelectDownloadError {
     Download(String),
 }
 
+/// Turns one server message into the session events it carries, folding
+/// buddy status into `buddies` on the way.
+pub fn session_events(msg: ServerMessage, buddies: &mut BuddyList) -> Vec<SessionEvent> {
+    if let ServerMessage::FileSearchResponseSummary(summary) = msg {
+        return vec![SessionEvent::Search(summary)];
+    }
+    let mut events: Vec<SessionEvent> = buddies
+        .apply(&msg)
+        .into_iter()
+        .map(SessionEvent::Buddy)
+        .collect();
+    events.extend(room_event_from_message(msg).map(SessionEvent::Room));
+    events
+}
+
+fn room_event_from_message(msg: ServerMessage) -> Option<RoomEvent> {
+    let event = match msg {
+        ServerMessage::UserJoinedRoom(payload) => RoomEvent::UserJoined {
+            room: payload.room,
+            username: payload.username,
+        },
+        ServerMessage::UserLeftRoom(payload) => RoomEvent::UserLeft {
+            room: payload.room,
+            username: payload.username,
+        },
+        ServerMessage::SayChatRoom(payload) => RoomEvent::RoomMessage {
+            room: payload.room,
+            username: payload.username,
+            message: payload.message,
+        },
+        ServerMessage::RoomMembers(payload) => RoomEvent::MembersSnapshot(payload),
+        ServerMessage::RoomOperators(payload) => RoomEvent::OperatorsSnapshot(payload),
+        ServerMessage::RoomTicker(payload) => RoomEvent::TickerSnapshot(payload),
+        ServerMessage::RoomTickerUserAdded(payload) => RoomEvent::TickerSet {
+            room: payload.room,
+            username: payload.username,
+            ticker: payload.ticker,
+        },
+        ServerMessage::RoomTickerUserRemoved(payload) => RoomEvent::TickerRemoved {
+            room: payload.room,
+            username: payload.username,
+        },
+        ServerMessage::JoinRoom(payload) if !payload.users.is_empty() => {
+            RoomEvent::MembersSnapshot(RoomMembersPayload {
+                room: payload.room,
+                users: payload.users,
+            })
+        }
+        _ => return None,
+    };
+    Some(event)
+}
+
 fn collect_search_summaries(messages: &[ServerMessage]) -> Vec<SearchResponseSummary> {
     messages
         .iter()
@@ -2207,6 +2897,7 @@ fn flatten_summary_candidates(summaries: &[SearchResponseSummary]) -> Vec<Search
                 file_size: file.file_size,
                 peer_addr: None,
                 connect_token: None,
+                bitrate: None,
                 source: SearchResultSource::ServerSummary,
             });
         }
@@ -2238,6 +2929,7 @@ struct DistributedSearchHit {
     peer_addr: String,
     file_path: String,
     file_size: u64,
+    bitrate: Option<u32>,
     connect_token: u32,
     score: i64,
 }
@@ -2335,6 +3027,7 @@ async fn collect_distributed_candidates(
                     peer_addr: peer_addr.clone(),
                     file_path: raw_path,
                     file_size: file.file_size,
+                    bitrate: file.bitrate,
                     connect_token: candidate.token,
                     score,
                 });
@@ -2379,6 +3072,37 @@ fn normalize_peer_virtual_path(file_path: &str) -> String {
     normalized.trim_start_matches('\\').to_owned()
 }
 
+/// Folder part of a shared file path, e.g. `Music\Album` for
+/// `Music\Album\01.flac`. Empty for files at the share root.
+pub fn remote_folder(file_path: &str) -> &str {
+    file_path
+        .rfind(['\\', '/'])
+        .map_or("", |split| &file_path[..split])
+}
+
+/// Where a downloaded file lands under `output_dir`, keeping the remote folder
+/// layout. Share prefixes, drive letters and `.`/`..` components are dropped.
+pub fn local_download_path(output_dir: &Path, file_path: &str) -> PathBuf {
+    let normalized = normalize_peer_virtual_path(file_path);
+    let mut out = output_dir.to_path_buf();
+    for component in normalized.split('\\') {
+        let component = component.trim();
+        if matches!(component, "" | "." | "..") || component.ends_with(':') {
+            continue;
+        }
+        out.push(component.replace(':', "_"));
+    }
+    out
+}
+
+/// Folder listings may name entries relative to the folder or in full.
+fn folder_entry_path(directory: &str, entry_path: &str) -> String {
+    if entry_path.starts_with(directory) || directory.is_empty() {
+        return entry_path.to_owned();
+    }
+    format!("{}\\{entry_path}", directory.trim_end_matches(['\\', '/']))
+}
+
 fn score_track_candidate(file_path: &str, query: &str, strict_track: Option<&str>) -> i64 {
     let lower = file_path.to_ascii_lowercase();
     let mut score = 0_i64;
@@ -2428,19 +3152,13 @@ async fn write_peer_init_frame(
         }
         _ => 0,
     };
-    let mut payload = Vec::new();
-    payload.push(1_u8);
-    let mut writer = PayloadWriter::new();
-    writer.write_string(username);
-    writer.write_string(connection_type);
-    writer.write_u32(wire_token);
-    payload.extend_from_slice(&writer.into_inner());
-
-    let mut frame = Vec::with_capacity(4 + payload.len());
-    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
-    frame.extend_from_slice(&payload);
+    let frame = encode_peer_init_message(&PeerInitMessage::PeerInit(PeerInitPayload {
+        username: username.to_string(),
+        connection_type: connection_type.to_string(),
+        token: wire_token,
+    }));
     stream
-        .write_all(&frame)
+        .write_all(&frame.encode())
         .await
         .context("write peer init frame")?;
     stream.flush().await.context("flush peer init frame")?;
@@ -2448,15 +3166,11 @@ async fn write_peer_init_frame(
 }
 
 async fn write_pierce_firewall_frame(stream: &mut TcpStream, token: u32) -> Result<()> {
-    let mut payload = Vec::with_capacity(5);
-    payload.push(0_u8);
-    payload.extend_from_slice(&token.to_le_bytes());
-
-    let mut frame = Vec::with_capacity(4 + payload.len());
-    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
-    frame.extend_from_slice(&payload);
+    let frame = encode_peer_init_message(&PeerInitMessage::PierceFirewall(PierceFirewallPayload {
+        token,
+    }));
     stream
-        .write_all(&frame)
+        .write_all(&frame.encode())
         .await
         .context("write pierce-firewall frame")?;
     stream
@@ -2466,38 +3180,6 @@ async fn write_pierce_firewall_frame(stream: &mut TcpStream, token: u32) -> Resu
     Ok(())
 }
 
-#[derive(Debug, Clone)]
-struct PeerInitPayload {
-    username: String,
-    connection_type: String,
-    token: u32,
-}
-
-fn parse_peer_init_payload(payload: &[u8]) -> Result<PeerInitPayload> {
-    if payload.is_empty() {
-        bail!("peer init payload is empty");
-    }
-    let message_type = payload[0];
-    if message_type != 1 {
-        bail!("unexpected peer init message type: {message_type}");
-    }
-    let mut reader = PayloadReader::new(&payload[1..]);
-    let username = reader
-        .read_string()
-        .map_err(|err| anyhow!("decode peer init username: {err}"))?;
-    let connection_type = reader
-        .read_string()
-        .map_err(|err| anyhow!("decode peer init connection type: {err}"))?;
-    let token = reader
-        .read_u32()
-        .map_err(|err| anyhow!("decode peer init token: {err}"))?;
-    Ok(PeerInitPayload {
-        username,
-        connection_type,
-        token,
-    })
-}
-
 async fn read_peer_init_payload(stream: &mut TcpStream) -> Result<PeerInitPayload> {
     let mut len_buf = [0_u8; 4];
     stream
@@ -2513,7 +3195,10 @@ async fn read_peer_init_payload(stream: &mut TcpStream) -> Result<PeerInitPayloa
         .read_exact(&mut body)
         .await
         .context("read peer init frame body")?;
-    parse_peer_init_payload(&body)
+    match decode_peer_init_message(&body)? {
+        PeerInitMessage::PeerInit(payload) => Ok(payload),
+        other => bail!("unexpected peer init message type: {}", other.code()),
+    }
 }
 
 async fn accept_peer_connection_with_init(
@@ -2831,6 +3516,7 @@ fn resolve_send_pierce_firewall_on_outbound_file_init() -> bool {
     )
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum OutboundFileVariantOrder {
     NoInitFirst,
@@ -3041,7 +3727,7 @@ fn is_connection_eof(err: &anyhow::Error) -> bool {
     })
 }
 
-pub async fn write_frame(stream: &mut TcpStream, frame: &Frame) -> Result<()> {
+pub async fn write_frame<W: AsyncWrite + Unpin>(stream: &mut W, frame: &Frame) -> Result<()> {
     let bytes = frame.encode();
     stream.write_all(&bytes).await.context("write frame")?;
     stream.flush().await.context("flush frame")?;
@@ -3068,31 +3754,426 @@ pub async fn read_frame(stream: &mut TcpStream) -> Result<Frame> {
     Frame::decode(&frame_bytes)
 }
 
-pub async fn download_single_file(plan: &DownloadPlan) -> Result<DownloadResult> {
-    let mut stream = TcpStream::connect(&plan.peer_addr)
-        .await
-        .with_context(|| format!("connect peer failed: {}", plan.peer_addr))?;
+impl PeerDownload {
+    /// Transfers the file over peer connections only.
+    pub async fn run(
+        self,
+    ) -> std::result::Result<SearchSelectDownloadResult, SearchSelectDownloadError> {
+        match self.flows {
+            PeerFlows::Direct => {
+                let download_result = download_single_file(&self.plan())
+                    .await
+                    .map_err(|err| SearchSelectDownloadError::Download(err.to_string()))?;
+                Ok(self.finished(download_result))
+            }
+            PeerFlows::Queued => self
+                .run_queued_flows()
+                .await
+                .map_err(SearchSelectDownloadError::Download),
+            PeerFlows::Distributed { .. } => self.run_distributed_flows().await.map_err(|errors| {
+                SearchSelectDownloadError::Download(format!(
+                    "distributed candidate failed: {errors}"
+                ))
+            }),
+        }
+    }
 
-    let request = build_download_transfer_request_runtime(plan.token, &plan.virtual_path, plan.file_size);
-    write_frame(&mut stream, &request).await?;
-    let response = read_transfer_response(&mut stream).await?;
+    fn plan(&self) -> DownloadPlan {
+        DownloadPlan {
+            peer_addr: self.peer_addr.clone(),
+            token: self.request.transfer_token,
+            virtual_path: self.candidate.file_path.clone(),
+            file_size: self.candidate.file_size,
+            output_path: self.request.output_path.clone(),
+        }
+    }
 
-    validate_transfer_response(plan.token, &response)?;
-    ensure_parent_dir(&plan.output_path).await?;
+    fn finished(&self, download_result: DownloadResult) -> SearchSelectDownloadResult {
+        SearchSelectDownloadResult {
+            selected_username: self.candidate.username.clone(),
+            selected_virtual_path: self.candidate.file_path.clone(),
+            selected_file_size: self.candidate.file_size,
+            peer_addr: self.peer_addr.clone(),
+            transfer_token: self.request.transfer_token,
+            output_path: download_result.output_path,
+            bytes_written: download_result.bytes_written,
+            search_source: self.candidate.source,
+        }
+    }
 
-    let content = read_transfer_body(&mut stream, plan.file_size).await?;
-    validate_transfer_content(&content, plan.file_size)?;
-    fs::write(&plan.output_path, &content)
+    /// Finishes the inbound wait-port flow when its handshake succeeded.
+    async fn run_inbound_flow(&mut self, plan: &DownloadPlan) -> Option<DownloadResult> {
+        let inbound = self.inbound.take()?;
+        let wait_port = inbound.wait_port;
+        match tokio::time::timeout(
+            resolve_inbound_wait_port_flow_timeout(),
+            download_via_inbound_peer(inbound, plan, &self.login_username),
+        )
         .await
-        .with_context(|| format!("write output file: {}", plan.output_path.display()))?;
-
-    Ok(DownloadResult {
-        output_path: plan.output_path.clone(),
-        bytes_written: content.len() as u64,
-    })
-}
+        {
+            Ok(Ok(download_result)) => {
+                transfer_debug("inbound wait-port flow succeeded");
+                Some(download_result)
+            }
+            Ok(Err(err)) => {
+                let rendered = format_error_chain(&err);
+                transfer_debug(format!("inbound wait-port flow failed: {rendered}"));
+                self.errors.push(format!(
+                    "inbound wait-port flow failed (port {wait_port}): {rendered}"
+                ));
+                None
+            }
+            Err(_) => {
+                transfer_debug("inbound wait-port flow timed out");
+                self.errors.push(format!(
+                    "inbound wait-port flow timed out (port {wait_port})"
+                ));
+                None
+            }
+        }
+    }
 
-pub async fn download_single_file_with_peer_init(
+    async fn run_queued_flows(mut self) -> std::result::Result<SearchSelectDownloadResult, String> {
+        let plan = self.plan();
+        if let Some(download_result) = self.run_inbound_flow(&plan).await {
+            return Ok(self.finished(download_result));
+        }
+        match tokio::time::timeout(
+            Duration::from_secs(45),
+            download_single_file_via_queue_upload(
+                &plan,
+                &self.login_username,
+                &self.candidate.username,
+                self.wire_token,
+                self.wait_port,
+                None,
+                None,
+            ),
+        )
+        .await
+        {
+            Ok(Ok(download_result)) => return Ok(self.finished(download_result)),
+            Ok(Err(queue_err)) => self
+                .errors
+                .push(format!("queue-upload flow failed: {queue_err}")),
+            Err(_) => self.errors.push("queue-upload flow timed out".to_string()),
+        }
+        match download_single_file_with_peer_init(
+            &plan,
+            &self.login_username,
+            &self.transfer_connection_type,
+            self.wire_token,
+        )
+        .await
+        {
+            Ok(download_result) => Ok(self.finished(download_result)),
+            Err(err) => {
+                self.errors.push(format!("direct flow failed: {err}"));
+                Err(self.errors.join("; "))
+            }
+        }
+    }
+
+    /// Runs every transfer flow against one distributed candidate and returns
+    /// the joined flow errors when none succeeded.
+    async fn run_distributed_flows(
+        mut self,
+    ) -> std::result::Result<SearchSelectDownloadResult, String> {
+        let PeerFlows::Distributed {
+            file_peer_addr,
+            file_connect_token,
+        } = &self.flows
+        else {
+            return Err("not a distributed candidate download".to_string());
+        };
+        let (file_peer_addr, file_connect_token) = (file_peer_addr.clone(), *file_connect_token);
+        let plan = self.plan();
+        if let Some(download_result) = self.run_inbound_flow(&plan).await {
+            return Ok(self.finished(download_result));
+        }
+        if !resolve_skip_transfer_request_flow() {
+            let transfer_flow_timeout = resolve_transfer_flow_timeout();
+            let transfer_request_flow = tokio::time::timeout(
+                transfer_flow_timeout,
+                download_single_file_via_transfer_request(
+                    &plan,
+                    &self.login_username,
+                    self.wire_token,
+                    self.wait_port,
+                    Some(&file_peer_addr),
+                    Some(file_connect_token),
+                ),
+            )
+            .await;
+            match transfer_request_flow {
+                Ok(Ok(download_result)) => {
+                    transfer_debug("transfer-request flow succeeded");
+                    return Ok(self.finished(download_result));
+                }
+                Ok(Err(err)) => {
+                    let rendered = err.to_string();
+                    transfer_debug(format!("transfer-request flow failed: {rendered}"));
+                    self.errors
+                        .push(format!("transfer-request flow failed: {rendered}"));
+                }
+                Err(_) => {
+                    transfer_debug("transfer-request flow timed out");
+                    self.errors
+                        .push("transfer-request flow timed out".to_string());
+                }
+            }
+        } else {
+            transfer_debug("transfer-request flow skipped by NSS_SKIP_TRANSFER_REQUEST_FLOW");
+        }
+        let queue_flow_timeout = resolve_transfer_flow_timeout();
+        let modern_queue = tokio::time::timeout(
+            queue_flow_timeout,
+            download_single_file_via_queue_upload(
+                &plan,
+                &self.login_username,
+                &self.candidate.username,
+                self.wire_token,
+                self.wait_port,
+                Some(&file_peer_addr),
+                Some(file_connect_token),
+            ),
+        )
+        .await;
+        match modern_queue {
+            Ok(Ok(download_result)) => {
+                transfer_debug("queue-upload flow succeeded");
+                return Ok(self.finished(download_result));
+            }
+            Ok(Err(err)) => {
+                let rendered = format_error_chain(&err);
+                transfer_debug(format!("queue-upload flow failed: {rendered}"));
+                self.errors
+                    .push(format!("queue-upload flow failed: {rendered}"));
+            }
+            Err(_) => {
+                transfer_debug("queue-upload flow timed out");
+                self.errors.push("queue-upload flow timed out".to_string());
+            }
+        }
+
+        match tokio::time::timeout(
+            resolve_direct_transfer_flow_timeout(),
+            download_single_file_with_peer_init(
+                &DownloadPlan {
+                    peer_addr: file_peer_addr.clone(),
+                    ..plan
+                },
+                &self.login_username,
+                &self.transfer_connection_type,
+                file_connect_token,
+            ),
+        )
+        .await
+        {
+            Ok(Ok(download_result)) => {
+                transfer_debug("direct transfer flow succeeded");
+                return Ok(self.finished(download_result));
+            }
+            Ok(Err(err)) => {
+                let rendered = format_error_chain(&err);
+                transfer_debug(format!("direct flow failed: {rendered}"));
+                self.errors.push(format!("direct flow failed: {rendered}"));
+            }
+            Err(_) => {
+                transfer_debug("direct flow timed out");
+                self.errors.push("direct flow timed out".to_string());
+            }
+        }
+        Err(self.errors.join(" | "))
+    }
+}
+
+/// Finishes the inbound wait-port flow over the P connection the peer opened:
+/// queue the upload, allow the transfer and read the file from the F
+/// connection that arrives on the same listener.
+async fn download_via_inbound_peer(
+    inbound: InboundPeer,
+    plan: &DownloadPlan,
+    login_username: &str,
+) -> Result<DownloadResult> {
+    let InboundPeer {
+        listener,
+        mut p_stream,
+        ..
+    } = inbound;
+    let transfer_request = send_queue_upload_and_wait_transfer_request(
+        &mut p_stream,
+        login_username,
+        &plan.virtual_path,
+        resolve_transfer_flow_timeout(),
+    )
+    .await?;
+    transfer_debug(format!(
+        "inbound wait-port received transfer request token={} direction={:?} size={}",
+        transfer_request.token, transfer_request.direction, transfer_request.file_size
+    ));
+    write_transfer_allow(&mut p_stream, transfer_request.token).await?;
+
+    let (mut f_stream, maybe_f_init, addr) =
+        accept_peer_file_socket(&listener, Duration::from_secs(45))
+            .await
+            .context("accept inbound F connection")?;
+    if let Some(f_init) = maybe_f_init {
+        transfer_debug(format!(
+            "accepted inbound F connection with init token={} user={} from={}",
+            f_init.token, f_init.username, addr
+        ));
+    } else {
+        transfer_debug(format!(
+            "accepted inbound F connection without init from={addr}"
+        ));
+    }
+    ensure_parent_dir(&plan.output_path).await?;
+    let expected_size = if transfer_request.file_size == 0 {
+        plan.file_size
+    } else {
+        transfer_request.file_size
+    };
+    let content =
+        read_file_transfer_content(&mut f_stream, expected_size, transfer_request.token).await?;
+    validate_transfer_content(&content, expected_size)?;
+    fs::write(&plan.output_path, &content)
+        .await
+        .with_context(|| format!("write output file: {}", plan.output_path.display()))?;
+
+    Ok(DownloadResult {
+        output_path: plan.output_path.clone(),
+        bytes_written: content.len() as u64,
+    })
+}
+
+pub async fn download_single_file(plan: &DownloadPlan) -> Result<DownloadResult> {
+    let mut stream = TcpStream::connect(&plan.peer_addr)
+        .await
+        .with_context(|| format!("connect peer failed: {}", plan.peer_addr))?;
+
+    let request = build_download_transfer_request_runtime(plan.token, &plan.virtual_path, plan.file_size);
+    write_frame(&mut stream, &request).await?;
+    let response = read_transfer_response(&mut stream).await?;
+
+    validate_transfer_response(plan.token, &response)?;
+    ensure_parent_dir(&plan.output_path).await?;
+
+    let content = read_transfer_body(&mut stream, plan.file_size).await?;
+    validate_transfer_content(&content, plan.file_size)?;
+    fs::write(&plan.output_path, &content)
+        .await
+        .with_context(|| format!("write output file: {}", plan.output_path.display()))?;
+
+    Ok(DownloadResult {
+        output_path: plan.output_path.clone(),
+        bytes_written: content.len() as u64,
+    })
+}
+
+pub async fn request_user_info(
+    peer_addr: &str,
+    login_username: &str,
+    token: u32,
+    timeout: Duration,
+) -> Result<UserInfoReplyPayload> {
+    let mut stream = tokio::time::timeout(timeout, TcpStream::connect(peer_addr))
+        .await
+        .with_context(|| format!("connect peer timed out: {peer_addr}"))?
+        .with_context(|| format!("connect peer failed: {peer_addr}"))?;
+    write_peer_init_frame(&mut stream, login_username, "P", token).await?;
+    write_frame(&mut stream, &build_user_info_request()).await?;
+
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let frame = match tokio::time::timeout(remaining, read_frame(&mut stream)).await {
+            Ok(frame) => frame.context("read user info reply")?,
+            Err(_) => break,
+        };
+        if let Ok(PeerMessage::UserInfoReply(payload)) =
+            decode_peer_message(frame.code, &frame.payload)
+        {
+            return Ok(payload);
+        }
+    }
+    bail!("timed out waiting for user info reply from {peer_addr}")
+}
+
+pub async fn request_shared_file_list(
+    peer_addr: &str,
+    login_username: &str,
+    token: u32,
+    timeout: Duration,
+) -> Result<Vec<SharedFileEntry>> {
+    let mut stream = tokio::time::timeout(timeout, TcpStream::connect(peer_addr))
+        .await
+        .with_context(|| format!("connect peer timed out: {peer_addr}"))?
+        .with_context(|| format!("connect peer failed: {peer_addr}"))?;
+    write_peer_init_frame(&mut stream, login_username, "P", token).await?;
+    write_frame(
+        &mut stream,
+        &build_get_shared_file_list_request(login_username),
+    )
+    .await?;
+
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let frame = match tokio::time::timeout(remaining, read_frame(&mut stream)).await {
+            Ok(frame) => frame.context("read shared file list")?,
+            Err(_) => break,
+        };
+        if let Ok(PeerMessage::SharedFileList(payload)) =
+            decode_peer_message(frame.code, &frame.payload)
+        {
+            return Ok(payload.entries);
+        }
+    }
+    bail!("timed out waiting for shared file list from {peer_addr}")
+}
+
+pub async fn request_folder_contents(
+    peer_addr: &str,
+    login_username: &str,
+    token: u32,
+    directory: &str,
+    timeout: Duration,
+) -> Result<Vec<SharedFileEntry>> {
+    let mut stream = tokio::time::timeout(timeout, TcpStream::connect(peer_addr))
+        .await
+        .with_context(|| format!("connect peer timed out: {peer_addr}"))?
+        .with_context(|| format!("connect peer failed: {peer_addr}"))?;
+    write_peer_init_frame(&mut stream, login_username, "P", token).await?;
+    write_frame(
+        &mut stream,
+        &build_get_shared_files_in_folder_request(directory),
+    )
+    .await?;
+
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let frame = match tokio::time::timeout(remaining, read_frame(&mut stream)).await {
+            Ok(frame) => frame.context("read folder contents")?,
+            Err(_) => break,
+        };
+        if frame.code != CODE_PM_SHARED_FILES_IN_FOLDER {
+            continue;
+        }
+        let listing = parse_shared_files_in_folder_payload_decompressed(&frame.payload)?;
+        if listing.directory != directory {
+            continue;
+        }
+        if listing.listing_format != SharedFilesInFolderListingFormat::BinaryEntries {
+            bail!("folder listing from {peer_addr} has no file entries: {directory}");
+        }
+        return Ok(listing.entries);
+    }
+    bail!("timed out waiting for folder contents from {peer_addr}")
+}
+
+pub async fn download_single_file_with_peer_init(
     plan: &DownloadPlan,
     login_username: &str,
     connection_type: &str,
@@ -3198,6 +4279,7 @@ async fn download_single_file_via_transfer_request(
                 "transfer-request queued, waiting up to {}s for peer grant",
                 wait_secs
             ));
+            report_progress(TransferProgress::Queued { place: None });
             let queued_request =
                 read_peer_transfer_request(&mut p_stream, Duration::from_secs(wait_secs)).await?;
             transfer_debug(format!(
@@ -3217,16 +4299,15 @@ async fn download_single_file_via_transfer_request(
 
     if let Some(content) =
         try_read_transfer_body_on_control_channel(&mut p_stream, expected_size).await?
+        && validate_transfer_content(&content, expected_size).is_ok()
     {
-        if validate_transfer_content(&content, expected_size).is_ok() {
-            fs::write(&plan.output_path, &content)
-                .await
-                .with_context(|| format!("write output file: {}", plan.output_path.display()))?;
-            return Ok(DownloadResult {
-                output_path: plan.output_path.clone(),
-                bytes_written: content.len() as u64,
-            });
-        }
+        fs::write(&plan.output_path, &content)
+            .await
+            .with_context(|| format!("write output file: {}", plan.output_path.display()))?;
+        return Ok(DownloadResult {
+            output_path: plan.output_path.clone(),
+            bytes_written: content.len() as u64,
+        });
     }
     match read_file_transfer_content(&mut p_stream, expected_size, file_transfer_token).await {
         Ok(content) => {
@@ -3348,6 +4429,7 @@ async fn read_file_transfer_content_outbound_with_variants(
     file_transfer_token: u32,
     request_token: u32,
 ) -> Result<Vec<u8>> {
+    #[allow(clippy::too_many_arguments)]
     async fn run_variant(
         peer_addr: &str,
         login_username: &str,
@@ -3698,16 +4780,15 @@ async fn download_single_file_via_queue_upload(
 
     if let Some(content) =
         try_read_transfer_body_on_control_channel(&mut p_stream, expected_size).await?
+        && validate_transfer_content(&content, expected_size).is_ok()
     {
-        if validate_transfer_content(&content, expected_size).is_ok() {
-            fs::write(&plan.output_path, &content)
-                .await
-                .with_context(|| format!("write output file: {}", plan.output_path.display()))?;
-            return Ok(DownloadResult {
-                output_path: plan.output_path.clone(),
-                bytes_written: content.len() as u64,
-            });
-        }
+        fs::write(&plan.output_path, &content)
+            .await
+            .with_context(|| format!("write output file: {}", plan.output_path.display()))?;
+        return Ok(DownloadResult {
+            output_path: plan.output_path.clone(),
+            bytes_written: content.len() as u64,
+        });
     }
     match read_file_transfer_content(&mut p_stream, expected_size, transfer_request.token).await {
         Ok(content) => {
@@ -3969,6 +5050,9 @@ async fn try_read_transfer_body_on_control_channel(
             let decoded = decode_peer_message(frame.code, &frame.payload);
             match (frame.code, decoded) {
                 (CODE_PM_UPLOAD_PLACE_IN_LINE, Ok(PeerMessage::UploadPlaceInLine(payload))) => {
+                    report_progress(TransferProgress::Queued {
+                        place: Some(payload.place),
+                    });
                     bail!(
                         "peer queued transfer (place={} user={} path={})",
                         payload.place,
@@ -4009,6 +5093,8 @@ async fn try_read_transfer_body_on_control_channel(
     }
     let mut content = Vec::with_capacity(expected.max(first_read));
     content.extend_from_slice(&first[..first_read]);
+    let mut progress = ProgressReporter::new(expected_size);
+    progress.update(content.len() as u64);
     if expected_size == 0 {
         loop {
             let n = match tokio::time::timeout(resolve_transfer_body_chunk_timeout(), stream.read(&mut first))
@@ -4022,7 +5108,9 @@ async fn try_read_transfer_body_on_control_channel(
                 break;
             }
             content.extend_from_slice(&first[..n]);
+            progress.update(content.len() as u64);
         }
+        progress.finish(content.len() as u64);
         return Ok(Some(content));
     }
     while content.len() < expected {
@@ -4042,7 +5130,9 @@ async fn try_read_transfer_body_on_control_channel(
             break;
         }
         content.extend_from_slice(&first[..n]);
+        progress.update(content.len() as u64);
     }
+    progress.finish(content.len() as u64);
     Ok(Some(content))
 }
 
@@ -4190,6 +5280,9 @@ async fn read_peer_transfer_request(
                 return Ok(payload);
             }
             (CODE_PM_UPLOAD_PLACE_IN_LINE, Ok(PeerMessage::UploadPlaceInLine(payload))) => {
+                report_progress(TransferProgress::Queued {
+                    place: Some(payload.place),
+                });
                 bail!(
                     "queued by peer (place={} user={} path={})",
                     payload.place,
@@ -4251,12 +5344,14 @@ fn is_file_not_shared_error(err: &anyhow::Error) -> bool {
 }
 
 async fn read_transfer_body(stream: &mut TcpStream, expected_size: u64) -> Result<Vec<u8>> {
+    let mut progress = ProgressReporter::new(expected_size);
     if expected_size == 0 {
         let mut content = Vec::new();
         stream
             .read_to_end(&mut content)
             .await
             .context("read file body")?;
+        progress.finish(content.len() as u64);
         return Ok(content);
     }
 
@@ -4277,7 +5372,9 @@ async fn read_transfer_body(stream: &mut TcpStream, expected_size: u64) -> Resul
             break;
         }
         content.extend_from_slice(&buffer[..n]);
+        progress.update(content.len() as u64);
     }
+    progress.finish(content.len() as u64);
     Ok(content)
 }
 
@@ -4356,12 +5453,111 @@ pub struct UploadSessionResult {
     pub request: TransferRequestPayload,
     pub decision: UploadDecisionKind,
     pub bytes_sent: u64,
+    pub user_info_served: u32,
+}
+
+pub const DEFAULT_UPLOAD_SLOTS: u32 = 2;
+
+/// Tracks upload slots and the waiting queue; the counts are advertised to
+/// peers in `UserInfoReply`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadScheduler {
+    slots: u32,
+    active: u32,
+    queued: u32,
+    total_uploads: u32,
+}
+
+impl Default for UploadScheduler {
+    fn default() -> Self {
+        Self::new(DEFAULT_UPLOAD_SLOTS)
+    }
+}
+
+impl UploadScheduler {
+    pub fn new(slots: u32) -> Self {
+        Self {
+            slots,
+            active: 0,
+            queued: 0,
+            total_uploads: 0,
+        }
+    }
+
+    pub fn enqueue(&mut self) {
+        self.queued += 1;
+    }
+
+    /// Moves one queued upload into a slot. Returns `false` when every slot is busy.
+    pub fn start(&mut self) -> bool {
+        if self.active >= self.slots {
+            return false;
+        }
+        self.active += 1;
+        self.queued = self.queued.saturating_sub(1);
+        true
+    }
+
+    pub fn finish(&mut self, completed: bool) {
+        self.active = self.active.saturating_sub(1);
+        if completed {
+            self.total_uploads += 1;
+        }
+    }
+
+    pub fn queue_size(&self) -> u32 {
+        self.queued
+    }
+
+    pub fn free_slots(&self) -> u32 {
+        self.slots.saturating_sub(self.active)
+    }
+
+    pub fn total_uploads(&self) -> u32 {
+        self.total_uploads
+    }
+}
+
+/// Profile served to peers that send `UserInfoRequest`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserProfile {
+    pub description: String,
+    pub picture: Option<Vec<u8>>,
+}
+
+impl UserProfile {
+    pub fn load(description: impl Into<String>, picture_path: Option<&Path>) -> Result<Self> {
+        let picture = picture_path
+            .map(|path| {
+                std_fs::read(path)
+                    .with_context(|| format!("read profile picture: {}", path.display()))
+            })
+            .transpose()?;
+        Ok(Self {
+            description: description.into(),
+            picture,
+        })
+    }
+
+    pub fn user_info_reply(&self, uploads: &UploadScheduler) -> UserInfoReplyPayload {
+        UserInfoReplyPayload {
+            description: self.description.clone(),
+            has_picture: self.picture.is_some(),
+            picture: self.picture.clone().unwrap_or_default(),
+            total_uploads: uploads.total_uploads(),
+            queue_size: uploads.queue_size(),
+            slots_free: uploads.free_slots() > 0,
+            upload_permissions: None,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct UploadAgent {
     listener: TcpListener,
     policy: UploadPolicy,
+    profile: UserProfile,
+    scheduler: UploadScheduler,
 }
 
 impl UploadAgent {
@@ -4372,15 +5568,29 @@ impl UploadAgent {
         Ok(Self {
             listener,
             policy: UploadPolicy::Manual,
+            profile: UserProfile::default(),
+            scheduler: UploadScheduler::default(),
         })
     }
 
+    pub fn with_profile(mut self, profile: UserProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn with_scheduler(mut self, scheduler: UploadScheduler) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
     pub fn local_addr(&self) -> Result<SocketAddr> {
         Ok(self.listener.local_addr()?)
     }
 
+    /// Answers `UserInfoRequest` frames from the profile until the peer sends
+    /// its transfer request, then applies the manual decision.
     pub async fn serve_single_manual(
-        self,
+        mut self,
         decision: ManualUploadDecision,
         source_file: Option<PathBuf>,
     ) -> Result<UploadSessionResult> {
@@ -4391,10 +5601,19 @@ impl UploadAgent {
         let bind_addr = self.listener.local_addr()?;
         let (mut socket, peer_addr) = self.listener.accept().await.context("accept upload peer")?;
 
-        let first_frame = read_frame(&mut socket).await?;
-        let request = match decode_peer_message(first_frame.code, &first_frame.payload)? {
-            PeerMessage::TransferRequest(payload) => payload,
-            other => bail!("expected transfer request, got {other:?}"),
+        let mut user_info_served = 0;
+        let request = loop {
+            let frame = read_frame(&mut socket).await?;
+            match decode_peer_message(frame.code, &frame.payload)? {
+                PeerMessage::TransferRequest(payload) => break payload,
+                PeerMessage::UserInfoRequest(_) => {
+                    let reply = self.profile.user_info_reply(&self.scheduler);
+                    let frame = encode_peer_message(&PeerMessage::UserInfoReply(reply));
+                    write_frame(&mut socket, &frame).await?;
+                    user_info_served += 1;
+                }
+                other => bail!("expected transfer request, got {other:?}"),
+            }
         };
 
         let allowed = decision.decision == UploadDecisionKind::Accept;
@@ -4416,19 +5635,24 @@ impl UploadAgent {
 
         let mut bytes_sent = 0_u64;
         if allowed {
-            if let Some(path) = source_file {
-                let bytes = fs::read(&path)
-                    .await
-                    .with_context(|| format!("read upload source file: {}", path.display()))?;
-                socket
-                    .write_all(&bytes)
-                    .await
-                    .context("write upload bytes")?;
-                socket.flush().await.context("flush upload bytes")?;
-                bytes_sent = bytes.len() as u64;
-            }
+            self.scheduler.enqueue();
+            self.scheduler.start();
+        }
+        if allowed && let Some(path) = source_file {
+            let bytes = fs::read(&path)
+                .await
+                .with_context(|| format!("read upload source file: {}", path.display()))?;
+            socket
+                .write_all(&bytes)
+                .await
+                .context("write upload bytes")?;
+            socket.flush().await.context("flush upload bytes")?;
+            bytes_sent = bytes.len() as u64;
         }
         socket.shutdown().await.context("shutdown upload socket")?;
+        if allowed {
+            self.scheduler.finish(true);
+        }
 
         Ok(UploadSessionResult {
             bind_addr,
@@ -4436,6 +5660,7 @@ impl UploadAgent {
             request,
             decision: decision.decision,
             bytes_sent,
+            user_info_served,
         })
     }
 }
@@ -4456,7 +5681,8 @@ mod tests {
         CODE_SM_INFORM_USER_OF_PRIVILEGES_ACK, CODE_SM_JOIN_ROOM, CODE_SM_LEAVE_ROOM,
         CODE_SM_LOGIN, CODE_SM_MESSAGE_USER, CODE_SM_MESSAGE_USERS, CODE_SM_PRIVILEGED_LIST,
         CODE_SM_REMOVE_LIKE_TERM, CODE_SM_REMOVE_ROOM_MEMBER, CODE_SM_REMOVE_ROOM_OPERATOR,
-        CODE_SM_ROOM_LIST, CODE_SM_UNIGNORE_USER, CODE_SM_UPLOAD_SPEED, JoinRoomPayload,
+        CODE_SM_ROOM_LIST, CODE_SM_SEARCH_ROOM, CODE_SM_SEARCH_USER_FILES, CODE_SM_UNIGNORE_USER,
+        CODE_SM_UPLOAD_SPEED, JoinRoomPayload,
         LoginResponsePayload, LoginResponseSuccessPayload, MessageUserIncomingPayload,
         OwnPrivilegesStatusPayload, PeerAddressResponsePayload, PrivilegedListPayload,
         RecommendationEntry, RecommendationUsersPayload, RecommendationsPayload,
@@ -4574,51 +5800,961 @@ mod tests {
             wait_port: None,
             skip_connect_probe: true,
             search_mode: SearchMode::Auto,
+            search_scope: SearchScope::Global,
             strict_track: None,
         };
         let err = client
             .search_select_and_download(&request)
             .await
-            .expect_err("invalid index expected");
+            .expect_err("invalid index expected");
+        assert!(matches!(
+            err,
+            SearchSelectDownloadError::InvalidSearchResultIndex { .. }
+        ));
+
+        server.await.expect("server task");
+    }
+
+    #[tokio::test]
+    async fn search_select_and_download_mock_flow_works_for_flim_query() {
+        let server_listener = TcpListener::bind("127.0.0.1:0").await.expect("bind server");
+        let server_addr = server_listener.local_addr().expect("server addr");
+
+        let peer_listener = TcpListener::bind("127.0.0.1:0").await.expect("bind peer");
+        let peer_addr = peer_listener.local_addr().expect("peer addr");
+
+        let server_task = tokio::spawn(async move {
+            let (mut socket, _) = server_listener.accept().await.expect("accept server");
+            let login = read_frame(&mut socket).await.expect("login frame");
+            assert_eq!(login.code, CODE_SM_LOGIN);
+            write_frame(&mut socket, &login_success_frame())
+                .await
+                .expect("write login success");
+
+            let request = read_frame(&mut socket).await.expect("search request");
+            assert_eq!(request.code, CODE_SM_FILE_SEARCH);
+
+            let summary = encode_server_message(&ServerMessage::FileSearchResponseSummary(
+                SearchResponseSummary {
+                    username: "peer_aphex".into(),
+                    token: 9101,
+                    files_count: 1,
+                    slots_free: 1,
+                    speed: 1024,
+                    in_queue: false,
+                    files: vec![SearchFileSummary {
+                        file_path: "Music\\Aphex Twin\\Flim.mp3".into(),
+                        file_size: 6,
+                        extension: "mp3".into(),
+                        attr_count: 0,
+                    }],
+                },
+            ));
+            write_frame(&mut socket, &summary)
+                .await
+                .expect("write summary");
+        });
+
+        let peer_task = tokio::spawn(async move {
+            let (mut socket, _) = peer_listener.accept().await.expect("accept peer");
+            let transfer_request = read_frame(&mut socket).await.expect("transfer request");
+            assert_eq!(transfer_request.code, protocol::CODE_PM_TRANSFER_REQUEST);
+            let parsed = parse_transfer_request(&transfer_request.payload).expect("parse request");
+            assert_eq!(parsed.token, 9202);
+            assert_eq!(parsed.virtual_path, "Music\\Aphex Twin\\Flim.mp3");
+
+            let response = protocol::build_transfer_response(9202, true, "");
+            write_frame(&mut socket, &response)
+                .await
+                .expect("write transfer response");
+            socket.write_all(b"flim!!").await.expect("write payload");
+            socket.shutdown().await.expect("shutdown peer");
+        });
+
+        let mut client = SessionClient::connect(&server_addr.to_string())
+            .await
+            .expect("connect");
+        client
+            .login(&Credentials {
+                username: "fede_test1234".into(),
+                password: "fede1234".into(),
+                client_version: 160,
+                minor_version: 1,
+            })
+            .await
+            .expect("login");
+
+        let output = std::env::temp_dir().join("neosoulseek-flim-mock-test.bin");
+        let request = SearchSelectDownloadRequest {
+            search_token: 9101,
+            query: "aphex twin flim".into(),
+            search_timeout: Duration::from_secs(1),
+            max_messages: 16,
+            result_index: 0,
+            file_index: 0,
+            transfer_token: 9202,
+            output_path: output.clone(),
+            peer_addr_override: Some(peer_addr.to_string()),
+            peer_lookup_timeout: Duration::from_secs(1),
+            connection_type: "P".into(),
+            wait_port: None,
+            skip_connect_probe: true,
+            search_mode: SearchMode::Auto,
+            search_scope: SearchScope::Global,
+            strict_track: None,
+        };
+        let result = client
+            .search_select_and_download(&request)
+            .await
+            .expect("download result");
+
+        assert_eq!(result.selected_username, "peer_aphex");
+        assert_eq!(result.selected_virtual_path, "Music\\Aphex Twin\\Flim.mp3");
+        assert_eq!(result.bytes_written, 6);
+
+        let written = fs::read(&result.output_path).await.expect("read output");
+        assert_eq!(written, b"flim!!");
+
+        let _ = fs::remove_file(output).await;
+        server_task.await.expect("join server task");
+        peer_task.await.expect("join peer task");
+    }
+
+    #[tokio::test]
+    async fn download_candidate_fetches_exact_file_without_searching() {
+        let server_listener = TcpListener::bind("127.0.0.1:0").await.expect("bind server");
+        let server_addr = server_listener.local_addr().expect("server addr");
+
+        let peer_listener = TcpListener::bind("127.0.0.1:0").await.expect("bind peer");
+        let peer_addr = peer_listener.local_addr().expect("peer addr");
+
+        let server_task = tokio::spawn(async move {
+            let (mut socket, _) = server_listener.accept().await.expect("accept server");
+            let login = read_frame(&mut socket).await.expect("login frame");
+            assert_eq!(login.code, CODE_SM_LOGIN);
+            write_frame(&mut socket, &login_success_frame())
+                .await
+                .expect("write login success");
+
+            // The candidate already carries its peer address, so nothing else
+            // (no search, no peer lookup) should reach the server.
+            let next =
+                tokio::time::timeout(Duration::from_millis(300), read_frame(&mut socket)).await;
+            assert!(!matches!(next, Ok(Ok(_))), "unexpected server frame");
+        });
+
+        let peer_task = tokio::spawn(async move {
+            let (mut socket, _) = peer_listener.accept().await.expect("accept peer");
+            let transfer_request = read_frame(&mut socket).await.expect("transfer request");
+            assert_eq!(transfer_request.code, protocol::CODE_PM_TRANSFER_REQUEST);
+            let parsed = parse_transfer_request(&transfer_request.payload).expect("parse request");
+            assert_eq!(parsed.token, 9303);
+            assert_eq!(parsed.virtual_path, "Music\\Boards\\Roygbiv.flac");
+
+            let response = protocol::build_transfer_response(9303, true, "");
+            write_frame(&mut socket, &response)
+                .await
+                .expect("write transfer response");
+            socket.write_all(b"roygbiv").await.expect("write payload");
+            socket.shutdown().await.expect("shutdown peer");
+        });
+
+        let mut client = SessionClient::connect(&server_addr.to_string())
+            .await
+            .expect("connect");
+        client
+            .login(&Credentials {
+                username: "fede_test1234".into(),
+                password: "fede1234".into(),
+                client_version: 160,
+                minor_version: 1,
+            })
+            .await
+            .expect("login");
+
+        let candidate = SearchCandidate {
+            username: "peer_boards".into(),
+            file_path: "Music\\Boards\\Roygbiv.flac".into(),
+            file_size: 7,
+            peer_addr: Some(peer_addr.to_string()),
+            connect_token: None,
+            bitrate: None,
+            source: SearchResultSource::ServerSummary,
+        };
+        let output = std::env::temp_dir().join("neosoulseek-candidate-download-test.bin");
+        let request = CandidateDownloadRequest {
+            transfer_token: 9303,
+            output_path: output.clone(),
+            peer_addr_override: None,
+            peer_lookup_timeout: Duration::from_secs(1),
+            connection_type: "P".into(),
+            wait_port: None,
+            skip_connect_probe: true,
+        };
+        let download = client
+            .prepare_candidate_download(&candidate, &request)
+            .await
+            .expect("prepare download");
+        // Only the preparation needs the session; the transfer runs without it.
+        drop(client);
+        let (progress_tx, mut progress_rx) = tokio::sync::mpsc::unbounded_channel();
+        let result = with_transfer_progress(progress_tx, download.run())
+            .await
+            .expect("download result");
+
+        let mut last_progress = None;
+        while let Ok(event) = progress_rx.try_recv() {
+            last_progress = Some(event);
+        }
+        assert_eq!(
+            last_progress,
+            Some(TransferProgress::Receiving { bytes: 7, total: 7 })
+        );
+        assert_eq!(result.selected_username, "peer_boards");
+        assert_eq!(result.selected_virtual_path, "Music\\Boards\\Roygbiv.flac");
+        assert_eq!(result.bytes_written, 7);
+        let written = fs::read(&result.output_path).await.expect("read output");
+        assert_eq!(written, b"roygbiv");
+
+        let _ = fs::remove_file(output).await;
+        server_task.await.expect("join server task");
+        peer_task.await.expect("join peer task");
+    }
+
+    #[test]
+    fn transfer_meter_reports_queue_place_speed_and_eta() {
+        let start = Instant::now();
+        let mut meter = TransferMeter::new(0);
+        meter.record(TransferProgress::Queued { place: Some(4) }, start);
+        assert!(meter.queued);
+        assert_eq!(meter.queue_place, Some(4));
+        assert_eq!(meter.ratio(), None);
+
+        meter.record(
+            TransferProgress::Receiving {
+                bytes: 0,
+                total: 4_000,
+            },
+            start + Duration::from_secs(10),
+        );
+        assert!(!meter.queued);
+        assert_eq!(meter.bytes_per_sec(), None);
+
+        meter.record(
+            TransferProgress::Receiving {
+                bytes: 1_000,
+                total: 4_000,
+            },
+            start + Duration::from_secs(12),
+        );
+        assert_eq!(meter.ratio(), Some(0.25));
+        assert_eq!(meter.bytes_per_sec(), Some(500));
+        assert_eq!(meter.eta(), Some(Duration::from_secs(6)));
+    }
+
+    #[tokio::test]
+    async fn login_and_search_send_expected_codes() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("addr");
+
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.expect("accept");
+            let first = read_frame(&mut socket).await.expect("first");
+            let login_response = login_success_frame();
+            write_frame(&mut socket, &login_response)
+                .await
+                .expect("write login response");
+            let second = read_frame(&mut socket).await.expect("second");
+            (first.code, second.code)
+        });
+
+        let mut client = SessionClient::connect(&addr.to_string())
+            .await
+            .expect("connect");
+        client
+            .login(&Credentials {
+                username: "alice".into(),
+                password: "secret-pass".into(),
+                client_version: 157,
+                minor_version: 19,
+            })
+            .await
+            .expect("login");
+        client.search(12345, "aphex twin").await.expect("search");
+
+        let (first_code, second_code) = server.await.expect("server task");
+        assert_eq!(first_code, CODE_SM_LOGIN);
+        assert_eq!(second_code, CODE_SM_FILE_SEARCH);
+    }
+
+    #[tokio::test]
+    async fn scoped_searches_send_room_and_user_requests_and_collect_candidates() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("addr");
+
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.expect("accept");
+            let _login = read_frame(&mut socket).await.expect("login frame");
+            write_frame(&mut socket, &login_success_frame())
+                .await
+                .expect("write login response");
+
+            let mut requests = Vec::new();
+            for _ in 0..3 {
+                let frame = read_frame(&mut socket).await.expect("search frame");
+                requests.push((
+                    frame.code,
+                    decode_server_message(frame.code, &frame.payload).expect("decode search"),
+                ));
+            }
+
+            let summary = encode_server_message(&ServerMessage::FileSearchResponseSummary(
+                SearchResponseSummary {
+                    username: "bob".into(),
+                    token: 0,
+                    files_count: 1,
+                    slots_free: 1,
+                    speed: 100,
+                    in_queue: false,
+                    files: vec![SearchFileSummary {
+                        file_path: "Music\\Boards\\Roygbiv.flac".into(),
+                        file_size: 42,
+                        extension: "flac".into(),
+                        attr_count: 0,
+                    }],
+                },
+            ));
+            write_frame(&mut socket, &summary)
+                .await
+                .expect("write summary");
+            requests
+        });
+
+        let mut client = SessionClient::connect(&addr.to_string())
+            .await
+            .expect("connect");
+        client
+            .login(&Credentials {
+                username: "alice".into(),
+                password: "secret-pass".into(),
+                client_version: 157,
+                minor_version: 19,
+            })
+            .await
+            .expect("login");
+        client
+            .search_scoped(1, &SearchScope::Room("indie".into()), "roygbiv")
+            .await
+            .expect("room search");
+        let candidates = client
+            .search_collect_scoped_candidates(
+                2,
+                &SearchScope::Users(vec!["bob".into(), "carol".into()]),
+                "roygbiv",
+                Duration::from_millis(250),
+                4,
+                SearchMode::Summary,
+                None,
+                "P",
+            )
+            .await
+            .expect("user search");
+
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].username, "bob");
+        assert_eq!(candidates[0].source, SearchResultSource::ServerSummary);
+
+        let requests = server.await.expect("server task");
+        assert_eq!(requests[0].0, CODE_SM_SEARCH_ROOM);
+        assert_eq!(
+            requests[0].1,
+            ServerMessage::SearchRoom(protocol::SearchRoomPayload {
+                room: "indie".into(),
+                search_text: "roygbiv".into(),
+            })
+        );
+        assert_eq!(requests[1].0, CODE_SM_SEARCH_USER_FILES);
+        assert_eq!(requests[2].0, CODE_SM_SEARCH_USER_FILES);
+        assert_eq!(
+            requests[2].1,
+            ServerMessage::SearchUserFiles(protocol::SearchUserFilesPayload {
+                username: "carol".into(),
+                search_text: "roygbiv".into(),
+            })
+        );
+    }
+
+    fn wishlist_candidate(username: &str, file_path: &str, file_size: u64) -> SearchCandidate {
+        SearchCandidate {
+            username: username.into(),
+            file_path: file_path.into(),
+            file_size,
+            peer_addr: None,
+            connect_token: None,
+            bitrate: None,
+            source: SearchResultSource::ServerSummary,
+        }
+    }
+
+    #[test]
+    fn auto_away_engages_after_idle_and_lifts_on_activity() {
+        let start = Instant::now();
+        let mut auto_away = AutoAway::new(Some(Duration::from_secs(60)), start);
+        assert_eq!(
+            auto_away.poll(UserPresence::Online, start + Duration::from_secs(59)),
+            None
+        );
+        let idle = start + Duration::from_secs(61);
+        assert_eq!(
+            auto_away.poll(UserPresence::Online, idle),
+            Some(UserPresence::Away)
+        );
+        assert!(auto_away.is_engaged());
+        assert_eq!(auto_away.poll(UserPresence::Away, idle), None);
+        assert_eq!(auto_away.record_activity(idle), Some(UserPresence::Online));
+        assert_eq!(auto_away.record_activity(idle), None);
+
+        // A manual away status is never lifted by input.
+        let later = idle + Duration::from_secs(120);
+        assert_eq!(auto_away.poll(UserPresence::Away, later), None);
+        assert_eq!(auto_away.record_activity(later), None);
+
+        let mut disabled = AutoAway::new(Some(Duration::ZERO), start);
+        assert_eq!(disabled.poll(UserPresence::Online, later), None);
+    }
+
+    #[tokio::test]
+    async fn away_status_is_sent_and_restored_after_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("addr");
+
+        let server = tokio::spawn(async move {
+            let mut statuses = Vec::new();
+            for _ in 0..2 {
+                let (mut socket, _) = listener.accept().await.expect("accept");
+                let _login = read_frame(&mut socket).await.expect("login frame");
+                write_frame(&mut socket, &login_success_frame())
+                    .await
+                    .expect("write login response");
+                let frame = read_frame(&mut socket).await.expect("status frame");
+                assert_eq!(frame.code, protocol::CODE_SM_SET_STATUS);
+                let ServerMessage::SetStatus(payload) =
+                    decode_server_message(frame.code, &frame.payload).expect("decode status")
+                else {
+                    panic!("expected set status");
+                };
+                statuses.push(payload.status);
+            }
+            statuses
+        });
+
+        let credentials = Credentials {
+            username: "alice".into(),
+            password: "secret-pass".into(),
+            client_version: 157,
+            minor_version: 19,
+        };
+        let mut client = SessionClient::connect(&addr.to_string())
+            .await
+            .expect("connect");
+        assert!(client.set_status(UserPresence::Away).await.is_err());
+        client.login(&credentials).await.expect("login");
+        assert!(client.set_status(UserPresence::Offline).await.is_err());
+        client
+            .set_status(UserPresence::Away)
+            .await
+            .expect("set away");
+        client
+            .reconnect(&addr.to_string(), &credentials)
+            .await
+            .expect("reconnect");
+        assert_eq!(client.status(), UserPresence::Away);
+
+        assert_eq!(server.await.expect("server task"), vec![1, 1]);
+    }
+
+    #[test]
+    fn wishlist_scheduler_cycles_items_and_reports_only_new_filtered_matches() {
+        let start = Instant::now();
+        let items = vec![
+            WishlistItem {
+                query: "flim".into(),
+                filter: WishlistFilter {
+                    min_file_size: Some(10),
+                    extensions: vec![".flac".into()],
+                    excluded_terms: vec!["live".into()],
+                    ..WishlistFilter::default()
+                },
+                auto_download: true,
+            },
+            WishlistItem {
+                query: "roygbiv".into(),
+                filter: WishlistFilter::default(),
+                auto_download: false,
+            },
+        ];
+        let mut scheduler = WishlistScheduler::new(start);
+
+        assert_eq!(scheduler.take_due(&items, start), Some(0));
+        assert_eq!(scheduler.take_due(&items, start), None);
+        scheduler.set_interval(Duration::from_secs(60));
+        assert_eq!(scheduler.next_due(), start + Duration::from_secs(60));
+        assert_eq!(
+            scheduler.take_due(&items, start + Duration::from_secs(60)),
+            Some(1)
+        );
+        assert_eq!(
+            scheduler.take_due(&items, start + Duration::from_secs(120)),
+            Some(0)
+        );
+
+        let candidates = vec![
+            wishlist_candidate("bob", "Music\\Flim.flac", 100),
+            wishlist_candidate("bob", "Music\\Flim.mp3", 100),
+            wishlist_candidate("bob", "Music\\Flim (Live).flac", 100),
+            wishlist_candidate("carol", "Music\\Flim.flac", 5),
+        ];
+        let accepted = scheduler.accept_results(&items[0], &candidates);
+        assert_eq!(accepted, vec![candidates[0].clone()]);
+        assert!(scheduler.accept_results(&items[0], &candidates).is_empty());
+    }
+
+    #[tokio::test]
+    async fn wishlist_search_uses_low_priority_code_and_server_interval() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("addr");
+
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.expect("accept");
+            let _login = read_frame(&mut socket).await.expect("login frame");
+            write_frame(&mut socket, &login_success_frame())
+                .await
+                .expect("write login response");
+            let search = read_frame(&mut socket).await.expect("wishlist frame");
+
+            let wait = encode_server_message(&ServerMessage::WishlistWait(
+                protocol::WishlistWaitPayload {
+                    interval_seconds: 30,
+                },
+            ));
+            write_frame(&mut socket, &wait).await.expect("write wait");
+            let summary = encode_server_message(&ServerMessage::FileSearchResponseSummary(
+                SearchResponseSummary {
+                    username: "bob".into(),
+                    token: 77,
+                    files_count: 1,
+                    slots_free: 1,
+                    speed: 100,
+                    in_queue: false,
+                    files: vec![SearchFileSummary {
+                        file_path: "Music\\Flim.flac".into(),
+                        file_size: 42,
+                        extension: "flac".into(),
+                        attr_count: 0,
+                    }],
+                },
+            ));
+            write_frame(&mut socket, &summary)
+                .await
+                .expect("write summary");
+            search.code
+        });
+
+        let mut client = SessionClient::connect(&addr.to_string())
+            .await
+            .expect("connect");
+        client
+            .login(&Credentials {
+                username: "alice".into(),
+                password: "secret-pass".into(),
+                client_version: 157,
+                minor_version: 19,
+            })
+            .await
+            .expect("login");
+
+        let item = WishlistItem {
+            query: "flim".into(),
+            filter: WishlistFilter::default(),
+            auto_download: true,
+        };
+        let mut scheduler = WishlistScheduler::new(Instant::now());
+        let matches = client
+            .run_wishlist_search(&mut scheduler, &item, 77, Duration::from_millis(250), 4)
+            .await
+            .expect("wishlist search");
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].file_path, "Music\\Flim.flac");
+        assert_eq!(client.wishlist_interval(), Some(Duration::from_secs(30)));
+        assert_eq!(scheduler.interval(), Duration::from_secs(30));
+        assert_eq!(
+            server.await.expect("server task"),
+            protocol::CODE_SM_LOW_PRIORITY_FILE_SEARCH
+        );
+    }
+
+    #[test]
+    fn buddy_list_tracks_status_changes_and_persists_only_buddies() {
+        let mut buddies = BuddyList::new();
+        assert!(buddies.upsert("bob", Some("flac collector"), Some("friends")));
+        assert!(!buddies.upsert("bob", None, Some("trusted")));
+        assert!(buddies.upsert("carol", None, None));
+        assert_eq!(
+            buddies.get("bob").map(|b| b.note.as_str()),
+            Some("flac collector")
+        );
+        assert_eq!(buddies.groups(), vec!["trusted".to_string()]);
+
+        let online = ServerMessage::GetUserStatusResponse(UserStatusResponsePayload {
+            username: "bob".into(),
+            status: 2,
+            privileged: false,
+        });
+        assert_eq!(
+            buddies.apply(&online),
+            vec![BuddyEvent::StatusChanged {
+                username: "bob".into(),
+                previous: None,
+                presence: UserPresence::Online,
+                privileged: false,
+            }]
+        );
+        assert!(buddies.apply(&online).is_empty());
+
+        let stranger = ServerMessage::GetUserStatusResponse(UserStatusResponsePayload {
+            username: "mallory".into(),
+            status: 2,
+            privileged: false,
+        });
+        assert!(buddies.apply(&stranger).is_empty());
+
+        let missing = ServerMessage::AddUserResponse(protocol::AddUserResponsePayload {
+            username: "carol".into(),
+            exists: false,
+            stats: None,
+        });
+        assert_eq!(
+            buddies.apply(&missing),
+            vec![BuddyEvent::NotFound {
+                username: "carol".into()
+            }]
+        );
+
+        let json = serde_json::to_string(&buddies).expect("serialize buddies");
+        assert!(!json.contains("presence"));
+        let restored: BuddyList = serde_json::from_str(&json).expect("parse buddies");
+        assert_eq!(
+            restored.usernames(),
+            vec!["bob".to_string(), "carol".to_string()]
+        );
+        assert!(restored.status("bob").is_none());
+    }
+
+    fn incoming_message(message_id: u32, username: &str, text: &str) -> MessageUserIncomingPayload {
+        MessageUserIncomingPayload {
+            message_id,
+            timestamp: 1_705_000_000 + message_id,
+            username: username.into(),
+            message: text.into(),
+            is_new: false,
+        }
+    }
+
+    #[test]
+    fn chat_store_threads_count_unread_and_search_history() {
+        let mut store = ChatStore::new();
+        assert!(store.record_incoming(&incoming_message(1, "bob", "Got the Flim rip?")));
+        assert!(!store.record_incoming(&incoming_message(1, "bob", "Got the Flim rip?")));
+        store.record_outgoing("carol", "see you later", 1_705_000_500);
+        assert!(!store.record_incoming(&incoming_message(1, "bob", "Got the Flim rip?")));
+        assert_eq!(
+            store.threads()[0].username,
+            "carol",
+            "redelivery keeps thread order"
+        );
+        assert!(store.record_incoming(&incoming_message(2, "bob", "flim is up")));
+
+        let users: Vec<&str> = store
+            .threads()
+            .iter()
+            .map(|t| t.username.as_str())
+            .collect();
+        assert_eq!(users, vec!["bob", "carol"]);
+        assert_eq!(store.unread("bob"), 2);
+        assert_eq!(store.unread("carol"), 0);
+        assert_eq!(store.total_unread(), 2);
+        assert!(store.thread("bob").expect("bob thread").messages[0].offline);
+
+        let hits = store.search("FLIM");
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().all(|hit| hit.username == "bob"));
+        assert!(store.search("  ").is_empty());
+
+        assert_eq!(store.mark_read("bob"), 2);
+        assert_eq!(store.total_unread(), 0);
+
+        let path = std::env::temp_dir().join("neosoulseek-chat-store-test.json");
+        store.save(&path).expect("save chat store");
+        let restored = ChatStore::load(&path).expect("load chat store");
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(restored, store);
+        assert_eq!(
+            restored.thread("carol").expect("carol thread").messages[0].direction,
+            ChatDirection::Outgoing
+        );
+    }
+
+    #[test]
+    fn room_log_rotates_and_exports_history_in_order() {
+        let dir = std::env::temp_dir().join("neosoulseek-room-log-test");
+        let _ = std::fs::remove_dir_all(&dir);
+        let log = RoomLog::new(&dir).with_rotation(120, 1);
+
+        let events = [
+            RoomEvent::UserJoined {
+                room: "indie rock".into(),
+                username: "bob".into(),
+            },
+            RoomEvent::RoomMessage {
+                room: "indie rock".into(),
+                username: Some("bob".into()),
+                message: "anyone got the flim rip?".into(),
+            },
+            RoomEvent::MembersSnapshot(RoomMembersPayload {
+                room: "indie rock".into(),
+                users: vec!["bob".into()],
+            }),
+            RoomEvent::TickerSet {
+                room: "indie rock".into(),
+                username: "bob".into(),
+                ticker: "listening to flim".into(),
+            },
+            RoomEvent::TickerRemoved {
+                room: "indie rock".into(),
+                username: "bob".into(),
+            },
+            RoomEvent::UserLeft {
+                room: "indie rock".into(),
+                username: "bob".into(),
+            },
+        ];
+        let mut written = 0;
+        for (idx, event) in events.iter().enumerate() {
+            written += log
+                .record_event(event, 1_705_000_000 + idx as u64)
+                .expect("record event");
+        }
+        assert_eq!(written, 5);
+        assert!(dir.join("indie_rock.1.jsonl").exists());
+
+        // One rotated file is kept, so the oldest entries are gone.
+        let history = log.history("indie rock").expect("history");
+        assert_eq!(history.len(), 3);
+        assert_eq!(
+            history.last().map(|entry| &entry.kind),
+            Some(&RoomLogKind::Left {
+                username: "bob".into()
+            })
+        );
+        assert!(history.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+
+        let text = log.export("indie rock", RoomLogFormat::Text).expect("text");
+        assert!(text.ends_with("[2024-01-11 19:06:45] * bob left\n"));
+        let json = log.export("indie rock", RoomLogFormat::Json).expect("json");
+        let parsed: Vec<RoomLogEntry> = serde_json::from_str(&json).expect("parse export");
+        assert_eq!(parsed, history);
+        assert!(log.export("jazz", RoomLogFormat::Text).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn offline_private_messages_are_acked_and_stored_after_login() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("addr");
+
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.expect("accept");
+            let _login = read_frame(&mut socket).await.expect("login frame");
+            write_frame(&mut socket, &login_success_frame())
+                .await
+                .expect("write login success");
+            for (id, text) in [(5, "while you were out"), (6, "ping me")] {
+                let incoming = encode_server_message(&ServerMessage::MessageUserIncoming(
+                    incoming_message(id, "bob", text),
+                ));
+                write_frame(&mut socket, &incoming)
+                    .await
+                    .expect("write offline message");
+            }
+
+            let mut acked = Vec::new();
+            for _ in 0..2 {
+                let ack = read_frame(&mut socket).await.expect("ack frame");
+                assert_eq!(ack.code, CODE_SM_MESSAGE_ACKED);
+                acked.push(u32::from_le_bytes(ack.payload[..4].try_into().expect("id")));
+            }
+            let search = read_frame(&mut socket).await.expect("search frame");
+            assert_eq!(search.code, CODE_SM_FILE_SEARCH);
+            acked
+        });
+
+        let mut client = SessionClient::connect(&addr.to_string())
+            .await
+            .expect("connect");
+        client
+            .login(&Credentials {
+                username: "alice".into(),
+                password: "secret-pass".into(),
+                client_version: 160,
+                minor_version: 1,
+            })
+            .await
+            .expect("login");
+
+        // The first offline message is consumed by an unrelated collector; it
+        // must still be acked and reach the store.
+        let mut buddies = BuddyList::new();
+        let _ = client
+            .collect_buddy_events(&mut buddies, Duration::from_millis(200), 1)
+            .await
+            .expect("collect buddy events");
+
+        let mut store = ChatStore::new();
+        let stored = client
+            .collect_private_messages(&mut store, Duration::from_millis(200), 8)
+            .await
+            .expect("collect private messages");
+        assert_eq!(stored.len(), 2);
+        assert_eq!(store.unread("bob"), 2);
+        assert!(client.take_private_messages().is_empty());
+
+        client.search(1, "flim").await.expect("search");
+        assert_eq!(server.await.expect("server task"), vec![5, 6]);
+    }
+
+    #[tokio::test]
+    async fn watch_buddies_sends_add_user_and_collects_status_events() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("addr");
+
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.expect("accept");
+            let _login = read_frame(&mut socket).await.expect("login frame");
+            write_frame(&mut socket, &login_success_frame())
+                .await
+                .expect("write login response");
+            let watch = read_frame(&mut socket).await.expect("add user frame");
+
+            let response = encode_server_message(&ServerMessage::AddUserResponse(
+                protocol::AddUserResponsePayload {
+                    username: "bob".into(),
+                    exists: true,
+                    stats: Some(protocol::WatchedUserStats {
+                        status: 1,
+                        avg_speed: 2048,
+                        upload_num: 3,
+                        files: 120,
+                        dirs: 8,
+                        country_code: None,
+                    }),
+                },
+            ));
+            write_frame(&mut socket, &response)
+                .await
+                .expect("write add user response");
+            let online = encode_server_message(&ServerMessage::GetUserStatusResponse(
+                UserStatusResponsePayload {
+                    username: "bob".into(),
+                    status: 2,
+                    privileged: true,
+                },
+            ));
+            write_frame(&mut socket, &online)
+                .await
+                .expect("write status change");
+            watch
+        });
+
+        let mut client = SessionClient::connect(&addr.to_string())
+            .await
+            .expect("connect");
+        client
+            .login(&Credentials {
+                username: "alice".into(),
+                password: "secret-pass".into(),
+                client_version: 157,
+                minor_version: 19,
+            })
+            .await
+            .expect("login");
+
+        let mut buddies = BuddyList::new();
+        buddies.upsert("bob", None, None);
+        client.watch_buddies(&buddies).await.expect("watch buddies");
+        let events = client
+            .collect_buddy_events(&mut buddies, Duration::from_millis(250), 8)
+            .await
+            .expect("collect buddy events");
+
+        assert_eq!(events.len(), 3);
         assert!(matches!(
-            err,
-            SearchSelectDownloadError::InvalidSearchResultIndex { .. }
+            events[2],
+            BuddyEvent::StatusChanged {
+                previous: Some(UserPresence::Away),
+                presence: UserPresence::Online,
+                privileged: true,
+                ..
+            }
         ));
+        let status = buddies.status("bob").expect("bob status");
+        assert_eq!(status.stats.map(|stats| stats.files), Some(120));
 
-        server.await.expect("server task");
+        let watch = server.await.expect("server task");
+        assert_eq!(watch.code, protocol::CODE_SM_ADD_USER);
     }
 
     #[tokio::test]
-    async fn search_select_and_download_mock_flow_works_for_flim_query() {
-        let server_listener = TcpListener::bind("127.0.0.1:0").await.expect("bind server");
-        let server_addr = server_listener.local_addr().expect("server addr");
-
-        let peer_listener = TcpListener::bind("127.0.0.1:0").await.expect("bind peer");
-        let peer_addr = peer_listener.local_addr().expect("peer addr");
+    async fn collect_session_events_keeps_buddy_room_and_search_events() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("addr");
 
-        let server_task = tokio::spawn(async move {
-            let (mut socket, _) = server_listener.accept().await.expect("accept server");
-            let login = read_frame(&mut socket).await.expect("login frame");
-            assert_eq!(login.code, CODE_SM_LOGIN);
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.expect("accept");
+            let _login = read_frame(&mut socket).await.expect("login frame");
             write_frame(&mut socket, &login_success_frame())
                 .await
                 .expect("write login success");
 
-            let request = read_frame(&mut socket).await.expect("search request");
-            assert_eq!(request.code, CODE_SM_FILE_SEARCH);
-
+            let status = encode_server_message(&ServerMessage::GetUserStatusResponse(
+                UserStatusResponsePayload {
+                    username: "bob".into(),
+                    status: 2,
+                    privileged: false,
+                },
+            ));
+            let said =
+                encode_server_message(&ServerMessage::SayChatRoom(protocol::SayChatRoomPayload {
+                    room: "nicotine".into(),
+                    username: Some("carol".into()),
+                    message: "hello room".into(),
+                }));
+            write_frame(&mut socket, &status)
+                .await
+                .expect("write status");
+            write_frame(&mut socket, &said).await.expect("write say");
             let summary = encode_server_message(&ServerMessage::FileSearchResponseSummary(
                 SearchResponseSummary {
-                    username: "peer_aphex".into(),
-                    token: 9101,
+                    username: "dave".into(),
+                    token: 4242,
                     files_count: 1,
                     slots_free: 1,
-                    speed: 1024,
+                    speed: 900,
                     in_queue: false,
-                    files: vec![SearchFileSummary {
-                        file_path: "Music\\Aphex Twin\\Flim.mp3".into(),
-                        file_size: 6,
-                        extension: "mp3".into(),
+                    files: vec![protocol::SearchFileSummary {
+                        file_path: "Music\\Flim.flac".into(),
+                        file_size: 42,
+                        extension: "flac".into(),
                         attr_count: 0,
                     }],
                 },
@@ -4628,84 +6764,70 @@ mod tests {
                 .expect("write summary");
         });
 
-        let peer_task = tokio::spawn(async move {
-            let (mut socket, _) = peer_listener.accept().await.expect("accept peer");
-            let transfer_request = read_frame(&mut socket).await.expect("transfer request");
-            assert_eq!(transfer_request.code, protocol::CODE_PM_TRANSFER_REQUEST);
-            let parsed = parse_transfer_request(&transfer_request.payload).expect("parse request");
-            assert_eq!(parsed.token, 9202);
-            assert_eq!(parsed.virtual_path, "Music\\Aphex Twin\\Flim.mp3");
-
-            let response = protocol::build_transfer_response(9202, true, "");
-            write_frame(&mut socket, &response)
-                .await
-                .expect("write transfer response");
-            socket.write_all(b"flim!!").await.expect("write payload");
-            socket.shutdown().await.expect("shutdown peer");
-        });
-
-        let mut client = SessionClient::connect(&server_addr.to_string())
+        let mut client = SessionClient::connect(&addr.to_string())
             .await
             .expect("connect");
         client
             .login(&Credentials {
-                username: "fede_test1234".into(),
-                password: "fede1234".into(),
+                username: "alice".into(),
+                password: "secret-pass".into(),
                 client_version: 160,
                 minor_version: 1,
             })
             .await
             .expect("login");
 
-        let output = std::env::temp_dir().join("neosoulseek-flim-mock-test.bin");
-        let request = SearchSelectDownloadRequest {
-            search_token: 9101,
-            query: "aphex twin flim".into(),
-            search_timeout: Duration::from_secs(1),
-            max_messages: 16,
-            result_index: 0,
-            file_index: 0,
-            transfer_token: 9202,
-            output_path: output.clone(),
-            peer_addr_override: Some(peer_addr.to_string()),
-            peer_lookup_timeout: Duration::from_secs(1),
-            connection_type: "P".into(),
-            wait_port: None,
-            skip_connect_probe: true,
-            search_mode: SearchMode::Auto,
-            strict_track: None,
-        };
-        let result = client
-            .search_select_and_download(&request)
+        let mut buddies = BuddyList::new();
+        buddies.upsert("bob", None, None);
+        let events = client
+            .collect_session_events(&mut buddies, Duration::from_millis(300), 8)
             .await
-            .expect("download result");
-
-        assert_eq!(result.selected_username, "peer_aphex");
-        assert_eq!(result.selected_virtual_path, "Music\\Aphex Twin\\Flim.mp3");
-        assert_eq!(result.bytes_written, 6);
+            .expect("collect session events");
 
-        let written = fs::read(&result.output_path).await.expect("read output");
-        assert_eq!(written, b"flim!!");
+        assert_eq!(events.len(), 3);
+        assert!(matches!(
+            &events[0],
+            SessionEvent::Buddy(BuddyEvent::StatusChanged {
+                presence: UserPresence::Online,
+                ..
+            })
+        ));
+        assert!(matches!(
+            &events[1],
+            SessionEvent::Room(RoomEvent::RoomMessage { room, message, .. })
+                if room == "nicotine" && message == "hello room"
+        ));
+        assert!(matches!(
+            &events[2],
+            SessionEvent::Search(summary) if summary.token == 4242 && summary.files.len() == 1
+        ));
 
-        let _ = fs::remove_file(output).await;
-        server_task.await.expect("join server task");
-        peer_task.await.expect("join peer task");
+        server.await.expect("server task");
     }
 
     #[tokio::test]
-    async fn login_and_search_send_expected_codes() {
+    async fn session_reader_resumes_a_frame_after_a_cancelled_read() {
         let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
         let addr = listener.local_addr().expect("addr");
+        let (release_tx, release_rx) = tokio::sync::oneshot::channel::<()>();
 
         let server = tokio::spawn(async move {
             let (mut socket, _) = listener.accept().await.expect("accept");
-            let first = read_frame(&mut socket).await.expect("first");
-            let login_response = login_success_frame();
-            write_frame(&mut socket, &login_response)
+            let _login = read_frame(&mut socket).await.expect("login frame");
+            write_frame(&mut socket, &login_success_frame())
                 .await
-                .expect("write login response");
-            let second = read_frame(&mut socket).await.expect("second");
-            (first.code, second.code)
+                .expect("write login success");
+            let said =
+                encode_server_message(&ServerMessage::SayChatRoom(protocol::SayChatRoomPayload {
+                    room: "nicotine".into(),
+                    username: Some("carol".into()),
+                    message: "split across segments".into(),
+                }))
+                .encode();
+            socket.write_all(&said[..6]).await.expect("write head");
+            socket.flush().await.expect("flush head");
+            release_rx.await.expect("release");
+            socket.write_all(&said[6..]).await.expect("write tail");
         });
 
         let mut client = SessionClient::connect(&addr.to_string())
@@ -4715,16 +6837,34 @@ mod tests {
             .login(&Credentials {
                 username: "alice".into(),
                 password: "secret-pass".into(),
-                client_version: 157,
-                minor_version: 19,
+                client_version: 160,
+                minor_version: 1,
             })
             .await
             .expect("login");
-        client.search(12345, "aphex twin").await.expect("search");
 
-        let (first_code, second_code) = server.await.expect("server task");
-        assert_eq!(first_code, CODE_SM_LOGIN);
-        assert_eq!(second_code, CODE_SM_FILE_SEARCH);
+        let mut reader = client.take_reader().expect("reader");
+        assert!(client.read_next_frame().await.is_err());
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), reader.read_frame())
+                .await
+                .is_err()
+        );
+        release_tx.send(()).expect("release tail");
+        client.restore_reader(reader);
+
+        let mut buddies = BuddyList::new();
+        let events = client
+            .collect_session_events(&mut buddies, Duration::from_millis(500), 1)
+            .await
+            .expect("collect session events");
+        assert!(matches!(
+            &events[..],
+            [SessionEvent::Room(RoomEvent::RoomMessage { message, .. })]
+                if message == "split across segments"
+        ));
+
+        server.await.expect("server task");
     }
 
     #[tokio::test]
@@ -5007,6 +7147,8 @@ mod tests {
                 encode_server_message(&ServerMessage::RoomList(RoomListPayload {
                     room_count: 2,
                     rooms: vec!["nicotine".into(), "electronic".into()],
+                    user_counts: vec![42, 7],
+                    private_rooms: None,
                 }));
             write_frame(&mut socket, &room_list_frame)
                 .await
@@ -5032,6 +7174,7 @@ mod tests {
             .expect("list rooms");
         assert_eq!(room_list.room_count, 2);
         assert_eq!(room_list.rooms.len(), 2);
+        assert_eq!(room_list.user_counts, vec![42, 7]);
 
         server.await.expect("server task");
     }
@@ -5560,6 +7703,16 @@ mod tests {
             write_frame(&mut socket, &left)
                 .await
                 .expect("write left event");
+            let ticker = encode_server_message(&ServerMessage::RoomTickerUserAdded(
+                protocol::RoomTickerUserAddedPayload {
+                    room: "nicotine".into(),
+                    username: "bob".into(),
+                    ticker: "afk".into(),
+                },
+            ));
+            write_frame(&mut socket, &ticker)
+                .await
+                .expect("write ticker event");
         });
 
         let mut client = SessionClient::connect(&addr.to_string())
@@ -5575,8 +7728,11 @@ mod tests {
             .await
             .expect("login");
 
+        let dir = std::env::temp_dir().join("neosoulseek-room-presence-log-test");
+        let _ = std::fs::remove_dir_all(&dir);
+        let log = RoomLog::new(&dir);
         let events = client
-            .collect_room_events(Duration::from_millis(400), 4)
+            .collect_room_events_logged(&log, Duration::from_millis(400), 4)
             .await
             .expect("collect room events");
         assert!(
@@ -5584,6 +7740,14 @@ mod tests {
                 .iter()
                 .any(|event| matches!(event, RoomEvent::UserJoined { room, username } if room == "nicotine" && username == "bob"))
         );
+        assert!(
+            events
+                .iter()
+                .any(|event| matches!(event, RoomEvent::TickerSet { .. }))
+        );
+        let history = log.history("nicotine").expect("room history");
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(history.len(), 3);
         assert!(
             events
                 .iter()
@@ -6372,6 +8536,275 @@ mod tests {
         assert_eq!(result.bytes_sent, 0);
     }
 
+    #[tokio::test]
+    async fn upload_agent_serves_user_info_from_profile_and_scheduler() {
+        let mut scheduler = UploadScheduler::new(1);
+        scheduler.enqueue();
+        scheduler.enqueue();
+        assert!(scheduler.start());
+        assert!(!scheduler.start());
+        scheduler.finish(true);
+        assert_eq!(scheduler.queue_size(), 1);
+        assert_eq!(scheduler.free_slots(), 1);
+        assert_eq!(scheduler.total_uploads(), 1);
+
+        let profile = UserProfile {
+            description: "lossless only".into(),
+            picture: Some(vec![0x89, b'P', b'N', b'G']),
+        };
+        let agent = UploadAgent::bind_manual("127.0.0.1:0")
+            .await
+            .expect("bind agent")
+            .with_profile(profile)
+            .with_scheduler(scheduler);
+        let addr = agent.local_addr().expect("local addr");
+
+        let task = tokio::spawn(async move {
+            agent
+                .serve_single_manual(
+                    ManualUploadDecision {
+                        decision: UploadDecisionKind::Deny,
+                        reason: String::new(),
+                    },
+                    None,
+                )
+                .await
+                .expect("serve")
+        });
+
+        let mut client = TcpStream::connect(addr).await.expect("connect agent");
+        write_frame(&mut client, &build_user_info_request())
+            .await
+            .expect("write user info request");
+        let reply = read_frame(&mut client).await.expect("read user info reply");
+        let Ok(PeerMessage::UserInfoReply(reply)) = decode_peer_message(reply.code, &reply.payload)
+        else {
+            panic!("expected user info reply");
+        };
+        assert_eq!(reply.description, "lossless only");
+        assert!(reply.has_picture);
+        assert_eq!(reply.picture, vec![0x89, b'P', b'N', b'G']);
+        assert_eq!(reply.total_uploads, 1);
+        assert_eq!(reply.queue_size, 1);
+        assert!(reply.slots_free);
+
+        let req = protocol::build_transfer_request(
+            TransferDirection::Download,
+            405,
+            "Music\\later.flac",
+            10,
+        );
+        write_frame(&mut client, &req).await.expect("write req");
+        let response = read_frame(&mut client).await.expect("read response");
+        let response = parse_transfer_response(&response.payload).expect("parse response");
+        assert!(!response.allowed);
+
+        let result = task.await.expect("join task");
+        assert_eq!(result.user_info_served, 1);
+    }
+
+    #[tokio::test]
+    async fn request_user_info_sends_peer_init_and_reads_reply() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind peer");
+        let addr = listener.local_addr().expect("peer addr");
+
+        let peer = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.expect("accept");
+            let init = read_peer_init_payload(&mut socket)
+                .await
+                .expect("peer init");
+            assert_eq!(init.username, "alice");
+            assert_eq!(init.connection_type, "P");
+            let request = read_frame(&mut socket).await.expect("user info request");
+            assert_eq!(request.code, protocol::CODE_PM_USER_INFO_REQUEST);
+            let reply = UserProfile {
+                description: "hello".into(),
+                picture: None,
+            }
+            .user_info_reply(&UploadScheduler::new(0));
+            let frame = encode_peer_message(&PeerMessage::UserInfoReply(reply));
+            write_frame(&mut socket, &frame).await.expect("write reply");
+        });
+
+        let reply = request_user_info(&addr.to_string(), "alice", 77, Duration::from_secs(2))
+            .await
+            .expect("user info");
+        assert_eq!(reply.description, "hello");
+        assert!(!reply.has_picture);
+        assert!(!reply.slots_free);
+        peer.await.expect("peer task");
+    }
+
+    #[tokio::test]
+    async fn request_folder_contents_skips_other_folders_and_decodes_entries() {
+        use flate2::{Compression, write::ZlibEncoder};
+        use std::io::Write;
+
+        fn listing_frame(directory: &str, files: &[(&str, u64)]) -> Frame {
+            let mut listing = PayloadWriter::new();
+            listing.write_u32(files.len() as u32);
+            for (path, size) in files {
+                listing.write_string(path);
+                listing.write_u64(*size);
+            }
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder
+                .write_all(&listing.into_inner())
+                .expect("zlib write");
+            encode_peer_message(&PeerMessage::SharedFilesInFolder(
+                protocol::SharedFilesInFolderPayload {
+                    directory: directory.into(),
+                    compressed_listing: encoder.finish().expect("zlib finish"),
+                },
+            ))
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind peer");
+        let addr = listener.local_addr().expect("peer addr");
+        let peer = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.expect("accept");
+            let init = read_peer_init_payload(&mut socket)
+                .await
+                .expect("peer init");
+            assert_eq!(init.connection_type, "P");
+            let request = read_frame(&mut socket).await.expect("folder request");
+            match decode_peer_message(request.code, &request.payload).expect("decode") {
+                PeerMessage::GetSharedFilesInFolder(payload) => {
+                    assert_eq!(payload.directory, "Music\\Album");
+                }
+                other => panic!("unexpected request: {other:?}"),
+            }
+            let other = listing_frame("Music\\Other", &[("x.flac", 1)]);
+            write_frame(&mut socket, &other).await.expect("write other");
+            let frame = listing_frame(
+                "Music\\Album",
+                &[("01.flac", 100), ("Music\\Album\\02.flac", 200)],
+            );
+            write_frame(&mut socket, &frame)
+                .await
+                .expect("write listing");
+        });
+
+        let entries = request_folder_contents(
+            &addr.to_string(),
+            "alice",
+            5,
+            "Music\\Album",
+            Duration::from_secs(2),
+        )
+        .await
+        .expect("folder contents");
+        peer.await.expect("peer task");
+        let paths: Vec<String> = entries
+            .iter()
+            .map(|entry| folder_entry_path("Music\\Album", &entry.virtual_path))
+            .collect();
+        assert_eq!(paths, ["Music\\Album\\01.flac", "Music\\Album\\02.flac"]);
+        assert_eq!(entries[1].size, 200);
+    }
+
+    #[tokio::test]
+    async fn browse_user_fetches_the_file_list_once_and_caches_the_tree() {
+        let server = TcpListener::bind("127.0.0.1:0").await.expect("bind server");
+        let server_addr = server.local_addr().expect("server addr");
+        let peer = TcpListener::bind("127.0.0.1:0").await.expect("bind peer");
+        let peer_port = peer.local_addr().expect("peer addr").port();
+
+        let server_task = tokio::spawn(async move {
+            let (mut socket, _) = server.accept().await.expect("accept");
+            let _login = read_frame(&mut socket).await.expect("login");
+            write_frame(&mut socket, &login_success_frame())
+                .await
+                .expect("login ok");
+            let request = read_frame(&mut socket).await.expect("peer address request");
+            assert_eq!(request.code, CODE_SM_GET_PEER_ADDRESS);
+            let reply = encode_server_message(&ServerMessage::GetPeerAddressResponse(
+                PeerAddressResponsePayload {
+                    username: "bob".into(),
+                    ip_address: "127.0.0.1".into(),
+                    port: u32::from(peer_port),
+                    obfuscation_type: 0,
+                    obfuscated_port: 0,
+                },
+            ));
+            write_frame(&mut socket, &reply)
+                .await
+                .expect("peer address");
+            // Keep the session open until the client is done.
+            let _ = read_frame(&mut socket).await;
+        });
+        let peer_task = tokio::spawn(async move {
+            let (mut socket, _) = peer.accept().await.expect("accept peer");
+            let init = read_peer_init_payload(&mut socket)
+                .await
+                .expect("peer init");
+            assert_eq!(init.username, "alice");
+            let request = read_frame(&mut socket).await.expect("file list request");
+            assert_eq!(request.code, protocol::CODE_PM_GET_SHARED_FILE_LIST);
+            let reply = encode_peer_message(&PeerMessage::SharedFileList(
+                protocol::SharedFileListPayload {
+                    entries: vec![
+                        SharedFileEntry {
+                            virtual_path: "Music\\Album\\01.flac".into(),
+                            size: 10,
+                        },
+                        SharedFileEntry {
+                            virtual_path: "Music\\Album\\02.flac".into(),
+                            size: 20,
+                        },
+                    ],
+                },
+            ));
+            write_frame(&mut socket, &reply).await.expect("file list");
+        });
+
+        let mut client = SessionClient::connect(&server_addr.to_string())
+            .await
+            .expect("connect");
+        client
+            .login(&Credentials {
+                username: "alice".into(),
+                password: "secret".into(),
+                client_version: 160,
+                minor_version: 1,
+            })
+            .await
+            .expect("login");
+        let timeout = Duration::from_secs(2);
+        let tree = client
+            .browse_user("bob", 9, timeout, false)
+            .await
+            .expect("browse");
+        peer_task.await.expect("peer task");
+        let album = tree.root.find("Music\\Album").expect("album");
+        assert_eq!(album.file_count(), 2);
+        assert_eq!(album.total_size(), 30);
+
+        // The peer listener is gone, so only the cache can answer.
+        let cached = client
+            .browse_user("bob", 9, timeout, false)
+            .await
+            .expect("cached");
+        assert_eq!(cached, tree);
+        drop(client);
+        server_task.await.expect("server task");
+    }
+
+    #[test]
+    fn download_paths_keep_the_remote_folder_layout() {
+        assert_eq!(remote_folder("Music\\Album\\01.flac"), "Music\\Album");
+        assert_eq!(remote_folder("loose.mp3"), "");
+        let out = Path::new("/downloads");
+        assert_eq!(
+            local_download_path(out, "@@alice\\Music\\Album\\01.flac"),
+            out.join("Music").join("Album").join("01.flac")
+        );
+        assert_eq!(
+            local_download_path(out, "C:\\..\\Music\\.\\Odd: Name.flac"),
+            out.join("Music").join("Odd_ Name.flac")
+        );
+    }
+
     #[test]
     fn normalize_peer_virtual_path_handles_prefix_and_separators() {
         assert_eq!(
@@ -0,0 +1,142 @@
+use std::future::Future;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::time::{Duration, Instant};
+
+/// Bytes between two `Receiving` reports while a file body is streamed.
+const PROGRESS_REPORT_STEP: u64 = 256 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferProgress {
+    /// The peer put the request in its upload queue; `place` is set when the
+    /// peer told us our position.
+    Queued { place: Option<u32> },
+    /// `total` is 0 when the file size is unknown.
+    Receiving { bytes: u64, total: u64 },
+}
+
+tokio::task_local! {
+    static PROGRESS_SINK: UnboundedSender<TransferProgress>;
+}
+
+/// Runs `future` with transfer progress reported to `sink`. Downloads started
+/// outside such a scope report nothing.
+pub async fn with_transfer_progress<F: Future>(
+    sink: UnboundedSender<TransferProgress>,
+    future: F,
+) -> F::Output {
+    PROGRESS_SINK.scope(sink, future).await
+}
+
+pub(crate) fn report_progress(event: TransferProgress) {
+    let _ = PROGRESS_SINK.try_with(|sink| sink.send(event));
+}
+
+/// Throttles `Receiving` reports for one body read to every
+/// `PROGRESS_REPORT_STEP` bytes plus the final chunk.
+pub(crate) struct ProgressReporter {
+    total: u64,
+    last_reported: u64,
+}
+
+impl ProgressReporter {
+    pub(crate) fn new(total: u64) -> Self {
+        report_progress(TransferProgress::Receiving { bytes: 0, total });
+        Self {
+            total,
+            last_reported: 0,
+        }
+    }
+
+    pub(crate) fn update(&mut self, bytes: u64) {
+        let finished = self.total != 0 && bytes >= self.total;
+        if bytes >= self.last_reported + PROGRESS_REPORT_STEP
+            || (finished && bytes > self.last_reported)
+        {
+            self.last_reported = bytes;
+            report_progress(TransferProgress::Receiving {
+                bytes,
+                total: self.total,
+            });
+        }
+    }
+
+    pub(crate) fn finish(&mut self, bytes: u64) {
+        if bytes != self.last_reported {
+            self.last_reported = bytes;
+            report_progress(TransferProgress::Receiving {
+                bytes,
+                total: self.total,
+            });
+        }
+    }
+}
+
+/// Running speed and ETA estimate for one transfer, fed with progress events.
+#[derive(Debug, Clone)]
+pub struct TransferMeter {
+    pub bytes: u64,
+    pub total: u64,
+    pub queue_place: Option<u32>,
+    pub queued: bool,
+    receiving_since: Option<(Instant, u64)>,
+    last_update: Option<Instant>,
+}
+
+impl TransferMeter {
+    pub fn new(total: u64) -> Self {
+        Self {
+            bytes: 0,
+            total,
+            queue_place: None,
+            queued: false,
+            receiving_since: None,
+            last_update: None,
+        }
+    }
+
+    pub fn record(&mut self, event: TransferProgress, now: Instant) {
+        match event {
+            TransferProgress::Queued { place } => {
+                self.queued = true;
+                self.queue_place = place;
+            }
+            TransferProgress::Receiving { bytes, total } => {
+                self.queued = false;
+                self.queue_place = None;
+                if total != 0 {
+                    self.total = total;
+                }
+                if self.receiving_since.is_none() {
+                    self.receiving_since = Some((now, bytes));
+                }
+                self.bytes = bytes;
+                self.last_update = Some(now);
+            }
+        }
+    }
+
+    /// Fraction done in `0.0..=1.0`, or `None` when the size is unknown.
+    pub fn ratio(&self) -> Option<f64> {
+        (self.total != 0).then(|| (self.bytes as f64 / self.total as f64).min(1.0))
+    }
+
+    /// Average receive speed since the first body byte.
+    pub fn bytes_per_sec(&self) -> Option<u64> {
+        let (started, start_bytes) = self.receiving_since?;
+        let elapsed = self.last_update?.saturating_duration_since(started);
+        if elapsed.is_zero() {
+            return None;
+        }
+        let received = self.bytes.saturating_sub(start_bytes);
+        Some((received as f64 / elapsed.as_secs_f64()) as u64)
+    }
+
+    pub fn eta(&self) -> Option<Duration> {
+        let speed = self.bytes_per_sec().filter(|speed| *speed > 0)?;
+        if self.total == 0 {
+            return None;
+        }
+        let remaining = self.total.saturating_sub(self.bytes);
+        Some(Duration::from_secs(remaining.div_ceil(speed)))
+    }
+}
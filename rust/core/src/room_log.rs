@@ -0,0 +1,301 @@
+use crate::RoomEvent;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_ROOM_LOG_MAX_BYTES: u64 = 1024 * 1024;
+pub const DEFAULT_ROOM_LOG_KEEP: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RoomLogKind {
+    Message {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        username: Option<String>,
+        text: String,
+    },
+    Joined {
+        username: String,
+    },
+    Left {
+        username: String,
+    },
+    /// `ticker: None` records a removed ticker.
+    Ticker {
+        username: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ticker: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomLogEntry {
+    pub timestamp: u64,
+    pub room: String,
+    #[serde(flatten)]
+    pub kind: RoomLogKind,
+}
+
+impl RoomLogEntry {
+    /// Log entries for a room event; snapshots other than the ticker list
+    /// are not history and yield nothing.
+    pub fn from_event(event: &RoomEvent, timestamp: u64) -> Vec<Self> {
+        let entry = |room: &str, kind| Self {
+            timestamp,
+            room: room.to_string(),
+            kind,
+        };
+        match event {
+            RoomEvent::UserJoined { room, username } => vec![entry(
+                room,
+                RoomLogKind::Joined {
+                    username: username.clone(),
+                },
+            )],
+            RoomEvent::UserLeft { room, username } => vec![entry(
+                room,
+                RoomLogKind::Left {
+                    username: username.clone(),
+                },
+            )],
+            RoomEvent::RoomMessage {
+                room,
+                username,
+                message,
+            } => vec![entry(
+                room,
+                RoomLogKind::Message {
+                    username: username.clone(),
+                    text: message.clone(),
+                },
+            )],
+            RoomEvent::TickerSnapshot(payload) => payload
+                .entries
+                .iter()
+                .map(|ticker| {
+                    entry(
+                        &payload.room,
+                        RoomLogKind::Ticker {
+                            username: ticker.username.clone(),
+                            ticker: Some(ticker.ticker.clone()),
+                        },
+                    )
+                })
+                .collect(),
+            RoomEvent::TickerSet {
+                room,
+                username,
+                ticker,
+            } => vec![entry(
+                room,
+                RoomLogKind::Ticker {
+                    username: username.clone(),
+                    ticker: Some(ticker.clone()),
+                },
+            )],
+            RoomEvent::TickerRemoved { room, username } => vec![entry(
+                room,
+                RoomLogKind::Ticker {
+                    username: username.clone(),
+                    ticker: None,
+                },
+            )],
+            RoomEvent::MembersSnapshot(_) | RoomEvent::OperatorsSnapshot(_) => Vec::new(),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let stamp = format_utc(self.timestamp);
+        match &self.kind {
+            RoomLogKind::Message { username, text } => {
+                format!(
+                    "[{stamp}] <{}> {text}",
+                    username.as_deref().unwrap_or("server")
+                )
+            }
+            RoomLogKind::Joined { username } => format!("[{stamp}] * {username} joined"),
+            RoomLogKind::Left { username } => format!("[{stamp}] * {username} left"),
+            RoomLogKind::Ticker {
+                username,
+                ticker: Some(ticker),
+            } => format!("[{stamp}] * {username} set ticker: {ticker}"),
+            RoomLogKind::Ticker {
+                username,
+                ticker: None,
+            } => format!("[{stamp}] * {username} cleared ticker"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoomLogFormat {
+    Text,
+    Json,
+}
+
+/// Append-only JSON-lines log per room under one directory. The live file is
+/// `<room>.jsonl`; once it reaches `max_bytes` it is rotated to `<room>.1.jsonl`
+/// and so on, keeping at most `keep` rotated files.
+#[derive(Debug, Clone)]
+pub struct RoomLog {
+    dir: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+}
+
+impl RoomLog {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_bytes: DEFAULT_ROOM_LOG_MAX_BYTES,
+            keep: DEFAULT_ROOM_LOG_KEEP,
+        }
+    }
+
+    pub fn with_rotation(mut self, max_bytes: u64, keep: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self.keep = keep;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn record(&self, entry: &RoomLogEntry) -> Result<()> {
+        fs::create_dir_all(&self.dir).with_context(|| format!("create {}", self.dir.display()))?;
+        let path = self.file_path(&entry.room, 0);
+        let size = fs::metadata(&path).map(|meta| meta.len()).unwrap_or(0);
+        if size > 0 && size >= self.max_bytes {
+            self.rotate(&entry.room)?;
+        }
+
+        let mut line = serde_json::to_string(entry).context("serialize room log entry")?;
+        line.push('\n');
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("open room log: {}", path.display()))?;
+        file.write_all(line.as_bytes())
+            .with_context(|| format!("write room log: {}", path.display()))
+    }
+
+    /// Records every loggable entry of `event` and returns how many were written.
+    pub fn record_event(&self, event: &RoomEvent, timestamp: u64) -> Result<usize> {
+        let entries = RoomLogEntry::from_event(event, timestamp);
+        for entry in &entries {
+            self.record(entry)?;
+        }
+        Ok(entries.len())
+    }
+
+    /// Full history of a room, oldest first, across rotated files.
+    pub fn history(&self, room: &str) -> Result<Vec<RoomLogEntry>> {
+        let mut entries = Vec::new();
+        for generation in (0..=self.keep).rev() {
+            let path = self.file_path(room, generation);
+            if !path.exists() {
+                continue;
+            }
+            let raw = fs::read_to_string(&path)
+                .with_context(|| format!("read room log: {}", path.display()))?;
+            for (idx, line) in raw.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let entry: RoomLogEntry = serde_json::from_str(line)
+                    .with_context(|| format!("parse room log: {}:{}", path.display(), idx + 1))?;
+                if entry.room == room {
+                    entries.push(entry);
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    pub fn export(&self, room: &str, format: RoomLogFormat) -> Result<String> {
+        let entries = self.history(room)?;
+        if entries.is_empty() {
+            bail!("no log for room {room} in {}", self.dir.display());
+        }
+        match format {
+            RoomLogFormat::Text => Ok(entries.iter().map(|entry| entry.to_text() + "\n").collect()),
+            RoomLogFormat::Json => {
+                serde_json::to_string_pretty(&entries).context("serialize room history")
+            }
+        }
+    }
+
+    fn rotate(&self, room: &str) -> Result<()> {
+        if self.keep == 0 {
+            let path = self.file_path(room, 0);
+            return fs::remove_file(&path)
+                .with_context(|| format!("truncate room log: {}", path.display()));
+        }
+        let oldest = self.file_path(room, self.keep);
+        if oldest.exists() {
+            fs::remove_file(&oldest)
+                .with_context(|| format!("drop room log: {}", oldest.display()))?;
+        }
+        for generation in (0..self.keep).rev() {
+            let from = self.file_path(room, generation);
+            if from.exists() {
+                let to = self.file_path(room, generation + 1);
+                fs::rename(&from, &to)
+                    .with_context(|| format!("rotate room log: {}", from.display()))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn file_path(&self, room: &str, generation: usize) -> PathBuf {
+        let stem: String = room
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let name = match generation {
+            0 => format!("{stem}.jsonl"),
+            n => format!("{stem}.{n}.jsonl"),
+        };
+        self.dir.join(name)
+    }
+}
+
+pub(crate) fn unix_now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// `YYYY-MM-DD HH:MM:SS` in UTC for a unix timestamp.
+fn format_utc(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let secs = timestamp % 86_400;
+    // Howard Hinnant's days-to-civil conversion.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60
+    )
+}
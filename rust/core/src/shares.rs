@@ -0,0 +1,216 @@
+use protocol::SharedFileEntry;
+use std::collections::HashMap;
+use tokio::time::{Duration, Instant};
+
+use crate::{SearchCandidate, SearchResultSource};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedFile {
+    pub name: String,
+    /// Full virtual path as the peer shares it; used to request the file.
+    pub path: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SharedDir {
+    pub name: String,
+    /// Path components joined with `\`; empty for the root.
+    pub path: String,
+    pub dirs: Vec<SharedDir>,
+    pub files: Vec<SharedFile>,
+}
+
+impl SharedDir {
+    pub fn file_count(&self) -> usize {
+        self.files.len() + self.dirs.iter().map(SharedDir::file_count).sum::<usize>()
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.files.iter().map(|file| file.size).sum::<u64>()
+            + self.dirs.iter().map(SharedDir::total_size).sum::<u64>()
+    }
+
+    /// Looks up a directory below this one by a `\` or `/` separated path.
+    pub fn find(&self, path: &str) -> Option<&SharedDir> {
+        path_components(path).try_fold(self, |dir, name| {
+            dir.dirs.iter().find(|child| child.name == name)
+        })
+    }
+
+    /// Every file in this directory and below, depth first.
+    pub fn walk_files(&self) -> Vec<&SharedFile> {
+        let mut files: Vec<&SharedFile> = self.files.iter().collect();
+        for dir in &self.dirs {
+            files.extend(dir.walk_files());
+        }
+        files
+    }
+
+    fn child_mut(&mut self, name: &str) -> &mut SharedDir {
+        let idx = match self.dirs.iter().position(|child| child.name == name) {
+            Some(idx) => idx,
+            None => {
+                let path = if self.path.is_empty() {
+                    name.to_owned()
+                } else {
+                    format!("{}\\{name}", self.path)
+                };
+                self.dirs.push(SharedDir {
+                    name: name.to_owned(),
+                    path,
+                    ..SharedDir::default()
+                });
+                self.dirs.len() - 1
+            }
+        };
+        &mut self.dirs[idx]
+    }
+
+    fn sort(&mut self) {
+        self.dirs.sort_by_cached_key(|dir| dir.name.to_lowercase());
+        self.files
+            .sort_by_cached_key(|file| file.name.to_lowercase());
+        self.dirs.iter_mut().for_each(SharedDir::sort);
+    }
+}
+
+/// A user's shared file list arranged as directories, sorted by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareTree {
+    pub username: String,
+    pub root: SharedDir,
+}
+
+impl ShareTree {
+    pub fn from_entries(username: &str, entries: &[SharedFileEntry]) -> Self {
+        let mut root = SharedDir::default();
+        for entry in entries {
+            let mut components: Vec<&str> = path_components(&entry.virtual_path).collect();
+            let Some(name) = components.pop() else {
+                continue;
+            };
+            let dir = components
+                .into_iter()
+                .fold(&mut root, |dir, component| dir.child_mut(component));
+            dir.files.push(SharedFile {
+                name: name.to_owned(),
+                path: entry.virtual_path.clone(),
+                size: entry.size,
+            });
+        }
+        root.sort();
+        Self {
+            username: username.to_owned(),
+            root,
+        }
+    }
+
+    /// A download candidate for `file`; the peer address is looked up again
+    /// when the download starts.
+    pub fn candidate(&self, file: &SharedFile) -> SearchCandidate {
+        SearchCandidate {
+            username: self.username.clone(),
+            file_path: file.path.clone(),
+            file_size: file.size,
+            peer_addr: None,
+            connect_token: None,
+            bitrate: None,
+            source: SearchResultSource::ServerSummary,
+        }
+    }
+}
+
+/// Share trees fetched this session, kept for `ttl` so browsing the same user
+/// again does not ask the peer for the whole list.
+#[derive(Debug)]
+pub struct ShareCache {
+    ttl: Duration,
+    trees: HashMap<String, (Instant, ShareTree)>,
+}
+
+impl ShareCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            trees: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, username: &str, now: Instant) -> Option<&ShareTree> {
+        self.trees
+            .get(username)
+            .filter(|(fetched, _)| now.saturating_duration_since(*fetched) < self.ttl)
+            .map(|(_, tree)| tree)
+    }
+
+    pub fn insert(&mut self, tree: ShareTree, now: Instant) {
+        self.trees.insert(tree.username.clone(), (now, tree));
+    }
+
+    pub fn forget(&mut self, username: &str) {
+        self.trees.remove(username);
+    }
+}
+
+fn path_components(path: &str) -> impl Iterator<Item = &str> {
+    path.split(['\\', '/'])
+        .filter(|component| !component.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, size: u64) -> SharedFileEntry {
+        SharedFileEntry {
+            virtual_path: path.into(),
+            size,
+        }
+    }
+
+    #[test]
+    fn entries_build_a_sorted_tree() {
+        let tree = ShareTree::from_entries(
+            "bob",
+            &[
+                entry("Music\\Zed\\b.flac", 2),
+                entry("Music\\Alpha\\a.flac", 1),
+                entry("Music\\Alpha\\CD1\\c.flac", 3),
+                entry("readme.txt", 4),
+                entry("Music/Zed/a.flac", 5),
+            ],
+        );
+        assert_eq!(tree.root.file_count(), 5);
+        assert_eq!(tree.root.total_size(), 15);
+        assert_eq!(tree.root.files[0].name, "readme.txt");
+
+        let music = tree.root.find("Music").expect("music dir");
+        let names: Vec<&str> = music.dirs.iter().map(|dir| dir.name.as_str()).collect();
+        assert_eq!(names, ["Alpha", "Zed"]);
+        let zed = tree.root.find("Music/Zed").expect("zed dir");
+        assert_eq!(zed.path, "Music\\Zed");
+        assert_eq!(zed.files[0].path, "Music/Zed/a.flac");
+
+        let alpha = tree.root.find("Music\\Alpha").expect("alpha dir");
+        let paths: Vec<&str> = alpha
+            .walk_files()
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect();
+        assert_eq!(paths, ["Music\\Alpha\\a.flac", "Music\\Alpha\\CD1\\c.flac"]);
+        assert!(tree.root.find("Music\\Missing").is_none());
+        assert_eq!(tree.candidate(&alpha.files[0]).username, "bob");
+    }
+
+    #[test]
+    fn cache_expires_and_forgets() {
+        let now = Instant::now();
+        let mut cache = ShareCache::new(Duration::from_secs(60));
+        cache.insert(ShareTree::from_entries("bob", &[entry("a.mp3", 1)]), now);
+        assert!(cache.get("bob", now + Duration::from_secs(59)).is_some());
+        assert!(cache.get("bob", now + Duration::from_secs(60)).is_none());
+        cache.forget("bob");
+        assert!(cache.get("bob", now).is_none());
+    }
+}
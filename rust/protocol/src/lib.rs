@@ -5,6 +5,8 @@ use std::io::Read;
 use std::net::Ipv4Addr;
 use thiserror::Error;
 
+pub mod samples;
+
 pub const CODE_SM_LOGIN: u32 = 1;
 pub const CODE_SM_SET_WAIT_PORT: u32 = 2;
 pub const CODE_SM_GET_PEER_ADDRESS: u32 = 3;
@@ -139,12 +141,316 @@ pub const CODE_PM_UPLOAD_DENIED: u32 = 50;
 pub const CODE_PM_UPLOAD_PLACE_IN_LINE_REQUEST: u32 = 51;
 pub const CODE_PM_NOTHING: u32 = 52;
 
+pub const CODE_DM_PING: u8 = 0;
+pub const CODE_DM_SEARCH_REQUEST: u8 = 3;
+pub const CODE_DM_BRANCH_LEVEL: u8 = 4;
+pub const CODE_DM_BRANCH_ROOT: u8 = 5;
+pub const CODE_DM_CHILD_DEPTH: u8 = 7;
+pub const CODE_DM_EMBEDDED_MESSAGE: u8 = 93;
+
+pub const CODE_PI_PIERCE_FIREWALL: u8 = 0;
+pub const CODE_PI_PEER_INIT: u8 = 1;
+
+/// Every named code each codec dispatches on, so tooling can enumerate the
+/// protocol without parsing this file. Aliases keep their own entry.
+pub const SERVER_MESSAGE_CODES: &[(&str, u32)] = &[
+    ("SM_LOGIN", CODE_SM_LOGIN),
+    ("SM_SET_WAIT_PORT", CODE_SM_SET_WAIT_PORT),
+    ("SM_GET_PEER_ADDRESS", CODE_SM_GET_PEER_ADDRESS),
+    ("SM_ADD_USER", CODE_SM_ADD_USER),
+    ("SM_REMOVE_USER", CODE_SM_REMOVE_USER),
+    ("SM_GET_USER_STATUS", CODE_SM_GET_USER_STATUS),
+    ("SM_ADD_CHATROOM", CODE_SM_ADD_CHATROOM),
+    ("SM_IGNORE_USER", CODE_SM_IGNORE_USER),
+    ("SM_UNIGNORE_USER", CODE_SM_UNIGNORE_USER),
+    ("SM_SAY_CHATROOM", CODE_SM_SAY_CHATROOM),
+    ("SM_JOIN_ROOM", CODE_SM_JOIN_ROOM),
+    ("SM_LEAVE_ROOM", CODE_SM_LEAVE_ROOM),
+    ("SM_USER_JOINED_ROOM", CODE_SM_USER_JOINED_ROOM),
+    ("SM_USER_LEFT_ROOM", CODE_SM_USER_LEFT_ROOM),
+    ("SM_CONNECT_TO_PEER", CODE_SM_CONNECT_TO_PEER),
+    ("SM_MESSAGE_USER", CODE_SM_MESSAGE_USER),
+    ("SM_MESSAGE_ACKED", CODE_SM_MESSAGE_ACKED),
+    ("SM_FILE_SEARCH", CODE_SM_FILE_SEARCH),
+    ("SM_SET_STATUS", CODE_SM_SET_STATUS),
+    ("SM_HEARTBEAT", CODE_SM_HEARTBEAT),
+    ("SM_SEND_CONNECT_TOKEN", CODE_SM_SEND_CONNECT_TOKEN),
+    ("SM_ROOM_LIST", CODE_SM_ROOM_LIST),
+    ("SM_FILE_SEARCH_RESPONSE", CODE_SM_FILE_SEARCH_RESPONSE),
+    ("SM_PRIVILEGED_LIST", CODE_SM_PRIVILEGED_LIST),
+    ("SM_DOWNLOAD_SPEED", CODE_SM_DOWNLOAD_SPEED),
+    ("SM_SHARED_FOLDERS_FILES", CODE_SM_SHARED_FOLDERS_FILES),
+    ("SM_GET_USER_STATS", CODE_SM_GET_USER_STATS),
+    ("SM_RELOGGED", CODE_SM_RELOGGED),
+    ("SM_SEARCH_USER_FILES", CODE_SM_SEARCH_USER_FILES),
+    ("SM_GET_SIMILAR_TERMS", CODE_SM_GET_SIMILAR_TERMS),
+    ("SM_ADD_LIKE_TERM", CODE_SM_ADD_LIKE_TERM),
+    ("SM_REMOVE_LIKE_TERM", CODE_SM_REMOVE_LIKE_TERM),
+    ("SM_GET_RECOMMENDATIONS", CODE_SM_GET_RECOMMENDATIONS),
+    ("SM_GET_MY_RECOMMENDATIONS", CODE_SM_GET_MY_RECOMMENDATIONS),
+    (
+        "SM_GET_GLOBAL_RECOMMENDATIONS",
+        CODE_SM_GET_GLOBAL_RECOMMENDATIONS,
+    ),
+    (
+        "SM_GET_USER_RECOMMENDATIONS",
+        CODE_SM_GET_USER_RECOMMENDATIONS,
+    ),
+    ("SM_COMMAND", CODE_SM_COMMAND),
+    ("SM_PLACE_IN_LINE", CODE_SM_PLACE_IN_LINE),
+    ("SM_PLACE_IN_LINE_RESPONSE", CODE_SM_PLACE_IN_LINE_RESPONSE),
+    ("SM_USER_LIST", CODE_SM_USER_LIST),
+    ("SM_ROOM_ADDED", CODE_SM_ROOM_ADDED),
+    ("SM_ROOM_REMOVED", CODE_SM_ROOM_REMOVED),
+    ("SM_EXACT_FILE_SEARCH", CODE_SM_EXACT_FILE_SEARCH),
+    ("SM_ADMIN_MESSAGE", CODE_SM_ADMIN_MESSAGE),
+    ("SM_GLOBAL_USER_LIST", CODE_SM_GLOBAL_USER_LIST),
+    ("SM_CONNECT_TO_CLIENT", CODE_SM_CONNECT_TO_CLIENT),
+    ("SM_SEND_DISTRIBUTIONS", CODE_SM_SEND_DISTRIBUTIONS),
+    ("SM_NOTE_PARENT", CODE_SM_NOTE_PARENT),
+    ("SM_CHILD_PARENT_MAP", CODE_SM_CHILD_PARENT_MAP),
+    ("SM_SET_PARENT_MIN_SPEED", CODE_SM_SET_PARENT_MIN_SPEED),
+    (
+        "SM_SET_PARENT_SPEED_CONNECTION_RATIO",
+        CODE_SM_SET_PARENT_SPEED_CONNECTION_RATIO,
+    ),
+    (
+        "SM_SET_PARENT_INACTIVITY_BEFORE_DISCONNECT",
+        CODE_SM_SET_PARENT_INACTIVITY_BEFORE_DISCONNECT,
+    ),
+    (
+        "SM_SET_SERVER_INACTIVITY_BEFORE_DISCONNECT",
+        CODE_SM_SET_SERVER_INACTIVITY_BEFORE_DISCONNECT,
+    ),
+    (
+        "SM_NODES_IN_CACHE_BEFORE_DISCONNECT",
+        CODE_SM_NODES_IN_CACHE_BEFORE_DISCONNECT,
+    ),
+    (
+        "SM_SET_SECONDS_BEFORE_PING_CHILDREN",
+        CODE_SM_SET_SECONDS_BEFORE_PING_CHILDREN,
+    ),
+    ("SM_ADD_PRIVILEGED_USER", CODE_SM_ADD_PRIVILEGED_USER),
+    (
+        "SM_GET_OWN_PRIVILEGES_STATUS",
+        CODE_SM_GET_OWN_PRIVILEGES_STATUS,
+    ),
+    ("SM_DNET_MESSAGE", CODE_SM_DNET_MESSAGE),
+    ("SM_CAN_PARENT", CODE_SM_CAN_PARENT),
+    ("SM_POSSIBLE_PARENTS", CODE_SM_POSSIBLE_PARENTS),
+    (
+        "SM_LOW_PRIORITY_FILE_SEARCH",
+        CODE_SM_LOW_PRIORITY_FILE_SEARCH,
+    ),
+    ("SM_WISHLIST_WAIT", CODE_SM_WISHLIST_WAIT),
+    ("SM_GET_RECOMMENDED_USERS", CODE_SM_GET_RECOMMENDED_USERS),
+    (
+        "SM_GET_TERM_RECOMMENDATIONS",
+        CODE_SM_GET_TERM_RECOMMENDATIONS,
+    ),
+    (
+        "SM_GET_RECOMMENDATION_USERS",
+        CODE_SM_GET_RECOMMENDATION_USERS,
+    ),
+    ("SM_GET_ROOM_TICKER", CODE_SM_GET_ROOM_TICKER),
+    ("SM_ROOM_TICKER_USER_ADDED", CODE_SM_ROOM_TICKER_USER_ADDED),
+    (
+        "SM_ROOM_TICKER_USER_REMOVED",
+        CODE_SM_ROOM_TICKER_USER_REMOVED,
+    ),
+    ("SM_SET_TICKER", CODE_SM_SET_TICKER),
+    ("SM_ADD_HATE_TERM", CODE_SM_ADD_HATE_TERM),
+    ("SM_REMOVE_HATE_TERM", CODE_SM_REMOVE_HATE_TERM),
+    ("SM_SEARCH_ROOM", CODE_SM_SEARCH_ROOM),
+    (
+        "SM_GET_USER_PRIVILEGES_STATUS",
+        CODE_SM_GET_USER_PRIVILEGES_STATUS,
+    ),
+    ("SM_GIVE_PRIVILEGE", CODE_SM_GIVE_PRIVILEGE),
+    (
+        "SM_INFORM_USER_OF_PRIVILEGES",
+        CODE_SM_INFORM_USER_OF_PRIVILEGES,
+    ),
+    (
+        "SM_INFORM_USER_OF_PRIVILEGES_ACK",
+        CODE_SM_INFORM_USER_OF_PRIVILEGES_ACK,
+    ),
+    ("SM_DNET_LEVEL", CODE_SM_DNET_LEVEL),
+    ("SM_DNET_GROUP_LEADER", CODE_SM_DNET_GROUP_LEADER),
+    ("SM_DNET_DELIVERY_REPORT", CODE_SM_DNET_DELIVERY_REPORT),
+    ("SM_DNET_CHILD_DEPTH", CODE_SM_DNET_CHILD_DEPTH),
+    ("SM_DNET_RESET", CODE_SM_DNET_RESET),
+    ("SM_UPLOAD_SPEED", CODE_SM_UPLOAD_SPEED),
+    ("SM_FLOOD", CODE_SM_FLOOD),
+    ("SM_BAN_USER", CODE_SM_BAN_USER),
+    ("SM_ADD_ROOM_MEMBER", CODE_SM_ADD_ROOM_MEMBER),
+    ("SM_REMOVE_ROOM_MEMBER", CODE_SM_REMOVE_ROOM_MEMBER),
+    (
+        "SM_REMOVE_OWN_ROOM_MEMBERSHIP",
+        CODE_SM_REMOVE_OWN_ROOM_MEMBERSHIP,
+    ),
+    ("SM_GIVE_UP_ROOM", CODE_SM_GIVE_UP_ROOM),
+    (
+        "SM_TRANSFER_ROOM_OWNERSHIP",
+        CODE_SM_TRANSFER_ROOM_OWNERSHIP,
+    ),
+    ("SM_ADD_ROOM_MEMBERSHIP", CODE_SM_ADD_ROOM_MEMBERSHIP),
+    ("SM_REMOVE_ROOM_MEMBERSHIP", CODE_SM_REMOVE_ROOM_MEMBERSHIP),
+    (
+        "SM_ENABLE_PRIVATE_ROOM_ADD",
+        CODE_SM_ENABLE_PRIVATE_ROOM_ADD,
+    ),
+    ("SM_CHANGE_PASSWORD", CODE_SM_CHANGE_PASSWORD),
+    ("SM_ADD_ROOM_OPERATOR", CODE_SM_ADD_ROOM_OPERATOR),
+    ("SM_REMOVE_ROOM_OPERATOR", CODE_SM_REMOVE_ROOM_OPERATOR),
+    ("SM_ADD_ROOM_OPERATORSHIP", CODE_SM_ADD_ROOM_OPERATORSHIP),
+    (
+        "SM_REMOVE_ROOM_OPERATORSHIP",
+        CODE_SM_REMOVE_ROOM_OPERATORSHIP,
+    ),
+    (
+        "SM_REMOVE_OWN_ROOM_OPERATORSHIP",
+        CODE_SM_REMOVE_OWN_ROOM_OPERATORSHIP,
+    ),
+    ("SM_ROOM_MEMBERS", CODE_SM_ROOM_MEMBERS),
+    ("SM_ROOM_OPERATORS", CODE_SM_ROOM_OPERATORS),
+    ("SM_MESSAGE_USERS", CODE_SM_MESSAGE_USERS),
+    ("SM_JOIN_GLOBAL_ROOM", CODE_SM_JOIN_GLOBAL_ROOM),
+    ("SM_LEAVE_GLOBAL_ROOM", CODE_SM_LEAVE_GLOBAL_ROOM),
+    ("SM_SAY_GLOBAL_ROOM", CODE_SM_SAY_GLOBAL_ROOM),
+    ("SM_SEARCH_CORRELATIONS", CODE_SM_SEARCH_CORRELATIONS),
+    ("SM_PEER_MESSAGE", CODE_SM_PEER_MESSAGE),
+    ("SM_PEER_MESSAGE_ALT", CODE_SM_PEER_MESSAGE_ALT),
+];
+
+pub const PEER_MESSAGE_CODES: &[(&str, u32)] = &[
+    ("PM_SAY", CODE_PM_SAY),
+    ("PM_GET_SHARED_FILE_LIST", CODE_PM_GET_SHARED_FILE_LIST),
+    ("PM_SHARED_FILE_LIST", CODE_PM_SHARED_FILE_LIST),
+    ("PM_FILE_SEARCH_REQUEST", CODE_PM_FILE_SEARCH_REQUEST),
+    ("PM_FILE_SEARCH_RESULT", CODE_PM_FILE_SEARCH_RESULT),
+    ("PM_INVITE_USER_TO_ROOM", CODE_PM_INVITE_USER_TO_ROOM),
+    (
+        "PM_CANCELLED_QUEUED_TRANSFER",
+        CODE_PM_CANCELLED_QUEUED_TRANSFER,
+    ),
+    ("PM_USER_INFO_REQUEST", CODE_PM_USER_INFO_REQUEST),
+    ("PM_USER_INFO_REPLY", CODE_PM_USER_INFO_REPLY),
+    ("PM_SEND_CONNECT_TOKEN", CODE_PM_SEND_CONNECT_TOKEN),
+    ("PM_MOVE_DOWNLOAD_TO_TOP", CODE_PM_MOVE_DOWNLOAD_TO_TOP),
+    (
+        "PM_GET_SHARED_FILES_IN_FOLDER",
+        CODE_PM_GET_SHARED_FILES_IN_FOLDER,
+    ),
+    ("PM_SHARED_FILES_IN_FOLDER", CODE_PM_SHARED_FILES_IN_FOLDER),
+    ("PM_TRANSFER_REQUEST", CODE_PM_TRANSFER_REQUEST),
+    ("PM_TRANSFER_RESPONSE", CODE_PM_TRANSFER_RESPONSE),
+    ("PM_PLACEHOLD_UPLOAD", CODE_PM_PLACEHOLD_UPLOAD),
+    ("PM_QUEUE_UPLOAD", CODE_PM_QUEUE_UPLOAD),
+    ("PM_UPLOAD_PLACE_IN_LINE", CODE_PM_UPLOAD_PLACE_IN_LINE),
+    (
+        "PM_EXACT_FILE_SEARCH_REQUEST",
+        CODE_PM_EXACT_FILE_SEARCH_REQUEST,
+    ),
+    ("PM_QUEUED_DOWNLOADS", CODE_PM_QUEUED_DOWNLOADS),
+    (
+        "PM_INDIRECT_FILE_SEARCH_REQUEST",
+        CODE_PM_INDIRECT_FILE_SEARCH_REQUEST,
+    ),
+    ("PM_UPLOAD_FAILED", CODE_PM_UPLOAD_FAILED),
+    ("PM_UPLOAD_DENIED", CODE_PM_UPLOAD_DENIED),
+    (
+        "PM_UPLOAD_PLACE_IN_LINE_REQUEST",
+        CODE_PM_UPLOAD_PLACE_IN_LINE_REQUEST,
+    ),
+    ("PM_NOTHING", CODE_PM_NOTHING),
+];
+
+pub const DISTRIBUTED_MESSAGE_CODES: &[(&str, u8)] = &[
+    ("DM_PING", CODE_DM_PING),
+    ("DM_SEARCH_REQUEST", CODE_DM_SEARCH_REQUEST),
+    ("DM_BRANCH_LEVEL", CODE_DM_BRANCH_LEVEL),
+    ("DM_BRANCH_ROOT", CODE_DM_BRANCH_ROOT),
+    ("DM_CHILD_DEPTH", CODE_DM_CHILD_DEPTH),
+    ("DM_EMBEDDED_MESSAGE", CODE_DM_EMBEDDED_MESSAGE),
+];
+
+pub const PEER_INIT_MESSAGE_CODES: &[(&str, u8)] = &[
+    ("PI_PIERCE_FIREWALL", CODE_PI_PIERCE_FIREWALL),
+    ("PI_PEER_INIT", CODE_PI_PEER_INIT),
+];
+
+pub fn is_known_server_code(code: u32) -> bool {
+    SERVER_MESSAGE_CODES.iter().any(|&(_, known)| known == code)
+        || is_opaque_server_control_code(code)
+}
+
+pub fn is_known_peer_code(code: u32) -> bool {
+    PEER_MESSAGE_CODES.iter().any(|&(_, known)| known == code)
+}
+
+pub fn is_known_distributed_code(code: u8) -> bool {
+    DISTRIBUTED_MESSAGE_CODES
+        .iter()
+        .any(|&(_, known)| known == code)
+}
+
+pub fn is_known_peer_init_code(code: u8) -> bool {
+    PEER_INIT_MESSAGE_CODES
+        .iter()
+        .any(|&(_, known)| known == code)
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Frame {
     pub code: u32,
     pub payload: Vec<u8>,
 }
 
+/// Frames on distributed (`D`) connections carry a single-byte message code.
+/// The peer-init messages that open every peer connection use the same
+/// framing, so [`encode_peer_init_message`] returns this type as well.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DistributedFrame {
+    pub code: u8,
+    pub payload: Vec<u8>,
+}
+
+impl DistributedFrame {
+    pub fn new(code: u8, payload: Vec<u8>) -> Self {
+        Self { code, payload }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let body_len = 1 + self.payload.len();
+        let mut out = Vec::with_capacity(4 + body_len);
+        out.extend_from_slice(&(body_len as u32).to_le_bytes());
+        out.push(self.code);
+        out.extend_from_slice(&self.payload);
+        out
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.len() < 5 {
+            bail!("distributed frame too short: {}", buf.len());
+        }
+
+        let declared = u32::from_le_bytes(buf[0..4].try_into().context("frame length")?) as usize;
+        if declared + 4 != buf.len() {
+            bail!(
+                "distributed frame length mismatch: declared={} actual={}",
+                declared,
+                buf.len() - 4
+            );
+        }
+
+        Ok(Self {
+            code: buf[4],
+            payload: buf[5..].to_vec(),
+        })
+    }
+}
+
 impl Frame {
     pub fn new(code: u32, payload: Vec<u8>) -> Self {
         Self { code, payload }
@@ -185,6 +491,8 @@ impl Frame {
 pub enum DecoderError {
     #[error("need at least {needed} bytes, have {have}")]
     NotEnough { needed: usize, have: usize },
+    #[error("unsupported {scope} message code {code}")]
+    UnsupportedCode { scope: &'static str, code: u32 },
 }
 
 #[derive(Debug, Clone)]
@@ -418,6 +726,34 @@ pub struct UserStatsResponsePayload {
     pub dirs: u32,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatchedUserStats {
+    pub status: u32,
+    pub avg_speed: u32,
+    pub upload_num: u64,
+    pub files: u32,
+    pub dirs: u32,
+    pub country_code: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddUserResponsePayload {
+    pub username: String,
+    pub exists: bool,
+    pub stats: Option<WatchedUserStats>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WishlistWaitPayload {
+    pub interval_seconds: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlaceInLinePayload {
+    pub virtual_path: String,
+    pub place: u32,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct OwnPrivilegesStatusPayload {
     pub time_left_seconds: u32,
@@ -555,6 +891,23 @@ pub struct SimilarTermsPayload {
 pub struct RoomListPayload {
     pub room_count: u32,
     pub rooms: Vec<String>,
+    /// Users per public room, parallel to `rooms`; empty when the server
+    /// sent names only.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub user_counts: Vec<u32>,
+    /// Private room sections that follow the public list; `None` when the
+    /// list ends after the public rooms.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub private_rooms: Option<PrivateRoomLists>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrivateRoomLists {
+    pub owned: Vec<String>,
+    pub owned_user_counts: Vec<u32>,
+    pub member: Vec<String>,
+    pub member_user_counts: Vec<u32>,
+    pub operated: Vec<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -841,6 +1194,48 @@ pub struct DnetMessagePayload {
     pub distrib_payload: Vec<u8>,
 }
 
+impl DnetMessagePayload {
+    /// Decodes the distributed message carried by the server or a branch root.
+    pub fn embedded_message(&self) -> Result<DistributedMessage> {
+        decode_distributed_message(self.distrib_code, &self.distrib_payload)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DistributedSearchPayload {
+    pub unknown: u32,
+    pub username: String,
+    pub token: u32,
+    pub query: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DistributedBranchLevelPayload {
+    pub level: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DistributedBranchRootPayload {
+    pub root_username: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DistributedChildDepthPayload {
+    pub depth: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PierceFirewallPayload {
+    pub token: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerInitPayload {
+    pub username: String,
+    pub connection_type: String,
+    pub token: u32,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ParentCandidatePayload {
     pub username: String,
@@ -938,6 +1333,9 @@ pub struct PeerSearchResultFile {
     pub file_size: u64,
     pub extension: String,
     pub attr_count: u32,
+    /// Bitrate attribute in kbps, when the peer sent one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitrate: Option<u32>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -1066,6 +1464,33 @@ pub struct SendConnectTokenPayload {
     pub token: u32,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SayPayload {
+    pub message: String,
+}
+
+/// Legacy peer text is seen both length-prefixed and as the raw remainder of
+/// the payload; the form is kept so that re-encoding is byte exact.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerTextEncoding {
+    #[default]
+    LengthPrefixed,
+    Raw,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlaceholderUploadPayload {
+    pub virtual_path: String,
+    pub encoding: PeerTextEncoding,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NothingPayload {
+    pub text: String,
+    pub encoding: PeerTextEncoding,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct UploadPlaceInLineRequestPayload {
     pub virtual_path: String,
@@ -1080,6 +1505,7 @@ pub enum ServerMessage {
     GetPeerAddress(UserLookupPayload),
     GetPeerAddressResponse(PeerAddressResponsePayload),
     AddUser(UserLookupPayload),
+    AddUserResponse(AddUserResponsePayload),
     RemoveUser(UserLookupPayload),
     IgnoreUser(UserLookupPayload),
     UnignoreUser(UserLookupPayload),
@@ -1095,9 +1521,9 @@ pub enum ServerMessage {
     LowPriorityFileSearch(FileSearchPayload),
     SetStatus(SetStatusPayload),
     Heartbeat(HeartbeatPayload),
-    SendConnectToken(OpaquePayload),
-    PlaceInLine(OpaquePayload),
-    PlaceInLineResponse(OpaquePayload),
+    SendConnectToken(SendConnectTokenPayload),
+    PlaceInLine(PlaceInLinePayload),
+    PlaceInLineResponse(PlaceInLinePayload),
     RoomList(RoomListPayload),
     FileSearchResponseSummary(SearchResponseSummary),
     PrivilegedList(PrivilegedListPayload),
@@ -1122,7 +1548,7 @@ pub enum ServerMessage {
     AdminMessage(AdminMessagePayload),
     GetOwnPrivilegesStatus(EmptyPayload),
     OwnPrivilegesStatus(OwnPrivilegesStatusPayload),
-    WishlistWait(OpaquePayload),
+    WishlistWait(WishlistWaitPayload),
     GetUserPrivilegesStatus(UserLookupPayload),
     UserPrivilegesStatus(UserPrivilegesStatusPayload),
     GivePrivilege(GivePrivilegePayload),
@@ -1202,7 +1628,7 @@ pub enum ServerMessage {
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PeerMessage {
-    Say(OpaquePayload),
+    Say(SayPayload),
     GetSharedFileList(UserLookupPayload),
     SharedFileList(SharedFileListPayload),
     GetSharedFilesInFolder(SharedFilesInFolderRequestPayload),
@@ -1217,7 +1643,7 @@ pub enum PeerMessage {
     MoveDownloadToTop(PeerVirtualPathPayload),
     TransferRequest(TransferRequestPayload),
     TransferResponse(TransferResponsePayload),
-    PlaceholderUpload(OpaquePayload),
+    PlaceholderUpload(PlaceholderUploadPayload),
     QueueUpload(QueueUploadPayload),
     UploadPlaceInLine(UploadPlaceInLinePayload),
     ExactFileSearchRequest(PeerSearchQueryPayload),
@@ -1226,7 +1652,34 @@ pub enum PeerMessage {
     UploadFailed(UploadStatusPayload),
     UploadDenied(UploadStatusPayload),
     UploadPlaceInLineRequest(UploadPlaceInLineRequestPayload),
-    Nothing(OpaquePayload),
+    Nothing(NothingPayload),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DistributedMessage {
+    Ping(EmptyPayload),
+    SearchRequest(DistributedSearchPayload),
+    BranchLevel(DistributedBranchLevelPayload),
+    BranchRoot(DistributedBranchRootPayload),
+    ChildDepth(DistributedChildDepthPayload),
+    EmbeddedMessage(DnetMessagePayload),
+}
+
+/// First message on a fresh peer socket; like distributed messages it uses a
+/// single-byte code.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PeerInitMessage {
+    PierceFirewall(PierceFirewallPayload),
+    PeerInit(PeerInitPayload),
+}
+
+impl PeerInitMessage {
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::PierceFirewall(_) => CODE_PI_PIERCE_FIREWALL,
+            Self::PeerInit(_) => CODE_PI_PEER_INIT,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -1245,6 +1698,7 @@ fn ensure_payload_consumed(reader: &PayloadReader<'_>) -> Result<()> {
 pub const OPAQUE_SERVER_CONTROL_CODES: [u32; 0] = [];
 
 const MAX_SHARED_FILES_IN_FOLDER_DECOMPRESSED_BYTES: usize = 16 * 1024 * 1024;
+const FILE_ATTRIBUTE_BITRATE: u32 = 0;
 
 pub fn is_opaque_server_control_code(code: u32) -> bool {
     OPAQUE_SERVER_CONTROL_CODES.contains(&code)
@@ -1745,6 +2199,21 @@ pub fn encode_server_message(message: &ServerMessage) -> Frame {
             writer.write_string(&payload.username);
             CODE_SM_ADD_USER
         }
+        ServerMessage::AddUserResponse(payload) => {
+            writer.write_string(&payload.username);
+            writer.write_u8(u8::from(payload.exists));
+            if let Some(stats) = &payload.stats {
+                writer.write_u32(stats.status);
+                writer.write_u32(stats.avg_speed);
+                writer.write_u64(stats.upload_num);
+                writer.write_u32(stats.files);
+                writer.write_u32(stats.dirs);
+                if let Some(country_code) = &stats.country_code {
+                    writer.write_string(country_code);
+                }
+            }
+            CODE_SM_ADD_USER
+        }
         ServerMessage::RemoveUser(payload) => {
             writer.write_string(&payload.username);
             CODE_SM_REMOVE_USER
@@ -1832,21 +2301,38 @@ pub fn encode_server_message(message: &ServerMessage) -> Frame {
             CODE_SM_HEARTBEAT
         }
         ServerMessage::SendConnectToken(payload) => {
-            writer.write_raw_bytes(&payload.bytes);
+            writer.write_string(&payload.username);
+            writer.write_u32(payload.token);
             CODE_SM_SEND_CONNECT_TOKEN
         }
         ServerMessage::PlaceInLine(payload) => {
-            writer.write_raw_bytes(&payload.bytes);
+            writer.write_string(&payload.virtual_path);
+            writer.write_u32(payload.place);
             CODE_SM_PLACE_IN_LINE
         }
         ServerMessage::PlaceInLineResponse(payload) => {
-            writer.write_raw_bytes(&payload.bytes);
+            writer.write_string(&payload.virtual_path);
+            writer.write_u32(payload.place);
             CODE_SM_PLACE_IN_LINE_RESPONSE
         }
         ServerMessage::RoomList(payload) => {
-            writer.write_u32(payload.room_count);
-            for room in &payload.rooms {
-                writer.write_string(room);
+            // The client's request is the same code with an empty payload.
+            let request = payload.room_count == 0
+                && payload.rooms.is_empty()
+                && payload.user_counts.is_empty()
+                && payload.private_rooms.is_none();
+            if !request {
+                write_room_names(&mut writer, &payload.rooms);
+            }
+            if !payload.user_counts.is_empty() || payload.private_rooms.is_some() {
+                write_room_user_counts(&mut writer, &payload.user_counts);
+            }
+            if let Some(private) = &payload.private_rooms {
+                write_room_names(&mut writer, &private.owned);
+                write_room_user_counts(&mut writer, &private.owned_user_counts);
+                write_room_names(&mut writer, &private.member);
+                write_room_user_counts(&mut writer, &private.member_user_counts);
+                write_room_names(&mut writer, &private.operated);
             }
             CODE_SM_ROOM_LIST
         }
@@ -1952,7 +2438,7 @@ pub fn encode_server_message(message: &ServerMessage) -> Frame {
             CODE_SM_GET_OWN_PRIVILEGES_STATUS
         }
         ServerMessage::WishlistWait(payload) => {
-            writer.write_raw_bytes(&payload.bytes);
+            writer.write_u32(payload.interval_seconds);
             CODE_SM_WISHLIST_WAIT
         }
         ServerMessage::GetUserPrivilegesStatus(payload) => {
@@ -2408,10 +2894,12 @@ pub fn decode_server_message(code: u32, payload: &[u8]) -> Result<ServerMessage>
             }
         }
         CODE_SM_ADD_USER => {
-            let payload = UserLookupPayload {
-                username: reader.read_string()?,
-            };
-            ServerMessage::AddUser(payload)
+            allow_trailing_bytes = true;
+            if let Ok(request) = parse_user_lookup_payload(payload) {
+                ServerMessage::AddUser(request)
+            } else {
+                ServerMessage::AddUserResponse(parse_add_user_response_payload(payload)?)
+            }
         }
         CODE_SM_REMOVE_USER => {
             let payload = UserLookupPayload {
@@ -2498,22 +2986,25 @@ pub fn decode_server_message(code: u32, payload: &[u8]) -> Result<ServerMessage>
             ServerMessage::Heartbeat(payload)
         }
         CODE_SM_SEND_CONNECT_TOKEN => {
-            allow_trailing_bytes = true;
-            ServerMessage::SendConnectToken(OpaquePayload {
-                bytes: payload.to_vec(),
-            })
+            let payload = SendConnectTokenPayload {
+                username: reader.read_string()?,
+                token: reader.read_u32()?,
+            };
+            ServerMessage::SendConnectToken(payload)
         }
         CODE_SM_PLACE_IN_LINE => {
-            allow_trailing_bytes = true;
-            ServerMessage::PlaceInLine(OpaquePayload {
-                bytes: payload.to_vec(),
-            })
+            let payload = PlaceInLinePayload {
+                virtual_path: reader.read_string()?,
+                place: reader.read_u32()?,
+            };
+            ServerMessage::PlaceInLine(payload)
         }
         CODE_SM_PLACE_IN_LINE_RESPONSE => {
-            allow_trailing_bytes = true;
-            ServerMessage::PlaceInLineResponse(OpaquePayload {
-                bytes: payload.to_vec(),
-            })
+            let payload = PlaceInLinePayload {
+                virtual_path: reader.read_string()?,
+                place: reader.read_u32()?,
+            };
+            ServerMessage::PlaceInLineResponse(payload)
         }
         CODE_SM_PRIVILEGED_LIST => {
             allow_trailing_bytes = true;
@@ -2660,10 +3151,10 @@ pub fn decode_server_message(code: u32, payload: &[u8]) -> Result<ServerMessage>
             }
         }
         CODE_SM_WISHLIST_WAIT => {
-            allow_trailing_bytes = true;
-            ServerMessage::WishlistWait(OpaquePayload {
-                bytes: payload.to_vec(),
-            })
+            let payload = WishlistWaitPayload {
+                interval_seconds: reader.read_u32()?,
+            };
+            ServerMessage::WishlistWait(payload)
         }
         CODE_SM_GET_USER_PRIVILEGES_STATUS => {
             allow_trailing_bytes = true;
@@ -3019,7 +3510,10 @@ pub fn decode_server_message(code: u32, payload: &[u8]) -> Result<ServerMessage>
                 bytes: payload.to_vec(),
             })
         }
-        other => bail!("unsupported server message code {other}"),
+        other => bail!(DecoderError::UnsupportedCode {
+            scope: "server",
+            code: other,
+        }),
     };
 
     if !allow_trailing_bytes {
@@ -3064,26 +3558,75 @@ fn parse_optional_room_string(payload: &[u8]) -> Result<Option<String>> {
 }
 
 pub fn parse_room_list_payload(payload: &[u8]) -> Result<RoomListPayload> {
+    let mut list = RoomListPayload {
+        room_count: 0,
+        rooms: Vec::new(),
+        user_counts: Vec::new(),
+        private_rooms: None,
+    };
     if payload.is_empty() {
-        return Ok(RoomListPayload {
-            room_count: 0,
-            rooms: Vec::new(),
-        });
+        return Ok(list);
     }
 
     let mut reader = PayloadReader::new(payload);
-    let room_count = reader.read_u32()?;
-    if room_count > 50_000 {
-        bail!("room_count exceeds sanity threshold: {room_count}");
+    list.rooms = read_room_names(&mut reader)?;
+    list.room_count = list.rooms.len() as u32;
+    if reader.remaining() == 0 {
+        return Ok(list);
     }
-
-    let mut rooms = Vec::with_capacity(room_count as usize);
-    for _ in 0..room_count {
-        rooms.push(reader.read_string()?);
+    list.user_counts = read_room_user_counts(&mut reader, list.rooms.len())?;
+    if reader.remaining() == 0 {
+        return Ok(list);
     }
 
+    let owned = read_room_names(&mut reader)?;
+    let owned_user_counts = read_room_user_counts(&mut reader, owned.len())?;
+    let member = read_room_names(&mut reader)?;
+    let member_user_counts = read_room_user_counts(&mut reader, member.len())?;
+    let operated = read_room_names(&mut reader)?;
     ensure_payload_consumed(&reader)?;
-    Ok(RoomListPayload { room_count, rooms })
+    list.private_rooms = Some(PrivateRoomLists {
+        owned,
+        owned_user_counts,
+        member,
+        member_user_counts,
+        operated,
+    });
+    Ok(list)
+}
+
+fn read_room_names(reader: &mut PayloadReader<'_>) -> Result<Vec<String>> {
+    let count = reader.read_u32()?;
+    if count > 50_000 {
+        bail!("room_count exceeds sanity threshold: {count}");
+    }
+    Ok((0..count)
+        .map(|_| reader.read_string())
+        .collect::<Result<_, _>>()?)
+}
+
+fn read_room_user_counts(reader: &mut PayloadReader<'_>, rooms: usize) -> Result<Vec<u32>> {
+    let count = reader.read_u32()?;
+    if count as usize != rooms {
+        bail!("room user count list length {count} does not match room_count {rooms}");
+    }
+    Ok((0..count)
+        .map(|_| reader.read_u32())
+        .collect::<Result<_, _>>()?)
+}
+
+fn write_room_names(writer: &mut PayloadWriter, rooms: &[String]) {
+    writer.write_u32(rooms.len() as u32);
+    for room in rooms {
+        writer.write_string(room);
+    }
+}
+
+fn write_room_user_counts(writer: &mut PayloadWriter, counts: &[u32]) {
+    writer.write_u32(counts.len() as u32);
+    for count in counts {
+        writer.write_u32(*count);
+    }
 }
 
 pub fn parse_join_room_payload(payload: &[u8]) -> Result<JoinRoomPayload> {
@@ -3349,6 +3892,34 @@ fn parse_peer_address_response_payload(payload: &[u8]) -> Result<PeerAddressResp
     })
 }
 
+fn parse_add_user_response_payload(payload: &[u8]) -> Result<AddUserResponsePayload> {
+    let mut reader = PayloadReader::new(payload);
+    let username = reader.read_string()?;
+    let exists = reader.read_u8()? != 0;
+    let stats = if exists && reader.remaining() > 0 {
+        Some(WatchedUserStats {
+            status: reader.read_u32()?,
+            avg_speed: reader.read_u32()?,
+            upload_num: reader.read_u64()?,
+            files: reader.read_u32()?,
+            dirs: reader.read_u32()?,
+            country_code: if reader.remaining() > 0 {
+                Some(reader.read_string()?)
+            } else {
+                None
+            },
+        })
+    } else {
+        None
+    };
+    ensure_payload_consumed(&reader)?;
+    Ok(AddUserResponsePayload {
+        username,
+        exists,
+        stats,
+    })
+}
+
 fn parse_user_status_response_payload(payload: &[u8]) -> Result<UserStatusResponsePayload> {
     let mut reader = PayloadReader::new(payload);
     let username = reader.read_string()?;
@@ -3681,37 +4252,6 @@ fn parse_search_response_summary_v1(payload: &[u8]) -> Result<SearchResponseSumm
     Ok(summary)
 }
 
-fn parse_search_response_summary_room_list(payload: &[u8]) -> Result<SearchResponseSummary> {
-    let mut reader = PayloadReader::new(payload);
-    let room_count = reader.read_u32()?;
-    if room_count > 20_000 {
-        bail!("room_count exceeds sanity threshold: {room_count}");
-    }
-
-    let mut files = Vec::with_capacity(room_count.min(64) as usize);
-    for idx in 0..room_count {
-        let room_name = reader.read_string()?;
-        if idx < 64 {
-            files.push(SearchFileSummary {
-                file_path: room_name,
-                file_size: 0,
-                extension: "room".to_string(),
-                attr_count: 0,
-            });
-        }
-    }
-
-    Ok(SearchResponseSummary {
-        username: "<server_room_list>".to_string(),
-        token: 0,
-        files_count: room_count,
-        slots_free: 0,
-        speed: 0,
-        in_queue: false,
-        files,
-    })
-}
-
 fn infer_file_extension(file_path: &str) -> String {
     let name_start = file_path
         .rfind(['\\', '/'])
@@ -3769,6 +4309,7 @@ fn parse_peer_file_search_result_payload_legacy(payload: &[u8]) -> Result<FileSe
             file_size,
             extension,
             attr_count,
+            bitrate: None,
         });
     }
 
@@ -3829,12 +4370,18 @@ fn parse_peer_file_search_result_payload_compressed(
         if attr_count > 1024 {
             bail!("compressed peer search attr_count exceeds sanity threshold: {attr_count}");
         }
+        // Attributes are (code, value) pairs; code 0 is the bitrate.
+        let mut bitrate = None;
         for _ in 0..attr_count {
-            if reader.remaining() < 4 {
+            if reader.remaining() < 8 {
                 reader = entry_checkpoint;
                 break;
             }
-            let _ = reader.read_u32()?;
+            let code = reader.read_u32()?;
+            let value = reader.read_u32()?;
+            if code == FILE_ATTRIBUTE_BITRATE {
+                bitrate = Some(value);
+            }
         }
 
         let extension = if extension_raw.is_empty() {
@@ -3847,6 +4394,7 @@ fn parse_peer_file_search_result_payload_compressed(
             file_size,
             extension,
             attr_count,
+            bitrate,
         });
     }
 
@@ -3977,6 +4525,26 @@ fn parse_user_info_reply_payload(payload: &[u8]) -> Result<UserInfoReplyPayload>
     })
 }
 
+fn parse_peer_text(payload: &[u8]) -> (String, PeerTextEncoding) {
+    let mut reader = PayloadReader::new(payload);
+    if let Ok(text) = reader.read_string()
+        && reader.remaining() == 0
+    {
+        return (text, PeerTextEncoding::LengthPrefixed);
+    }
+    (
+        String::from_utf8_lossy(payload).into_owned(),
+        PeerTextEncoding::Raw,
+    )
+}
+
+fn write_peer_text(writer: &mut PayloadWriter, text: &str, encoding: PeerTextEncoding) {
+    match encoding {
+        PeerTextEncoding::LengthPrefixed => writer.write_string(text),
+        PeerTextEncoding::Raw => writer.write_raw_bytes(text.as_bytes()),
+    }
+}
+
 fn parse_peer_search_query_payload(payload: &[u8]) -> Result<PeerSearchQueryPayload> {
     let mut reader = PayloadReader::new(payload);
     if reader.remaining() >= 8 {
@@ -4040,7 +4608,7 @@ pub fn encode_peer_message(message: &PeerMessage) -> Frame {
     let mut writer = PayloadWriter::new();
     let code = match message {
         PeerMessage::Say(payload) => {
-            writer.write_raw_bytes(&payload.bytes);
+            writer.write_string(&payload.message);
             CODE_PM_SAY
         }
         PeerMessage::GetSharedFileList(payload) => {
@@ -4124,7 +4692,7 @@ pub fn encode_peer_message(message: &PeerMessage) -> Frame {
             CODE_PM_TRANSFER_RESPONSE
         }
         PeerMessage::PlaceholderUpload(payload) => {
-            writer.write_raw_bytes(&payload.bytes);
+            write_peer_text(&mut writer, &payload.virtual_path, payload.encoding);
             CODE_PM_PLACEHOLD_UPLOAD
         }
         PeerMessage::QueueUpload(payload) => {
@@ -4180,7 +4748,7 @@ pub fn encode_peer_message(message: &PeerMessage) -> Frame {
             CODE_PM_UPLOAD_PLACE_IN_LINE_REQUEST
         }
         PeerMessage::Nothing(payload) => {
-            writer.write_raw_bytes(&payload.bytes);
+            write_peer_text(&mut writer, &payload.text, payload.encoding);
             CODE_PM_NOTHING
         }
     };
@@ -4195,9 +4763,10 @@ pub fn decode_peer_message(code: u32, payload: &[u8]) -> Result<PeerMessage> {
     let message = match code {
         CODE_PM_SAY => {
             allow_trailing_bytes = true;
-            PeerMessage::Say(OpaquePayload {
-                bytes: payload.to_vec(),
-            })
+            let payload = SayPayload {
+                message: reader.read_string()?,
+            };
+            PeerMessage::Say(payload)
         }
         CODE_PM_GET_SHARED_FILE_LIST => {
             let payload = UserLookupPayload {
@@ -4207,7 +4776,9 @@ pub fn decode_peer_message(code: u32, payload: &[u8]) -> Result<PeerMessage> {
         }
         CODE_PM_SHARED_FILE_LIST => {
             let count = reader.read_u32()? as usize;
-            let mut entries = Vec::with_capacity(count);
+            // Each entry takes at least 12 bytes, which bounds the allocation
+            // a bogus count can request.
+            let mut entries = Vec::with_capacity(count.min(reader.remaining() / 12));
             for _ in 0..count {
                 entries.push(SharedFileEntry {
                     virtual_path: reader.read_string()?,
@@ -4284,8 +4855,10 @@ pub fn decode_peer_message(code: u32, payload: &[u8]) -> Result<PeerMessage> {
         }
         CODE_PM_PLACEHOLD_UPLOAD => {
             allow_trailing_bytes = true;
-            PeerMessage::PlaceholderUpload(OpaquePayload {
-                bytes: payload.to_vec(),
+            let (virtual_path, encoding) = parse_peer_text(payload);
+            PeerMessage::PlaceholderUpload(PlaceholderUploadPayload {
+                virtual_path,
+                encoding,
             })
         }
         CODE_PM_QUEUE_UPLOAD => {
@@ -4337,11 +4910,13 @@ pub fn decode_peer_message(code: u32, payload: &[u8]) -> Result<PeerMessage> {
         }
         CODE_PM_NOTHING => {
             allow_trailing_bytes = true;
-            PeerMessage::Nothing(OpaquePayload {
-                bytes: payload.to_vec(),
-            })
+            let (text, encoding) = parse_peer_text(payload);
+            PeerMessage::Nothing(NothingPayload { text, encoding })
         }
-        other => bail!("unsupported peer message code {other}"),
+        other => bail!(DecoderError::UnsupportedCode {
+            scope: "peer",
+            code: other,
+        }),
     };
 
     if !allow_trailing_bytes {
@@ -4350,6 +4925,127 @@ pub fn decode_peer_message(code: u32, payload: &[u8]) -> Result<PeerMessage> {
     Ok(message)
 }
 
+pub fn encode_distributed_message(message: &DistributedMessage) -> DistributedFrame {
+    let mut writer = PayloadWriter::new();
+    let code = match message {
+        DistributedMessage::Ping(_) => CODE_DM_PING,
+        DistributedMessage::SearchRequest(payload) => {
+            writer.write_u32(payload.unknown);
+            writer.write_string(&payload.username);
+            writer.write_u32(payload.token);
+            writer.write_string(&payload.query);
+            CODE_DM_SEARCH_REQUEST
+        }
+        DistributedMessage::BranchLevel(payload) => {
+            writer.write_u32(payload.level as u32);
+            CODE_DM_BRANCH_LEVEL
+        }
+        DistributedMessage::BranchRoot(payload) => {
+            writer.write_string(&payload.root_username);
+            CODE_DM_BRANCH_ROOT
+        }
+        DistributedMessage::ChildDepth(payload) => {
+            writer.write_u32(payload.depth);
+            CODE_DM_CHILD_DEPTH
+        }
+        DistributedMessage::EmbeddedMessage(payload) => {
+            writer.write_u8(payload.distrib_code);
+            writer.write_raw_bytes(&payload.distrib_payload);
+            CODE_DM_EMBEDDED_MESSAGE
+        }
+    };
+
+    DistributedFrame::new(code, writer.into_inner())
+}
+
+pub fn decode_distributed_message(code: u8, payload: &[u8]) -> Result<DistributedMessage> {
+    let mut reader = PayloadReader::new(payload);
+    let message = match code {
+        CODE_DM_PING => DistributedMessage::Ping(EmptyPayload),
+        CODE_DM_SEARCH_REQUEST => {
+            let payload = DistributedSearchPayload {
+                unknown: reader.read_u32()?,
+                username: reader.read_string()?,
+                token: reader.read_u32()?,
+                query: reader.read_string()?,
+            };
+            DistributedMessage::SearchRequest(payload)
+        }
+        CODE_DM_BRANCH_LEVEL => {
+            let payload = DistributedBranchLevelPayload {
+                level: reader.read_u32()? as i32,
+            };
+            DistributedMessage::BranchLevel(payload)
+        }
+        CODE_DM_BRANCH_ROOT => {
+            let payload = DistributedBranchRootPayload {
+                root_username: reader.read_string()?,
+            };
+            DistributedMessage::BranchRoot(payload)
+        }
+        CODE_DM_CHILD_DEPTH => {
+            let payload = DistributedChildDepthPayload {
+                depth: reader.read_u32()?,
+            };
+            DistributedMessage::ChildDepth(payload)
+        }
+        CODE_DM_EMBEDDED_MESSAGE => {
+            if payload.is_empty() {
+                bail!("embedded distributed message is empty");
+            }
+            DistributedMessage::EmbeddedMessage(parse_dnet_message_payload(payload)?)
+        }
+        other => bail!(DecoderError::UnsupportedCode {
+            scope: "distributed",
+            code: u32::from(other),
+        }),
+    };
+
+    if !matches!(message, DistributedMessage::EmbeddedMessage(_)) {
+        ensure_payload_consumed(&reader)?;
+    }
+    Ok(message)
+}
+
+pub fn encode_peer_init_message(message: &PeerInitMessage) -> DistributedFrame {
+    let mut writer = PayloadWriter::new();
+    match message {
+        PeerInitMessage::PierceFirewall(payload) => writer.write_u32(payload.token),
+        PeerInitMessage::PeerInit(payload) => {
+            writer.write_string(&payload.username);
+            writer.write_string(&payload.connection_type);
+            writer.write_u32(payload.token);
+        }
+    }
+
+    DistributedFrame::new(message.code(), writer.into_inner())
+}
+
+/// Decodes a frame body: the code byte followed by its payload. Bytes after
+/// the token are ignored, as some clients append extra fields.
+pub fn decode_peer_init_message(body: &[u8]) -> Result<PeerInitMessage> {
+    let Some((&code, payload)) = body.split_first() else {
+        bail!("peer init payload is empty");
+    };
+    let mut reader = PayloadReader::new(payload);
+    let message = match code {
+        CODE_PI_PIERCE_FIREWALL => PeerInitMessage::PierceFirewall(PierceFirewallPayload {
+            token: reader.read_u32()?,
+        }),
+        CODE_PI_PEER_INIT => PeerInitMessage::PeerInit(PeerInitPayload {
+            username: reader.read_string()?,
+            connection_type: reader.read_string()?,
+            token: reader.read_u32()?,
+        }),
+        other => bail!(DecoderError::UnsupportedCode {
+            scope: "peer init",
+            code: u32::from(other),
+        }),
+    };
+
+    Ok(message)
+}
+
 pub fn build_login_request(
     username: &str,
     password: &str,
@@ -4373,6 +5069,27 @@ pub fn build_file_search_request(token: u32, search_text: &str) -> Frame {
     }))
 }
 
+pub fn build_wishlist_search_request(token: u32, search_text: &str) -> Frame {
+    encode_server_message(&ServerMessage::LowPriorityFileSearch(FileSearchPayload {
+        search_token: token,
+        search_text: search_text.to_owned(),
+    }))
+}
+
+pub fn build_search_room_request(room: &str, search_text: &str) -> Frame {
+    encode_server_message(&ServerMessage::SearchRoom(SearchRoomPayload {
+        room: room.to_owned(),
+        search_text: search_text.to_owned(),
+    }))
+}
+
+pub fn build_search_user_files_request(username: &str, search_text: &str) -> Frame {
+    encode_server_message(&ServerMessage::SearchUserFiles(SearchUserFilesPayload {
+        username: username.to_owned(),
+        search_text: search_text.to_owned(),
+    }))
+}
+
 pub fn build_opaque_server_control_request(code: u32, payload: &[u8]) -> Result<Frame> {
     if !is_opaque_server_control_code(code) {
         bail!("unsupported opaque server control code: {code}");
@@ -4557,6 +5274,18 @@ pub fn build_remove_hate_term_request(term: &str) -> Frame {
     }))
 }
 
+pub fn build_add_user_request(username: &str) -> Frame {
+    encode_server_message(&ServerMessage::AddUser(UserLookupPayload {
+        username: username.to_owned(),
+    }))
+}
+
+pub fn build_remove_user_request(username: &str) -> Frame {
+    encode_server_message(&ServerMessage::RemoveUser(UserLookupPayload {
+        username: username.to_owned(),
+    }))
+}
+
 pub fn build_ignore_user_request(username: &str) -> Frame {
     encode_server_message(&ServerMessage::IgnoreUser(UserLookupPayload {
         username: username.to_owned(),
@@ -4954,6 +5683,12 @@ pub fn build_upload_place_in_line_request(virtual_path: &str) -> Frame {
     ))
 }
 
+pub fn build_get_shared_file_list_request(username: &str) -> Frame {
+    encode_peer_message(&PeerMessage::GetSharedFileList(UserLookupPayload {
+        username: username.to_owned(),
+    }))
+}
+
 pub fn build_get_shared_files_in_folder_request(directory: &str) -> Frame {
     encode_peer_message(&PeerMessage::GetSharedFilesInFolder(
         SharedFilesInFolderRequestPayload {
@@ -5079,6 +5814,21 @@ pub fn split_first_frame(buffer: &[u8]) -> Result<Option<(Frame, usize)>> {
     Ok(Some((frame, total)))
 }
 
+pub fn split_first_distributed_frame(buffer: &[u8]) -> Result<Option<(DistributedFrame, usize)>> {
+    if buffer.len() < 4 {
+        return Ok(None);
+    }
+
+    let declared = u32::from_le_bytes(buffer[0..4].try_into().context("frame length")?) as usize;
+    let total = declared + 4;
+    if buffer.len() < total {
+        return Ok(None);
+    }
+
+    let frame = DistributedFrame::decode(&buffer[0..total])?;
+    Ok(Some((frame, total)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -5094,632 +5844,9 @@ mod tests {
         out
     }
 
-    fn sample_messages() -> Vec<ProtocolMessage> {
-        vec![
-            ProtocolMessage::Server(ServerMessage::Login(LoginPayload {
-                username: "alice".into(),
-                password: "secret-pass".into(),
-                client_version: 157,
-                md5hash: compute_login_md5hash("alice", "secret-pass"),
-                minor_version: 19,
-            })),
-            ProtocolMessage::Server(ServerMessage::LoginResponse(LoginResponsePayload::Success(
-                LoginResponseSuccessPayload {
-                    greeting: "welcome".into(),
-                    ip_address: "127.0.0.1".into(),
-                    md5hash: "0123456789abcdef0123456789abcdef".into(),
-                    is_supporter: true,
-                },
-            ))),
-            ProtocolMessage::Server(ServerMessage::SetWaitPort(SetWaitPortPayload {
-                listen_port: 2234,
-            })),
-            ProtocolMessage::Server(ServerMessage::AddChatRoom(AddChatRoomPayload {
-                room: "new-room".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::GetPeerAddress(UserLookupPayload {
-                username: "bob".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::GetPeerAddressResponse(
-                PeerAddressResponsePayload {
-                    username: "bob".into(),
-                    ip_address: "203.0.113.5".into(),
-                    port: 2234,
-                    obfuscation_type: 1,
-                    obfuscated_port: 40123,
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::IgnoreUser(UserLookupPayload {
-                username: "eve".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::UnignoreUser(UserLookupPayload {
-                username: "eve".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::SayChatRoom(SayChatRoomPayload {
-                room: "nicotine".into(),
-                username: Some("alice".into()),
-                message: "hello room".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::JoinRoom(JoinRoomPayload {
-                room: "nicotine".into(),
-                users: vec!["alice".into(), "bob".into()],
-            })),
-            ProtocolMessage::Server(ServerMessage::LeaveRoom(LeaveRoomPayload {
-                room: "nicotine".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::UserJoinedRoom(RoomPresenceEventPayload {
-                room: "nicotine".into(),
-                username: "carol".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::UserLeftRoom(RoomPresenceEventPayload {
-                room: "nicotine".into(),
-                username: "dave".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::ConnectToPeerRequest(
-                ConnectToPeerRequestPayload {
-                    token: 77,
-                    username: "bob".into(),
-                    connection_type: "P".into(),
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::ConnectToPeerResponse(
-                ConnectToPeerResponsePayload {
-                    username: "bob".into(),
-                    connection_type: "P".into(),
-                    ip_address: "198.51.100.24".into(),
-                    port: 5566,
-                    token: 77,
-                    privileged: false,
-                    obfuscation_type: 0,
-                    obfuscated_port: 0,
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::ConnectToPeer(ConnectToPeerPayload {
-                username: "bob".into(),
-                token: 77,
-            })),
-            ProtocolMessage::Server(ServerMessage::RoomList(RoomListPayload {
-                room_count: 2,
-                rooms: vec!["nicotine".into(), "electronic".into()],
-            })),
-            ProtocolMessage::Server(ServerMessage::FileSearch(FileSearchPayload {
-                search_token: 12345,
-                search_text: "aphex twin".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::FileSearchResponseSummary(
-                SearchResponseSummary {
-                    username: "peer_user".into(),
-                    token: 12345,
-                    files_count: 1,
-                    slots_free: 2,
-                    speed: 4096,
-                    in_queue: false,
-                    files: vec![SearchFileSummary {
-                        file_path: "Music\\Aphex Twin\\Track.flac".into(),
-                        file_size: 123_456,
-                        extension: "flac".into(),
-                        attr_count: 3,
-                    }],
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::SearchRoom(SearchRoomPayload {
-                room: "electronic".into(),
-                search_text: "selected ambient works".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::ExactFileSearch(ExactFileSearchPayload {
-                virtual_path: "Music\\Aphex Twin\\Track.flac".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::SearchUserFiles(SearchUserFilesPayload {
-                username: "bob".into(),
-                search_text: "flac".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::GetSimilarTerms(SimilarTermsRequestPayload {
-                term: "electronic".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::AddLikeTerm(SimilarTermsRequestPayload {
-                term: "idm".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::RemoveLikeTerm(SimilarTermsRequestPayload {
-                term: "idm".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::GetSimilarTermsResponse(
-                SimilarTermsPayload {
-                    term: "electronic".into(),
-                    entries: vec![RecommendationEntry {
-                        term: "idm".into(),
-                        score: 7,
-                    }],
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::GetRecommendations(EmptyPayload)),
-            ProtocolMessage::Server(ServerMessage::GetRecommendationsResponse(
-                RecommendationsPayload {
-                    recommendations: vec![RecommendationEntry {
-                        term: "flac".into(),
-                        score: 3,
-                    }],
-                    unrecommendations: vec![RecommendationEntry {
-                        term: "low-bitrate".into(),
-                        score: -2,
-                    }],
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::GetMyRecommendations(EmptyPayload)),
-            ProtocolMessage::Server(ServerMessage::GetMyRecommendationsResponse(
-                RecommendationsPayload {
-                    recommendations: vec![RecommendationEntry {
-                        term: "ambient".into(),
-                        score: 4,
-                    }],
-                    unrecommendations: vec![],
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::GetGlobalRecommendations(EmptyPayload)),
-            ProtocolMessage::Server(ServerMessage::GetGlobalRecommendationsResponse(
-                RecommendationsPayload {
-                    recommendations: vec![RecommendationEntry {
-                        term: "lossless".into(),
-                        score: 8,
-                    }],
-                    unrecommendations: vec![RecommendationEntry {
-                        term: "ads".into(),
-                        score: -4,
-                    }],
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::GetOwnPrivilegesStatus(EmptyPayload)),
-            ProtocolMessage::Server(ServerMessage::OwnPrivilegesStatus(
-                OwnPrivilegesStatusPayload {
-                    time_left_seconds: 86_400,
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::GetUserPrivilegesStatus(UserLookupPayload {
-                username: "bob".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::UserPrivilegesStatus(
-                UserPrivilegesStatusPayload {
-                    username: "bob".into(),
-                    privileged: true,
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::GivePrivilege(GivePrivilegePayload {
-                username: "bob".into(),
-                days: 7,
-            })),
-            ProtocolMessage::Server(ServerMessage::InformUserOfPrivileges(
-                InformUserOfPrivilegesPayload {
-                    token: 1234,
-                    username: "bob".into(),
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::InformUserOfPrivilegesAck(
-                InformUserOfPrivilegesAckPayload { token: 1234 },
-            )),
-            ProtocolMessage::Server(ServerMessage::GetUserRecommendations(UserLookupPayload {
-                username: "bob".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::GetUserRecommendationsResponse(
-                UserRecommendationsPayload {
-                    username: "bob".into(),
-                    recommendations: RecommendationsPayload {
-                        recommendations: vec![RecommendationEntry {
-                            term: "aphex".into(),
-                            score: 9,
-                        }],
-                        unrecommendations: vec![],
-                    },
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::PrivilegedList(PrivilegedListPayload {
-                users: vec!["alice".into(), "bob".into()],
-            })),
-            ProtocolMessage::Server(ServerMessage::GetRecommendedUsers(EmptyPayload)),
-            ProtocolMessage::Server(ServerMessage::GetRecommendedUsersResponse(
-                RecommendedUsersPayload {
-                    users: vec![ScoredUserEntry {
-                        username: "alice".into(),
-                        score: 12,
-                    }],
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::GetTermRecommendations(
-                SimilarTermsRequestPayload { term: "idm".into() },
-            )),
-            ProtocolMessage::Server(ServerMessage::GetTermRecommendationsResponse(
-                TermRecommendationsPayload {
-                    term: "idm".into(),
-                    recommendations: vec![RecommendationEntry {
-                        term: "ambient".into(),
-                        score: 5,
-                    }],
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::GetRecommendationUsers(
-                SimilarTermsRequestPayload { term: "idm".into() },
-            )),
-            ProtocolMessage::Server(ServerMessage::GetRecommendationUsersResponse(
-                RecommendationUsersPayload {
-                    term: "idm".into(),
-                    users: vec![ScoredUserEntry {
-                        username: "charlie".into(),
-                        score: 3,
-                    }],
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::BanUser(UserLookupPayload {
-                username: "mallory".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::RoomMembers(RoomMembersPayload {
-                room: "nicotine".into(),
-                users: vec!["alice".into(), "bob".into(), "carol".into()],
-            })),
-            ProtocolMessage::Server(ServerMessage::RoomOperators(RoomOperatorsPayload {
-                room: "nicotine".into(),
-                operators: vec!["alice".into()],
-            })),
-            ProtocolMessage::Server(ServerMessage::AddRoomMember(RoomModerationPayload {
-                room: "private-room".into(),
-                username: "bob".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::RemoveRoomMember(RoomModerationPayload {
-                room: "private-room".into(),
-                username: "bob".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::AddRoomOperator(RoomModerationPayload {
-                room: "private-room".into(),
-                username: "alice".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::RemoveRoomOperator(RoomModerationPayload {
-                room: "private-room".into(),
-                username: "alice".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::SetParentMinSpeed(ParentMinSpeedPayload {
-                min_speed: 1,
-            })),
-            ProtocolMessage::Server(ServerMessage::SetParentSpeedConnectionRatio(
-                ParentSpeedConnectionRatioPayload { ratio: 50 },
-            )),
-            ProtocolMessage::Server(ServerMessage::GetRoomTicker(RoomTickerRequestPayload {
-                room: "nicotine".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::RoomTicker(RoomTickerPayload {
-                room: "nicotine".into(),
-                entries: vec![
-                    RoomTickerEntry {
-                        username: "alice".into(),
-                        ticker: "hello".into(),
-                    },
-                    RoomTickerEntry {
-                        username: "bob".into(),
-                        ticker: "world".into(),
-                    },
-                ],
-            })),
-            ProtocolMessage::Server(ServerMessage::MessageUserIncoming(
-                MessageUserIncomingPayload {
-                    message_id: 91,
-                    timestamp: 1_705_000_000,
-                    username: "bob".into(),
-                    message: "hello inbound".into(),
-                    is_new: true,
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::MessageUser(MessageUserPayload {
-                username: "bob".into(),
-                message: "hello".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::MessageAcked(MessageAckedPayload {
-                message_id: 55,
-            })),
-            ProtocolMessage::Server(ServerMessage::MessageUsers(MessageUsersPayload {
-                usernames: vec!["alice".into(), "bob".into()],
-                message: "broadcast test".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::PeerMessage(PeerMessagePayload {
-                username: "bob".into(),
-                message: "legacy request".into(),
-                token: Some(17),
-                code: Some(40),
-                ip_address: None,
-                port: None,
-            })),
-            ProtocolMessage::Server(ServerMessage::PeerMessage(PeerMessagePayload {
-                username: "bob".into(),
-                message: "legacy response".into(),
-                token: Some(17),
-                code: Some(40),
-                ip_address: Some("198.51.100.15".into()),
-                port: Some(2242),
-            })),
-            ProtocolMessage::Server(ServerMessage::GetUserStats(UserLookupPayload {
-                username: "bob".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::GetUserStatsResponse(
-                UserStatsResponsePayload {
-                    username: "bob".into(),
-                    avg_speed: 2048,
-                    download_num: 12,
-                    files: 500,
-                    dirs: 42,
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::GetUserStatus(UserLookupPayload {
-                username: "bob".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::GetUserStatusResponse(
-                UserStatusResponsePayload {
-                    username: "bob".into(),
-                    status: 2,
-                    privileged: true,
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::SharedFoldersFiles(
-                SharedFoldersFilesPayload {
-                    folder_count: 12,
-                    file_count: 200,
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::DownloadSpeed(SpeedPayload {
-                bytes_per_sec: 2048,
-            })),
-            ProtocolMessage::Server(ServerMessage::UploadSpeed(SpeedPayload {
-                bytes_per_sec: 1024,
-            })),
-            ProtocolMessage::Server(ServerMessage::AddUser(UserLookupPayload {
-                username: "new-user".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::RemoveUser(UserLookupPayload {
-                username: "old-user".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::LowPriorityFileSearch(FileSearchPayload {
-                search_token: 99,
-                search_text: "wishlist ambient".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::SetStatus(SetStatusPayload { status: 2 })),
-            ProtocolMessage::Server(ServerMessage::Heartbeat(HeartbeatPayload {
-                sequence: Some(1234),
-            })),
-            ProtocolMessage::Server(ServerMessage::Command(CommandPayload {
-                command: "/whois alice".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::RoomAdded(RoomNamePayload {
-                room: "new-lounge".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::RoomRemoved(RoomNamePayload {
-                room: "old-lounge".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::AdminMessage(AdminMessagePayload {
-                message: "maintenance window".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::SetParentInactivityBeforeDisconnect(
-                ParentInactivityBeforeDisconnectPayload { seconds: 60 },
-            )),
-            ProtocolMessage::Server(ServerMessage::SetServerInactivityBeforeDisconnect(
-                ServerInactivityBeforeDisconnectPayload { seconds: 120 },
-            )),
-            ProtocolMessage::Server(ServerMessage::NodesInCacheBeforeDisconnect(
-                NodesInCacheBeforeDisconnectPayload { nodes: 128 },
-            )),
-            ProtocolMessage::Server(ServerMessage::SetSecondsBeforePingChildren(
-                SecondsBeforePingChildrenPayload { seconds: 30 },
-            )),
-            ProtocolMessage::Server(ServerMessage::CanParent(CanParentPayload {
-                can_parent: true,
-            })),
-            ProtocolMessage::Server(ServerMessage::AddHateTerm(SimilarTermsRequestPayload {
-                term: "noise".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::RemoveHateTerm(SimilarTermsRequestPayload {
-                term: "noise".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::DnetReset(DnetResetPayload {
-                reason: Some(1),
-            })),
-            ProtocolMessage::Server(ServerMessage::RemoveOwnRoomMembership(RoomNamePayload {
-                room: "private-room".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::GiveUpRoom(RoomNamePayload {
-                room: "private-room".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::AddRoomMembership(RoomNamePayload {
-                room: "private-room".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::RemoveRoomMembership(RoomNamePayload {
-                room: "private-room".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::AddRoomOperatorship(RoomNamePayload {
-                room: "private-room".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::SendConnectToken(OpaquePayload {
-                bytes: vec![0x10, 0x20, 0x30, 0x40],
-            })),
-            ProtocolMessage::Server(ServerMessage::PlaceInLine(OpaquePayload {
-                bytes: vec![0xaa, 0xbb, 0xcc],
-            })),
-            ProtocolMessage::Server(ServerMessage::PlaceInLineResponse(OpaquePayload {
-                bytes: vec![0xde, 0xad, 0xbe, 0xef],
-            })),
-            ProtocolMessage::Server(ServerMessage::AddPrivilegedUser(UserLookupPayload {
-                username: "vip-user".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::WishlistWait(OpaquePayload {
-                bytes: vec![0x3c, 0x00, 0x00, 0x00],
-            })),
-            ProtocolMessage::Server(ServerMessage::DnetLevel(DnetLevelPayload {
-                level: Some(1),
-                extension_reserved_bytes: Vec::new(),
-            })),
-            ProtocolMessage::Server(ServerMessage::DnetGroupLeader(DnetGroupLeaderPayload {
-                username: Some("branch-root".into()),
-                extension_reserved_bytes: Vec::new(),
-            })),
-            ProtocolMessage::Server(ServerMessage::DnetDeliveryReport(
-                DnetDeliveryReportPayload {
-                    report: Some(3),
-                    extension_reserved_bytes: Vec::new(),
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::DnetChildDepth(DnetChildDepthPayload {
-                depth: Some(4),
-                extension_reserved_bytes: Vec::new(),
-            })),
-            ProtocolMessage::Server(ServerMessage::Flood(FloodPayload {
-                flood_code: Some(5),
-                extension_reserved_bytes: Vec::new(),
-            })),
-            ProtocolMessage::Server(ServerMessage::RemoveRoomOperatorship(
-                RoomOperatorshipRevocationPayload {
-                    room: Some("private-room".into()),
-                    extension_reserved_bytes: Vec::new(),
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::RemoveOwnRoomOperatorship(
-                RoomOperatorshipRevocationPayload {
-                    room: Some("private-room".into()),
-                    extension_reserved_bytes: Vec::new(),
-                },
-            )),
-            ProtocolMessage::Server(ServerMessage::JoinGlobalRoom(GlobalRoomTogglePayload {
-                room: None,
-            })),
-            ProtocolMessage::Server(ServerMessage::LeaveGlobalRoom(GlobalRoomTogglePayload {
-                room: None,
-            })),
-            ProtocolMessage::Server(ServerMessage::SayGlobalRoom(GlobalRoomMessagePayload {
-                message: "global hello".into(),
-            })),
-            ProtocolMessage::Server(ServerMessage::SearchCorrelations(
-                SearchCorrelationsPayload {
-                    term: "ambient".into(),
-                },
-            )),
-            ProtocolMessage::Peer(PeerMessage::Say(OpaquePayload {
-                bytes: b"peer say".to_vec(),
-            })),
-            ProtocolMessage::Peer(PeerMessage::GetSharedFileList(UserLookupPayload {
-                username: "alice".into(),
-            })),
-            ProtocolMessage::Peer(PeerMessage::SharedFileList(SharedFileListPayload {
-                entries: vec![
-                    SharedFileEntry {
-                        virtual_path: "Music\\A.flac".into(),
-                        size: 100,
-                    },
-                    SharedFileEntry {
-                        virtual_path: "Music\\B.flac".into(),
-                        size: 200,
-                    },
-                ],
-            })),
-            ProtocolMessage::Peer(PeerMessage::GetSharedFilesInFolder(
-                SharedFilesInFolderRequestPayload {
-                    directory: "Music\\Albums".into(),
-                },
-            )),
-            ProtocolMessage::Peer(PeerMessage::SharedFilesInFolder(
-                SharedFilesInFolderPayload {
-                    directory: "Music\\Albums".into(),
-                    compressed_listing: vec![0x78, 0x9c, 0x03, 0x00],
-                },
-            )),
-            ProtocolMessage::Peer(PeerMessage::FileSearchRequest(FileSearchRequestPayload {
-                token: 9,
-                query: "ambient".into(),
-            })),
-            ProtocolMessage::Peer(PeerMessage::FileSearchResult(FileSearchResultPayload {
-                token: 9,
-                username: "bob".into(),
-                result_count: 2,
-                files: Vec::new(),
-                extension_tail: Vec::new(),
-            })),
-            ProtocolMessage::Peer(PeerMessage::InviteUserToRoom(PeerRoomInvitePayload {
-                room: "nicotine".into(),
-            })),
-            ProtocolMessage::Peer(PeerMessage::CancelledQueuedTransfer(
-                PeerVirtualPathPayload {
-                    virtual_path: "Music\\A.flac".into(),
-                },
-            )),
-            ProtocolMessage::Peer(PeerMessage::UserInfoRequest(UserInfoRequestPayload)),
-            ProtocolMessage::Peer(PeerMessage::UserInfoReply(UserInfoReplyPayload {
-                description: "hello".into(),
-                has_picture: false,
-                picture: Vec::new(),
-                total_uploads: 12,
-                queue_size: 2,
-                slots_free: true,
-                upload_permissions: Some(1),
-            })),
-            ProtocolMessage::Peer(PeerMessage::SendConnectToken(SendConnectTokenPayload {
-                username: "alice".into(),
-                token: 33,
-            })),
-            ProtocolMessage::Peer(PeerMessage::TransferRequest(TransferRequestPayload {
-                direction: TransferDirection::Download,
-                token: 555,
-                virtual_path: "Music\\Aphex Twin\\Track.flac".into(),
-                file_size: 123_456_789,
-            })),
-            ProtocolMessage::Peer(PeerMessage::TransferResponse(TransferResponsePayload {
-                token: 555,
-                allowed: true,
-                queue_or_reason: String::new(),
-            })),
-            ProtocolMessage::Peer(PeerMessage::PlaceholderUpload(OpaquePayload {
-                bytes: vec![0x42, 0x42, 0x42],
-            })),
-            ProtocolMessage::Peer(PeerMessage::QueueUpload(QueueUploadPayload {
-                username: "alice".into(),
-                virtual_path: "Music\\queued.flac".into(),
-            })),
-            ProtocolMessage::Peer(PeerMessage::UploadPlaceInLine(UploadPlaceInLinePayload {
-                username: "alice".into(),
-                virtual_path: "Music\\queued.flac".into(),
-                place: 3,
-            })),
-            ProtocolMessage::Peer(PeerMessage::MoveDownloadToTop(PeerVirtualPathPayload {
-                virtual_path: "Music\\queued.flac".into(),
-            })),
-            ProtocolMessage::Peer(PeerMessage::ExactFileSearchRequest(
-                PeerSearchQueryPayload {
-                    token: Some(123),
-                    query: "Music\\A.flac".into(),
-                },
-            )),
-            ProtocolMessage::Peer(PeerMessage::QueuedDownloads(PeerQueuedDownloadsPayload {
-                virtual_paths: vec!["Music\\A.flac".into(), "Music\\B.flac".into()],
-            })),
-            ProtocolMessage::Peer(PeerMessage::IndirectFileSearchRequest(
-                PeerSearchQueryPayload {
-                    token: None,
-                    query: "A.flac".into(),
-                },
-            )),
-            ProtocolMessage::Peer(PeerMessage::UploadFailed(UploadStatusPayload {
-                username: "alice".into(),
-                virtual_path: "Music\\queued.flac".into(),
-                reason: "network".into(),
-            })),
-            ProtocolMessage::Peer(PeerMessage::UploadDenied(UploadStatusPayload {
-                username: "alice".into(),
-                virtual_path: "Music\\queued.flac".into(),
-                reason: "blocked".into(),
-            })),
-            ProtocolMessage::Peer(PeerMessage::UploadPlaceInLineRequest(
-                UploadPlaceInLineRequestPayload {
-                    virtual_path: "Music\\queued.flac".into(),
-                },
-            )),
-            ProtocolMessage::Peer(PeerMessage::Nothing(OpaquePayload {
-                bytes: vec![0x00, 0x01],
-            })),
-        ]
-    }
-
     #[test]
     fn roundtrip_all_core_messages() {
-        for message in sample_messages() {
+        for message in samples::protocol_messages() {
             let frame = encode_message(&message);
             match &message {
                 ProtocolMessage::Server(_) => {
@@ -5736,6 +5863,188 @@ mod tests {
         }
     }
 
+    #[test]
+    fn distributed_messages_roundtrip_with_single_byte_codes() {
+        for message in samples::distributed_messages() {
+            let frame = encode_distributed_message(&message);
+            let bytes = frame.encode();
+            let (split, consumed) = split_first_distributed_frame(&bytes)
+                .expect("split frame")
+                .expect("complete frame");
+            assert_eq!(consumed, bytes.len());
+            assert_eq!(split, frame);
+            let decoded =
+                decode_distributed_message(split.code, &split.payload).expect("decode distributed");
+            assert_eq!(decoded, message);
+        }
+
+        let level = encode_distributed_message(&DistributedMessage::BranchLevel(
+            DistributedBranchLevelPayload { level: 2 },
+        ));
+        assert_eq!(level.encode(), decode_hex("050000000402000000"));
+        assert!(
+            split_first_distributed_frame(&decode_hex("0500000004"))
+                .expect("partial frame")
+                .is_none()
+        );
+        assert!(decode_distributed_message(CODE_DM_BRANCH_LEVEL, &[1, 0]).is_err());
+        assert!(decode_distributed_message(42, &[]).is_err());
+    }
+
+    #[test]
+    fn dnet_message_unwraps_embedded_distributed_search() {
+        let search = DistributedMessage::SearchRequest(DistributedSearchPayload {
+            unknown: 0x31,
+            username: "searcher".into(),
+            token: 77,
+            query: "flim".into(),
+        });
+        let inner = encode_distributed_message(&search);
+        let mut payload = vec![inner.code];
+        payload.extend_from_slice(&inner.payload);
+
+        let ServerMessage::DnetMessage(dnet) =
+            decode_server_message(CODE_SM_DNET_MESSAGE, &payload).expect("decode dnet message")
+        else {
+            panic!("expected dnet message");
+        };
+        assert_eq!(dnet.embedded_message().expect("embedded message"), search);
+
+        let embedded = DistributedMessage::EmbeddedMessage(dnet.clone());
+        let frame = encode_distributed_message(&embedded);
+        assert_eq!(frame.code, CODE_DM_EMBEDDED_MESSAGE);
+        let DistributedMessage::EmbeddedMessage(decoded) =
+            decode_distributed_message(frame.code, &frame.payload).expect("decode embedded")
+        else {
+            panic!("expected embedded message");
+        };
+        assert_eq!(decoded.embedded_message().expect("unwrap embedded"), search);
+    }
+
+    #[test]
+    fn room_list_reads_user_counts_and_private_room_sections() {
+        let mut writer = PayloadWriter::new();
+        write_room_names(&mut writer, &["nicotine".into(), "electronic".into()]);
+        write_room_user_counts(&mut writer, &[42, 7]);
+        write_room_names(&mut writer, &["secret".into()]);
+        write_room_user_counts(&mut writer, &[3]);
+        write_room_names(&mut writer, &["club".into(), "den".into()]);
+        write_room_user_counts(&mut writer, &[5, 8]);
+        write_room_names(&mut writer, &["den".into()]);
+        let frame = Frame::new(CODE_SM_ROOM_LIST, writer.into_inner());
+
+        let parsed = parse_room_list_payload(&frame.payload).expect("parse room list");
+        assert_eq!(parsed.rooms, vec!["nicotine", "electronic"]);
+        assert_eq!(parsed.user_counts, vec![42, 7]);
+        let private = parsed.private_rooms.clone().expect("private sections");
+        assert_eq!(private.owned, vec!["secret"]);
+        assert_eq!(private.member_user_counts, vec![5, 8]);
+        assert_eq!(private.operated, vec!["den"]);
+        assert_eq!(
+            encode_server_message(&ServerMessage::RoomList(parsed)),
+            frame
+        );
+
+        let mut mismatched = PayloadWriter::new();
+        mismatched.write_u32(1);
+        mismatched.write_string("nicotine");
+        mismatched.write_u32(3);
+        assert!(parse_room_list_payload(&mismatched.into_inner()).is_err());
+    }
+
+    #[test]
+    fn captured_room_list_frames_reencode_byte_exactly() {
+        let capture =
+            include_str!("../../../captures/redacted/login-room-list/official_frames.hex");
+        let frames: Vec<Frame> = capture
+            .lines()
+            .map(|line| Frame::decode(&decode_hex(line)).expect("decode capture frame"))
+            .filter(|frame| frame.code == CODE_SM_ROOM_LIST)
+            .collect();
+        assert!(frames.iter().any(|frame| frame.payload.is_empty()));
+
+        let mut with_private_sections = 0;
+        for frame in &frames {
+            let message = decode_server_message(frame.code, &frame.payload).expect("decode");
+            if let ServerMessage::RoomList(list) = &message
+                && list.private_rooms.is_some()
+            {
+                with_private_sections += 1;
+            }
+            assert_eq!(&encode_server_message(&message), frame);
+        }
+        assert!(with_private_sections > 0);
+    }
+
+    #[test]
+    fn peer_init_messages_roundtrip_with_single_byte_codes() {
+        let pierce = PeerInitMessage::PierceFirewall(PierceFirewallPayload { token: 7 });
+        let bytes = encode_peer_init_message(&pierce).encode();
+        assert_eq!(bytes, decode_hex("050000000007000000"));
+        assert_eq!(
+            decode_peer_init_message(&bytes[4..]).expect("decode pierce"),
+            pierce
+        );
+
+        let init = PeerInitMessage::PeerInit(PeerInitPayload {
+            username: "alice".into(),
+            connection_type: "P".into(),
+            token: 0,
+        });
+        let frame = encode_peer_init_message(&init);
+        assert_eq!(frame.code, CODE_PI_PEER_INIT);
+        let bytes = frame.encode();
+        assert_eq!(bytes.len(), 4 + 1 + 4 + 5 + 4 + 1 + 4);
+        assert_eq!(
+            decode_peer_init_message(&bytes[4..]).expect("decode init"),
+            init
+        );
+
+        assert!(decode_peer_init_message(&[]).is_err());
+        assert!(decode_peer_init_message(&[9, 0, 0, 0, 0]).is_err());
+        assert_eq!(
+            decode_peer_init_message(&[0, 7, 0, 0, 0, 1]).expect("decode trailing"),
+            pierce
+        );
+    }
+
+    #[test]
+    fn known_code_tables_match_the_decoders() {
+        fn unsupported<T>(result: Result<T>) -> bool {
+            result.is_err_and(|err| {
+                matches!(
+                    err.downcast_ref::<DecoderError>(),
+                    Some(DecoderError::UnsupportedCode { .. })
+                )
+            })
+        }
+
+        for code in 0..=400 {
+            assert_eq!(
+                is_known_server_code(code),
+                !unsupported(decode_server_message(code, &[])),
+                "server code {code}"
+            );
+            assert_eq!(
+                is_known_peer_code(code),
+                !unsupported(decode_peer_message(code, &[])),
+                "peer code {code}"
+            );
+        }
+        for code in 0..=u8::MAX {
+            assert_eq!(
+                is_known_distributed_code(code),
+                !unsupported(decode_distributed_message(code, &[])),
+                "distributed code {code}"
+            );
+            assert_eq!(
+                is_known_peer_init_code(code),
+                !unsupported(decode_peer_init_message(&[code])),
+                "peer init code {code}"
+            );
+        }
+    }
+
     #[test]
     fn room_request_builders_emit_expected_codes() {
         assert_eq!(build_room_list_request().code, CODE_SM_ROOM_LIST);
@@ -6564,6 +6873,42 @@ mod tests {
         assert_eq!(decoded.lines, vec!["SongA.flac", "SongB.mp3"]);
     }
 
+    #[test]
+    fn compressed_peer_search_result_reads_bitrate_attribute() {
+        use flate2::{Compression, write::ZlibEncoder};
+        use std::io::Write;
+
+        let mut writer = PayloadWriter::new();
+        writer.write_string("bob");
+        writer.write_u32(77);
+        writer.write_u32(2);
+        writer.write_u8(1);
+        writer.write_string("Music\\\\a.mp3");
+        writer.write_u64(4_096);
+        writer.write_string("mp3");
+        writer.write_u32(2);
+        writer.write_u32(0);
+        writer.write_u32(320);
+        writer.write_u32(1);
+        writer.write_u32(215);
+        writer.write_u8(1);
+        writer.write_string("Music\\\\b.flac");
+        writer.write_u64(8_192);
+        writer.write_string("");
+        writer.write_u32(0);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&writer.into_inner()).expect("zlib write");
+        let compressed = encoder.finish().expect("zlib finish");
+
+        let decoded =
+            parse_peer_file_search_result_payload(&compressed).expect("compressed result");
+        assert_eq!(decoded.token, 77);
+        assert_eq!(decoded.files.len(), 2);
+        assert_eq!(decoded.files[0].bitrate, Some(320));
+        assert_eq!(decoded.files[1].bitrate, None);
+        assert_eq!(decoded.files[1].extension, "flac");
+    }
+
     #[test]
     fn shared_files_in_folder_decompression_parser_rejects_oversized_listing() {
         use flate2::{Compression, write::ZlibEncoder};
@@ -6683,6 +7028,130 @@ mod tests {
         assert_eq!(frame.encode(), expected);
     }
 
+    #[test]
+    fn scoped_search_fixtures_match() {
+        let room = build_search_room_request("indie", "flim");
+        let expected = decode_hex("150000007800000005000000696e64696504000000666c696d");
+        assert_eq!(room.encode(), expected);
+
+        let user = build_search_user_files_request("alice", "flim");
+        let expected = decode_hex("150000002a00000005000000616c69636504000000666c696d");
+        assert_eq!(user.encode(), expected);
+    }
+
+    #[test]
+    fn add_user_response_decodes_missing_and_watched_users() {
+        let missing = decode_server_message(
+            CODE_SM_ADD_USER,
+            &decode_hex("0c00000072756e74696d652d7573657200"),
+        )
+        .expect("decode missing user");
+        assert_eq!(
+            missing,
+            ServerMessage::AddUserResponse(AddUserResponsePayload {
+                username: "runtime-user".into(),
+                exists: false,
+                stats: None,
+            })
+        );
+
+        let request = build_add_user_request("runtime-user");
+        assert_eq!(
+            decode_server_message(request.code, &request.payload).expect("decode request"),
+            ServerMessage::AddUser(UserLookupPayload {
+                username: "runtime-user".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn wishlist_wait_decodes_interval_seconds() {
+        let decoded = decode_server_message(CODE_SM_WISHLIST_WAIT, &decode_hex("d0020000"))
+            .expect("decode wishlist wait");
+        assert_eq!(
+            decoded,
+            ServerMessage::WishlistWait(WishlistWaitPayload {
+                interval_seconds: 720
+            })
+        );
+
+        let search = build_wishlist_search_request(7, "flim");
+        assert_eq!(search.code, CODE_SM_LOW_PRIORITY_FILE_SEARCH);
+    }
+
+    #[test]
+    fn legacy_messages_decode_capture_payloads_into_typed_fields() {
+        let token = decode_hex("0c00000072756e74696d652d7573657207000000");
+        assert_eq!(
+            decode_server_message(CODE_SM_SEND_CONNECT_TOKEN, &token).expect("decode token"),
+            ServerMessage::SendConnectToken(SendConnectTokenPayload {
+                username: "runtime-user".into(),
+                token: 7,
+            })
+        );
+
+        let place = decode_hex("180000004d757369635c52756e74696d655c747261636b2e666c616304000000");
+        let expected = PlaceInLinePayload {
+            virtual_path: "Music\\Runtime\\track.flac".into(),
+            place: 4,
+        };
+        assert_eq!(
+            decode_server_message(CODE_SM_PLACE_IN_LINE, &place).expect("decode place"),
+            ServerMessage::PlaceInLine(expected.clone())
+        );
+        assert_eq!(
+            decode_server_message(CODE_SM_PLACE_IN_LINE_RESPONSE, &place)
+                .expect("decode place response"),
+            ServerMessage::PlaceInLineResponse(expected)
+        );
+
+        let say = decode_hex("0b00000072756e74696d6520736179");
+        assert_eq!(
+            decode_peer_message(CODE_PM_SAY, &say).expect("decode say"),
+            PeerMessage::Say(SayPayload {
+                message: "runtime say".into(),
+            })
+        );
+
+        let placeholder = decode_hex("72756e74696d652d706c616365686f6c646572");
+        let decoded =
+            decode_peer_message(CODE_PM_PLACEHOLD_UPLOAD, &placeholder).expect("decode raw");
+        assert_eq!(
+            decoded,
+            PeerMessage::PlaceholderUpload(PlaceholderUploadPayload {
+                virtual_path: "runtime-placeholder".into(),
+                encoding: PeerTextEncoding::Raw,
+            })
+        );
+        assert_eq!(encode_peer_message(&decoded).payload, placeholder);
+
+        let nothing = decode_hex("72756e74696d652d6e6f7468696e67");
+        let decoded = decode_peer_message(CODE_PM_NOTHING, &nothing).expect("decode nothing");
+        assert_eq!(
+            decoded,
+            PeerMessage::Nothing(NothingPayload {
+                text: "runtime-nothing".into(),
+                encoding: PeerTextEncoding::Raw,
+            })
+        );
+        assert_eq!(encode_peer_message(&decoded).payload, nothing);
+        assert_eq!(
+            decode_peer_message(CODE_PM_NOTHING, &[0xff, 0xfe]).expect("decode lossy"),
+            PeerMessage::Nothing(NothingPayload {
+                text: "\u{fffd}\u{fffd}".into(),
+                encoding: PeerTextEncoding::Raw,
+            })
+        );
+        let mut say_trailing = say.clone();
+        say_trailing.push(0);
+        assert_eq!(
+            decode_peer_message(CODE_PM_SAY, &say_trailing).expect("decode say trailing"),
+            PeerMessage::Say(SayPayload {
+                message: "runtime say".into(),
+            })
+        );
+    }
+
     #[test]
     fn transfer_request_fixture_matches_download_shape() {
         let frame = build_transfer_request(
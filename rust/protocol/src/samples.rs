@@ -0,0 +1,724 @@
+//! Representative messages for every codec variant. The protocol tests
+//! round-trip them, and `verify coverage` uses them as round-trip evidence.
+
+use super::*;
+
+pub fn protocol_messages() -> Vec<ProtocolMessage> {
+    vec![
+        ProtocolMessage::Server(ServerMessage::Login(LoginPayload {
+            username: "alice".into(),
+            password: "secret-pass".into(),
+            client_version: 157,
+            md5hash: compute_login_md5hash("alice", "secret-pass"),
+            minor_version: 19,
+        })),
+        ProtocolMessage::Server(ServerMessage::LoginResponse(LoginResponsePayload::Success(
+            LoginResponseSuccessPayload {
+                greeting: "welcome".into(),
+                ip_address: "127.0.0.1".into(),
+                md5hash: "0123456789abcdef0123456789abcdef".into(),
+                is_supporter: true,
+            },
+        ))),
+        ProtocolMessage::Server(ServerMessage::SetWaitPort(SetWaitPortPayload {
+            listen_port: 2234,
+        })),
+        ProtocolMessage::Server(ServerMessage::AddChatRoom(AddChatRoomPayload {
+            room: "new-room".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::GetPeerAddress(UserLookupPayload {
+            username: "bob".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::GetPeerAddressResponse(
+            PeerAddressResponsePayload {
+                username: "bob".into(),
+                ip_address: "203.0.113.5".into(),
+                port: 2234,
+                obfuscation_type: 1,
+                obfuscated_port: 40123,
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::IgnoreUser(UserLookupPayload {
+            username: "eve".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::UnignoreUser(UserLookupPayload {
+            username: "eve".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::SayChatRoom(SayChatRoomPayload {
+            room: "nicotine".into(),
+            username: Some("alice".into()),
+            message: "hello room".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::JoinRoom(JoinRoomPayload {
+            room: "nicotine".into(),
+            users: vec!["alice".into(), "bob".into()],
+        })),
+        ProtocolMessage::Server(ServerMessage::LeaveRoom(LeaveRoomPayload {
+            room: "nicotine".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::UserJoinedRoom(RoomPresenceEventPayload {
+            room: "nicotine".into(),
+            username: "carol".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::UserLeftRoom(RoomPresenceEventPayload {
+            room: "nicotine".into(),
+            username: "dave".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::ConnectToPeerRequest(
+            ConnectToPeerRequestPayload {
+                token: 77,
+                username: "bob".into(),
+                connection_type: "P".into(),
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::ConnectToPeerResponse(
+            ConnectToPeerResponsePayload {
+                username: "bob".into(),
+                connection_type: "P".into(),
+                ip_address: "198.51.100.24".into(),
+                port: 5566,
+                token: 77,
+                privileged: false,
+                obfuscation_type: 0,
+                obfuscated_port: 0,
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::ConnectToPeer(ConnectToPeerPayload {
+            username: "bob".into(),
+            token: 77,
+        })),
+        ProtocolMessage::Server(ServerMessage::RoomList(RoomListPayload {
+            room_count: 2,
+            rooms: vec!["nicotine".into(), "electronic".into()],
+            user_counts: Vec::new(),
+            private_rooms: None,
+        })),
+        ProtocolMessage::Server(ServerMessage::RoomList(RoomListPayload {
+            room_count: 2,
+            rooms: vec!["nicotine".into(), "electronic".into()],
+            user_counts: vec![42, 7],
+            private_rooms: Some(PrivateRoomLists {
+                owned: vec!["secret".into()],
+                owned_user_counts: vec![3],
+                ..PrivateRoomLists::default()
+            }),
+        })),
+        ProtocolMessage::Server(ServerMessage::FileSearch(FileSearchPayload {
+            search_token: 12345,
+            search_text: "aphex twin".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::FileSearchResponseSummary(
+            SearchResponseSummary {
+                username: "peer_user".into(),
+                token: 12345,
+                files_count: 1,
+                slots_free: 2,
+                speed: 4096,
+                in_queue: false,
+                files: vec![SearchFileSummary {
+                    file_path: "Music\\Aphex Twin\\Track.flac".into(),
+                    file_size: 123_456,
+                    extension: "flac".into(),
+                    attr_count: 3,
+                }],
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::SearchRoom(SearchRoomPayload {
+            room: "electronic".into(),
+            search_text: "selected ambient works".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::ExactFileSearch(ExactFileSearchPayload {
+            virtual_path: "Music\\Aphex Twin\\Track.flac".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::SearchUserFiles(SearchUserFilesPayload {
+            username: "bob".into(),
+            search_text: "flac".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::GetSimilarTerms(SimilarTermsRequestPayload {
+            term: "electronic".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::AddLikeTerm(SimilarTermsRequestPayload {
+            term: "idm".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::RemoveLikeTerm(SimilarTermsRequestPayload {
+            term: "idm".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::GetSimilarTermsResponse(
+            SimilarTermsPayload {
+                term: "electronic".into(),
+                entries: vec![RecommendationEntry {
+                    term: "idm".into(),
+                    score: 7,
+                }],
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::GetRecommendations(EmptyPayload)),
+        ProtocolMessage::Server(ServerMessage::GetRecommendationsResponse(
+            RecommendationsPayload {
+                recommendations: vec![RecommendationEntry {
+                    term: "flac".into(),
+                    score: 3,
+                }],
+                unrecommendations: vec![RecommendationEntry {
+                    term: "low-bitrate".into(),
+                    score: -2,
+                }],
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::GetMyRecommendations(EmptyPayload)),
+        ProtocolMessage::Server(ServerMessage::GetMyRecommendationsResponse(
+            RecommendationsPayload {
+                recommendations: vec![RecommendationEntry {
+                    term: "ambient".into(),
+                    score: 4,
+                }],
+                unrecommendations: vec![],
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::GetGlobalRecommendations(EmptyPayload)),
+        ProtocolMessage::Server(ServerMessage::GetGlobalRecommendationsResponse(
+            RecommendationsPayload {
+                recommendations: vec![RecommendationEntry {
+                    term: "lossless".into(),
+                    score: 8,
+                }],
+                unrecommendations: vec![RecommendationEntry {
+                    term: "ads".into(),
+                    score: -4,
+                }],
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::GetOwnPrivilegesStatus(EmptyPayload)),
+        ProtocolMessage::Server(ServerMessage::OwnPrivilegesStatus(
+            OwnPrivilegesStatusPayload {
+                time_left_seconds: 86_400,
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::GetUserPrivilegesStatus(UserLookupPayload {
+            username: "bob".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::UserPrivilegesStatus(
+            UserPrivilegesStatusPayload {
+                username: "bob".into(),
+                privileged: true,
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::GivePrivilege(GivePrivilegePayload {
+            username: "bob".into(),
+            days: 7,
+        })),
+        ProtocolMessage::Server(ServerMessage::InformUserOfPrivileges(
+            InformUserOfPrivilegesPayload {
+                token: 1234,
+                username: "bob".into(),
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::InformUserOfPrivilegesAck(
+            InformUserOfPrivilegesAckPayload { token: 1234 },
+        )),
+        ProtocolMessage::Server(ServerMessage::GetUserRecommendations(UserLookupPayload {
+            username: "bob".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::GetUserRecommendationsResponse(
+            UserRecommendationsPayload {
+                username: "bob".into(),
+                recommendations: RecommendationsPayload {
+                    recommendations: vec![RecommendationEntry {
+                        term: "aphex".into(),
+                        score: 9,
+                    }],
+                    unrecommendations: vec![],
+                },
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::PrivilegedList(PrivilegedListPayload {
+            users: vec!["alice".into(), "bob".into()],
+        })),
+        ProtocolMessage::Server(ServerMessage::GetRecommendedUsers(EmptyPayload)),
+        ProtocolMessage::Server(ServerMessage::GetRecommendedUsersResponse(
+            RecommendedUsersPayload {
+                users: vec![ScoredUserEntry {
+                    username: "alice".into(),
+                    score: 12,
+                }],
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::GetTermRecommendations(
+            SimilarTermsRequestPayload { term: "idm".into() },
+        )),
+        ProtocolMessage::Server(ServerMessage::GetTermRecommendationsResponse(
+            TermRecommendationsPayload {
+                term: "idm".into(),
+                recommendations: vec![RecommendationEntry {
+                    term: "ambient".into(),
+                    score: 5,
+                }],
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::GetRecommendationUsers(
+            SimilarTermsRequestPayload { term: "idm".into() },
+        )),
+        ProtocolMessage::Server(ServerMessage::GetRecommendationUsersResponse(
+            RecommendationUsersPayload {
+                term: "idm".into(),
+                users: vec![ScoredUserEntry {
+                    username: "charlie".into(),
+                    score: 3,
+                }],
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::BanUser(UserLookupPayload {
+            username: "mallory".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::RoomMembers(RoomMembersPayload {
+            room: "nicotine".into(),
+            users: vec!["alice".into(), "bob".into(), "carol".into()],
+        })),
+        ProtocolMessage::Server(ServerMessage::RoomOperators(RoomOperatorsPayload {
+            room: "nicotine".into(),
+            operators: vec!["alice".into()],
+        })),
+        ProtocolMessage::Server(ServerMessage::AddRoomMember(RoomModerationPayload {
+            room: "private-room".into(),
+            username: "bob".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::RemoveRoomMember(RoomModerationPayload {
+            room: "private-room".into(),
+            username: "bob".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::AddRoomOperator(RoomModerationPayload {
+            room: "private-room".into(),
+            username: "alice".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::RemoveRoomOperator(RoomModerationPayload {
+            room: "private-room".into(),
+            username: "alice".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::SetParentMinSpeed(ParentMinSpeedPayload {
+            min_speed: 1,
+        })),
+        ProtocolMessage::Server(ServerMessage::SetParentSpeedConnectionRatio(
+            ParentSpeedConnectionRatioPayload { ratio: 50 },
+        )),
+        ProtocolMessage::Server(ServerMessage::GetRoomTicker(RoomTickerRequestPayload {
+            room: "nicotine".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::RoomTicker(RoomTickerPayload {
+            room: "nicotine".into(),
+            entries: vec![
+                RoomTickerEntry {
+                    username: "alice".into(),
+                    ticker: "hello".into(),
+                },
+                RoomTickerEntry {
+                    username: "bob".into(),
+                    ticker: "world".into(),
+                },
+            ],
+        })),
+        ProtocolMessage::Server(ServerMessage::MessageUserIncoming(
+            MessageUserIncomingPayload {
+                message_id: 91,
+                timestamp: 1_705_000_000,
+                username: "bob".into(),
+                message: "hello inbound".into(),
+                is_new: true,
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::MessageUser(MessageUserPayload {
+            username: "bob".into(),
+            message: "hello".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::MessageAcked(MessageAckedPayload {
+            message_id: 55,
+        })),
+        ProtocolMessage::Server(ServerMessage::MessageUsers(MessageUsersPayload {
+            usernames: vec!["alice".into(), "bob".into()],
+            message: "broadcast test".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::PeerMessage(PeerMessagePayload {
+            username: "bob".into(),
+            message: "legacy request".into(),
+            token: Some(17),
+            code: Some(40),
+            ip_address: None,
+            port: None,
+        })),
+        ProtocolMessage::Server(ServerMessage::PeerMessage(PeerMessagePayload {
+            username: "bob".into(),
+            message: "legacy response".into(),
+            token: Some(17),
+            code: Some(40),
+            ip_address: Some("198.51.100.15".into()),
+            port: Some(2242),
+        })),
+        ProtocolMessage::Server(ServerMessage::GetUserStats(UserLookupPayload {
+            username: "bob".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::GetUserStatsResponse(
+            UserStatsResponsePayload {
+                username: "bob".into(),
+                avg_speed: 2048,
+                download_num: 12,
+                files: 500,
+                dirs: 42,
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::GetUserStatus(UserLookupPayload {
+            username: "bob".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::GetUserStatusResponse(
+            UserStatusResponsePayload {
+                username: "bob".into(),
+                status: 2,
+                privileged: true,
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::SharedFoldersFiles(
+            SharedFoldersFilesPayload {
+                folder_count: 12,
+                file_count: 200,
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::DownloadSpeed(SpeedPayload {
+            bytes_per_sec: 2048,
+        })),
+        ProtocolMessage::Server(ServerMessage::UploadSpeed(SpeedPayload {
+            bytes_per_sec: 1024,
+        })),
+        ProtocolMessage::Server(ServerMessage::AddUser(UserLookupPayload {
+            username: "new-user".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::AddUserResponse(AddUserResponsePayload {
+            username: "new-user".into(),
+            exists: true,
+            stats: Some(WatchedUserStats {
+                status: 2,
+                avg_speed: 4096,
+                upload_num: 12,
+                files: 340,
+                dirs: 21,
+                country_code: Some("NL".into()),
+            }),
+        })),
+        ProtocolMessage::Server(ServerMessage::AddUserResponse(AddUserResponsePayload {
+            username: "missing-user".into(),
+            exists: false,
+            stats: None,
+        })),
+        ProtocolMessage::Server(ServerMessage::RemoveUser(UserLookupPayload {
+            username: "old-user".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::LowPriorityFileSearch(FileSearchPayload {
+            search_token: 99,
+            search_text: "wishlist ambient".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::SetStatus(SetStatusPayload { status: 2 })),
+        ProtocolMessage::Server(ServerMessage::Heartbeat(HeartbeatPayload {
+            sequence: Some(1234),
+        })),
+        ProtocolMessage::Server(ServerMessage::Command(CommandPayload {
+            command: "/whois alice".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::RoomAdded(RoomNamePayload {
+            room: "new-lounge".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::RoomRemoved(RoomNamePayload {
+            room: "old-lounge".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::AdminMessage(AdminMessagePayload {
+            message: "maintenance window".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::SetParentInactivityBeforeDisconnect(
+            ParentInactivityBeforeDisconnectPayload { seconds: 60 },
+        )),
+        ProtocolMessage::Server(ServerMessage::SetServerInactivityBeforeDisconnect(
+            ServerInactivityBeforeDisconnectPayload { seconds: 120 },
+        )),
+        ProtocolMessage::Server(ServerMessage::NodesInCacheBeforeDisconnect(
+            NodesInCacheBeforeDisconnectPayload { nodes: 128 },
+        )),
+        ProtocolMessage::Server(ServerMessage::SetSecondsBeforePingChildren(
+            SecondsBeforePingChildrenPayload { seconds: 30 },
+        )),
+        ProtocolMessage::Server(ServerMessage::CanParent(CanParentPayload {
+            can_parent: true,
+        })),
+        ProtocolMessage::Server(ServerMessage::AddHateTerm(SimilarTermsRequestPayload {
+            term: "noise".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::RemoveHateTerm(SimilarTermsRequestPayload {
+            term: "noise".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::DnetReset(DnetResetPayload {
+            reason: Some(1),
+        })),
+        ProtocolMessage::Server(ServerMessage::RemoveOwnRoomMembership(RoomNamePayload {
+            room: "private-room".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::GiveUpRoom(RoomNamePayload {
+            room: "private-room".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::AddRoomMembership(RoomNamePayload {
+            room: "private-room".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::RemoveRoomMembership(RoomNamePayload {
+            room: "private-room".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::AddRoomOperatorship(RoomNamePayload {
+            room: "private-room".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::SendConnectToken(SendConnectTokenPayload {
+            username: "runtime-user".into(),
+            token: 7,
+        })),
+        ProtocolMessage::Server(ServerMessage::PlaceInLine(PlaceInLinePayload {
+            virtual_path: "Music\\Runtime\\track.flac".into(),
+            place: 4,
+        })),
+        ProtocolMessage::Server(ServerMessage::PlaceInLineResponse(PlaceInLinePayload {
+            virtual_path: "Music\\Runtime\\track.flac".into(),
+            place: 4,
+        })),
+        ProtocolMessage::Server(ServerMessage::AddPrivilegedUser(UserLookupPayload {
+            username: "vip-user".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::WishlistWait(WishlistWaitPayload {
+            interval_seconds: 720,
+        })),
+        ProtocolMessage::Server(ServerMessage::DnetLevel(DnetLevelPayload {
+            level: Some(1),
+            extension_reserved_bytes: Vec::new(),
+        })),
+        ProtocolMessage::Server(ServerMessage::DnetGroupLeader(DnetGroupLeaderPayload {
+            username: Some("branch-root".into()),
+            extension_reserved_bytes: Vec::new(),
+        })),
+        ProtocolMessage::Server(ServerMessage::DnetDeliveryReport(
+            DnetDeliveryReportPayload {
+                report: Some(3),
+                extension_reserved_bytes: Vec::new(),
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::DnetChildDepth(DnetChildDepthPayload {
+            depth: Some(4),
+            extension_reserved_bytes: Vec::new(),
+        })),
+        ProtocolMessage::Server(ServerMessage::Flood(FloodPayload {
+            flood_code: Some(5),
+            extension_reserved_bytes: Vec::new(),
+        })),
+        ProtocolMessage::Server(ServerMessage::RemoveRoomOperatorship(
+            RoomOperatorshipRevocationPayload {
+                room: Some("private-room".into()),
+                extension_reserved_bytes: Vec::new(),
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::RemoveOwnRoomOperatorship(
+            RoomOperatorshipRevocationPayload {
+                room: Some("private-room".into()),
+                extension_reserved_bytes: Vec::new(),
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::JoinGlobalRoom(GlobalRoomTogglePayload {
+            room: None,
+        })),
+        ProtocolMessage::Server(ServerMessage::LeaveGlobalRoom(GlobalRoomTogglePayload {
+            room: None,
+        })),
+        ProtocolMessage::Server(ServerMessage::SayGlobalRoom(GlobalRoomMessagePayload {
+            message: "global hello".into(),
+        })),
+        ProtocolMessage::Server(ServerMessage::SearchCorrelations(
+            SearchCorrelationsPayload {
+                term: "ambient".into(),
+            },
+        )),
+        ProtocolMessage::Server(ServerMessage::Relogged(ReloggedPayload)),
+        ProtocolMessage::Server(ServerMessage::UserList(UserListPayload {
+            users: vec![UserListEntryPayload {
+                username: "alice".into(),
+                status: Some(2),
+                avg_speed: Some(320),
+                upload_num: Some(12),
+                unknown: Some(0),
+                files: Some(2_000),
+                dirs: Some(100),
+                slots_full: Some(0),
+                country: Some("AR".into()),
+            }],
+        })),
+        ProtocolMessage::Server(ServerMessage::GlobalUserList(UserListPayload {
+            users: vec![UserListEntryPayload {
+                username: "alice".into(),
+                status: Some(2),
+                avg_speed: Some(320),
+                upload_num: Some(12),
+                unknown: Some(0),
+                files: Some(2_000),
+                dirs: Some(100),
+                slots_full: Some(0),
+                country: Some("AR".into()),
+            }],
+        })),
+        ProtocolMessage::Peer(PeerMessage::Say(SayPayload {
+            message: "peer say".into(),
+        })),
+        ProtocolMessage::Peer(PeerMessage::GetSharedFileList(UserLookupPayload {
+            username: "alice".into(),
+        })),
+        ProtocolMessage::Peer(PeerMessage::SharedFileList(SharedFileListPayload {
+            entries: vec![
+                SharedFileEntry {
+                    virtual_path: "Music\\A.flac".into(),
+                    size: 100,
+                },
+                SharedFileEntry {
+                    virtual_path: "Music\\B.flac".into(),
+                    size: 200,
+                },
+            ],
+        })),
+        ProtocolMessage::Peer(PeerMessage::GetSharedFilesInFolder(
+            SharedFilesInFolderRequestPayload {
+                directory: "Music\\Albums".into(),
+            },
+        )),
+        ProtocolMessage::Peer(PeerMessage::SharedFilesInFolder(
+            SharedFilesInFolderPayload {
+                directory: "Music\\Albums".into(),
+                compressed_listing: vec![0x78, 0x9c, 0x03, 0x00],
+            },
+        )),
+        ProtocolMessage::Peer(PeerMessage::FileSearchRequest(FileSearchRequestPayload {
+            token: 9,
+            query: "ambient".into(),
+        })),
+        ProtocolMessage::Peer(PeerMessage::FileSearchResult(FileSearchResultPayload {
+            token: 9,
+            username: "bob".into(),
+            result_count: 2,
+            files: Vec::new(),
+            extension_tail: Vec::new(),
+        })),
+        ProtocolMessage::Peer(PeerMessage::InviteUserToRoom(PeerRoomInvitePayload {
+            room: "nicotine".into(),
+        })),
+        ProtocolMessage::Peer(PeerMessage::CancelledQueuedTransfer(
+            PeerVirtualPathPayload {
+                virtual_path: "Music\\A.flac".into(),
+            },
+        )),
+        ProtocolMessage::Peer(PeerMessage::UserInfoRequest(UserInfoRequestPayload)),
+        ProtocolMessage::Peer(PeerMessage::UserInfoReply(UserInfoReplyPayload {
+            description: "hello".into(),
+            has_picture: false,
+            picture: Vec::new(),
+            total_uploads: 12,
+            queue_size: 2,
+            slots_free: true,
+            upload_permissions: Some(1),
+        })),
+        ProtocolMessage::Peer(PeerMessage::SendConnectToken(SendConnectTokenPayload {
+            username: "alice".into(),
+            token: 33,
+        })),
+        ProtocolMessage::Peer(PeerMessage::TransferRequest(TransferRequestPayload {
+            direction: TransferDirection::Download,
+            token: 555,
+            virtual_path: "Music\\Aphex Twin\\Track.flac".into(),
+            file_size: 123_456_789,
+        })),
+        ProtocolMessage::Peer(PeerMessage::TransferResponse(TransferResponsePayload {
+            token: 555,
+            allowed: true,
+            queue_or_reason: String::new(),
+        })),
+        ProtocolMessage::Peer(PeerMessage::PlaceholderUpload(PlaceholderUploadPayload {
+            virtual_path: "Music\\placeholder.flac".into(),
+            encoding: PeerTextEncoding::LengthPrefixed,
+        })),
+        ProtocolMessage::Peer(PeerMessage::PlaceholderUpload(PlaceholderUploadPayload {
+            virtual_path: "runtime-placeholder".into(),
+            encoding: PeerTextEncoding::Raw,
+        })),
+        ProtocolMessage::Peer(PeerMessage::QueueUpload(QueueUploadPayload {
+            username: "alice".into(),
+            virtual_path: "Music\\queued.flac".into(),
+        })),
+        ProtocolMessage::Peer(PeerMessage::UploadPlaceInLine(UploadPlaceInLinePayload {
+            username: "alice".into(),
+            virtual_path: "Music\\queued.flac".into(),
+            place: 3,
+        })),
+        ProtocolMessage::Peer(PeerMessage::MoveDownloadToTop(PeerVirtualPathPayload {
+            virtual_path: "Music\\queued.flac".into(),
+        })),
+        ProtocolMessage::Peer(PeerMessage::ExactFileSearchRequest(
+            PeerSearchQueryPayload {
+                token: Some(123),
+                query: "Music\\A.flac".into(),
+            },
+        )),
+        ProtocolMessage::Peer(PeerMessage::QueuedDownloads(PeerQueuedDownloadsPayload {
+            virtual_paths: vec!["Music\\A.flac".into(), "Music\\B.flac".into()],
+        })),
+        ProtocolMessage::Peer(PeerMessage::IndirectFileSearchRequest(
+            PeerSearchQueryPayload {
+                token: None,
+                query: "A.flac".into(),
+            },
+        )),
+        ProtocolMessage::Peer(PeerMessage::UploadFailed(UploadStatusPayload {
+            username: "alice".into(),
+            virtual_path: "Music\\queued.flac".into(),
+            reason: "network".into(),
+        })),
+        ProtocolMessage::Peer(PeerMessage::UploadDenied(UploadStatusPayload {
+            username: "alice".into(),
+            virtual_path: "Music\\queued.flac".into(),
+            reason: "blocked".into(),
+        })),
+        ProtocolMessage::Peer(PeerMessage::UploadPlaceInLineRequest(
+            UploadPlaceInLineRequestPayload {
+                virtual_path: "Music\\queued.flac".into(),
+            },
+        )),
+        ProtocolMessage::Peer(PeerMessage::Nothing(NothingPayload {
+            text: "runtime-nothing".into(),
+            encoding: PeerTextEncoding::Raw,
+        })),
+    ]
+}
+
+pub fn distributed_messages() -> Vec<DistributedMessage> {
+    vec![
+        DistributedMessage::Ping(EmptyPayload),
+        DistributedMessage::SearchRequest(DistributedSearchPayload {
+            unknown: 0x31,
+            username: "searcher".into(),
+            token: 4242,
+            query: "aphex twin flim".into(),
+        }),
+        DistributedMessage::BranchLevel(DistributedBranchLevelPayload { level: -1 }),
+        DistributedMessage::BranchRoot(DistributedBranchRootPayload {
+            root_username: "branch-root".into(),
+        }),
+        DistributedMessage::ChildDepth(DistributedChildDepthPayload { depth: 3 }),
+        DistributedMessage::EmbeddedMessage(DnetMessagePayload {
+            distrib_code: CODE_DM_CHILD_DEPTH,
+            distrib_payload: vec![3, 0, 0, 0],
+        }),
+    ]
+}
+
+pub fn peer_init_messages() -> Vec<PeerInitMessage> {
+    vec![
+        PeerInitMessage::PierceFirewall(PierceFirewallPayload { token: 7 }),
+        PeerInitMessage::PeerInit(PeerInitPayload {
+            username: "alice".into(),
+            connection_type: "P".into(),
+            token: 0,
+        }),
+    ]
+}
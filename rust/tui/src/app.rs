@@ -1,30 +1,33 @@
+use std::collections::{HashMap, VecDeque};
 use std::env;
 use std::path::PathBuf;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
-use protocol::ServerMessage;
+use protocol::{MessageUserIncomingPayload, ServerMessage};
 use soul_core::{
-    probe_login_versions, Credentials, SearchMode, SearchResultSource,
-    SearchSelectDownloadRequest, SessionClient, SessionState,
+    AutoAway, BuddyEvent, CandidateDownloadRequest, ChatStore, CredentialStore, Credentials,
+    SearchCandidate, SearchMode, SearchScope, SessionClient, SessionEvent, SessionState,
+    TransferMeter, UserPresence, WishlistItem, WishlistScheduler, local_download_path,
+    probe_login_versions, session_events,
 };
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 
+use crate::conversations::ConversationTabs;
+use crate::rooms::RoomsState;
+use crate::searches::{SearchRow, SearchStatus, SearchTabs};
+use crate::shares::SharesState;
 use crate::state::{
-    PersistedAppStateV1, PersistedDownloadEntry, PersistedDownloadStatus,
-    recover_in_progress_downloads,
+    MainTab, PersistedAppState, PersistedDownloadEntry, PersistedDownloadStatus,
+    PersistedWishlistEntry, SearchTargetKind, recover_in_progress_downloads,
 };
-use crate::storage;
+use crate::storage::{self, PasswordOrigin};
+use crate::tasks::{self, ReaderTask, TaskEvent, TaskSession};
 
 const LOG_LIMIT: usize = 120;
-
-#[derive(Debug, Clone)]
-pub struct SearchRow {
-    pub username: String,
-    pub file_path: String,
-    pub file_size: u64,
-    pub peer_addr: Option<String>,
-    pub source: SearchResultSource,
-}
+const SCROLL_STEP: isize = 10;
+/// How long a search tab keeps collecting streamed results.
+const SEARCH_WINDOW: Duration = Duration::from_secs(8);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UiPhase {
@@ -36,6 +39,14 @@ pub enum UiPhase {
 pub enum InputMode {
     Normal,
     EditingQuery,
+    EditingTarget,
+    EditingBuddy,
+    EditingFilter,
+    EditingRoomName,
+    EditingRoomMessage,
+    EditingChatUser,
+    EditingChatMessage,
+    EditingBrowseUser,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -43,22 +54,27 @@ pub enum LoginField {
     Server,
     Username,
     Password,
+    /// Unlocks the encrypted credential store; only offered while it is locked.
+    Passphrase,
 }
 
 impl LoginField {
-    pub fn next(self) -> Self {
+    pub fn next(self, with_passphrase: bool) -> Self {
         match self {
             Self::Server => Self::Username,
             Self::Username => Self::Password,
-            Self::Password => Self::Server,
+            Self::Password if with_passphrase => Self::Passphrase,
+            Self::Password | Self::Passphrase => Self::Server,
         }
     }
 
-    pub fn previous(self) -> Self {
+    pub fn previous(self, with_passphrase: bool) -> Self {
         match self {
+            Self::Server if with_passphrase => Self::Passphrase,
             Self::Server => Self::Password,
             Self::Username => Self::Server,
             Self::Password => Self::Username,
+            Self::Passphrase => Self::Password,
         }
     }
 }
@@ -69,38 +85,117 @@ pub enum PendingAction {
     Login,
     Search,
     Download,
+    DownloadFolder,
+    ToggleBuddy,
+    ListRooms,
+    JoinRoom,
+    LeaveRoom,
+    SayRoom,
+    SendPrivateMessage,
+    BrowseUser,
+    RefreshShares,
     RunDiagnostics,
     Quit,
 }
 
+/// Cursor of each list-style tab; search tabs keep their own cursor.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TabSelection {
+    pub transfer: usize,
+    pub chat: usize,
+    pub user: usize,
+}
+
 pub struct App {
     pub phase: UiPhase,
     pub login_focus: LoginField,
     pub input_mode: InputMode,
     pub query_buffer: String,
-    pub selected_result: usize,
+    pub target_buffer: String,
+    pub buddy_buffer: String,
+    /// Room name or chat line being typed on the Rooms tab.
+    pub room_buffer: String,
+    /// Username or private message being typed on the Chat tab.
+    pub chat_buffer: String,
+    /// Filter bar text being edited for the active search tab.
+    pub filter_buffer: String,
+    /// User whose shares are browsed next.
+    pub browse_buffer: String,
     pub session_state: SessionState,
     pub login_error: Option<String>,
     pub logs: Vec<String>,
-    pub results: Vec<SearchRow>,
+    pub searches: SearchTabs,
     pub diagnostics_visible: bool,
     pub diagnostics_lines: Vec<String>,
-    pub state: PersistedAppStateV1,
+    pub state: PersistedAppState,
     pub output_dir: PathBuf,
+    pub presence: UserPresence,
+    /// Live progress of running downloads, keyed by download id.
+    pub transfers: HashMap<String, TransferMeter>,
+    pub chats: ChatStore,
+    pub conversations: ConversationTabs,
+    pub tab_selection: TabSelection,
+    pub rooms: RoomsState,
+    pub shares: SharesState,
+    /// Files waiting for the session, started one at a time in order.
+    pub download_queue: VecDeque<(String, SearchCandidate)>,
+    /// Download whose transfer is running; it no longer holds the session,
+    /// but the next queued file waits for it.
+    active_download: Option<String>,
+    /// Label of the background task currently holding the session.
+    busy: Option<&'static str>,
+    task_tx: UnboundedSender<TaskEvent>,
+    task_rx: UnboundedReceiver<TaskEvent>,
+    pending_presence: Option<UserPresence>,
+    /// Where the login password is kept; `None` keeps it in memory only.
+    credentials: Option<Box<dyn CredentialStore>>,
+    /// The file store could be opened with a passphrase typed at login.
+    store_locked: bool,
+    /// Typed on the login screen and dropped once the store is opened.
+    store_passphrase: String,
+    auto_away: AutoAway,
     auto_login_pending: bool,
     transfer_token: u32,
+    search_token: u32,
+    wishlist: WishlistScheduler,
+    wishlist_token: u32,
     session: Option<SessionClient>,
+    /// Forwards server pushes while the session idles here.
+    reader: Option<ReaderTask>,
+    /// Private messages that arrived through the reader and await their ack.
+    pending_acks: Vec<u32>,
 }
 
 impl App {
     pub fn bootstrap() -> anyhow::Result<Self> {
         let mut state = storage::load_state()?;
         let mut changed = false;
-
-        // Migrate legacy default server port to current production default.
-        if state.server.trim() == "server.slsknet.org:2242" {
-            state.server = "server.slsknet.org:2416".to_string();
-            changed = true;
+        let mut logs =
+            vec!["NeoSoulSeek ready. Login is required before search/download.".to_string()];
+
+        let mut store_locked = false;
+        let mut credentials = match soul_core::open_credential_store() {
+            Ok(store) => {
+                store_locked = store.is_none() && soul_core::credential_store_uses_passphrase();
+                store
+            }
+            Err(err) => {
+                logs.push(format!("Credential store unavailable: {err}"));
+                None
+            }
+        };
+        match credentials.as_deref_mut() {
+            Some(store) => changed |= restore_from_store(&mut state, store, &mut logs),
+            None if !state.legacy_password.is_empty() => {
+                state.password = state.legacy_password.clone();
+                logs.push(
+                    "The saved password stays in plaintext in the state file until a credential \
+                     store is unlocked; enter its passphrase at login or set \
+                     NSS_CREDENTIALS_PASSPHRASE to move it there."
+                        .to_string(),
+                );
+            }
+            None => {}
         }
 
         if let Ok(server) = env::var("NSS_TEST_SERVER") {
@@ -132,28 +227,58 @@ impl App {
         let auto_login_pending = !state.username.trim().is_empty() && !state.password.is_empty();
         let output_dir = PathBuf::from(state.output_dir.clone());
         let query_buffer = state.last_query.clone();
+        let target_buffer = state.search_target_name.clone();
+        let chats = ChatStore::load(storage::chat_file_path()?)?;
+        let (task_tx, task_rx) = mpsc::unbounded_channel();
 
         Ok(Self {
             phase: UiPhase::LoginModal,
             login_focus: LoginField::Username,
             input_mode: InputMode::Normal,
             query_buffer,
-            selected_result: 0,
+            target_buffer,
+            buddy_buffer: String::new(),
+            room_buffer: String::new(),
+            chat_buffer: String::new(),
+            filter_buffer: String::new(),
+            browse_buffer: String::new(),
             session_state: SessionState::Disconnected,
             login_error: None,
-            logs: vec!["NeoSoulSeek ready. Login is required before search/download.".to_string()],
-            results: Vec::new(),
+            logs,
+            searches: SearchTabs::default(),
             diagnostics_visible: false,
             diagnostics_lines: vec![
                 "Press g to run diagnostics.".to_string(),
                 "The wizard checks server address parsing, DNS, TCP connect, and login probe."
                     .to_string(),
             ],
+            wishlist: wishlist_scheduler_for(&state),
+            auto_away: auto_away_for(&state),
             state,
             output_dir,
+            presence: UserPresence::Online,
+            transfers: HashMap::new(),
+            chats,
+            conversations: ConversationTabs::default(),
+            tab_selection: TabSelection::default(),
+            rooms: RoomsState::default(),
+            shares: SharesState::default(),
+            download_queue: VecDeque::new(),
+            active_download: None,
+            busy: None,
+            task_tx,
+            task_rx,
+            pending_presence: None,
+            credentials,
+            store_locked,
+            store_passphrase: String::new(),
             auto_login_pending,
             transfer_token: 555,
+            search_token: 1_000,
+            wishlist_token: 90_000,
             session: None,
+            reader: None,
+            pending_acks: Vec::new(),
         })
     }
 
@@ -165,6 +290,34 @@ impl App {
         }
     }
 
+    pub fn target_for_display(&self) -> String {
+        let name = if self.input_mode == InputMode::EditingTarget {
+            self.target_buffer.as_str()
+        } else {
+            self.state.search_target_name.as_str()
+        };
+        match self.state.search_target {
+            SearchTargetKind::Global => "global".to_string(),
+            SearchTargetKind::Buddies => format!("buddies ({})", self.state.buddies.len()),
+            kind => format!("{} {name}", kind.label()),
+        }
+    }
+
+    pub fn search_scope(&self) -> Result<SearchScope, String> {
+        let name = self.state.search_target_name.trim();
+        match self.state.search_target {
+            SearchTargetKind::Global => Ok(SearchScope::Global),
+            SearchTargetKind::Room if name.is_empty() => Err("room name is empty".to_string()),
+            SearchTargetKind::Room => Ok(SearchScope::Room(name.to_string())),
+            SearchTargetKind::User if name.is_empty() => Err("username is empty".to_string()),
+            SearchTargetKind::User => Ok(SearchScope::User(name.to_string())),
+            SearchTargetKind::Buddies if self.state.buddies.is_empty() => {
+                Err("buddy list is empty".to_string())
+            }
+            SearchTargetKind::Buddies => Ok(SearchScope::Users(self.state.buddies.usernames())),
+        }
+    }
+
     pub fn password_mask(&self) -> String {
         if self.state.password.is_empty() {
             "(empty)".to_string()
@@ -173,8 +326,20 @@ impl App {
         }
     }
 
+    pub fn passphrase_mask(&self) -> String {
+        if self.store_passphrase.is_empty() {
+            "(not unlocked)".to_string()
+        } else {
+            "*".repeat(self.store_passphrase.len().max(8))
+        }
+    }
+
+    pub fn store_locked(&self) -> bool {
+        self.store_locked
+    }
+
     pub fn selected_search_row(&self) -> Option<&SearchRow> {
-        self.results.get(self.selected_result)
+        self.searches.active_tab()?.selected_row()
     }
 
     pub fn downloads_visible(&self) -> bool {
@@ -199,6 +364,9 @@ impl App {
     }
 
     pub async fn login(&mut self) {
+        if !self.unlock_credentials() {
+            return;
+        }
         let username = self.state.username.trim().to_string();
         if username.is_empty() || self.state.password.is_empty() {
             self.login_error = Some("Username and password are required.".to_string());
@@ -217,16 +385,30 @@ impl App {
                 };
                 match client.login(&creds).await {
                     Ok(()) => {
+                        if self.presence != UserPresence::Online {
+                            match client.set_status(self.presence).await {
+                                Ok(()) => self.push_log(format!(
+                                    "Restored {} status.",
+                                    self.presence.label()
+                                )),
+                                Err(err) => self.push_log(format!("Status restore failed: {err}")),
+                            }
+                        }
                         self.session_state = client.state();
                         self.session = Some(client);
+                        self.start_reader();
                         self.phase = UiPhase::Main;
                         self.login_error = None;
                         self.query_buffer = self.state.last_query.clone();
                         self.push_log(format!("Login ok: {}", self.state.server));
+                        self.remember_password();
+                        self.watch_buddies().await;
+                        self.rejoin_rooms().await;
                         self.persist_state();
                     }
                     Err(err) => {
                         self.session = None;
+                        self.reader = None;
                         self.session_state = SessionState::Disconnected;
                         self.phase = UiPhase::LoginModal;
                         self.login_error = Some(format!("Login failed: {err}"));
@@ -236,6 +418,7 @@ impl App {
             }
             Err(err) => {
                 self.session = None;
+                self.reader = None;
                 self.session_state = SessionState::Disconnected;
                 self.phase = UiPhase::LoginModal;
                 self.login_error = Some(format!("Connect failed: {err}"));
@@ -244,80 +427,78 @@ impl App {
         }
     }
 
-    pub async fn search(&mut self) {
+    /// Sends the query under a fresh token in the background and opens a tab
+    /// that collects its results as they stream in.
+    pub fn search(&mut self) {
         if self.phase != UiPhase::Main {
             self.push_log("Search blocked: login is required.");
             return;
         }
 
-        if self.state.last_query.trim().is_empty() {
+        let query = self.state.last_query.trim().to_string();
+        if query.is_empty() {
             self.push_log("Search blocked: query cannot be empty.");
             return;
         }
-
-        let mut client = match self.session.take() {
-            Some(client) => client,
-            None => {
-                self.phase = UiPhase::LoginModal;
-                self.session_state = SessionState::Disconnected;
-                self.push_log("Search blocked: session is not logged in.");
+        let scope = match self.search_scope() {
+            Ok(scope) => scope,
+            Err(err) => {
+                self.push_log(format!("Search blocked: {err}."));
                 return;
             }
         };
 
-        self.push_log("Trying server summary...");
-        let response = client
-            .search_collect_candidates(
-                self.transfer_token,
-                &self.state.last_query,
-                Duration::from_secs(6),
-                32,
-                SearchMode::Auto,
-                None,
-                "P",
-            )
-            .await;
-
-        match response {
-            Ok(candidates) => {
-                self.results.clear();
-                let used_distributed = candidates
-                    .iter()
-                    .any(|candidate| candidate.source == SearchResultSource::DistributedPeer);
-                if used_distributed {
-                    self.push_log("Falling back to distributed peers...");
-                }
-                for candidate in candidates {
-                    self.results.push(SearchRow {
-                        username: candidate.username,
-                        file_path: candidate.file_path,
-                        file_size: candidate.file_size,
-                        peer_addr: candidate.peer_addr,
-                        source: candidate.source,
-                    });
-                }
-                self.selected_result = 0;
-                if self.results.is_empty() {
-                    self.push_log("No matching track found for query.");
-                } else {
-                    self.push_log(format!(
-                        "Search ok: query='{}' rows={}",
-                        self.state.last_query,
-                        self.results.len()
-                    ));
-                }
+        let Some(client) = self.take_session_for("Search") else {
+            return;
+        };
+        self.search_token = self.search_token.wrapping_add(1);
+        let token = self.search_token;
+        let target = self.target_for_display();
+        let scoped = scope != SearchScope::Global;
+        let until = tokio::time::Instant::now() + SEARCH_WINDOW;
+        self.searches.start(token, &query, &target, scoped, until);
+        self.push_log(format!(
+            "Searching '{query}' on {target} (token {token})..."
+        ));
+        self.busy = Some("search");
+        let session = self.task_session(client);
+        tasks::spawn_search_request(self.task_tx.clone(), session, token, scope, query);
+    }
+
+    /// Closes the streaming window of searches that ran long enough. A search
+    /// that got no server summaries falls back to asking distributed peers.
+    pub fn finish_searches(&mut self) {
+        for token in self.searches.expire(tokio::time::Instant::now()) {
+            let Some(tab) = self.searches.tab_mut(token) else {
+                continue;
+            };
+            let (query, rows, scoped) = (tab.query.clone(), tab.rows.len(), tab.scoped);
+            if rows > 0 || scoped {
+                self.push_log(format!("Search '{query}' finished: {rows} rows."));
+                continue;
             }
-            Err(err) => {
-                self.push_log(format!("Search failed: {err}"));
+            let Some(client) = self.take_session_for("Distributed search") else {
+                continue;
+            };
+            if let Some(tab) = self.searches.tab_mut(token) {
+                tab.status = SearchStatus::Fallback;
             }
+            self.busy = Some("search");
+            self.push_log(format!(
+                "No summaries for '{query}'; falling back to distributed peers..."
+            ));
+            tasks::spawn_search(
+                self.task_tx.clone(),
+                self.task_session(client),
+                token,
+                SearchScope::Global,
+                query,
+                SearchMode::Distributed,
+            );
         }
-
-        self.session_state = client.state();
-        self.session = Some(client);
-        self.persist_state();
     }
 
-    pub async fn download_selected(&mut self) {
+    pub fn download_selected(&mut self) {
         if self.phase != UiPhase::Main {
             self.push_log("Download blocked: login is required.");
             return;
@@ -330,15 +511,17 @@ impl App {
                 return;
             }
         };
+        if self.active_download.is_some() {
+            self.push_log(format!(
+                "Download queued behind the running transfer: {}",
+                selected.file_path
+            ));
+            self.queue_downloads(vec![selected.to_candidate()]);
+            return;
+        }
 
-        let mut client = match self.session.take() {
-            Some(client) => client,
-            None => {
-                self.phase = UiPhase::LoginModal;
-                self.session_state = SessionState::Disconnected;
-                self.push_log("Download blocked: session is not logged in.");
-                return;
-            }
+        let Some(client) = self.take_session_for("Download") else {
+            return;
         };
 
         let now = now_unix_secs();
@@ -352,161 +535,839 @@ impl App {
             started_at: now,
             ended_at: None,
         });
-        let download_index = self.state.downloads.len().saturating_sub(1);
+        self.start_download(client, download_id, selected.to_candidate());
+    }
+
+    /// Lists the remote folder of the selected result in the background; its
+    /// files are then queued for download.
+    pub fn download_folder(&mut self) {
+        if self.phase != UiPhase::Main {
+            self.push_log("Folder download blocked: login is required.");
+            return;
+        }
+        let Some(row) = self.selected_search_row() else {
+            self.push_log("Folder download blocked: no selected result.");
+            return;
+        };
+        let (username, folder) = (row.username.clone(), row.folder().to_string());
+        if folder.is_empty() {
+            self.push_log("Folder download blocked: the file is not inside a folder.");
+            return;
+        }
+        let Some(client) = self.take_session_for("Folder download") else {
+            return;
+        };
+        self.busy = Some("folder listing");
+        self.push_log(format!("Listing folder: user={username} folder={folder}"));
+        tasks::spawn_folder_listing(
+            self.task_tx.clone(),
+            self.task_session(client),
+            username,
+            folder,
+            self.transfer_token,
+        );
+    }
+
+    /// Fetches the shares of the user in `browse_buffer` in the background
+    /// and shows them on the Shares tab.
+    pub fn browse_user(&mut self) {
+        let username = std::mem::take(&mut self.browse_buffer).trim().to_string();
+        if username.is_empty() {
+            self.push_log("Browse blocked: username cannot be empty.");
+            return;
+        }
+        self.start_browse(username, false);
+    }
+
+    /// Asks the peer for the browsed shares again instead of using the cache.
+    pub fn refresh_shares(&mut self) {
+        if let Some(username) = self.shares.username().map(str::to_string) {
+            self.start_browse(username, true);
+        }
+    }
+
+    fn start_browse(&mut self, username: String, refresh: bool) {
+        if self.phase != UiPhase::Main {
+            self.push_log("Browse blocked: login is required.");
+            return;
+        }
+        let Some(client) = self.take_session_for("Browse") else {
+            return;
+        };
+        self.select_tab(MainTab::Shares);
+        self.busy = Some("browse");
+        self.push_log(format!("Browsing shares of {username}..."));
+        tasks::spawn_browse(
+            self.task_tx.clone(),
+            self.task_session(client),
+            username,
+            self.transfer_token,
+            refresh,
+        );
+    }
+
+    fn queue_selected_shares(&mut self) {
+        let candidates = self.shares.selected_candidates();
+        if candidates.is_empty() {
+            self.push_log("Nothing selected to download.");
+            return;
+        }
+        self.push_log(format!(
+            "Queued {} files from {}.",
+            candidates.len(),
+            candidates[0].username
+        ));
+        self.queue_downloads(candidates);
+    }
+
+    /// Starts the next queued download once the session is free. Queued
+    /// files wait while searches stream, since a download would read the
+    /// session and drop their results.
+    pub fn start_queued_download(&mut self) {
+        if self.phase != UiPhase::Main
+            || self.busy.is_some()
+            || self.active_download.is_some()
+            || self.session.is_none()
+            || self.searches.any_streaming()
+        {
+            return;
+        }
+        let Some((download_id, candidate)) = self.download_queue.pop_front() else {
+            return;
+        };
+        let Some(entry) = self
+            .state
+            .downloads
+            .iter_mut()
+            .find(|entry| entry.id == download_id)
+        else {
+            // Removed from the transfers list while it waited.
+            return;
+        };
+        entry.status = PersistedDownloadStatus::InProgress;
+        entry.started_at = now_unix_secs();
+        let Some(client) = self.session.take() else {
+            return;
+        };
+        self.start_download(client, download_id, candidate);
+    }
+
+    fn queue_downloads(&mut self, candidates: Vec<SearchCandidate>) {
+        let now = now_unix_secs();
+        for candidate in candidates {
+            let id = format!("fq-{now}-{}", self.state.downloads.len() + 1);
+            self.state.downloads.push(PersistedDownloadEntry {
+                id: id.clone(),
+                username: candidate.username.clone(),
+                file_path: candidate.file_path.clone(),
+                bytes: 0,
+                status: PersistedDownloadStatus::Queued,
+                started_at: now,
+                ended_at: None,
+            });
+            self.download_queue.push_back((id, candidate));
+        }
+        self.persist_state();
+    }
+
+    /// Runs one download in the background, saving it under the output
+    /// directory with the remote folder layout.
+    fn start_download(
+        &mut self,
+        client: SessionClient,
+        download_id: String,
+        candidate: SearchCandidate,
+    ) {
+        self.transfers
+            .insert(download_id.clone(), TransferMeter::new(candidate.file_size));
         self.persist_state();
 
-        let safe_name = selected.file_path.replace('\\', "_").replace('/', "_");
-        let output_path = self.output_dir.join(format!("download-auto-{safe_name}"));
+        let output_path = local_download_path(&self.output_dir, &candidate.file_path);
         let wait_port = std::env::var("NSS_WAIT_PORT")
             .ok()
             .and_then(|value| value.parse::<u16>().ok())
             .or(Some(50036));
-        let request = SearchSelectDownloadRequest {
-            search_token: self.transfer_token,
-            query: self.state.last_query.clone(),
-            search_timeout: Duration::from_secs(6),
-            max_messages: 32,
-            result_index: self.selected_result,
-            file_index: 0,
+        // The row already names the exact peer and file, so download it
+        // directly instead of repeating the search and picking by index.
+        let request = CandidateDownloadRequest {
             transfer_token: self.transfer_token,
             output_path,
-            peer_addr_override: selected.peer_addr.clone(),
+            peer_addr_override: None,
             peer_lookup_timeout: Duration::from_secs(5),
             connection_type: "P".to_string(),
             wait_port,
             skip_connect_probe: false,
-            search_mode: if selected.source == SearchResultSource::DistributedPeer {
-                SearchMode::Distributed
-            } else {
-                SearchMode::Auto
-            },
-            strict_track: None,
         };
 
-        match client.search_select_and_download(&request).await {
-            Ok(result) => {
-                if let Some(entry) = self.state.downloads.get_mut(download_index) {
-                    entry.status = PersistedDownloadStatus::Done;
-                    entry.bytes = result.bytes_written;
-                    entry.ended_at = Some(now_unix_secs());
+        self.busy = Some("download");
+        self.active_download = Some(download_id.clone());
+        self.push_log(format!(
+            "Download started: user={} path={}",
+            candidate.username, candidate.file_path
+        ));
+        tasks::spawn_download(
+            self.task_tx.clone(),
+            self.task_session(client),
+            download_id,
+            candidate,
+            request,
+        );
+    }
+
+    pub fn busy_task(&self) -> Option<&'static str> {
+        self.busy
+    }
+
+    /// Takes the session for a user action, or explains why it cannot run.
+    fn take_session_for(&mut self, action: &str) -> Option<SessionClient> {
+        if let Some(task) = self.busy {
+            self.push_log(format!("{action} blocked: {task} still running."));
+            return None;
+        }
+        let client = self.session.take();
+        if client.is_none() {
+            self.phase = UiPhase::LoginModal;
+            self.session_state = SessionState::Disconnected;
+            self.push_log(format!("{action} blocked: session is not logged in."));
+        }
+        client
+    }
+
+    /// Pairs a session taken for a background task with the reader holding
+    /// its read half, which the task stops before it waits for replies.
+    fn task_session(&mut self, client: SessionClient) -> TaskSession {
+        TaskSession::new(client, self.reader.take())
+    }
+
+    /// Lends the read half of the idle session to a reader task, so server
+    /// pushes arrive as task events and the UI never waits on the socket.
+    fn start_reader(&mut self) {
+        if self.reader.is_some() {
+            return;
+        }
+        let Some(reader) = self.session.as_mut().and_then(SessionClient::take_reader) else {
+            return;
+        };
+        self.reader = Some(tasks::spawn_reader(self.task_tx.clone(), reader));
+    }
+
+    /// Applies every event background tasks have reported so far.
+    pub fn drain_task_events(&mut self) {
+        while let Ok(event) = self.task_rx.try_recv() {
+            self.apply_task_event(event);
+        }
+    }
+
+    fn apply_task_event(&mut self, event: TaskEvent) {
+        match event {
+            TaskEvent::ServerPush(message) => self.apply_server_push(message),
+            TaskEvent::ReaderFailed(err) => self.push_log(format!("Session read failed: {err}")),
+            TaskEvent::SearchSent {
+                token,
+                query,
+                result,
+            } => {
+                if let Err(err) = result {
+                    if let Some(tab) = self.searches.tab_mut(token) {
+                        tab.status = SearchStatus::Done;
+                    }
+                    self.push_log(format!("Search '{query}' failed: {err}"));
                 }
-                self.push_log(format!(
-                    "Download ok: user={} bytes={} path={}",
-                    result.selected_username, result.bytes_written, result.selected_virtual_path
-                ));
             }
-            Err(err) => {
-                if let Some(entry) = self.state.downloads.get_mut(download_index) {
-                    entry.status = PersistedDownloadStatus::Failed;
-                    entry.ended_at = Some(now_unix_secs());
+            TaskEvent::SearchFinished {
+                token,
+                query,
+                result,
+            } => {
+                let tab = self.searches.tab_mut(token);
+                match (result, tab) {
+                    (Ok(candidates), Some(tab)) => {
+                        tab.status = SearchStatus::Done;
+                        let rows = candidates
+                            .into_iter()
+                            .map(SearchRow::from_candidate)
+                            .collect();
+                        let added = tab.append(rows);
+                        self.push_log(format!("Search '{query}' finished: {added} peer rows."));
+                    }
+                    (Err(err), Some(tab)) => {
+                        tab.status = SearchStatus::Done;
+                        self.push_log(format!("Search '{query}' failed: {err}"));
+                    }
+                    // The tab was closed while the fallback ran.
+                    (_, None) => {}
+                }
+            }
+            TaskEvent::DownloadProgress {
+                download_id,
+                progress,
+            } => {
+                self.transfers
+                    .entry(download_id)
+                    .or_insert_with(|| TransferMeter::new(0))
+                    .record(progress, tokio::time::Instant::now());
+            }
+            TaskEvent::DownloadFinished {
+                download_id,
+                result,
+            } => {
+                self.transfers.remove(&download_id);
+                if self.active_download.as_ref() == Some(&download_id) {
+                    self.active_download = None;
+                }
+                let entry = self
+                    .state
+                    .downloads
+                    .iter_mut()
+                    .find(|entry| entry.id == download_id);
+                match result {
+                    Ok(result) => {
+                        if let Some(entry) = entry {
+                            entry.status = PersistedDownloadStatus::Done;
+                            entry.bytes = result.bytes_written;
+                            entry.ended_at = Some(now_unix_secs());
+                        }
+                        self.push_log(format!(
+                            "Download ok: user={} bytes={} path={}",
+                            result.selected_username,
+                            result.bytes_written,
+                            result.selected_virtual_path
+                        ));
+                    }
+                    Err(err) => {
+                        if let Some(entry) = entry {
+                            entry.status = PersistedDownloadStatus::Failed;
+                            entry.ended_at = Some(now_unix_secs());
+                        }
+                        self.push_log(format!("Download failed: {err}"));
+                    }
+                }
+                self.persist_state();
+            }
+            TaskEvent::FolderListed {
+                username,
+                folder,
+                result,
+            } => match result {
+                Ok(candidates) if candidates.is_empty() => {
+                    self.push_log(format!("Folder is empty: user={username} folder={folder}"));
+                }
+                Ok(candidates) => {
+                    self.push_log(format!(
+                        "Queued {} files: user={username} folder={folder}",
+                        candidates.len()
+                    ));
+                    self.queue_downloads(candidates);
+                }
+                Err(err) => self.push_log(format!("Folder listing failed: {err}")),
+            },
+            TaskEvent::BrowseFinished { username, result } => match result {
+                Ok(tree) => {
+                    self.push_log(format!(
+                        "Shares of {username}: {} files.",
+                        tree.root.file_count()
+                    ));
+                    self.shares.set_tree(tree);
+                }
+                Err(err) => self.push_log(format!("Browse of {username} failed: {err}")),
+            },
+            TaskEvent::RoomListFinished(result) => match result {
+                Ok(list) => {
+                    self.rooms.set_room_list(&list);
+                    self.push_log(format!("Room list: {} rooms.", self.rooms.browser.len()));
+                }
+                Err(err) => self.push_log(format!("Room list failed: {err}")),
+            },
+            TaskEvent::WishlistFinished { item, result } => {
+                match result.map(|found| self.wishlist.accept_results(&item, &found)) {
+                    Ok(matches) if matches.is_empty() => {}
+                    Ok(matches) if item.auto_download => {
+                        self.queue_wishlist_matches(&item.query, matches);
+                    }
+                    Ok(matches) => {
+                        for candidate in matches {
+                            self.push_log(format!(
+                                "Wishlist match '{}': user={} path={}",
+                                item.query, candidate.username, candidate.file_path
+                            ));
+                        }
+                    }
+                    Err(err) => self.push_log(format!("Wishlist search failed: {err}")),
+                }
+            }
+            TaskEvent::SessionReturned(mut client) => {
+                self.busy = None;
+                // A re-login while the task ran replaces the old session.
+                if self.phase == UiPhase::Main && self.session.is_none() {
+                    if let Some(interval) = client.wishlist_interval() {
+                        self.wishlist.set_interval(interval);
+                    }
+                    let private = client.take_private_messages();
+                    self.session_state = client.state();
+                    self.session = Some(client);
+                    self.start_reader();
+                    self.record_private_messages(private);
                 }
-                self.push_log(format!("Download failed: {err}"));
             }
         }
-
-        self.session_state = client.state();
-        self.session = Some(client);
-        self.persist_state();
     }
 
-    pub fn clear_download_history(&mut self) {
-        if cfg!(test) {
-            self.state.downloads.clear();
-            self.push_log("Downloads history cleared (files on disk were not removed).");
+    /// Starts the next wishlist search in the background once the
+    /// server-provided interval has elapsed; new matches go to the download
+    /// queue.
+    pub fn start_wishlist_if_due(&mut self) {
+        // A wishlist run reads every search summary, so it waits until the
+        // open search tabs stopped streaming.
+        if self.phase != UiPhase::Main
+            || self.busy.is_some()
+            || self.session.is_none()
+            || self.searches.any_streaming()
+        {
             return;
         }
+        let items: Vec<WishlistItem> = self
+            .state
+            .wishlist
+            .iter()
+            .map(PersistedWishlistEntry::to_item)
+            .collect();
+        let Some(index) = self.wishlist.take_due(&items, tokio::time::Instant::now()) else {
+            return;
+        };
+        let Some(client) = self.session.take() else {
+            return;
+        };
 
-        match storage::clear_download_history(&mut self.state) {
-            Ok(()) => self.push_log("Downloads history cleared (files on disk were not removed)."),
-            Err(err) => self.push_log(format!("Failed to clear downloads history: {err}")),
-        }
+        self.wishlist_token = self.wishlist_token.wrapping_add(1);
+        self.busy = Some("wishlist search");
+        tasks::spawn_wishlist_search(
+            self.task_tx.clone(),
+            self.task_session(client),
+            items[index].clone(),
+            self.wishlist_token,
+        );
     }
 
-    pub fn toggle_downloads_panel(&mut self) {
-        self.state.ui.downloads_visible = !self.state.ui.downloads_visible;
+    fn queue_wishlist_matches(&mut self, query: &str, matches: Vec<SearchCandidate>) {
+        let now = now_unix_secs();
+        for candidate in matches {
+            self.push_log(format!(
+                "Wishlist '{}' queued: user={} path={}",
+                query, candidate.username, candidate.file_path
+            ));
+            let id = format!("wl-{now}-{}", self.state.downloads.len() + 1);
+            self.state.downloads.push(PersistedDownloadEntry {
+                id,
+                username: candidate.username,
+                file_path: candidate.file_path,
+                bytes: 0,
+                status: PersistedDownloadStatus::Queued,
+                started_at: now,
+                ended_at: None,
+            });
+        }
         self.persist_state();
-        if self.state.ui.downloads_visible {
-            self.push_log("Downloads panel is now visible.");
+    }
+
+    pub fn toggle_wishlist_query(&mut self) {
+        let query = self.state.last_query.trim().to_string();
+        if query.is_empty() {
+            self.push_log("Wishlist blocked: query cannot be empty.");
+            return;
+        }
+        if let Some(position) = self
+            .state
+            .wishlist
+            .iter()
+            .position(|entry| entry.query.eq_ignore_ascii_case(&query))
+        {
+            self.state.wishlist.remove(position);
+            self.push_log(format!("Removed '{query}' from wishlist."));
         } else {
-            self.push_log("Downloads panel is now hidden.");
+            self.state
+                .wishlist
+                .push(PersistedWishlistEntry::new(&query));
+            self.push_log(format!("Added '{query}' to wishlist."));
         }
+        self.persist_state();
     }
 
-    pub fn move_selection(&mut self, delta: isize) {
-        if self.results.is_empty() {
-            self.selected_result = 0;
+    async fn watch_buddies(&mut self) {
+        let Some(client) = self.session.as_mut() else {
             return;
+        };
+        if let Err(err) = client.watch_buddies(&self.state.buddies).await {
+            self.push_log(format!("Buddy watch failed: {err}"));
         }
-        let len = self.results.len() as isize;
-        let current = self.selected_result as isize;
-        self.selected_result = (current + delta).clamp(0, len - 1) as usize;
     }
 
-    pub async fn run_diagnostics(&mut self) {
-        self.diagnostics_visible = true;
-        self.diagnostics_lines.clear();
-        self.diagnostics_lines
-            .push("Running diagnostics wizard...".to_string());
-
-        let server = self.state.server.trim().to_string();
-        if server.is_empty() {
-            self.diagnostics_lines
-                .push("Server is empty. Fill Server and rerun (g).".to_string());
-            self.push_log("Diagnostics failed: empty server.");
+    /// Adds the typed user to the buddy list, or removes them if already listed.
+    pub async fn toggle_buddy(&mut self) {
+        let username = self.buddy_buffer.trim().to_string();
+        self.buddy_buffer.clear();
+        if username.is_empty() {
+            self.push_log("Buddy name cannot be empty.");
             return;
         }
 
-        let (host, port) = match parse_server_host_port(&server) {
-            Ok(value) => value,
-            Err(err) => {
-                self.diagnostics_lines
-                    .push(format!("Server parse failed: {err}"));
-                self.diagnostics_lines.push(
-                    "Expected format is host:port (example: server.slsknet.org:2242).".to_string(),
-                );
-                self.push_log("Diagnostics failed: invalid server format.");
-                return;
-            }
+        let added = if self.state.buddies.remove(&username) {
+            self.push_log(format!("Removed {username} from buddies."));
+            false
+        } else {
+            self.state.buddies.upsert(&username, None, None);
+            self.push_log(format!("Added {username} to buddies."));
+            true
         };
-        self.diagnostics_lines
-            .push(format!("Server parsed: host={host} port={port}"));
+        self.persist_state();
 
-        match tokio::time::timeout(
-            Duration::from_secs(4),
-            tokio::net::lookup_host((host.as_str(), port)),
-        )
-        .await
-        {
-            Ok(Ok(mut addrs)) => {
-                if let Some(addr) = addrs.next() {
-                    self.diagnostics_lines
-                        .push(format!("DNS lookup ok: first resolved endpoint {addr}"));
-                } else {
-                    self.diagnostics_lines.push(
-                        "DNS lookup returned no addresses for the configured host.".to_string(),
-                    );
-                }
+        if let Some(client) = self.session.as_mut() {
+            let result = if added {
+                client.watch_user(&username).await
+            } else {
+                client.unwatch_user(&username).await
+            };
+            if let Err(err) = result {
+                self.push_log(format!("Buddy update failed: {err}"));
             }
-            Ok(Err(err)) => {
-                self.diagnostics_lines
-                    .push(format!("DNS lookup failed: {err}"));
+        }
+    }
+
+    /// Applies a message the reader task forwarded: buddy status, room
+    /// traffic, search summaries, private messages and the wishlist interval.
+    fn apply_server_push(&mut self, message: ServerMessage) {
+        match &message {
+            ServerMessage::WishlistWait(payload) => {
+                self.wishlist
+                    .set_interval(Duration::from_secs(u64::from(payload.interval_seconds)));
             }
-            Err(_) => {
-                self.diagnostics_lines
-                    .push("DNS lookup timed out after 4s.".to_string());
+            ServerMessage::MessageUserIncoming(payload) => {
+                // The server re-sends unacked messages at every login.
+                self.pending_acks.push(payload.message_id);
+                self.record_private_messages(vec![payload.clone()]);
             }
+            _ => {}
         }
-
-        match tokio::time::timeout(Duration::from_secs(4), SessionClient::connect(&server)).await {
-            Ok(Ok(_)) => self
-                .diagnostics_lines
-                .push("TCP connect check: ok.".to_string()),
-            Ok(Err(err)) => self
-                .diagnostics_lines
-                .push(format!("TCP connect check failed: {err}")),
-            Err(_) => self
-                .diagnostics_lines
-                .push("TCP connect check timed out after 4s.".to_string()),
+        let timestamp = u64::try_from(now_unix_secs()).unwrap_or_default();
+        for event in session_events(message, &mut self.state.buddies) {
+            match event {
+                SessionEvent::Buddy(event) => self.push_log(buddy_event_line(&event)),
+                SessionEvent::Room(event) => {
+                    self.rooms.apply_event(&event, timestamp);
+                }
+                SessionEvent::Search(summary) => {
+                    self.searches.add_summary(&summary);
+                }
+            }
+        }
+    }
+
+    /// Acks the private messages the reader forwarded. The write runs outside
+    /// any timeout; ids that fail stay queued on the session for the next
+    /// flush.
+    pub async fn flush_message_acks(&mut self) {
+        if self.pending_acks.is_empty() {
+            return;
+        }
+        let Some(client) = self.session.as_mut() else {
+            return;
+        };
+        for message_id in self.pending_acks.drain(..) {
+            client.queue_message_ack(message_id);
+        }
+        if let Err(err) = client.flush_message_acks().await {
+            self.push_log(format!("Private message ack failed: {err}"));
+        }
+    }
+
+    /// Stores incoming private messages. Messages for the conversation on
+    /// screen are read right away; others stay unread and are announced.
+    fn record_private_messages(&mut self, payloads: Vec<MessageUserIncomingPayload>) {
+        let mut changed = false;
+        for payload in payloads {
+            if !self.chats.record_incoming(&payload) {
+                continue;
+            }
+            changed = true;
+            if self.active_tab() == MainTab::Chat
+                && self.conversations.is_viewing(&payload.username)
+            {
+                self.chats.mark_read(&payload.username);
+            } else {
+                self.push_log(format!(
+                    "Message from {}: {}",
+                    payload.username, payload.message
+                ));
+            }
+        }
+        if changed {
+            self.persist_chats();
+        }
+    }
+
+    /// Sends `chat_buffer` to the open conversation and records it locally.
+    pub async fn send_private_message(&mut self) {
+        let text = self.chat_buffer.trim().to_string();
+        self.chat_buffer.clear();
+        let Some(username) = self.conversations.active_user().map(str::to_string) else {
+            return;
+        };
+        if text.is_empty() {
+            return;
+        }
+        let Some(mut client) = self.take_session_for("Private message") else {
+            return;
+        };
+        let result = client.send_private_message(&username, &text).await;
+        self.session = Some(client);
+        match result {
+            Ok(()) => {
+                let timestamp = u64::try_from(now_unix_secs()).unwrap_or_default();
+                self.chats.record_outgoing(&username, &text, timestamp);
+                self.persist_chats();
+            }
+            Err(err) => self.push_log(format!("Private message failed: {err}")),
+        }
+    }
+
+    pub fn list_rooms(&mut self) {
+        let Some(client) = self.take_session_for("Room list") else {
+            return;
+        };
+        self.busy = Some("room list");
+        self.push_log("Fetching room list...");
+        tasks::spawn_room_list(self.task_tx.clone(), self.task_session(client));
+    }
+
+    /// Joins the room named in `room_buffer` and opens its tab.
+    pub async fn join_room(&mut self) {
+        let room = self.room_buffer.trim().to_string();
+        self.room_buffer.clear();
+        if room.is_empty() {
+            self.push_log("Room name cannot be empty.");
+            return;
+        }
+        let Some(mut client) = self.take_session_for("Join room") else {
+            return;
+        };
+        let result = client.join_room(&room).await;
+        self.session = Some(client);
+        match result {
+            Ok(()) => {
+                self.rooms.open(&room);
+                if !self.state.rooms.contains(&room) {
+                    self.state.rooms.push(room.clone());
+                    self.persist_state();
+                }
+                self.push_log(format!("Joined room {room}."));
+            }
+            Err(err) => self.push_log(format!("Join room failed: {err}")),
+        }
+    }
+
+    pub async fn leave_active_room(&mut self) {
+        let Some(room) = self.rooms.active_room().map(|room| room.name.clone()) else {
+            return;
+        };
+        let Some(mut client) = self.take_session_for("Leave room") else {
+            return;
+        };
+        let result = client.leave_room(&room).await;
+        self.session = Some(client);
+        match result {
+            Ok(()) => {
+                self.rooms.leave(&room);
+                self.state.rooms.retain(|name| *name != room);
+                self.persist_state();
+                self.push_log(format!("Left room {room}."));
+            }
+            Err(err) => self.push_log(format!("Leave room failed: {err}")),
+        }
+    }
+
+    /// Sends `room_buffer` to the active room. The server echoes the line
+    /// back, so it shows up in the history with the next poll.
+    pub async fn say_in_active_room(&mut self) {
+        let text = self.room_buffer.trim().to_string();
+        self.room_buffer.clear();
+        let Some(room) = self.rooms.active_room().map(|room| room.name.clone()) else {
+            return;
+        };
+        if text.is_empty() {
+            return;
+        }
+        let Some(mut client) = self.take_session_for("Room message") else {
+            return;
+        };
+        let result = client.say_chatroom(&room, &text).await;
+        self.session = Some(client);
+        if let Err(err) = result {
+            self.push_log(format!("Room message failed: {err}"));
+        }
+    }
+
+    async fn rejoin_rooms(&mut self) {
+        let active = self.rooms.active;
+        for room in self.state.rooms.clone() {
+            let Some(client) = self.session.as_mut() else {
+                return;
+            };
+            match client.join_room(&room).await {
+                Ok(()) => self.rooms.open(&room),
+                Err(err) => self.push_log(format!("Rejoin {room} failed: {err}")),
+            }
+        }
+        self.rooms.active = active.filter(|idx| *idx < self.rooms.joined.len());
+    }
+
+    pub fn presence_label(&self) -> String {
+        if self.auto_away.is_engaged() {
+            format!("{} (auto)", self.presence.label())
+        } else {
+            self.presence.label().to_string()
+        }
+    }
+
+    pub fn toggle_away(&mut self) {
+        let current = self.pending_presence.unwrap_or(self.presence);
+        self.pending_presence = Some(if current == UserPresence::Away {
+            UserPresence::Online
+        } else {
+            UserPresence::Away
+        });
+    }
+
+    /// Applies manual and auto-away status changes to the session.
+    pub async fn sync_presence(&mut self) {
+        if self.phase != UiPhase::Main {
+            return;
+        }
+        let current = self.pending_presence.unwrap_or(self.presence);
+        if let Some(presence) = self.auto_away.poll(current, tokio::time::Instant::now()) {
+            self.pending_presence = Some(presence);
+        }
+        let Some(target) = self.pending_presence.take() else {
+            return;
+        };
+        if target == self.presence {
+            return;
+        }
+        if self.busy.is_some() {
+            // Retry once the background task hands the session back.
+            self.pending_presence = Some(target);
+            return;
+        }
+        let Some(client) = self.session.as_mut() else {
+            self.presence = target;
+            return;
+        };
+        match client.set_status(target).await {
+            Ok(()) => {
+                self.presence = target;
+                self.push_log(format!("Status set to {}.", self.presence_label()));
+            }
+            Err(err) => self.push_log(format!("Status update failed: {err}")),
+        }
+    }
+
+    pub fn toggle_users_panel(&mut self) {
+        self.state.ui.users_visible = !self.state.ui.users_visible;
+        self.persist_state();
+    }
+
+    pub fn clear_download_history(&mut self) {
+        if cfg!(test) {
+            self.state.downloads.clear();
+            self.push_log("Downloads history cleared (files on disk were not removed).");
+            return;
+        }
+
+        match storage::clear_download_history(&mut self.state) {
+            Ok(()) => self.push_log("Downloads history cleared (files on disk were not removed)."),
+            Err(err) => self.push_log(format!("Failed to clear downloads history: {err}")),
+        }
+    }
+
+    pub fn toggle_downloads_panel(&mut self) {
+        self.state.ui.downloads_visible = !self.state.ui.downloads_visible;
+        self.persist_state();
+        if self.state.ui.downloads_visible {
+            self.push_log("Downloads panel is now visible.");
+        } else {
+            self.push_log("Downloads panel is now hidden.");
+        }
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        if let Some(tab) = self.searches.active_tab_mut() {
+            tab.move_selection(delta);
+        }
+    }
+
+    pub async fn run_diagnostics(&mut self) {
+        self.diagnostics_visible = true;
+        self.diagnostics_lines.clear();
+        self.diagnostics_lines
+            .push("Running diagnostics wizard...".to_string());
+
+        let server = self.state.server.trim().to_string();
+        if server.is_empty() {
+            self.diagnostics_lines
+                .push("Server is empty. Fill Server and rerun (g).".to_string());
+            self.push_log("Diagnostics failed: empty server.");
+            return;
+        }
+
+        let (host, port) = match parse_server_host_port(&server) {
+            Ok(value) => value,
+            Err(err) => {
+                self.diagnostics_lines
+                    .push(format!("Server parse failed: {err}"));
+                self.diagnostics_lines.push(
+                    "Expected format is host:port (example: server.slsknet.org:2242).".to_string(),
+                );
+                self.push_log("Diagnostics failed: invalid server format.");
+                return;
+            }
+        };
+        self.diagnostics_lines
+            .push(format!("Server parsed: host={host} port={port}"));
+
+        match tokio::time::timeout(
+            Duration::from_secs(4),
+            tokio::net::lookup_host((host.as_str(), port)),
+        )
+        .await
+        {
+            Ok(Ok(mut addrs)) => {
+                if let Some(addr) = addrs.next() {
+                    self.diagnostics_lines
+                        .push(format!("DNS lookup ok: first resolved endpoint {addr}"));
+                } else {
+                    self.diagnostics_lines.push(
+                        "DNS lookup returned no addresses for the configured host.".to_string(),
+                    );
+                }
+            }
+            Ok(Err(err)) => {
+                self.diagnostics_lines
+                    .push(format!("DNS lookup failed: {err}"));
+            }
+            Err(_) => {
+                self.diagnostics_lines
+                    .push("DNS lookup timed out after 4s.".to_string());
+            }
+        }
+
+        match tokio::time::timeout(Duration::from_secs(4), SessionClient::connect(&server)).await {
+            Ok(Ok(_)) => self
+                .diagnostics_lines
+                .push("TCP connect check: ok.".to_string()),
+            Ok(Err(err)) => self
+                .diagnostics_lines
+                .push(format!("TCP connect check failed: {err}")),
+            Err(_) => self
+                .diagnostics_lines
+                .push("TCP connect check timed out after 4s.".to_string()),
         }
 
         let username = self.state.username.trim();
@@ -543,6 +1404,9 @@ impl App {
     }
 
     pub fn handle_key(&mut self, key: KeyEvent) -> PendingAction {
+        if let Some(presence) = self.auto_away.record_activity(tokio::time::Instant::now()) {
+            self.pending_presence = Some(presence);
+        }
         if self.diagnostics_visible {
             return self.handle_diagnostics_key(key);
         }
@@ -569,11 +1433,11 @@ impl App {
             KeyCode::Char('q') => PendingAction::Quit,
             KeyCode::Char('g') => PendingAction::RunDiagnostics,
             KeyCode::Tab => {
-                self.login_focus = self.login_focus.next();
+                self.login_focus = self.login_focus.next(self.store_locked);
                 PendingAction::None
             }
             KeyCode::BackTab => {
-                self.login_focus = self.login_focus.previous();
+                self.login_focus = self.login_focus.previous(self.store_locked);
                 PendingAction::None
             }
             KeyCode::Esc => {
@@ -598,8 +1462,19 @@ impl App {
     }
 
     fn handle_main_key(&mut self, key: KeyEvent) -> PendingAction {
-        if self.input_mode == InputMode::EditingQuery {
-            return self.handle_query_edit_key(key);
+        match self.input_mode {
+            InputMode::EditingQuery => return self.handle_query_edit_key(key),
+            InputMode::EditingTarget => return self.handle_target_edit_key(key),
+            InputMode::EditingBuddy => return self.handle_buddy_edit_key(key),
+            InputMode::EditingFilter => return self.handle_filter_edit_key(key),
+            InputMode::EditingRoomName | InputMode::EditingRoomMessage => {
+                return self.handle_room_edit_key(key);
+            }
+            InputMode::EditingChatUser | InputMode::EditingChatMessage => {
+                return self.handle_chat_edit_key(key);
+            }
+            InputMode::EditingBrowseUser => return self.handle_browse_edit_key(key),
+            InputMode::Normal => {}
         }
         match key.code {
             KeyCode::Char('q') => PendingAction::Quit,
@@ -607,23 +1482,109 @@ impl App {
             KeyCode::Char('l') => {
                 self.phase = UiPhase::LoginModal;
                 self.session = None;
+                self.reader = None;
                 self.session_state = SessionState::Disconnected;
                 self.login_error = None;
                 self.push_log("Logged out. Login is required.");
                 PendingAction::None
             }
+            KeyCode::Char('a') => {
+                self.toggle_away();
+                PendingAction::None
+            }
+            KeyCode::Char('b') => {
+                self.input_mode = InputMode::EditingBuddy;
+                self.buddy_buffer.clear();
+                self.push_log("Type a username and press Enter to add or remove a buddy.");
+                PendingAction::None
+            }
+            KeyCode::Char(digit @ '1'..='6') => {
+                let index = digit as usize - '1' as usize;
+                self.select_tab(MainTab::ALL[index]);
+                PendingAction::None
+            }
+            KeyCode::Char(']') => {
+                self.select_tab(self.active_tab().next());
+                PendingAction::None
+            }
+            KeyCode::Char('[') => {
+                self.select_tab(self.active_tab().previous());
+                PendingAction::None
+            }
+            _ => match self.active_tab() {
+                MainTab::Search => self.handle_search_key(key),
+                MainTab::Transfers => self.handle_transfers_key(key),
+                MainTab::Chat => self.handle_chat_key(key),
+                MainTab::Rooms => self.handle_rooms_key(key),
+                MainTab::Users => self.handle_users_key(key),
+                MainTab::Shares => self.handle_shares_key(key),
+            },
+        }
+    }
+
+    fn handle_search_key(&mut self, key: KeyEvent) -> PendingAction {
+        match key.code {
             KeyCode::Char('/') => {
                 self.input_mode = InputMode::EditingQuery;
                 self.query_buffer = self.state.last_query.clone();
                 self.push_log("Query edit mode enabled. Press Enter to search.");
                 PendingAction::None
             }
+            KeyCode::Tab => {
+                self.state.search_target = self.state.search_target.next();
+                self.persist_state();
+                self.push_log(format!("Search target: {}", self.target_for_display()));
+                PendingAction::None
+            }
+            KeyCode::Char('e') => {
+                match self.state.search_target {
+                    SearchTargetKind::Global => {
+                        self.push_log(
+                            "Global search has no target to edit. Press Tab to change target.",
+                        );
+                        return PendingAction::None;
+                    }
+                    SearchTargetKind::Buddies => {
+                        self.push_log(
+                            "Buddies target searches the buddy list. Press b to edit it.",
+                        );
+                        return PendingAction::None;
+                    }
+                    SearchTargetKind::Room | SearchTargetKind::User => {}
+                }
+                self.input_mode = InputMode::EditingTarget;
+                self.target_buffer = self.state.search_target_name.clone();
+                self.push_log("Target edit mode enabled. Press Enter to save.");
+                PendingAction::None
+            }
             KeyCode::Enter => PendingAction::Search,
             KeyCode::Char('d') => PendingAction::Download,
+            KeyCode::Char('D') => PendingAction::DownloadFolder,
+            KeyCode::Char('v') => match self.selected_search_row() {
+                Some(row) => {
+                    self.browse_buffer = row.username.clone();
+                    PendingAction::BrowseUser
+                }
+                None => PendingAction::None,
+            },
+            KeyCode::Char('m') => {
+                if let Some(username) = self.selected_search_row().map(|row| row.username.clone()) {
+                    self.message_user(&username);
+                }
+                PendingAction::None
+            }
             KeyCode::Char('t') => {
                 self.toggle_downloads_panel();
                 PendingAction::None
             }
+            KeyCode::Char('w') => {
+                self.toggle_wishlist_query();
+                PendingAction::None
+            }
+            KeyCode::Char('u') => {
+                self.toggle_users_panel();
+                PendingAction::None
+            }
             KeyCode::Char('c') => {
                 self.clear_download_history();
                 PendingAction::None
@@ -636,43 +1597,482 @@ impl App {
                 self.move_selection(1);
                 PendingAction::None
             }
-            _ => PendingAction::None,
-        }
-    }
-
-    fn handle_query_edit_key(&mut self, key: KeyEvent) -> PendingAction {
-        match key.code {
-            KeyCode::Esc => {
-                self.input_mode = InputMode::Normal;
-                self.query_buffer = self.state.last_query.clone();
-                self.push_log("Query edit canceled.");
+            KeyCode::Left => {
+                self.searches.cycle(-1);
                 PendingAction::None
             }
-            KeyCode::Backspace => {
-                self.query_buffer.pop();
+            KeyCode::Right => {
+                self.searches.cycle(1);
                 PendingAction::None
             }
-            KeyCode::Enter => {
-                let trimmed = self.query_buffer.trim();
-                if trimmed.is_empty() {
-                    self.push_log("Query cannot be empty.");
-                    return PendingAction::None;
+            KeyCode::Char('x') => {
+                if let Some(tab) = self.searches.close_active() {
+                    self.push_log(format!("Closed search '{}'.", tab.query));
                 }
-                self.state.last_query = trimmed.to_string();
-                self.query_buffer = self.state.last_query.clone();
-                self.input_mode = InputMode::Normal;
-                self.persist_state();
-                PendingAction::Search
+                PendingAction::None
             }
-            KeyCode::Char(ch) => {
-                if key.modifiers.contains(KeyModifiers::CONTROL) {
-                    return PendingAction::None;
+            KeyCode::Char('f') => {
+                if let Some(tab) = self.searches.active_tab() {
+                    self.filter_buffer = tab.filter_text.clone();
+                    self.input_mode = InputMode::EditingFilter;
                 }
-                self.query_buffer.push(ch);
                 PendingAction::None
             }
-            _ => PendingAction::None,
-        }
+            KeyCode::Char('s') => {
+                if let Some(tab) = self.searches.active_tab_mut() {
+                    tab.cycle_sort();
+                }
+                PendingAction::None
+            }
+            KeyCode::Char('r') => {
+                if let Some(tab) = self.searches.active_tab_mut() {
+                    tab.reverse_sort();
+                }
+                PendingAction::None
+            }
+            _ => PendingAction::None,
+        }
+    }
+
+    fn handle_transfers_key(&mut self, key: KeyEvent) -> PendingAction {
+        let len = self.visible_downloads().len();
+        match key.code {
+            KeyCode::Up => step_cursor(&mut self.tab_selection.transfer, len, -1),
+            KeyCode::Down => step_cursor(&mut self.tab_selection.transfer, len, 1),
+            KeyCode::Char('f') => {
+                self.state.ui.transfers_filter = self.state.ui.transfers_filter.next();
+                self.tab_selection.transfer = 0;
+                self.persist_state();
+            }
+            KeyCode::Char('x') => self.remove_selected_download(),
+            KeyCode::Char('c') => self.clear_download_history(),
+            _ => {}
+        }
+        PendingAction::None
+    }
+
+    fn handle_rooms_key(&mut self, key: KeyEvent) -> PendingAction {
+        match key.code {
+            KeyCode::Left => self.rooms.cycle(-1),
+            KeyCode::Right => self.rooms.cycle(1),
+            KeyCode::Char('r') => return PendingAction::ListRooms,
+            KeyCode::Char('j') => {
+                self.input_mode = InputMode::EditingRoomName;
+                self.room_buffer.clear();
+                self.push_log("Type a room name and press Enter to join.");
+            }
+            _ if self.rooms.active.is_none() => {
+                let len = self.rooms.browser.len();
+                match key.code {
+                    KeyCode::Up => step_cursor(&mut self.rooms.browser_selected, len, -1),
+                    KeyCode::Down => step_cursor(&mut self.rooms.browser_selected, len, 1),
+                    KeyCode::Enter => {
+                        if let Some(room) = self.rooms.selected_browser_room() {
+                            self.room_buffer = room.to_string();
+                            return PendingAction::JoinRoom;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            KeyCode::Enter | KeyCode::Char('i') => {
+                self.input_mode = InputMode::EditingRoomMessage;
+                self.room_buffer.clear();
+            }
+            KeyCode::PageUp => {
+                if let Some(room) = self.rooms.active_room_mut() {
+                    room.scroll_by(SCROLL_STEP);
+                }
+            }
+            KeyCode::PageDown => {
+                if let Some(room) = self.rooms.active_room_mut() {
+                    room.scroll_by(-SCROLL_STEP);
+                }
+            }
+            KeyCode::Char('x') => return PendingAction::LeaveRoom,
+            _ => {}
+        }
+        PendingAction::None
+    }
+
+    fn handle_chat_key(&mut self, key: KeyEvent) -> PendingAction {
+        match key.code {
+            KeyCode::Left => {
+                self.conversations.cycle(-1);
+                self.mark_conversation_read();
+            }
+            KeyCode::Right => {
+                self.conversations.cycle(1);
+                self.mark_conversation_read();
+            }
+            KeyCode::Char('n') => {
+                self.input_mode = InputMode::EditingChatUser;
+                self.chat_buffer.clear();
+                self.push_log("Type a username and press Enter to open a conversation.");
+            }
+            _ if self.conversations.active.is_none() => {
+                let len = self.chats.threads().len();
+                match key.code {
+                    KeyCode::Up => step_cursor(&mut self.tab_selection.chat, len, -1),
+                    KeyCode::Down => step_cursor(&mut self.tab_selection.chat, len, 1),
+                    KeyCode::Enter => {
+                        if let Some(username) = self
+                            .chats
+                            .threads()
+                            .get(self.tab_selection.chat)
+                            .map(|thread| thread.username.clone())
+                        {
+                            self.open_conversation(&username);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            KeyCode::Enter | KeyCode::Char('i') => {
+                self.input_mode = InputMode::EditingChatMessage;
+                self.chat_buffer.clear();
+            }
+            KeyCode::PageUp | KeyCode::PageDown => {
+                let messages = self
+                    .conversations
+                    .active_user()
+                    .and_then(|username| self.chats.thread(username))
+                    .map_or(0, |thread| thread.messages.len());
+                let step = if key.code == KeyCode::PageUp {
+                    SCROLL_STEP
+                } else {
+                    -SCROLL_STEP
+                };
+                self.conversations.scroll_by(step, messages);
+            }
+            KeyCode::Char('x') => {
+                self.conversations.close_active();
+                self.mark_conversation_read();
+            }
+            _ => {}
+        }
+        PendingAction::None
+    }
+
+    fn handle_users_key(&mut self, key: KeyEvent) -> PendingAction {
+        let len = self.state.buddies.len();
+        match key.code {
+            KeyCode::Up => step_cursor(&mut self.tab_selection.user, len, -1),
+            KeyCode::Down => step_cursor(&mut self.tab_selection.user, len, 1),
+            KeyCode::Char('x') => {
+                if let Some(buddy) = self.state.buddies.buddies().get(self.tab_selection.user) {
+                    self.buddy_buffer = buddy.username.clone();
+                    step_cursor(&mut self.tab_selection.user, len - 1, 0);
+                    return PendingAction::ToggleBuddy;
+                }
+            }
+            KeyCode::Char('m') => {
+                if let Some(username) = self
+                    .state
+                    .buddies
+                    .buddies()
+                    .get(self.tab_selection.user)
+                    .map(|buddy| buddy.username.clone())
+                {
+                    self.message_user(&username);
+                }
+            }
+            KeyCode::Char('v') => {
+                if let Some(buddy) = self.state.buddies.buddies().get(self.tab_selection.user) {
+                    self.browse_buffer = buddy.username.clone();
+                    return PendingAction::BrowseUser;
+                }
+            }
+            _ => {}
+        }
+        PendingAction::None
+    }
+
+    fn handle_shares_key(&mut self, key: KeyEvent) -> PendingAction {
+        match key.code {
+            KeyCode::Up => self.shares.move_selection(-1),
+            KeyCode::Down => self.shares.move_selection(1),
+            KeyCode::PageUp => self.shares.move_selection(-SCROLL_STEP),
+            KeyCode::PageDown => self.shares.move_selection(SCROLL_STEP),
+            KeyCode::Enter | KeyCode::Right => self.shares.toggle_selected(),
+            KeyCode::Left => self.shares.collapse_selected(),
+            KeyCode::Char('n') => {
+                self.input_mode = InputMode::EditingBrowseUser;
+                self.browse_buffer.clear();
+            }
+            KeyCode::Char('r') if self.shares.tree.is_some() => {
+                return PendingAction::RefreshShares;
+            }
+            KeyCode::Char('d') => self.queue_selected_shares(),
+            _ => {}
+        }
+        PendingAction::None
+    }
+
+    fn handle_browse_edit_key(&mut self, key: KeyEvent) -> PendingAction {
+        match key.code {
+            KeyCode::Esc => {
+                self.input_mode = InputMode::Normal;
+                self.browse_buffer.clear();
+            }
+            KeyCode::Backspace => {
+                self.browse_buffer.pop();
+            }
+            KeyCode::Enter => {
+                self.input_mode = InputMode::Normal;
+                return PendingAction::BrowseUser;
+            }
+            KeyCode::Char(ch) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.browse_buffer.push(ch);
+            }
+            _ => {}
+        }
+        PendingAction::None
+    }
+
+    pub fn active_tab(&self) -> MainTab {
+        self.state.ui.active_tab
+    }
+
+    pub fn select_tab(&mut self, tab: MainTab) {
+        if self.state.ui.active_tab != tab {
+            self.state.ui.active_tab = tab;
+            self.persist_state();
+        }
+        if tab == MainTab::Chat {
+            self.mark_conversation_read();
+        }
+    }
+
+    /// Opens the conversation with `username` on the Chat tab.
+    pub fn open_conversation(&mut self, username: &str) {
+        self.conversations.open(username);
+        self.select_tab(MainTab::Chat);
+    }
+
+    /// Opens the conversation with `username` and starts typing a message.
+    pub fn message_user(&mut self, username: &str) {
+        self.open_conversation(username);
+        self.input_mode = InputMode::EditingChatMessage;
+        self.chat_buffer.clear();
+    }
+
+    fn mark_conversation_read(&mut self) {
+        if let Some(username) = self.conversations.active_user().map(str::to_string)
+            && self.chats.mark_read(&username) > 0
+        {
+            self.persist_chats();
+        }
+    }
+
+    /// Download history under the Transfers filter, newest first.
+    pub fn visible_downloads(&self) -> Vec<&PersistedDownloadEntry> {
+        let filter = self.state.ui.transfers_filter;
+        self.state
+            .downloads
+            .iter()
+            .rev()
+            .filter(|entry| filter.matches(&entry.status))
+            .collect()
+    }
+
+    fn remove_selected_download(&mut self) {
+        let Some(id) = self
+            .visible_downloads()
+            .get(self.tab_selection.transfer)
+            .map(|entry| entry.id.clone())
+        else {
+            return;
+        };
+        if self.transfers.contains_key(&id) {
+            self.push_log("Running downloads cannot be removed.");
+            return;
+        }
+        self.state.downloads.retain(|entry| entry.id != id);
+        let len = self.visible_downloads().len();
+        step_cursor(&mut self.tab_selection.transfer, len, 0);
+        self.persist_state();
+    }
+
+    fn persist_chats(&mut self) {
+        if cfg!(test) {
+            return;
+        }
+        let result = storage::chat_file_path().and_then(|path| self.chats.save(path));
+        if let Err(err) = result {
+            self.push_log(format!("Chat history save failed: {err}"));
+        }
+    }
+
+    fn handle_query_edit_key(&mut self, key: KeyEvent) -> PendingAction {
+        match key.code {
+            KeyCode::Esc => {
+                self.input_mode = InputMode::Normal;
+                self.query_buffer = self.state.last_query.clone();
+                self.push_log("Query edit canceled.");
+                PendingAction::None
+            }
+            KeyCode::Backspace => {
+                self.query_buffer.pop();
+                PendingAction::None
+            }
+            KeyCode::Enter => {
+                let trimmed = self.query_buffer.trim();
+                if trimmed.is_empty() {
+                    self.push_log("Query cannot be empty.");
+                    return PendingAction::None;
+                }
+                self.state.last_query = trimmed.to_string();
+                self.query_buffer = self.state.last_query.clone();
+                self.input_mode = InputMode::Normal;
+                self.persist_state();
+                PendingAction::Search
+            }
+            KeyCode::Char(ch) => {
+                if key.modifiers.contains(KeyModifiers::CONTROL) {
+                    return PendingAction::None;
+                }
+                self.query_buffer.push(ch);
+                PendingAction::None
+            }
+            _ => PendingAction::None,
+        }
+    }
+
+    fn handle_target_edit_key(&mut self, key: KeyEvent) -> PendingAction {
+        match key.code {
+            KeyCode::Esc => {
+                self.input_mode = InputMode::Normal;
+                self.target_buffer = self.state.search_target_name.clone();
+                self.push_log("Target edit canceled.");
+            }
+            KeyCode::Backspace => {
+                self.target_buffer.pop();
+            }
+            KeyCode::Enter => {
+                self.state.search_target_name = self.target_buffer.trim().to_string();
+                self.target_buffer = self.state.search_target_name.clone();
+                self.input_mode = InputMode::Normal;
+                self.persist_state();
+                self.push_log(format!("Search target: {}", self.target_for_display()));
+            }
+            KeyCode::Char(ch) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.target_buffer.push(ch);
+            }
+            _ => {}
+        }
+        PendingAction::None
+    }
+
+    fn handle_filter_edit_key(&mut self, key: KeyEvent) -> PendingAction {
+        match key.code {
+            KeyCode::Esc => {
+                self.input_mode = InputMode::Normal;
+                self.filter_buffer.clear();
+            }
+            KeyCode::Backspace => {
+                self.filter_buffer.pop();
+            }
+            KeyCode::Enter => {
+                let text = std::mem::take(&mut self.filter_buffer);
+                let result = match self.searches.active_tab_mut() {
+                    Some(tab) => tab.set_filter(&text),
+                    None => Ok(()),
+                };
+                match result {
+                    Ok(()) => self.input_mode = InputMode::Normal,
+                    Err(err) => {
+                        self.filter_buffer = text;
+                        self.push_log(format!("Filter not applied: {err}"));
+                    }
+                }
+            }
+            KeyCode::Char(ch) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.filter_buffer.push(ch);
+            }
+            _ => {}
+        }
+        PendingAction::None
+    }
+
+    fn handle_buddy_edit_key(&mut self, key: KeyEvent) -> PendingAction {
+        match key.code {
+            KeyCode::Esc => {
+                self.input_mode = InputMode::Normal;
+                self.buddy_buffer.clear();
+                PendingAction::None
+            }
+            KeyCode::Backspace => {
+                self.buddy_buffer.pop();
+                PendingAction::None
+            }
+            KeyCode::Enter => {
+                self.input_mode = InputMode::Normal;
+                PendingAction::ToggleBuddy
+            }
+            KeyCode::Char(ch) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.buddy_buffer.push(ch);
+                PendingAction::None
+            }
+            _ => PendingAction::None,
+        }
+    }
+
+    fn handle_room_edit_key(&mut self, key: KeyEvent) -> PendingAction {
+        match key.code {
+            KeyCode::Esc => {
+                self.input_mode = InputMode::Normal;
+                self.room_buffer.clear();
+            }
+            KeyCode::Backspace => {
+                self.room_buffer.pop();
+            }
+            KeyCode::Enter => {
+                let action = if self.input_mode == InputMode::EditingRoomName {
+                    PendingAction::JoinRoom
+                } else {
+                    PendingAction::SayRoom
+                };
+                self.input_mode = InputMode::Normal;
+                return action;
+            }
+            KeyCode::Char(ch) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.room_buffer.push(ch);
+            }
+            _ => {}
+        }
+        PendingAction::None
+    }
+
+    fn handle_chat_edit_key(&mut self, key: KeyEvent) -> PendingAction {
+        match key.code {
+            KeyCode::Esc => {
+                self.input_mode = InputMode::Normal;
+                self.chat_buffer.clear();
+            }
+            KeyCode::Backspace => {
+                self.chat_buffer.pop();
+            }
+            KeyCode::Enter => {
+                if self.input_mode == InputMode::EditingChatMessage {
+                    self.input_mode = InputMode::Normal;
+                    return PendingAction::SendPrivateMessage;
+                }
+                self.input_mode = InputMode::Normal;
+                let username = self.chat_buffer.trim().to_string();
+                self.chat_buffer.clear();
+                if username.is_empty() {
+                    self.push_log("Username cannot be empty.");
+                } else {
+                    self.open_conversation(&username);
+                }
+            }
+            KeyCode::Char(ch) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.chat_buffer.push(ch);
+            }
+            _ => {}
+        }
+        PendingAction::None
     }
 
     fn active_login_field_mut(&mut self) -> &mut String {
@@ -680,13 +2080,76 @@ impl App {
             LoginField::Server => &mut self.state.server,
             LoginField::Username => &mut self.state.username,
             LoginField::Password => &mut self.state.password,
+            LoginField::Passphrase => &mut self.store_passphrase,
         }
     }
 
+    /// Opens the file store with the passphrase typed at login, then fills
+    /// in the saved password unless one was typed. Returns `false` when the
+    /// passphrase was rejected and the login should stop.
+    fn unlock_credentials(&mut self) -> bool {
+        if !self.store_locked || self.store_passphrase.is_empty() {
+            return true;
+        }
+        let passphrase = std::mem::take(&mut self.store_passphrase);
+        let mut store = match soul_core::open_credential_store_with(|| Ok(Some(passphrase))) {
+            Ok(Some(store)) => store,
+            Ok(None) => return true,
+            Err(err) => {
+                self.login_error = Some(format!("Credential store locked: {err}"));
+                self.push_log(format!("Credential store unlock failed: {err}"));
+                return false;
+            }
+        };
+        self.store_locked = false;
+        if self.login_focus == LoginField::Passphrase {
+            self.login_focus = LoginField::Password;
+        }
+        self.push_log(format!(
+            "Unlocked the {} credential store.",
+            store.backend()
+        ));
+        if self.state.password.is_empty() || self.state.password == self.state.legacy_password {
+            let mut logs = Vec::new();
+            if restore_from_store(&mut self.state, store.as_mut(), &mut logs) {
+                self.persist_state();
+            }
+            for line in logs {
+                self.push_log(line);
+            }
+        }
+        self.credentials = Some(store);
+        true
+    }
+
     pub fn active_login_field(&self) -> LoginField {
         self.login_focus
     }
 
+    /// Saves the password that just logged in. Without a store, a plaintext
+    /// password from an old state file is replaced rather than left stale.
+    fn remember_password(&mut self) {
+        let Some(store) = self.credentials.as_mut() else {
+            if !self.state.legacy_password.is_empty() {
+                self.state.legacy_password = self.state.password.clone();
+                self.persist_state();
+            }
+            return;
+        };
+        match store.set(
+            &self.state.server,
+            &self.state.username,
+            &self.state.password,
+        ) {
+            Ok(()) if !self.state.legacy_password.is_empty() => {
+                self.state.legacy_password.clear();
+                self.persist_state();
+            }
+            Ok(()) => {}
+            Err(err) => self.push_log(format!("Password save failed: {err}")),
+        }
+    }
+
     pub fn persist_state(&mut self) {
         if cfg!(test) {
             return;
@@ -698,29 +2161,158 @@ impl App {
     }
 
     #[cfg(test)]
-    fn new_for_test(state: PersistedAppStateV1) -> Self {
+    fn new_for_test(state: PersistedAppState) -> Self {
         let output_dir = PathBuf::from(state.output_dir.clone());
+        let (task_tx, task_rx) = mpsc::unbounded_channel();
         Self {
             phase: UiPhase::LoginModal,
             login_focus: LoginField::Username,
             input_mode: InputMode::Normal,
             query_buffer: state.last_query.clone(),
-            selected_result: 0,
+            target_buffer: state.search_target_name.clone(),
+            buddy_buffer: String::new(),
+            room_buffer: String::new(),
+            chat_buffer: String::new(),
+            filter_buffer: String::new(),
+            browse_buffer: String::new(),
             session_state: SessionState::Disconnected,
             login_error: None,
             logs: vec!["test".to_string()],
-            results: Vec::new(),
+            searches: SearchTabs::default(),
             diagnostics_visible: false,
             diagnostics_lines: Vec::new(),
+            wishlist: wishlist_scheduler_for(&state),
+            auto_away: auto_away_for(&state),
             state,
             output_dir,
+            presence: UserPresence::Online,
+            transfers: HashMap::new(),
+            chats: ChatStore::new(),
+            conversations: ConversationTabs::default(),
+            tab_selection: TabSelection::default(),
+            rooms: RoomsState::default(),
+            shares: SharesState::default(),
+            download_queue: VecDeque::new(),
+            active_download: None,
+            busy: None,
+            task_tx,
+            task_rx,
+            pending_presence: None,
+            credentials: None,
+            store_locked: false,
+            store_passphrase: String::new(),
             auto_login_pending: false,
             transfer_token: 555,
+            search_token: 1_000,
+            wishlist_token: 90_000,
             session: None,
+            reader: None,
+            pending_acks: Vec::new(),
+        }
+    }
+}
+
+/// Gauge label for a running transfer: queue place, or size, speed and ETA.
+pub fn transfer_progress_label(meter: &TransferMeter) -> String {
+    if meter.queued {
+        return match meter.queue_place {
+            Some(place) => format!("queued at place {place}"),
+            None => "queued by peer".to_string(),
+        };
+    }
+    let mut label = match meter.ratio() {
+        Some(ratio) => format!(
+            "{:.0}% {}/{}",
+            ratio * 100.0,
+            format_bytes(meter.bytes),
+            format_bytes(meter.total)
+        ),
+        None => format_bytes(meter.bytes),
+    };
+    if let Some(speed) = meter.bytes_per_sec() {
+        label.push_str(&format!(" {}/s", format_bytes(speed)));
+    }
+    if let Some(eta) = meter.eta() {
+        let secs = eta.as_secs();
+        label.push_str(&format!(" eta {}:{:02}", secs / 60, secs % 60));
+    }
+    label
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Moves a list cursor by `delta` and keeps it inside `0..len`.
+fn step_cursor(cursor: &mut usize, len: usize, delta: isize) {
+    *cursor = if len == 0 {
+        0
+    } else {
+        cursor.saturating_add_signed(delta).min(len - 1)
+    };
+}
+
+fn auto_away_for(state: &PersistedAppState) -> AutoAway {
+    let idle_after = Duration::from_secs(state.auto_away_minutes.saturating_mul(60));
+    AutoAway::new(Some(idle_after), tokio::time::Instant::now())
+}
+
+/// Fills in the login password from a freshly opened store, logging a
+/// migration or failure. Returns whether the state file needs saving.
+fn restore_from_store(
+    state: &mut PersistedAppState,
+    store: &mut dyn CredentialStore,
+    logs: &mut Vec<String>,
+) -> bool {
+    match storage::restore_password(state, store) {
+        Ok(PasswordOrigin::Migrated) => {
+            logs.push(format!(
+                "Moved the saved password into the {} credential store.",
+                store.backend()
+            ));
+            true
         }
+        Ok(PasswordOrigin::Store | PasswordOrigin::Missing) => false,
+        Err(err) => {
+            logs.push(format!("Saved password unavailable: {err}"));
+            false
+        }
+    }
+}
+
+fn buddy_event_line(event: &BuddyEvent) -> String {
+    match event {
+        BuddyEvent::NotFound { username } => format!("Buddy {username} does not exist."),
+        BuddyEvent::StatusChanged {
+            username, presence, ..
+        } => format!("Buddy {username} is {}.", presence.label()),
+        BuddyEvent::StatsChanged { username, stats } => format!(
+            "Buddy {username} shares {} files in {} folders.",
+            stats.files, stats.dirs
+        ),
     }
 }
 
+/// Files already in the download history are not queued again by the wishlist.
+fn wishlist_scheduler_for(state: &PersistedAppState) -> WishlistScheduler {
+    let mut scheduler = WishlistScheduler::new(tokio::time::Instant::now());
+    for entry in &state.downloads {
+        scheduler.remember(&entry.username, &entry.file_path);
+    }
+    scheduler
+}
+
 fn parse_server_host_port(server: &str) -> Result<(String, u16), String> {
     let trimmed = server.trim();
     let (host, port_raw) = trimmed
@@ -745,19 +2337,44 @@ fn now_unix_secs() -> i64 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::searches::SortColumn;
+    use crate::state::TransferFilter;
+    use protocol::{SearchFileSummary, SearchResponseSummary};
+    use soul_core::{SearchResultSource, SearchSelectDownloadError, ShareTree, TransferProgress};
 
     #[test]
     fn login_modal_blocks_main_actions() {
-        let mut app = App::new_for_test(PersistedAppStateV1::default());
+        let mut app = App::new_for_test(PersistedAppState::default());
         app.phase = UiPhase::LoginModal;
         let action = app.handle_key(KeyEvent::new(KeyCode::Char('d'), KeyModifiers::NONE));
         assert_eq!(action, PendingAction::None);
         assert_eq!(app.phase, UiPhase::LoginModal);
     }
 
+    #[test]
+    fn passphrase_field_is_focusable_only_while_the_store_is_locked() {
+        let mut app = App::new_for_test(PersistedAppState::default());
+        app.phase = UiPhase::LoginModal;
+        app.login_focus = LoginField::Password;
+        let tab = KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE);
+
+        app.handle_key(tab);
+        assert_eq!(app.active_login_field(), LoginField::Server);
+
+        app.store_locked = true;
+        app.login_focus = LoginField::Password;
+        app.handle_key(tab);
+        assert_eq!(app.active_login_field(), LoginField::Passphrase);
+        app.handle_key(KeyEvent::new(KeyCode::Char('k'), KeyModifiers::NONE));
+        assert_eq!(app.store_passphrase, "k");
+        assert_eq!(app.state.password, "");
+        app.handle_key(KeyEvent::new(KeyCode::BackTab, KeyModifiers::SHIFT));
+        assert_eq!(app.active_login_field(), LoginField::Password);
+    }
+
     #[test]
     fn query_edit_enter_submits_search_action() {
-        let mut app = App::new_for_test(PersistedAppStateV1::default());
+        let mut app = App::new_for_test(PersistedAppState::default());
         app.phase = UiPhase::Main;
         app.input_mode = InputMode::EditingQuery;
         app.query_buffer = "boards of canada".to_string();
@@ -767,9 +2384,681 @@ mod tests {
         assert_eq!(app.state.last_query, "boards of canada");
     }
 
+    #[test]
+    fn search_target_cycles_and_builds_scopes() {
+        let mut app = App::new_for_test(PersistedAppState::default());
+        app.phase = UiPhase::Main;
+        assert_eq!(app.search_scope(), Ok(SearchScope::Global));
+
+        app.handle_key(KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE));
+        assert_eq!(app.state.search_target, SearchTargetKind::Room);
+        assert!(app.search_scope().is_err());
+
+        app.handle_key(KeyEvent::new(KeyCode::Char('e'), KeyModifiers::NONE));
+        assert_eq!(app.input_mode, InputMode::EditingTarget);
+        for ch in "indie".chars() {
+            app.handle_key(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE));
+        }
+        let action = app.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert_eq!(action, PendingAction::None);
+        assert_eq!(
+            app.search_scope(),
+            Ok(SearchScope::Room("indie".to_string()))
+        );
+
+        app.state.search_target = SearchTargetKind::Buddies;
+        assert!(app.search_scope().is_err());
+        app.state.buddies.upsert("alice", None, None);
+        app.state.buddies.upsert("bob", None, Some("friends"));
+        assert_eq!(
+            app.search_scope(),
+            Ok(SearchScope::Users(vec![
+                "alice".to_string(),
+                "bob".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn search_row_keeps_the_exact_candidate_for_download() {
+        let candidate = SearchCandidate {
+            username: "peer_boards".to_string(),
+            file_path: "Music\\Boards\\Roygbiv.flac".to_string(),
+            file_size: 4_096,
+            peer_addr: Some("10.0.0.7:2234".to_string()),
+            connect_token: Some(77),
+            bitrate: None,
+            source: SearchResultSource::DistributedPeer,
+        };
+        let row = SearchRow::from_candidate(candidate.clone());
+        assert_eq!(row.to_candidate(), candidate);
+    }
+
+    #[test]
+    fn search_tabs_stream_filter_sort_and_take_fallback_results() {
+        let mut app = App::new_for_test(PersistedAppState::default());
+        app.phase = UiPhase::Main;
+        let now = tokio::time::Instant::now();
+        app.searches.start(7, "boards", "global", false, now);
+        app.searches.start(8, "flim", "global", false, now);
+        let file = |path: &str, size| SearchFileSummary {
+            file_path: path.to_string(),
+            file_size: size,
+            extension: String::new(),
+            attr_count: 0,
+        };
+        app.searches.add_summary(&SearchResponseSummary {
+            username: "alice".to_string(),
+            token: 7,
+            files_count: 2,
+            slots_free: 1,
+            speed: 500,
+            in_queue: false,
+            files: vec![
+                file("Music\\Roygbiv.flac", 30),
+                file("Music\\Roygbiv.mp3", 9),
+            ],
+        });
+        assert_eq!(app.searches.active_tab().expect("tab").query, "flim");
+        assert!(app.selected_search_row().is_none());
+
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        app.handle_key(key(KeyCode::Left));
+        assert_eq!(app.selected_search_row().expect("row").file_size, 30);
+        app.handle_key(key(KeyCode::Char('f')));
+        for ch in "size:1x".chars() {
+            app.handle_key(key(KeyCode::Char(ch)));
+        }
+        app.handle_key(key(KeyCode::Enter));
+        assert_eq!(app.input_mode, InputMode::EditingFilter);
+        assert!(app.logs.last().unwrap().starts_with("Filter not applied"));
+        app.handle_key(key(KeyCode::Esc));
+        app.handle_key(key(KeyCode::Char('f')));
+        for ch in "ext:mp3".chars() {
+            app.handle_key(key(KeyCode::Char(ch)));
+        }
+        app.handle_key(key(KeyCode::Enter));
+        assert_eq!(app.input_mode, InputMode::Normal);
+        let tab = app.searches.active_tab().expect("tab");
+        assert_eq!(tab.filter_text, "ext:mp3");
+        assert_eq!(tab.visible_rows().len(), 1);
+
+        app.handle_key(key(KeyCode::Char('f')));
+        app.handle_key(key(KeyCode::Backspace));
+        for _ in 0..6 {
+            app.handle_key(key(KeyCode::Backspace));
+        }
+        app.handle_key(key(KeyCode::Enter));
+        for _ in 0..3 {
+            app.handle_key(key(KeyCode::Char('s')));
+        }
+        let tab = app.searches.active_tab().expect("tab");
+        assert_eq!((tab.sort, tab.descending), (SortColumn::Size, true));
+        app.handle_key(key(KeyCode::Char('r')));
+        assert_eq!(app.selected_search_row().expect("row").file_size, 9);
+
+        app.handle_key(key(KeyCode::Right));
+        app.searches.active_tab_mut().expect("tab").status = SearchStatus::Fallback;
+        app.task_tx
+            .send(TaskEvent::SearchFinished {
+                token: 8,
+                query: "flim".to_string(),
+                result: Ok(vec![SearchCandidate {
+                    username: "bob".to_string(),
+                    file_path: "Music\\Flim.flac".to_string(),
+                    file_size: 42,
+                    peer_addr: Some("10.0.0.7:2234".to_string()),
+                    connect_token: Some(3),
+                    bitrate: Some(900),
+                    source: SearchResultSource::DistributedPeer,
+                }]),
+            })
+            .unwrap();
+        app.drain_task_events();
+        let tab = app.searches.active_tab().expect("tab");
+        assert_eq!(tab.status, SearchStatus::Done);
+        assert_eq!(tab.rows[0].bitrate, Some(900));
+
+        app.handle_key(key(KeyCode::Char('x')));
+        assert_eq!(app.searches.tabs.len(), 1);
+        assert_eq!(app.searches.active_tab().expect("tab").token, 7);
+    }
+
+    #[tokio::test]
+    async fn folder_download_queues_listed_files_and_starts_them_in_order() {
+        let mut app = App::new_for_test(PersistedAppState::default());
+        app.phase = UiPhase::Main;
+        let file = |path: &str| SearchCandidate {
+            username: "bob".to_string(),
+            file_path: path.to_string(),
+            file_size: 10,
+            peer_addr: Some("10.0.0.7:2234".to_string()),
+            connect_token: None,
+            bitrate: None,
+            source: SearchResultSource::ServerSummary,
+        };
+        app.searches
+            .start(1, "album", "global", false, tokio::time::Instant::now());
+        let row = SearchRow::from_candidate(file("Music\\Album\\01.flac"));
+        app.searches.tabs[0].append(vec![row]);
+        app.searches.tabs[0].status = SearchStatus::Done;
+
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        assert_eq!(
+            app.handle_key(key(KeyCode::Char('D'))),
+            PendingAction::DownloadFolder
+        );
+        app.download_folder();
+        assert!(
+            app.logs
+                .last()
+                .unwrap()
+                .contains("session is not logged in")
+        );
+
+        app.phase = UiPhase::Main;
+        app.task_tx
+            .send(TaskEvent::FolderListed {
+                username: "bob".to_string(),
+                folder: "Music\\Album".to_string(),
+                result: Ok(vec![
+                    file("Music\\Album\\01.flac"),
+                    file("Music\\Album\\02.flac"),
+                ]),
+            })
+            .unwrap();
+        app.drain_task_events();
+        assert_eq!(app.download_queue.len(), 2);
+        assert!(
+            app.state
+                .downloads
+                .iter()
+                .all(|entry| entry.status == PersistedDownloadStatus::Queued)
+        );
+
+        app.start_queued_download();
+        assert_eq!(app.download_queue.len(), 2, "no session to start with");
+        app.session = Some(SessionClient::new_disconnected());
+        app.start_queued_download();
+        assert_eq!(app.busy_task(), Some("download"));
+        assert_eq!(app.download_queue.len(), 1);
+        assert_eq!(
+            app.state.downloads[0].status,
+            PersistedDownloadStatus::InProgress
+        );
+        assert_eq!(
+            app.state.downloads[1].status,
+            PersistedDownloadStatus::Queued
+        );
+        assert!(app.logs.last().unwrap().contains("Music\\Album\\01.flac"));
+    }
+
+    #[test]
+    fn shares_tab_browses_users_and_queues_tree_downloads() {
+        let mut app = App::new_for_test(PersistedAppState::default());
+        app.phase = UiPhase::Main;
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        app.handle_key(key(KeyCode::Char('6')));
+        assert_eq!(app.active_tab(), MainTab::Shares);
+        assert_eq!(app.handle_key(key(KeyCode::Char('r'))), PendingAction::None);
+
+        app.handle_key(key(KeyCode::Char('n')));
+        assert_eq!(app.input_mode, InputMode::EditingBrowseUser);
+        for ch in "bob".chars() {
+            app.handle_key(key(KeyCode::Char(ch)));
+        }
+        assert_eq!(
+            app.handle_key(key(KeyCode::Enter)),
+            PendingAction::BrowseUser
+        );
+        assert_eq!(app.browse_buffer, "bob");
+
+        let entries =
+            [("Music\\Album\\01.flac", 10), ("Music\\Album\\02.flac", 20)].map(|(path, size)| {
+                protocol::SharedFileEntry {
+                    virtual_path: path.to_string(),
+                    size,
+                }
+            });
+        app.task_tx
+            .send(TaskEvent::BrowseFinished {
+                username: "bob".to_string(),
+                result: Ok(ShareTree::from_entries("bob", &entries)),
+            })
+            .unwrap();
+        app.drain_task_events();
+        assert_eq!(app.shares.username(), Some("bob"));
+        assert_eq!(
+            app.logs.last().map(String::as_str),
+            Some("Shares of bob: 2 files.")
+        );
+
+        app.handle_key(key(KeyCode::Enter));
+        app.handle_key(key(KeyCode::Down));
+        app.handle_key(key(KeyCode::Char('d')));
+        assert_eq!(app.download_queue.len(), 2);
+        app.handle_key(key(KeyCode::Right));
+        app.handle_key(key(KeyCode::Down));
+        app.handle_key(key(KeyCode::Char('d')));
+        assert_eq!(app.download_queue.len(), 3);
+        assert_eq!(app.download_queue[2].1.file_path, "Music\\Album\\01.flac");
+        assert_eq!(
+            app.handle_key(key(KeyCode::Char('r'))),
+            PendingAction::RefreshShares
+        );
+    }
+
+    #[tokio::test]
+    async fn background_download_events_drive_gauges_and_history() {
+        let mut app = App::new_for_test(PersistedAppState::default());
+        app.phase = UiPhase::Main;
+        app.busy = Some("download");
+        app.active_download = Some("dl-1".to_string());
+        app.state.downloads.push(PersistedDownloadEntry {
+            id: "dl-1".to_string(),
+            username: "peer_boards".to_string(),
+            file_path: "Music\\Roygbiv.flac".to_string(),
+            bytes: 0,
+            status: PersistedDownloadStatus::InProgress,
+            started_at: 1,
+            ended_at: None,
+        });
+
+        app.search();
+        assert!(app.logs.last().unwrap().contains("download still running"));
+
+        let tx = app.task_tx.clone();
+        tx.send(TaskEvent::DownloadProgress {
+            download_id: "dl-1".to_string(),
+            progress: TransferProgress::Queued { place: Some(3) },
+        })
+        .unwrap();
+        app.drain_task_events();
+        assert_eq!(
+            transfer_progress_label(&app.transfers["dl-1"]),
+            "queued at place 3"
+        );
+
+        tx.send(TaskEvent::DownloadProgress {
+            download_id: "dl-1".to_string(),
+            progress: TransferProgress::Receiving {
+                bytes: 2_048,
+                total: 4_096,
+            },
+        })
+        .unwrap();
+        app.drain_task_events();
+        assert_eq!(
+            transfer_progress_label(&app.transfers["dl-1"]),
+            "50% 2.0 KiB/4.0 KiB"
+        );
+
+        // The session comes back once the peer side is set up, while the
+        // transfer keeps running; the next queued file still waits for it.
+        tx.send(TaskEvent::SessionReturned(SessionClient::new_disconnected()))
+            .unwrap();
+        app.drain_task_events();
+        assert_eq!(app.busy_task(), None);
+        assert!(app.session.is_some());
+        app.searches
+            .start(1, "indigo", "global", false, tokio::time::Instant::now());
+        app.searches.tabs[0].append(vec![SearchRow::from_candidate(SearchCandidate {
+            username: "peer_boards".to_string(),
+            file_path: "Music\\Indigo.flac".to_string(),
+            file_size: 10,
+            peer_addr: None,
+            connect_token: None,
+            bitrate: None,
+            source: SearchResultSource::ServerSummary,
+        })]);
+        app.download_selected();
+        assert_eq!(app.download_queue.len(), 1);
+        assert_eq!(
+            app.state.downloads[1].status,
+            PersistedDownloadStatus::Queued
+        );
+        app.start_queued_download();
+        assert_eq!(app.download_queue.len(), 1);
+
+        tx.send(TaskEvent::DownloadFinished {
+            download_id: "dl-1".to_string(),
+            result: Err(SearchSelectDownloadError::Download(
+                "peer went away".to_string(),
+            )),
+        })
+        .unwrap();
+        app.drain_task_events();
+        assert!(app.transfers.is_empty());
+        assert_eq!(
+            app.state.downloads[0].status,
+            PersistedDownloadStatus::Failed
+        );
+        assert!(app.active_download.is_none());
+    }
+
+    #[test]
+    fn tabs_switch_with_number_and_bracket_keys_and_scope_their_keys() {
+        let mut app = App::new_for_test(PersistedAppState::default());
+        app.phase = UiPhase::Main;
+        for (id, status) in [
+            ("d1", PersistedDownloadStatus::Done),
+            ("d2", PersistedDownloadStatus::Failed),
+            ("d3", PersistedDownloadStatus::Done),
+        ] {
+            app.state.downloads.push(PersistedDownloadEntry {
+                id: id.to_string(),
+                username: "alice".to_string(),
+                file_path: format!("{id}.flac"),
+                bytes: 0,
+                status,
+                started_at: 1,
+                ended_at: Some(2),
+            });
+        }
+
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        app.handle_key(key(KeyCode::Char('2')));
+        assert_eq!(app.active_tab(), MainTab::Transfers);
+        assert_eq!(app.handle_key(key(KeyCode::Char('d'))), PendingAction::None);
+
+        app.handle_key(key(KeyCode::Char('f')));
+        assert_eq!(app.state.ui.transfers_filter, TransferFilter::Active);
+        app.handle_key(key(KeyCode::Char('f')));
+        let ids: Vec<_> = app
+            .visible_downloads()
+            .iter()
+            .map(|e| e.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["d3", "d1"]);
+
+        app.handle_key(key(KeyCode::Down));
+        app.handle_key(key(KeyCode::Char('x')));
+        let ids: Vec<_> = app.state.downloads.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["d2", "d3"]);
+
+        app.handle_key(key(KeyCode::Char(']')));
+        assert_eq!(app.active_tab(), MainTab::Rooms);
+        app.handle_key(key(KeyCode::Char('[')));
+        app.handle_key(key(KeyCode::Char('[')));
+        assert_eq!(app.active_tab(), MainTab::Search);
+        app.handle_key(key(KeyCode::Char('[')));
+        assert_eq!(app.active_tab(), MainTab::Shares);
+
+        app.state.buddies.upsert("bob", None, None);
+        app.handle_key(key(KeyCode::Char('5')));
+        assert_eq!(
+            app.handle_key(key(KeyCode::Char('x'))),
+            PendingAction::ToggleBuddy
+        );
+        assert_eq!(app.buddy_buffer, "bob");
+    }
+
+    #[test]
+    fn rooms_tab_keys_join_write_scroll_and_leave() {
+        let mut app = App::new_for_test(PersistedAppState::default());
+        app.phase = UiPhase::Main;
+        app.rooms.set_room_list(&protocol::RoomListPayload {
+            room_count: 2,
+            rooms: vec!["small".into(), "nicotine".into()],
+            user_counts: vec![3, 40],
+            private_rooms: None,
+        });
+
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        app.handle_key(key(KeyCode::Char('3')));
+        assert_eq!(app.active_tab(), MainTab::Rooms);
+        assert_eq!(
+            app.handle_key(key(KeyCode::Char('r'))),
+            PendingAction::ListRooms
+        );
+        assert_eq!(app.handle_key(key(KeyCode::Enter)), PendingAction::JoinRoom);
+        assert_eq!(app.room_buffer, "nicotine");
+
+        app.handle_key(key(KeyCode::Char('j')));
+        assert_eq!(app.input_mode, InputMode::EditingRoomName);
+        for ch in "jazz".chars() {
+            app.handle_key(key(KeyCode::Char(ch)));
+        }
+        assert_eq!(app.handle_key(key(KeyCode::Enter)), PendingAction::JoinRoom);
+        assert_eq!(app.room_buffer, "jazz");
+
+        app.rooms.open("jazz");
+        for idx in 0..30 {
+            app.rooms.apply_event(
+                &soul_core::RoomEvent::RoomMessage {
+                    room: "jazz".into(),
+                    username: Some("bob".into()),
+                    message: format!("line {idx}"),
+                },
+                1,
+            );
+        }
+        app.handle_key(key(KeyCode::PageUp));
+        assert_eq!(app.rooms.active_room().map(|room| room.scroll), Some(10));
+
+        app.handle_key(key(KeyCode::Char('i')));
+        app.handle_key(key(KeyCode::Char('q')));
+        assert_eq!(app.room_buffer, "q");
+        assert_eq!(app.handle_key(key(KeyCode::Enter)), PendingAction::SayRoom);
+        assert_eq!(app.input_mode, InputMode::Normal);
+
+        assert_eq!(
+            app.handle_key(key(KeyCode::Char('x'))),
+            PendingAction::LeaveRoom
+        );
+        app.handle_key(key(KeyCode::Left));
+        assert_eq!(app.rooms.active, None);
+    }
+
+    #[tokio::test]
+    async fn reader_task_forwards_pushes_and_acks_private_messages() {
+        use protocol::{
+            CODE_SM_MESSAGE_ACKED, LoginResponsePayload, LoginResponseSuccessPayload,
+            encode_server_message,
+        };
+        use soul_core::{read_frame, write_frame};
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.expect("accept");
+            let _login = read_frame(&mut socket).await.expect("login frame");
+            let success = encode_server_message(&ServerMessage::LoginResponse(
+                LoginResponsePayload::Success(LoginResponseSuccessPayload {
+                    greeting: String::new(),
+                    ip_address: "127.0.0.1".into(),
+                    md5hash: "0123456789abcdef0123456789abcdef".into(),
+                    is_supporter: false,
+                }),
+            ));
+            write_frame(&mut socket, &success)
+                .await
+                .expect("write login success");
+            let message = encode_server_message(&ServerMessage::MessageUserIncoming(
+                MessageUserIncomingPayload {
+                    message_id: 7,
+                    timestamp: 1_700_000_000,
+                    username: "bob".into(),
+                    message: "hi".into(),
+                    is_new: true,
+                },
+            ));
+            write_frame(&mut socket, &message)
+                .await
+                .expect("write message");
+            let ack = read_frame(&mut socket).await.expect("ack frame");
+            assert_eq!(ack.code, CODE_SM_MESSAGE_ACKED);
+            assert_eq!(ack.payload, 7_u32.to_le_bytes());
+        });
+
+        let mut client = SessionClient::connect(&addr.to_string())
+            .await
+            .expect("connect");
+        client
+            .login(&Credentials {
+                username: "alice".into(),
+                password: "secret-pass".into(),
+                client_version: 160,
+                minor_version: 1,
+            })
+            .await
+            .expect("login");
+        let mut app = App::new_for_test(PersistedAppState::default());
+        app.phase = UiPhase::Main;
+        app.session = Some(client);
+        app.start_reader();
+        assert!(app.reader.is_some());
+
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(2);
+        while app.chats.unread("bob") == 0 {
+            assert!(tokio::time::Instant::now() < deadline, "push never arrived");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            app.drain_task_events();
+        }
+        assert_eq!(app.pending_acks, vec![7]);
+        app.flush_message_acks().await;
+        assert!(app.pending_acks.is_empty());
+
+        server.await.expect("server task");
+    }
+
+    #[test]
+    fn private_messages_notify_elsewhere_and_open_from_search_results() {
+        let mut app = App::new_for_test(PersistedAppState::default());
+        app.phase = UiPhase::Main;
+        app.searches
+            .start(1, "track", "global", false, tokio::time::Instant::now());
+        app.searches.tabs[0].append(vec![SearchRow::from_candidate(SearchCandidate {
+            username: "bob".to_string(),
+            file_path: "Music\\track.flac".to_string(),
+            file_size: 1,
+            peer_addr: None,
+            connect_token: None,
+            bitrate: None,
+            source: SearchResultSource::ServerSummary,
+        })]);
+        let incoming = |message_id, text: &str| MessageUserIncomingPayload {
+            message_id,
+            timestamp: 1_700_000_000,
+            username: "bob".to_string(),
+            message: text.to_string(),
+            is_new: true,
+        };
+
+        app.record_private_messages(vec![incoming(1, "hi"), incoming(1, "hi")]);
+        assert_eq!(app.chats.unread("bob"), 1);
+        assert_eq!(
+            app.logs.last().map(String::as_str),
+            Some("Message from bob: hi")
+        );
+
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        app.handle_key(key(KeyCode::Char('m')));
+        assert_eq!(app.active_tab(), MainTab::Chat);
+        assert!(app.conversations.is_viewing("bob"));
+        assert_eq!(app.input_mode, InputMode::EditingChatMessage);
+        assert_eq!(app.chats.unread("bob"), 0);
+        app.handle_key(key(KeyCode::Char('y')));
+        assert_eq!(
+            app.handle_key(key(KeyCode::Enter)),
+            PendingAction::SendPrivateMessage
+        );
+        assert_eq!(app.chat_buffer, "y");
+
+        app.record_private_messages(vec![incoming(2, "still here?")]);
+        assert_eq!(app.chats.unread("bob"), 0);
+
+        app.handle_key(key(KeyCode::Char('x')));
+        assert_eq!(app.conversations.active, None);
+        app.handle_key(key(KeyCode::Char('1')));
+        app.record_private_messages(vec![incoming(3, "ping")]);
+        assert_eq!(app.chats.unread("bob"), 1);
+        app.handle_key(key(KeyCode::Char('4')));
+        app.handle_key(key(KeyCode::Enter));
+        assert!(app.conversations.is_viewing("bob"));
+        assert_eq!(app.chats.total_unread(), 0);
+    }
+
+    #[tokio::test]
+    async fn away_key_and_idle_timer_drive_presence() {
+        let mut app = App::new_for_test(PersistedAppState::default());
+        app.phase = UiPhase::Main;
+
+        app.handle_key(KeyEvent::new(KeyCode::Char('a'), KeyModifiers::NONE));
+        app.sync_presence().await;
+        assert_eq!(app.presence, UserPresence::Away);
+        app.handle_key(KeyEvent::new(KeyCode::Char('a'), KeyModifiers::NONE));
+        app.sync_presence().await;
+        assert_eq!(app.presence, UserPresence::Online);
+
+        app.auto_away = AutoAway::new(Some(Duration::from_millis(5)), tokio::time::Instant::now());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        app.sync_presence().await;
+        assert_eq!(app.presence, UserPresence::Away);
+        assert_eq!(app.presence_label(), "away (auto)");
+
+        app.handle_key(KeyEvent::new(KeyCode::Down, KeyModifiers::NONE));
+        app.sync_presence().await;
+        assert_eq!(app.presence, UserPresence::Online);
+    }
+
+    #[tokio::test]
+    async fn buddy_input_toggles_buddy_list_entries() {
+        let mut app = App::new_for_test(PersistedAppState::default());
+        app.phase = UiPhase::Main;
+
+        app.handle_key(KeyEvent::new(KeyCode::Char('b'), KeyModifiers::NONE));
+        for ch in "carol".chars() {
+            app.handle_key(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE));
+        }
+        let action = app.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        assert_eq!(action, PendingAction::ToggleBuddy);
+        app.toggle_buddy().await;
+        assert_eq!(app.state.buddies.usernames(), vec!["carol".to_string()]);
+
+        app.buddy_buffer = "carol".to_string();
+        app.toggle_buddy().await;
+        assert!(app.state.buddies.is_empty());
+    }
+
+    #[test]
+    fn wishlist_toggle_and_queue_hook_update_state() {
+        let mut app = App::new_for_test(PersistedAppState::default());
+        app.phase = UiPhase::Main;
+        app.state.last_query = "flim".to_string();
+
+        app.handle_key(KeyEvent::new(KeyCode::Char('w'), KeyModifiers::NONE));
+        assert_eq!(
+            app.state.wishlist,
+            vec![PersistedWishlistEntry::new("flim")]
+        );
+
+        app.queue_wishlist_matches(
+            "flim",
+            vec![SearchCandidate {
+                username: "bob".to_string(),
+                file_path: "Music\\Flim.flac".to_string(),
+                file_size: 42,
+                peer_addr: None,
+                connect_token: None,
+                bitrate: None,
+                source: SearchResultSource::ServerSummary,
+            }],
+        );
+        assert_eq!(app.state.downloads.len(), 1);
+        assert_eq!(
+            app.state.downloads[0].status,
+            PersistedDownloadStatus::Queued
+        );
+
+        app.handle_key(KeyEvent::new(KeyCode::Char('w'), KeyModifiers::NONE));
+        assert!(app.state.wishlist.is_empty());
+    }
+
     #[test]
     fn toggle_and_clear_downloads_behave_as_expected() {
-        let mut app = App::new_for_test(PersistedAppStateV1::default());
+        let mut app = App::new_for_test(PersistedAppState::default());
         let before = app.downloads_visible();
         app.toggle_downloads_panel();
         assert_ne!(before, app.downloads_visible());
@@ -789,7 +3078,7 @@ mod tests {
 
     #[test]
     fn diagnostics_key_opens_wizard_from_login_modal() {
-        let mut app = App::new_for_test(PersistedAppStateV1::default());
+        let mut app = App::new_for_test(PersistedAppState::default());
         app.phase = UiPhase::LoginModal;
         let action = app.handle_key(KeyEvent::new(KeyCode::Char('g'), KeyModifiers::NONE));
         assert_eq!(action, PendingAction::RunDiagnostics);
@@ -797,7 +3086,7 @@ mod tests {
 
     #[test]
     fn diagnostics_modal_can_be_closed_with_escape() {
-        let mut app = App::new_for_test(PersistedAppStateV1::default());
+        let mut app = App::new_for_test(PersistedAppState::default());
         app.diagnostics_visible = true;
         let action = app.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
         assert_eq!(action, PendingAction::None);
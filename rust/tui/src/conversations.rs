@@ -0,0 +1,87 @@
+/// Private conversations opened as tabs on the Chat screen. `active: None`
+/// shows the conversation list, `Some(idx)` the conversation with `open[idx]`.
+#[derive(Debug, Clone, Default)]
+pub struct ConversationTabs {
+    pub open: Vec<String>,
+    pub active: Option<usize>,
+    /// Messages scrolled up from the newest one in the active conversation.
+    pub scroll: usize,
+}
+
+impl ConversationTabs {
+    pub fn active_user(&self) -> Option<&str> {
+        self.open.get(self.active?).map(String::as_str)
+    }
+
+    pub fn is_viewing(&self, username: &str) -> bool {
+        self.active_user() == Some(username)
+    }
+
+    /// Adds a tab for `username` if needed and switches to it.
+    pub fn open(&mut self, username: &str) {
+        let idx = match self.open.iter().position(|user| user == username) {
+            Some(idx) => idx,
+            None => {
+                self.open.push(username.to_string());
+                self.open.len() - 1
+            }
+        };
+        self.focus(Some(idx));
+    }
+
+    /// Closes the active conversation tab and shows the one before it.
+    pub fn close_active(&mut self) -> Option<String> {
+        let idx = self.active?;
+        let username = self.open.remove(idx);
+        self.focus(idx.checked_sub(1));
+        Some(username)
+    }
+
+    /// Moves between the list and the conversation tabs, wrapping around.
+    pub fn cycle(&mut self, delta: isize) {
+        let slots = self.open.len() as isize + 1;
+        let current = self.active.map_or(0, |idx| idx as isize + 1);
+        let next = (current + delta).rem_euclid(slots);
+        self.focus((next > 0).then(|| next as usize - 1));
+    }
+
+    pub fn scroll_by(&mut self, delta: isize, messages: usize) {
+        let max = messages.saturating_sub(1);
+        self.scroll = self.scroll.saturating_add_signed(delta).min(max);
+    }
+
+    fn focus(&mut self, active: Option<usize>) {
+        self.active = active;
+        self.scroll = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tabs_open_once_cycle_and_close() {
+        let mut tabs = ConversationTabs::default();
+        tabs.open("bob");
+        tabs.open("carol");
+        tabs.open("bob");
+        assert_eq!(tabs.open, vec!["bob".to_string(), "carol".to_string()]);
+        assert!(tabs.is_viewing("bob"));
+
+        tabs.scroll_by(5, 3);
+        assert_eq!(tabs.scroll, 2);
+        tabs.cycle(1);
+        assert!(tabs.is_viewing("carol"));
+        assert_eq!(tabs.scroll, 0);
+        tabs.cycle(1);
+        assert_eq!(tabs.active, None);
+
+        tabs.cycle(-1);
+        assert_eq!(tabs.close_active().as_deref(), Some("carol"));
+        assert!(tabs.is_viewing("bob"));
+        assert_eq!(tabs.close_active().as_deref(), Some("bob"));
+        assert_eq!(tabs.active, None);
+        assert_eq!(tabs.close_active(), None);
+    }
+}
@@ -1,6 +1,11 @@
 mod app;
+mod conversations;
+mod rooms;
+mod searches;
+mod shares;
 mod state;
 mod storage;
+mod tasks;
 mod ui;
 
 use anyhow::Result;
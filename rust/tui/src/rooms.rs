@@ -0,0 +1,325 @@
+use protocol::RoomListPayload;
+use soul_core::{RoomEvent, RoomLogEntry};
+
+/// Lines kept per joined room; older history is still in the room log.
+const ROOM_HISTORY_LIMIT: usize = 500;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JoinedRoom {
+    pub name: String,
+    pub history: Vec<RoomLogEntry>,
+    pub members: Vec<String>,
+    pub operators: Vec<String>,
+    /// `(username, ticker)` in the order the server sent them.
+    pub tickers: Vec<(String, String)>,
+    /// Lines scrolled up from the newest message.
+    pub scroll: usize,
+    pub unread: usize,
+}
+
+impl JoinedRoom {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            history: Vec::new(),
+            members: Vec::new(),
+            operators: Vec::new(),
+            tickers: Vec::new(),
+            scroll: 0,
+            unread: 0,
+        }
+    }
+
+    pub fn scroll_by(&mut self, delta: isize) {
+        let max = self.history.len().saturating_sub(1);
+        self.scroll = self.scroll.saturating_add_signed(delta).min(max);
+    }
+
+    fn push_history(&mut self, entries: Vec<RoomLogEntry>) {
+        if self.scroll > 0 {
+            // Keep the lines the user scrolled to in place.
+            self.scroll += entries.len();
+        }
+        self.history.extend(entries);
+        if self.history.len() > ROOM_HISTORY_LIMIT {
+            let overflow = self.history.len() - ROOM_HISTORY_LIMIT;
+            self.history.drain(0..overflow);
+        }
+        self.scroll = self.scroll.min(self.history.len().saturating_sub(1));
+    }
+}
+
+/// Room browser plus the rooms joined this session. `active: None` shows the
+/// browser, `Some(idx)` the joined room at `idx`.
+#[derive(Debug, Clone, Default)]
+pub struct RoomsState {
+    pub browser: Vec<(String, u32)>,
+    pub browser_selected: usize,
+    pub joined: Vec<JoinedRoom>,
+    pub active: Option<usize>,
+}
+
+impl RoomsState {
+    /// Replaces the browser with the server list, busiest rooms first.
+    pub fn set_room_list(&mut self, list: &RoomListPayload) {
+        self.browser = list
+            .rooms
+            .iter()
+            .enumerate()
+            .map(|(idx, room)| {
+                (
+                    room.clone(),
+                    list.user_counts.get(idx).copied().unwrap_or(0),
+                )
+            })
+            .collect();
+        self.browser
+            .sort_by(|left, right| right.1.cmp(&left.1).then_with(|| left.0.cmp(&right.0)));
+        self.browser_selected = 0;
+    }
+
+    pub fn selected_browser_room(&self) -> Option<&str> {
+        self.browser
+            .get(self.browser_selected)
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn active_room(&self) -> Option<&JoinedRoom> {
+        self.joined.get(self.active?)
+    }
+
+    pub fn active_room_mut(&mut self) -> Option<&mut JoinedRoom> {
+        self.joined.get_mut(self.active?)
+    }
+
+    pub fn total_unread(&self) -> usize {
+        self.joined.iter().map(|room| room.unread).sum()
+    }
+
+    /// Adds a room tab if needed and switches to it.
+    pub fn open(&mut self, name: &str) {
+        let idx = match self.joined.iter().position(|room| room.name == name) {
+            Some(idx) => idx,
+            None => {
+                self.joined.push(JoinedRoom::new(name));
+                self.joined.len() - 1
+            }
+        };
+        self.focus(Some(idx));
+    }
+
+    /// Drops a room tab; returns `false` when it was not joined.
+    pub fn leave(&mut self, name: &str) -> bool {
+        let Some(idx) = self.joined.iter().position(|room| room.name == name) else {
+            return false;
+        };
+        self.joined.remove(idx);
+        self.active = match self.active {
+            Some(active) if active == idx => idx.checked_sub(1),
+            Some(active) if active > idx => Some(active - 1),
+            active => active,
+        };
+        true
+    }
+
+    /// Moves between the browser and the room tabs, wrapping around.
+    pub fn cycle(&mut self, delta: isize) {
+        let slots = self.joined.len() as isize + 1;
+        let current = self.active.map_or(0, |idx| idx as isize + 1);
+        let next = (current + delta).rem_euclid(slots);
+        self.focus((next > 0).then(|| next as usize - 1));
+    }
+
+    /// Applies a server room event. Returns `true` when it was a message in a
+    /// room the user is not looking at.
+    pub fn apply_event(&mut self, event: &RoomEvent, timestamp: u64) -> bool {
+        let active = self.active;
+        let Some((idx, room)) = self
+            .joined
+            .iter_mut()
+            .enumerate()
+            .find(|(_, room)| room.name == event_room(event))
+        else {
+            return false;
+        };
+        match event {
+            RoomEvent::UserJoined { username, .. } => {
+                if !room.members.contains(username) {
+                    room.members.push(username.clone());
+                    room.members.sort();
+                }
+            }
+            RoomEvent::UserLeft { username, .. } => {
+                room.members.retain(|member| member != username);
+                room.tickers.retain(|(user, _)| user != username);
+            }
+            RoomEvent::MembersSnapshot(payload) => {
+                room.members = payload.users.clone();
+                room.members.sort();
+            }
+            RoomEvent::OperatorsSnapshot(payload) => room.operators = payload.operators.clone(),
+            RoomEvent::TickerSnapshot(payload) => {
+                room.tickers = payload
+                    .entries
+                    .iter()
+                    .map(|entry| (entry.username.clone(), entry.ticker.clone()))
+                    .collect();
+            }
+            RoomEvent::TickerSet {
+                username, ticker, ..
+            } => {
+                room.tickers.retain(|(user, _)| user != username);
+                room.tickers.push((username.clone(), ticker.clone()));
+            }
+            RoomEvent::TickerRemoved { username, .. } => {
+                room.tickers.retain(|(user, _)| user != username);
+            }
+            RoomEvent::RoomMessage { .. } => {}
+        }
+        // Ticker snapshots repeat on every join and would flood the history.
+        if matches!(event, RoomEvent::TickerSnapshot(_)) {
+            return false;
+        }
+        let entries = RoomLogEntry::from_event(event, timestamp);
+        if entries.is_empty() {
+            return false;
+        }
+        let unseen = active != Some(idx) && matches!(event, RoomEvent::RoomMessage { .. });
+        if unseen {
+            room.unread += 1;
+        }
+        room.push_history(entries);
+        unseen
+    }
+
+    fn focus(&mut self, active: Option<usize>) {
+        self.active = active;
+        if let Some(room) = self.active_room_mut() {
+            room.unread = 0;
+        }
+    }
+}
+
+fn event_room(event: &RoomEvent) -> &str {
+    match event {
+        RoomEvent::UserJoined { room, .. }
+        | RoomEvent::UserLeft { room, .. }
+        | RoomEvent::RoomMessage { room, .. }
+        | RoomEvent::TickerSet { room, .. }
+        | RoomEvent::TickerRemoved { room, .. } => room,
+        RoomEvent::MembersSnapshot(payload) => &payload.room,
+        RoomEvent::OperatorsSnapshot(payload) => &payload.room,
+        RoomEvent::TickerSnapshot(payload) => &payload.room,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::{RoomMembersPayload, RoomTickerEntry, RoomTickerPayload};
+
+    fn said(room: &str, username: &str, message: &str) -> RoomEvent {
+        RoomEvent::RoomMessage {
+            room: room.into(),
+            username: Some(username.into()),
+            message: message.into(),
+        }
+    }
+
+    #[test]
+    fn room_list_is_sorted_by_user_count() {
+        let mut rooms = RoomsState::default();
+        rooms.set_room_list(&RoomListPayload {
+            room_count: 3,
+            rooms: vec!["quiet".into(), "busy".into(), "alpha".into()],
+            user_counts: vec![2, 90, 2],
+            private_rooms: None,
+        });
+        assert_eq!(
+            rooms.browser,
+            vec![
+                ("busy".to_string(), 90),
+                ("alpha".to_string(), 2),
+                ("quiet".to_string(), 2)
+            ]
+        );
+        assert_eq!(rooms.selected_browser_room(), Some("busy"));
+    }
+
+    #[test]
+    fn events_update_members_tickers_history_and_unread() {
+        let mut rooms = RoomsState::default();
+        rooms.open("nicotine");
+        rooms.open("jazz");
+        assert_eq!(rooms.active, Some(1));
+
+        rooms.apply_event(
+            &RoomEvent::MembersSnapshot(RoomMembersPayload {
+                room: "nicotine".into(),
+                users: vec!["carol".into(), "bob".into()],
+            }),
+            10,
+        );
+        rooms.apply_event(
+            &RoomEvent::TickerSnapshot(RoomTickerPayload {
+                room: "nicotine".into(),
+                entries: vec![RoomTickerEntry {
+                    username: "bob".into(),
+                    ticker: "afk".into(),
+                }],
+            }),
+            10,
+        );
+        assert!(rooms.apply_event(&said("nicotine", "bob", "hi"), 11));
+        assert!(!rooms.apply_event(
+            &RoomEvent::UserLeft {
+                room: "nicotine".into(),
+                username: "bob".into(),
+            },
+            12,
+        ));
+        assert!(!rooms.apply_event(&said("jazz", "dave", "listening"), 12));
+        assert!(!rooms.apply_event(&said("elsewhere", "eve", "ignored"), 12));
+
+        let nicotine = &rooms.joined[0];
+        assert_eq!(nicotine.members, vec!["carol".to_string()]);
+        assert!(nicotine.tickers.is_empty());
+        assert_eq!(nicotine.history.len(), 2);
+        assert_eq!(nicotine.unread, 1);
+        assert_eq!(rooms.joined[1].unread, 0);
+
+        rooms.cycle(-1);
+        assert_eq!(rooms.active, Some(0));
+        assert_eq!(rooms.total_unread(), 0);
+        rooms.cycle(-1);
+        assert_eq!(rooms.active, None);
+
+        rooms.open("jazz");
+        assert!(rooms.leave("jazz"));
+        assert_eq!(rooms.active, Some(0));
+        assert_eq!(rooms.joined.len(), 1);
+        assert_eq!(rooms.joined[0].name, "nicotine");
+    }
+
+    #[test]
+    fn scrolled_history_stays_in_place_and_is_capped() {
+        let mut rooms = RoomsState::default();
+        rooms.open("nicotine");
+        for idx in 0..10 {
+            rooms.apply_event(&said("nicotine", "bob", &format!("line {idx}")), 1);
+        }
+        let room = rooms.active_room_mut().expect("active room");
+        room.scroll_by(3);
+        assert_eq!(room.scroll, 3);
+
+        rooms.apply_event(&said("nicotine", "bob", "newer"), 2);
+        assert_eq!(rooms.active_room().expect("active room").scroll, 4);
+
+        for idx in 0..ROOM_HISTORY_LIMIT {
+            rooms.apply_event(&said("nicotine", "bob", &format!("flood {idx}")), 3);
+        }
+        let room = rooms.active_room().expect("active room");
+        assert_eq!(room.history.len(), ROOM_HISTORY_LIMIT);
+        assert_eq!(room.scroll, ROOM_HISTORY_LIMIT - 1);
+    }
+}
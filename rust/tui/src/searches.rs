@@ -0,0 +1,568 @@
+use std::cmp::Ordering;
+
+use protocol::SearchResponseSummary;
+use soul_core::{SearchCandidate, SearchResultSource, remote_folder};
+use tokio::time::Instant;
+
+/// Finished tabs beyond this count are dropped, oldest first.
+const MAX_SEARCH_TABS: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchRow {
+    pub username: String,
+    pub file_path: String,
+    pub file_size: u64,
+    pub peer_addr: Option<String>,
+    pub connect_token: Option<u32>,
+    pub source: SearchResultSource,
+    pub bitrate: Option<u32>,
+    /// Whether the user had a free upload slot; unknown for peer results.
+    pub free_slot: Option<bool>,
+    /// Upload speed in bytes per second as reported by the server.
+    pub speed: Option<u32>,
+}
+
+impl SearchRow {
+    pub fn from_candidate(candidate: SearchCandidate) -> Self {
+        Self {
+            username: candidate.username,
+            file_path: candidate.file_path,
+            file_size: candidate.file_size,
+            peer_addr: candidate.peer_addr,
+            connect_token: candidate.connect_token,
+            source: candidate.source,
+            bitrate: candidate.bitrate,
+            free_slot: None,
+            speed: None,
+        }
+    }
+
+    pub fn from_summary(summary: &SearchResponseSummary) -> Vec<Self> {
+        summary
+            .files
+            .iter()
+            .map(|file| Self {
+                username: summary.username.clone(),
+                file_path: file.file_path.clone(),
+                file_size: file.file_size,
+                peer_addr: None,
+                connect_token: None,
+                source: SearchResultSource::ServerSummary,
+                bitrate: None,
+                free_slot: Some(summary.slots_free > 0),
+                speed: Some(summary.speed),
+            })
+            .collect()
+    }
+
+    pub fn to_candidate(&self) -> SearchCandidate {
+        SearchCandidate {
+            username: self.username.clone(),
+            file_path: self.file_path.clone(),
+            file_size: self.file_size,
+            peer_addr: self.peer_addr.clone(),
+            connect_token: self.connect_token,
+            bitrate: self.bitrate,
+            source: self.source,
+        }
+    }
+
+    pub fn folder(&self) -> &str {
+        remote_folder(&self.file_path)
+    }
+
+    /// Rows from the same user and remote folder form one group.
+    pub fn same_group(&self, other: &SearchRow) -> bool {
+        self.username == other.username && self.folder() == other.folder()
+    }
+
+    fn extension(&self) -> String {
+        let name = self
+            .file_path
+            .rsplit(['\\', '/'])
+            .next()
+            .unwrap_or_default();
+        name.rsplit_once('.')
+            .map(|(_, extension)| extension.to_ascii_lowercase())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortColumn {
+    #[default]
+    Arrival,
+    User,
+    Path,
+    Size,
+    Bitrate,
+    Speed,
+}
+
+impl SortColumn {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Arrival => Self::User,
+            Self::User => Self::Path,
+            Self::Path => Self::Size,
+            Self::Size => Self::Bitrate,
+            Self::Bitrate => Self::Speed,
+            Self::Speed => Self::Arrival,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Arrival => "arrival",
+            Self::User => "user",
+            Self::Path => "path",
+            Self::Size => "size",
+            Self::Bitrate => "bitrate",
+            Self::Speed => "speed",
+        }
+    }
+
+    /// Numeric columns start with the biggest value on top.
+    fn descending_by_default(self) -> bool {
+        matches!(self, Self::Size | Self::Bitrate | Self::Speed)
+    }
+
+    fn compare(self, left: &SearchRow, right: &SearchRow) -> Ordering {
+        match self {
+            Self::Arrival => Ordering::Equal,
+            Self::User => left
+                .username
+                .to_lowercase()
+                .cmp(&right.username.to_lowercase()),
+            Self::Path => left
+                .file_path
+                .to_lowercase()
+                .cmp(&right.file_path.to_lowercase()),
+            Self::Size => left.file_size.cmp(&right.file_size),
+            Self::Bitrate => left.bitrate.cmp(&right.bitrate),
+            Self::Speed => left.speed.cmp(&right.speed),
+        }
+    }
+}
+
+/// Row filter typed into the filter bar. Rows that do not report a bitrate
+/// or slot state never pass a filter on that value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub extension: Option<String>,
+    pub min_size: u64,
+    pub min_bitrate: u32,
+    pub free_slots_only: bool,
+}
+
+impl SearchFilter {
+    /// Parses space separated terms: `word` must appear in the path, `-word`
+    /// must not, `ext:flac`, `size:10M` (minimum, K/M/G suffixes),
+    /// `br:320` (minimum kbps) and `free`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut filter = Self::default();
+        for term in text.split_whitespace() {
+            let lower = term.to_lowercase();
+            if let Some(extension) = lower.strip_prefix("ext:") {
+                filter.extension = Some(extension.trim_start_matches('.').to_string());
+            } else if let Some(size) = lower.strip_prefix("size:") {
+                filter.min_size = parse_size(size)?;
+            } else if let Some(bitrate) = lower.strip_prefix("br:") {
+                filter.min_bitrate = bitrate
+                    .parse()
+                    .map_err(|_| format!("invalid bitrate: {bitrate}"))?;
+            } else if lower == "free" {
+                filter.free_slots_only = true;
+            } else if let Some(word) = lower.strip_prefix('-') {
+                if !word.is_empty() {
+                    filter.exclude.push(word.to_string());
+                }
+            } else {
+                filter.include.push(lower);
+            }
+        }
+        Ok(filter)
+    }
+
+    pub fn matches(&self, row: &SearchRow) -> bool {
+        let path = row.file_path.to_lowercase();
+        self.include.iter().all(|word| path.contains(word.as_str()))
+            && !self.exclude.iter().any(|word| path.contains(word.as_str()))
+            && self
+                .extension
+                .as_ref()
+                .is_none_or(|extension| row.extension() == *extension)
+            && row.file_size >= self.min_size
+            && (self.min_bitrate == 0 || row.bitrate.is_some_and(|br| br >= self.min_bitrate))
+            && (!self.free_slots_only || row.free_slot == Some(true))
+    }
+}
+
+fn parse_size(text: &str) -> Result<u64, String> {
+    let (digits, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(idx) => text.split_at(idx),
+        None => (text, ""),
+    };
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid size: {text}"))?;
+    let scale = match unit.trim_end_matches(['b', 'B']) {
+        "" => 1,
+        "k" => 1 << 10,
+        "m" => 1 << 20,
+        "g" => 1 << 30,
+        _ => return Err(format!("invalid size unit: {text}")),
+    };
+    Ok(value.saturating_mul(scale))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchStatus {
+    /// Results are appended as the server forwards them.
+    Streaming,
+    /// No summary rows arrived; a distributed peer search is running.
+    Fallback,
+    Done,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchTab {
+    pub token: u32,
+    pub query: String,
+    pub target: String,
+    /// Room and user searches carry no token on the wire, so their results
+    /// are matched to the newest such tab that is still streaming.
+    pub scoped: bool,
+    pub rows: Vec<SearchRow>,
+    pub selected: usize,
+    pub filter_text: String,
+    filter: SearchFilter,
+    pub sort: SortColumn,
+    pub descending: bool,
+    pub status: SearchStatus,
+    pub streaming_until: Instant,
+}
+
+impl SearchTab {
+    /// Rows passing the filter, grouped by user and remote folder. Groups
+    /// are ordered by their best row in the chosen sort order, and ties keep
+    /// arrival order.
+    pub fn visible_rows(&self) -> Vec<&SearchRow> {
+        let mut rows: Vec<&SearchRow> = self
+            .rows
+            .iter()
+            .filter(|row| self.filter.matches(row))
+            .collect();
+        rows.sort_by(|left, right| {
+            let ordering = self.sort.compare(left, right);
+            if self.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+        let mut leaders: Vec<&SearchRow> = Vec::new();
+        let mut rank = Vec::with_capacity(rows.len());
+        for row in &rows {
+            let group = match leaders.iter().position(|leader| leader.same_group(row)) {
+                Some(group) => group,
+                None => {
+                    leaders.push(row);
+                    leaders.len() - 1
+                }
+            };
+            rank.push(group);
+        }
+        let mut ranked: Vec<(usize, &SearchRow)> = rank.into_iter().zip(rows).collect();
+        ranked.sort_by_key(|(group, _)| *group);
+        ranked.into_iter().map(|(_, row)| row).collect()
+    }
+
+    pub fn selected_row(&self) -> Option<&SearchRow> {
+        self.visible_rows().get(self.selected).copied()
+    }
+
+    pub fn set_filter(&mut self, text: &str) -> Result<(), String> {
+        self.filter = SearchFilter::parse(text)?;
+        self.filter_text = text.trim().to_string();
+        self.clamp_selection();
+        Ok(())
+    }
+
+    pub fn cycle_sort(&mut self) {
+        self.sort = self.sort.next();
+        self.descending = self.sort.descending_by_default();
+        self.selected = 0;
+    }
+
+    pub fn reverse_sort(&mut self) {
+        self.descending = !self.descending;
+        self.selected = 0;
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        let len = self.visible_rows().len();
+        self.selected = self
+            .selected
+            .saturating_add_signed(delta)
+            .min(len.saturating_sub(1));
+    }
+
+    /// Appends rows not already listed; returns how many were new.
+    pub fn append(&mut self, rows: Vec<SearchRow>) -> usize {
+        let before = self.rows.len();
+        for row in rows {
+            let duplicate = self.rows.iter().any(|existing| {
+                existing.username == row.username && existing.file_path == row.file_path
+            });
+            if !duplicate {
+                self.rows.push(row);
+            }
+        }
+        self.rows.len() - before
+    }
+
+    fn clamp_selection(&mut self) {
+        let len = self.visible_rows().len();
+        self.selected = self.selected.min(len.saturating_sub(1));
+    }
+}
+
+/// Search tabs keyed by search token; `active` indexes `tabs`.
+#[derive(Debug, Clone, Default)]
+pub struct SearchTabs {
+    pub tabs: Vec<SearchTab>,
+    pub active: usize,
+}
+
+impl SearchTabs {
+    pub fn start(&mut self, token: u32, query: &str, target: &str, scoped: bool, until: Instant) {
+        if self.tabs.len() >= MAX_SEARCH_TABS
+            && let Some(idx) = self
+                .tabs
+                .iter()
+                .position(|tab| tab.status == SearchStatus::Done)
+        {
+            self.tabs.remove(idx);
+        }
+        self.tabs.push(SearchTab {
+            token,
+            query: query.to_string(),
+            target: target.to_string(),
+            scoped,
+            rows: Vec::new(),
+            selected: 0,
+            filter_text: String::new(),
+            filter: SearchFilter::default(),
+            sort: SortColumn::Arrival,
+            descending: false,
+            status: SearchStatus::Streaming,
+            streaming_until: until,
+        });
+        self.active = self.tabs.len() - 1;
+    }
+
+    pub fn active_tab(&self) -> Option<&SearchTab> {
+        self.tabs.get(self.active)
+    }
+
+    pub fn active_tab_mut(&mut self) -> Option<&mut SearchTab> {
+        self.tabs.get_mut(self.active)
+    }
+
+    pub fn tab_mut(&mut self, token: u32) -> Option<&mut SearchTab> {
+        self.tabs.iter_mut().find(|tab| tab.token == token)
+    }
+
+    pub fn any_streaming(&self) -> bool {
+        self.tabs
+            .iter()
+            .any(|tab| tab.status == SearchStatus::Streaming)
+    }
+
+    /// Appends a server summary to the tab it belongs to. Returns the token
+    /// of that tab, or `None` when no open search matches.
+    pub fn add_summary(&mut self, summary: &SearchResponseSummary) -> Option<u32> {
+        let idx = match self.tabs.iter().position(|tab| tab.token == summary.token) {
+            Some(idx) => idx,
+            None => self
+                .tabs
+                .iter()
+                .rposition(|tab| tab.scoped && tab.status == SearchStatus::Streaming)?,
+        };
+        let tab = &mut self.tabs[idx];
+        tab.append(SearchRow::from_summary(summary));
+        Some(tab.token)
+    }
+
+    /// Ends the streaming window of every tab whose deadline passed and
+    /// returns their tokens.
+    pub fn expire(&mut self, now: Instant) -> Vec<u32> {
+        self.tabs
+            .iter_mut()
+            .filter(|tab| tab.status == SearchStatus::Streaming && now >= tab.streaming_until)
+            .map(|tab| {
+                tab.status = SearchStatus::Done;
+                tab.token
+            })
+            .collect()
+    }
+
+    pub fn close_active(&mut self) -> Option<SearchTab> {
+        if self.tabs.is_empty() {
+            return None;
+        }
+        let tab = self.tabs.remove(self.active);
+        self.active = self.active.min(self.tabs.len().saturating_sub(1));
+        Some(tab)
+    }
+
+    pub fn cycle(&mut self, delta: isize) {
+        if self.tabs.is_empty() {
+            return;
+        }
+        let len = self.tabs.len() as isize;
+        self.active = (self.active as isize + delta).rem_euclid(len) as usize;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::SearchFileSummary;
+    use tokio::time::Duration;
+
+    fn summary(
+        token: u32,
+        username: &str,
+        slots_free: u32,
+        files: &[(&str, u64)],
+    ) -> SearchResponseSummary {
+        SearchResponseSummary {
+            username: username.to_string(),
+            token,
+            files_count: files.len() as u32,
+            slots_free,
+            speed: 1_000,
+            in_queue: false,
+            files: files
+                .iter()
+                .map(|(path, size)| SearchFileSummary {
+                    file_path: path.to_string(),
+                    file_size: *size,
+                    extension: String::new(),
+                    attr_count: 0,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn filter_terms_parse_and_match_rows() {
+        let filter = SearchFilter::parse("boards -live ext:flac size:2k br:320 free").unwrap();
+        assert_eq!(filter.include, vec!["boards".to_string()]);
+        assert_eq!(filter.exclude, vec!["live".to_string()]);
+        assert_eq!(filter.extension.as_deref(), Some("flac"));
+        assert_eq!(filter.min_size, 2_048);
+        assert_eq!(filter.min_bitrate, 320);
+        assert!(filter.free_slots_only);
+        assert!(SearchFilter::parse("size:12q").is_err());
+
+        let mut row = SearchRow::from_summary(&summary(
+            1,
+            "bob",
+            1,
+            &[("Music\\Boards\\Roygbiv.FLAC", 4_096)],
+        ))
+        .remove(0);
+        assert!(!filter.matches(&row), "rows without a bitrate fail br:");
+        row.bitrate = Some(320);
+        assert!(filter.matches(&row));
+        row.file_path = "Music\\Boards\\Roygbiv (live).flac".to_string();
+        assert!(!filter.matches(&row));
+    }
+
+    #[test]
+    fn rows_group_by_user_and_folder_in_sort_order() {
+        let mut searches = SearchTabs::default();
+        searches.start(1, "boards", "global", false, Instant::now());
+        let tab = searches.active_tab_mut().unwrap();
+        tab.append(SearchRow::from_summary(&summary(
+            1,
+            "bob",
+            1,
+            &[("Music\\A\\1.flac", 5), ("Music\\B\\1.flac", 50)],
+        )));
+        tab.append(SearchRow::from_summary(&summary(
+            1,
+            "carol",
+            1,
+            &[("Music\\A\\2.flac", 40)],
+        )));
+        tab.append(SearchRow::from_summary(&summary(
+            1,
+            "bob",
+            1,
+            &[("Music\\A\\3.flac", 30)],
+        )));
+        let order = |tab: &SearchTab| -> Vec<u64> {
+            tab.visible_rows().iter().map(|row| row.file_size).collect()
+        };
+        assert_eq!(order(tab), vec![5, 30, 50, 40]);
+        for _ in 0..3 {
+            tab.cycle_sort();
+        }
+        assert_eq!(order(tab), vec![50, 40, 30, 5]);
+        assert_eq!(tab.visible_rows()[2].folder(), "Music\\A");
+    }
+
+    #[test]
+    fn summaries_stream_into_tabs_by_token_and_sort() {
+        let now = Instant::now();
+        let mut searches = SearchTabs::default();
+        searches.start(10, "boards", "global", false, now + Duration::from_secs(5));
+        searches.start(11, "flim", "user bob", true, now + Duration::from_secs(5));
+
+        assert_eq!(
+            searches.add_summary(&summary(10, "carol", 0, &[("a.mp3", 10), ("b.flac", 30)])),
+            Some(10)
+        );
+        assert_eq!(
+            searches.add_summary(&summary(10, "carol", 0, &[("a.mp3", 10)])),
+            Some(10)
+        );
+        assert_eq!(
+            searches.add_summary(&summary(999, "bob", 2, &[("flim.flac", 20)])),
+            Some(11)
+        );
+        assert_eq!(searches.tabs[0].rows.len(), 2);
+        assert_eq!(searches.tabs[1].rows.len(), 1);
+
+        searches.cycle(1);
+        let tab = searches.active_tab_mut().unwrap();
+        assert_eq!(tab.token, 10);
+        tab.cycle_sort();
+        tab.cycle_sort();
+        tab.cycle_sort();
+        assert_eq!(tab.sort, SortColumn::Size);
+        let sizes: Vec<u64> = tab.visible_rows().iter().map(|row| row.file_size).collect();
+        assert_eq!(sizes, vec![30, 10]);
+        tab.reverse_sort();
+        assert_eq!(tab.selected_row().map(|row| row.file_size), Some(10));
+        tab.set_filter("ext:flac").unwrap();
+        assert_eq!(tab.visible_rows().len(), 1);
+
+        assert!(searches.expire(now).is_empty());
+        assert_eq!(searches.expire(now + Duration::from_secs(6)), vec![10, 11]);
+        assert!(!searches.any_streaming());
+        assert_eq!(
+            searches.add_summary(&summary(999, "bob", 2, &[("late.flac", 1)])),
+            None
+        );
+
+        assert_eq!(searches.close_active().map(|tab| tab.token), Some(10));
+        assert_eq!(searches.active_tab().map(|tab| tab.token), Some(11));
+    }
+}
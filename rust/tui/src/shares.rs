@@ -0,0 +1,205 @@
+use std::collections::HashSet;
+
+use soul_core::{SearchCandidate, ShareTree, SharedDir, SharedFile};
+
+#[derive(Debug, Clone, Copy)]
+pub enum ShareNode<'a> {
+    Dir {
+        dir: &'a SharedDir,
+        depth: usize,
+        expanded: bool,
+    },
+    File {
+        file: &'a SharedFile,
+        depth: usize,
+    },
+}
+
+/// The share tree shown on the Shares tab. Folders are listed collapsed and
+/// open one level at a time; `selected` indexes the visible nodes.
+#[derive(Debug, Clone, Default)]
+pub struct SharesState {
+    pub tree: Option<ShareTree>,
+    pub expanded: HashSet<String>,
+    pub selected: usize,
+}
+
+impl SharesState {
+    /// Shows a fetched tree. Refreshing the same user keeps open folders.
+    pub fn set_tree(&mut self, tree: ShareTree) {
+        let same_user = self
+            .tree
+            .as_ref()
+            .is_some_and(|current| current.username == tree.username);
+        if !same_user {
+            self.expanded.clear();
+            self.selected = 0;
+        }
+        self.tree = Some(tree);
+        self.clamp_selection();
+    }
+
+    pub fn username(&self) -> Option<&str> {
+        self.tree.as_ref().map(|tree| tree.username.as_str())
+    }
+
+    pub fn visible_nodes(&self) -> Vec<ShareNode<'_>> {
+        let mut nodes = Vec::new();
+        if let Some(tree) = &self.tree {
+            self.push_nodes(&tree.root, 0, &mut nodes);
+        }
+        nodes
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        let len = self.visible_nodes().len();
+        self.selected = self
+            .selected
+            .saturating_add_signed(delta)
+            .min(len.saturating_sub(1));
+    }
+
+    /// Opens the selected folder, or closes it when it is already open.
+    pub fn toggle_selected(&mut self) {
+        let path = match self.visible_nodes().get(self.selected) {
+            Some(ShareNode::Dir { dir, .. }) => dir.path.clone(),
+            _ => return,
+        };
+        if !self.expanded.remove(&path) {
+            self.expanded.insert(path);
+        }
+        self.clamp_selection();
+    }
+
+    /// Closes the selected open folder, otherwise jumps to the parent folder.
+    pub fn collapse_selected(&mut self) {
+        let nodes = self.visible_nodes();
+        let Some(node) = nodes.get(self.selected) else {
+            return;
+        };
+        let depth = match node {
+            ShareNode::Dir {
+                dir,
+                expanded: true,
+                ..
+            } => {
+                let path = dir.path.clone();
+                self.expanded.remove(&path);
+                return;
+            }
+            ShareNode::Dir { depth, .. } | ShareNode::File { depth, .. } => *depth,
+        };
+        let parent = nodes[..self.selected].iter().rposition(
+            |node| matches!(node, ShareNode::Dir { depth: parent, .. } if parent + 1 == depth),
+        );
+        if let Some(parent) = parent {
+            self.selected = parent;
+        }
+    }
+
+    /// Download candidates for the selected file, or every file below the
+    /// selected folder.
+    pub fn selected_candidates(&self) -> Vec<SearchCandidate> {
+        let Some(tree) = &self.tree else {
+            return Vec::new();
+        };
+        match self.visible_nodes().get(self.selected) {
+            Some(ShareNode::Dir { dir, .. }) => dir
+                .walk_files()
+                .into_iter()
+                .map(|file| tree.candidate(file))
+                .collect(),
+            Some(ShareNode::File { file, .. }) => vec![tree.candidate(file)],
+            None => Vec::new(),
+        }
+    }
+
+    fn push_nodes<'a>(&self, dir: &'a SharedDir, depth: usize, nodes: &mut Vec<ShareNode<'a>>) {
+        for child in &dir.dirs {
+            let expanded = self.expanded.contains(&child.path);
+            nodes.push(ShareNode::Dir {
+                dir: child,
+                depth,
+                expanded,
+            });
+            if expanded {
+                self.push_nodes(child, depth + 1, nodes);
+            }
+        }
+        nodes.extend(dir.files.iter().map(|file| ShareNode::File { file, depth }));
+    }
+
+    fn clamp_selection(&mut self) {
+        self.move_selection(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::SharedFileEntry;
+
+    fn tree(username: &str) -> ShareTree {
+        let entries: Vec<SharedFileEntry> = [
+            ("Music\\Album\\01.flac", 10),
+            ("Music\\Album\\02.flac", 20),
+            ("Music\\single.mp3", 5),
+            ("notes.txt", 1),
+        ]
+        .into_iter()
+        .map(|(path, size)| SharedFileEntry {
+            virtual_path: path.into(),
+            size,
+        })
+        .collect();
+        ShareTree::from_entries(username, &entries)
+    }
+
+    fn labels(shares: &SharesState) -> Vec<String> {
+        shares
+            .visible_nodes()
+            .iter()
+            .map(|node| match node {
+                ShareNode::Dir { dir, depth, .. } => format!("{depth}:{}/", dir.name),
+                ShareNode::File { file, depth } => format!("{depth}:{}", file.name),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn folders_expand_collapse_and_select_downloads() {
+        let mut shares = SharesState::default();
+        shares.set_tree(tree("bob"));
+        assert_eq!(labels(&shares), ["0:Music/", "0:notes.txt"]);
+
+        shares.toggle_selected();
+        shares.move_selection(1);
+        shares.toggle_selected();
+        assert_eq!(
+            labels(&shares),
+            [
+                "0:Music/",
+                "1:Album/",
+                "2:01.flac",
+                "2:02.flac",
+                "1:single.mp3",
+                "0:notes.txt"
+            ]
+        );
+        assert_eq!(shares.selected_candidates().len(), 2);
+
+        shares.move_selection(2);
+        let file = shares.selected_candidates();
+        assert_eq!(file.len(), 1);
+        assert_eq!(file[0].file_path, "Music\\Album\\02.flac");
+        shares.collapse_selected();
+        assert_eq!(shares.selected, 1);
+        shares.collapse_selected();
+        assert_eq!(labels(&shares).len(), 4);
+
+        shares.set_tree(tree("bob"));
+        assert_eq!(labels(&shares).len(), 4, "refresh keeps open folders");
+        shares.set_tree(tree("carol"));
+        assert_eq!((labels(&shares).len(), shares.selected), (2, 0));
+    }
+}
@@ -1,10 +1,12 @@
 use serde::{Deserialize, Serialize};
+use soul_core::{BuddyList, DEFAULT_AUTO_AWAY_AFTER, WishlistFilter, WishlistItem};
 
-pub const PERSISTED_STATE_VERSION: u8 = 1;
+pub const PERSISTED_STATE_VERSION: u8 = 2;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum PersistedDownloadStatus {
+    Queued,
     InProgress,
     Done,
     Failed,
@@ -22,41 +24,249 @@ pub struct PersistedDownloadEntry {
     pub ended_at: Option<i64>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PersistedWishlistEntry {
+    pub query: String,
+    #[serde(default)]
+    pub min_file_size: Option<u64>,
+    #[serde(default)]
+    pub max_file_size: Option<u64>,
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    #[serde(default)]
+    pub excluded_terms: Vec<String>,
+    #[serde(default = "default_auto_download")]
+    pub auto_download: bool,
+}
+
+fn default_auto_download() -> bool {
+    true
+}
+
+impl PersistedWishlistEntry {
+    pub fn new(query: &str) -> Self {
+        Self {
+            query: query.to_string(),
+            min_file_size: None,
+            max_file_size: None,
+            extensions: Vec::new(),
+            excluded_terms: Vec::new(),
+            auto_download: true,
+        }
+    }
+
+    pub fn to_item(&self) -> WishlistItem {
+        WishlistItem {
+            query: self.query.clone(),
+            filter: WishlistFilter {
+                min_file_size: self.min_file_size,
+                max_file_size: self.max_file_size,
+                extensions: self.extensions.clone(),
+                excluded_terms: self.excluded_terms.clone(),
+            },
+            auto_download: self.auto_download,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchTargetKind {
+    #[default]
+    Global,
+    Room,
+    User,
+    Buddies,
+}
+
+impl SearchTargetKind {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Global => Self::Room,
+            Self::Room => Self::User,
+            Self::User => Self::Buddies,
+            Self::Buddies => Self::Global,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Global => "global",
+            Self::Room => "room",
+            Self::User => "user",
+            Self::Buddies => "buddies",
+        }
+    }
+}
+
+/// Top-level workspaces, in tab-strip order.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MainTab {
+    #[default]
+    Search,
+    Transfers,
+    Rooms,
+    Chat,
+    Users,
+    Shares,
+}
+
+impl MainTab {
+    pub const ALL: [Self; 6] = [
+        Self::Search,
+        Self::Transfers,
+        Self::Rooms,
+        Self::Chat,
+        Self::Users,
+        Self::Shares,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Search => "Search",
+            Self::Transfers => "Transfers",
+            Self::Rooms => "Rooms",
+            Self::Chat => "Chat",
+            Self::Users => "Users",
+            Self::Shares => "Shares",
+        }
+    }
+
+    pub fn index(self) -> usize {
+        Self::ALL.iter().position(|tab| *tab == self).unwrap_or(0)
+    }
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self.index() + 1) % Self::ALL.len()]
+    }
+
+    pub fn previous(self) -> Self {
+        Self::ALL[(self.index() + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferFilter {
+    #[default]
+    All,
+    Active,
+    Finished,
+    Failed,
+}
+
+impl TransferFilter {
+    pub fn next(self) -> Self {
+        match self {
+            Self::All => Self::Active,
+            Self::Active => Self::Finished,
+            Self::Finished => Self::Failed,
+            Self::Failed => Self::All,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::Active => "active",
+            Self::Finished => "finished",
+            Self::Failed => "failed",
+        }
+    }
+
+    pub fn matches(self, status: &PersistedDownloadStatus) -> bool {
+        match self {
+            Self::All => true,
+            Self::Active => matches!(
+                status,
+                PersistedDownloadStatus::Queued | PersistedDownloadStatus::InProgress
+            ),
+            Self::Finished => *status == PersistedDownloadStatus::Done,
+            Self::Failed => matches!(
+                status,
+                PersistedDownloadStatus::Failed | PersistedDownloadStatus::Interrupted
+            ),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct PersistedUiState {
     pub downloads_visible: bool,
+    #[serde(default)]
+    pub users_visible: bool,
+    #[serde(default)]
+    pub active_tab: MainTab,
+    #[serde(default)]
+    pub transfers_filter: TransferFilter,
 }
 
 impl Default for PersistedUiState {
     fn default() -> Self {
         Self {
             downloads_visible: true,
+            users_visible: false,
+            active_tab: MainTab::Search,
+            transfers_filter: TransferFilter::All,
         }
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
-pub struct PersistedAppStateV1 {
+pub struct PersistedAppState {
     pub schema_version: u8,
     pub server: String,
     pub username: String,
+    /// Lives in the credential store, never in the state file.
+    #[serde(skip)]
     pub password: String,
+    /// Plaintext password from a state file written before the credential
+    /// store existed. It is kept and written back until it has been moved
+    /// into a store, so a missing store never loses it.
+    #[serde(rename = "password", default, skip_serializing_if = "String::is_empty")]
+    pub legacy_password: String,
     pub last_query: String,
+    #[serde(default)]
+    pub search_target: SearchTargetKind,
+    #[serde(default)]
+    pub search_target_name: String,
     pub output_dir: String,
     pub downloads: Vec<PersistedDownloadEntry>,
+    #[serde(default)]
+    pub wishlist: Vec<PersistedWishlistEntry>,
+    #[serde(default)]
+    pub buddies: BuddyList,
+    /// Minutes without input before the status switches to away; 0 disables it.
+    #[serde(default = "default_auto_away_minutes")]
+    pub auto_away_minutes: u64,
+    /// Rooms joined when the client last ran; rejoined after login.
+    #[serde(default)]
+    pub rooms: Vec<String>,
     pub ui: PersistedUiState,
 }
 
-impl Default for PersistedAppStateV1 {
+fn default_auto_away_minutes() -> u64 {
+    DEFAULT_AUTO_AWAY_AFTER.as_secs() / 60
+}
+
+impl Default for PersistedAppState {
     fn default() -> Self {
         Self {
             schema_version: PERSISTED_STATE_VERSION,
             server: "server.slsknet.org:2416".to_string(),
             username: String::new(),
             password: String::new(),
+            legacy_password: String::new(),
             last_query: "aphex twin".to_string(),
+            search_target: SearchTargetKind::Global,
+            search_target_name: String::new(),
             output_dir: "/tmp".to_string(),
             downloads: Vec::new(),
+            wishlist: Vec::new(),
+            buddies: BuddyList::new(),
+            auto_away_minutes: default_auto_away_minutes(),
+            rooms: Vec::new(),
             ui: PersistedUiState::default(),
         }
     }
@@ -112,4 +322,13 @@ mod tests {
         assert_eq!(rows[0].ended_at, Some(42));
         assert_eq!(rows[1].status, PersistedDownloadStatus::Done);
     }
+    #[test]
+    fn ui_state_without_tab_fields_defaults_to_search_tab() {
+        let ui: PersistedUiState =
+            serde_json::from_str(r#"{"downloads_visible":false}"#).expect("parse ui state");
+        assert_eq!(ui.active_tab, MainTab::Search);
+        assert_eq!(ui.transfers_filter, TransferFilter::All);
+        assert_eq!(MainTab::Shares.next(), MainTab::Search);
+        assert_eq!(MainTab::Search.previous(), MainTab::Shares);
+    }
 }
@@ -3,10 +3,18 @@ use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result, bail};
 use directories::ProjectDirs;
+use serde_json::{Map, Value};
+use soul_core::CredentialStore;
 
-use crate::state::PersistedAppStateV1;
+use crate::state::{PERSISTED_STATE_VERSION, PersistedAppState};
 
 const STATE_FILE_NAME: &str = "tui-state-v1.json";
+const CHAT_FILE_NAME: &str = "chats.json";
+
+type Migration = fn(&mut Map<String, Value>) -> Result<()>;
+
+/// `MIGRATIONS[n]` upgrades a state object from schema `n + 1` to `n + 2`.
+const MIGRATIONS: &[Migration] = &[migrate_v1_to_v2];
 
 pub fn state_file_path() -> Result<PathBuf> {
     if let Ok(override_path) = std::env::var("NSS_TUI_STATE_FILE") {
@@ -22,39 +30,150 @@ pub fn state_file_path() -> Result<PathBuf> {
     Ok(project_dirs.data_local_dir().join(STATE_FILE_NAME))
 }
 
-pub fn load_state() -> Result<PersistedAppStateV1> {
+/// Private chat history lives next to the state file.
+pub fn chat_file_path() -> Result<PathBuf> {
+    Ok(state_file_path()?.with_file_name(CHAT_FILE_NAME))
+}
+
+pub fn load_state() -> Result<PersistedAppState> {
     let path = state_file_path()?;
     load_state_from_path(&path)
 }
 
-pub fn save_state(state: &PersistedAppStateV1) -> Result<()> {
+pub fn save_state(state: &PersistedAppState) -> Result<()> {
     let path = state_file_path()?;
     save_state_to_path(&path, state)
 }
 
-pub fn clear_download_history(state: &mut PersistedAppStateV1) -> Result<()> {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordOrigin {
+    Missing,
+    Store,
+    /// A plaintext password from an older state file was moved to the store.
+    Migrated,
+}
+
+/// Fills in the login password from `store`, first moving a plaintext
+/// password left in the state file into it. The plaintext copy is only
+/// cleared once the store holds it, so saving afterwards drops it.
+pub fn restore_password(
+    state: &mut PersistedAppState,
+    store: &mut dyn CredentialStore,
+) -> Result<PasswordOrigin> {
+    let username = state.username.trim();
+    if username.is_empty() {
+        return Ok(PasswordOrigin::Missing);
+    }
+    if !state.legacy_password.is_empty() {
+        state.password = state.legacy_password.clone();
+        store.set(&state.server, username, &state.password)?;
+        state.legacy_password.clear();
+        return Ok(PasswordOrigin::Migrated);
+    }
+    match store.get(&state.server, username)? {
+        Some(password) => {
+            state.password = password;
+            Ok(PasswordOrigin::Store)
+        }
+        None => Ok(PasswordOrigin::Missing),
+    }
+}
+
+pub fn clear_download_history(state: &mut PersistedAppState) -> Result<()> {
     state.downloads.clear();
     save_state(state)
 }
 
 #[cfg(test)]
-fn clear_download_history_to_path(path: &Path, state: &mut PersistedAppStateV1) -> Result<()> {
+fn clear_download_history_to_path(path: &Path, state: &mut PersistedAppState) -> Result<()> {
     state.downloads.clear();
     save_state_to_path(path, state)
 }
 
-fn load_state_from_path(path: &Path) -> Result<PersistedAppStateV1> {
+/// Loads the state file, upgrading an older schema first. The file as it was
+/// before the upgrade is kept as `<name>.v<old version>.bak`, without its
+/// plaintext password: that copy stays in the upgraded file until it has been
+/// moved into the credential store.
+fn load_state_from_path(path: &Path) -> Result<PersistedAppState> {
     if !path.exists() {
-        return Ok(PersistedAppStateV1::default());
+        return Ok(PersistedAppState::default());
     }
     let raw = fs::read_to_string(path)
         .with_context(|| format!("read persisted state from {}", path.display()))?;
-    let state: PersistedAppStateV1 = serde_json::from_str(&raw)
+    let value: Value = serde_json::from_str(&raw)
         .with_context(|| format!("parse persisted state from {}", path.display()))?;
+    let original = value.clone();
+    let (value, from_version) =
+        migrate_state(value).with_context(|| format!("migrate state in {}", path.display()))?;
+    let state: PersistedAppState = serde_json::from_value(value)
+        .with_context(|| format!("parse persisted state from {}", path.display()))?;
+
+    if from_version < PERSISTED_STATE_VERSION {
+        let backup = backup_path(path, from_version);
+        fs::write(&backup, backup_payload(original)?)
+            .with_context(|| format!("write {}", backup.display()))?;
+        set_secure_permissions(&backup)?;
+        save_state_to_path(path, &state)?;
+    }
     Ok(state)
 }
 
-fn save_state_to_path(path: &Path, state: &PersistedAppStateV1) -> Result<()> {
+/// Runs every migration from the object's `schema_version` up to the current
+/// one. Returns the upgraded object and the version it started at; files
+/// from a newer client are refused rather than silently downgraded.
+fn migrate_state(mut value: Value) -> Result<(Value, u8)> {
+    let object = value
+        .as_object_mut()
+        .context("persisted state is not a JSON object")?;
+    let from_version = match object.get("schema_version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u8::try_from(version).ok())
+            .filter(|version| *version > 0)
+            .with_context(|| format!("invalid schema_version {version}"))?,
+    };
+    if from_version > PERSISTED_STATE_VERSION {
+        bail!(
+            "schema version {from_version} is newer than this client supports \
+             ({PERSISTED_STATE_VERSION})"
+        );
+    }
+
+    for version in from_version..PERSISTED_STATE_VERSION {
+        MIGRATIONS[usize::from(version - 1)](object)
+            .with_context(|| format!("upgrade schema {version} to {}", version + 1))?;
+        object.insert("schema_version".to_string(), Value::from(version + 1));
+    }
+    Ok((value, from_version))
+}
+
+/// v2 stops writing the password (it lives in the credential store) and
+/// moves the old default server port to the current one. A v1 password is
+/// left in place so the credential migration at startup can pick it up.
+fn migrate_v1_to_v2(state: &mut Map<String, Value>) -> Result<()> {
+    if let Some(server) = state.get_mut("server")
+        && server.as_str().map(str::trim) == Some("server.slsknet.org:2242")
+    {
+        *server = Value::from("server.slsknet.org:2416");
+    }
+    Ok(())
+}
+
+fn backup_payload(mut original: Value) -> Result<String> {
+    if let Some(object) = original.as_object_mut() {
+        object.remove("password");
+    }
+    serde_json::to_string_pretty(&original).context("serialize state backup")
+}
+
+fn backup_path(path: &Path, version: u8) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".v{version}.bak"));
+    path.with_file_name(name)
+}
+
+fn save_state_to_path(path: &Path, state: &PersistedAppState) -> Result<()> {
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent).with_context(|| format!("create {}", parent.display()))?;
     }
@@ -79,6 +198,7 @@ fn set_secure_permissions(path: &Path) -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use soul_core::{EncryptedFileStore, KdfParams};
     use std::time::{SystemTime, UNIX_EPOCH};
 
     fn unique_path() -> PathBuf {
@@ -93,28 +213,172 @@ mod tests {
     fn load_missing_state_uses_defaults() {
         let path = unique_path();
         let loaded = load_state_from_path(&path).expect("load default state");
-        assert_eq!(loaded, PersistedAppStateV1::default());
+        assert_eq!(loaded, PersistedAppState::default());
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn save_then_load_roundtrip() {
         let path = unique_path();
-        let mut state = PersistedAppStateV1::default();
+        let mut state = PersistedAppState::default();
         state.username = "alice".to_string();
         state.password = "secret".to_string();
         save_state_to_path(&path, &state).expect("save state");
 
+        let raw = fs::read_to_string(&path).expect("read state");
+        assert!(!raw.contains("secret"), "password must not be written");
+
         let loaded = load_state_from_path(&path).expect("load state");
         assert_eq!(loaded.username, "alice");
-        assert_eq!(loaded.password, "secret");
+        assert_eq!(loaded.password, "");
 
         let _ = fs::remove_file(path);
     }
 
+    #[test]
+    fn plaintext_password_migrates_into_the_credential_store() {
+        let path = unique_path();
+        let store_path = path.with_extension("enc.json");
+        let mut legacy = serde_json::to_value(PersistedAppState {
+            username: "alice".to_string(),
+            ..PersistedAppState::default()
+        })
+        .expect("serialize state");
+        legacy["password"] = "secret".into();
+        fs::write(&path, legacy.to_string()).expect("write legacy state");
+
+        let kdf = KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let mut store =
+            EncryptedFileStore::open_with_params(&store_path, "pass", kdf).expect("open store");
+        let state = load_state_from_path(&path).expect("load legacy state");
+        assert_eq!(state.legacy_password, "secret");
+        save_state_to_path(&path, &state).expect("save without a store");
+        let mut state = load_state_from_path(&path).expect("reload legacy state");
+        assert_eq!(
+            state.legacy_password, "secret",
+            "kept until a store holds it"
+        );
+
+        let origin = restore_password(&mut state, &mut store).expect("migrate");
+        assert_eq!(
+            (origin, state.password.as_str()),
+            (PasswordOrigin::Migrated, "secret")
+        );
+        assert_eq!(state.legacy_password, "");
+        save_state_to_path(&path, &state).expect("save state");
+        assert!(
+            !fs::read_to_string(&path)
+                .expect("read state")
+                .contains("secret")
+        );
+
+        let mut store = EncryptedFileStore::open(&store_path, "pass").expect("reopen store");
+        let mut state = load_state_from_path(&path).expect("load state");
+        let origin = restore_password(&mut state, &mut store).expect("restore");
+        assert_eq!(
+            (origin, state.password.as_str()),
+            (PasswordOrigin::Store, "secret")
+        );
+
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(store_path);
+    }
+
+    const V1_STATE: &str = r#"{
+        "schema_version": 1,
+        "server": "server.slsknet.org:2242",
+        "username": "alice",
+        "password": "secret",
+        "last_query": "aphex twin",
+        "output_dir": "/tmp",
+        "downloads": [],
+        "ui": { "downloads_visible": true }
+    }"#;
+
+    #[test]
+    fn every_schema_version_has_a_migration() {
+        assert_eq!(MIGRATIONS.len() + 1, usize::from(PERSISTED_STATE_VERSION));
+    }
+
+    #[test]
+    fn v1_to_v2_moves_the_legacy_server_port() {
+        let mut state: Map<String, Value> = serde_json::from_str(V1_STATE).expect("parse v1");
+        migrate_v1_to_v2(&mut state).expect("migrate");
+        assert_eq!(state["server"], "server.slsknet.org:2416");
+        assert_eq!(state["password"], "secret", "left for the credential store");
+
+        state["server"] = "example.org:2242".into();
+        migrate_v1_to_v2(&mut state).expect("migrate custom server");
+        assert_eq!(state["server"], "example.org:2242");
+    }
+
+    #[test]
+    fn loading_a_v1_file_upgrades_it_and_keeps_a_backup() {
+        let path = unique_path();
+        fs::write(&path, V1_STATE).expect("write v1 state");
+
+        let loaded = load_state_from_path(&path).expect("load v1 state");
+        assert_eq!(loaded.schema_version, PERSISTED_STATE_VERSION);
+        assert_eq!(loaded.server, "server.slsknet.org:2416");
+        assert_eq!(
+            (loaded.username.as_str(), loaded.legacy_password.as_str()),
+            ("alice", "secret")
+        );
+
+        let backup = backup_path(&path, 1);
+        let backup_raw = fs::read_to_string(&backup).expect("read backup");
+        assert!(
+            !backup_raw.contains("secret"),
+            "password must not be backed up"
+        );
+        let mut expected: Value = serde_json::from_str(V1_STATE).expect("parse v1");
+        expected
+            .as_object_mut()
+            .expect("v1 object")
+            .remove("password");
+        let backed_up: Value = serde_json::from_str(&backup_raw).expect("parse backup");
+        assert_eq!(backed_up, expected);
+        let upgraded: Value =
+            serde_json::from_str(&fs::read_to_string(&path).expect("read upgraded state"))
+                .expect("parse upgraded state");
+        assert_eq!(upgraded["schema_version"], 2);
+        assert_eq!(
+            upgraded["password"], "secret",
+            "kept until a store holds it"
+        );
+
+        let reloaded = load_state_from_path(&path).expect("load upgraded state");
+        assert_eq!(reloaded.legacy_password, "secret");
+        assert_eq!(reloaded.server, loaded.server);
+
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(backup);
+    }
+
+    #[test]
+    fn unknown_fields_load_and_newer_schemas_are_refused() {
+        let mut state = serde_json::to_value(PersistedAppState::default()).expect("serialize");
+        state["queue_v9"] = serde_json::json!([{ "id": 1 }]);
+        state["ui"]["theme"] = "dark".into();
+        let (migrated, from_version) = migrate_state(state.clone()).expect("migrate");
+        assert_eq!(from_version, PERSISTED_STATE_VERSION);
+        let loaded: PersistedAppState = serde_json::from_value(migrated).expect("parse");
+        assert_eq!(loaded, PersistedAppState::default());
+
+        state["schema_version"] = (PERSISTED_STATE_VERSION + 1).into();
+        let err = migrate_state(state).expect_err("newer schema");
+        assert!(err.to_string().contains("newer than this client"), "{err}");
+        assert!(migrate_state(serde_json::json!({ "schema_version": 0 })).is_err());
+    }
+
     #[test]
     fn clear_history_empties_downloads() {
         let path = unique_path();
-        let mut state = PersistedAppStateV1::default();
+        let mut state = PersistedAppState::default();
         state.downloads.push(crate::state::PersistedDownloadEntry {
             id: "id-1".to_string(),
             username: "user".to_string(),
@@ -0,0 +1,278 @@
+use protocol::{RoomListPayload, ServerMessage, decode_server_message};
+use soul_core::{
+    CandidateDownloadRequest, SearchCandidate, SearchMode, SearchScope, SearchSelectDownloadError,
+    SearchSelectDownloadResult, SessionClient, SessionReader, ShareTree, TransferProgress,
+    WishlistItem, with_transfer_progress,
+};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+
+/// Messages from background session work back to the UI loop. A task owns the
+/// session while it runs and hands it back with `SessionReturned` after its
+/// final result event; a download hands it back as soon as the peer side is
+/// set up and reports `DownloadFinished` later. While the session idles, the
+/// reader task forwards what the server pushes as `ServerPush`.
+pub enum TaskEvent {
+    ServerPush(ServerMessage),
+    /// The reader stopped on a failed read; pushes stop until the next login.
+    ReaderFailed(anyhow::Error),
+    SearchSent {
+        token: u32,
+        query: String,
+        result: anyhow::Result<()>,
+    },
+    SearchFinished {
+        token: u32,
+        query: String,
+        result: anyhow::Result<Vec<SearchCandidate>>,
+    },
+    DownloadProgress {
+        download_id: String,
+        progress: TransferProgress,
+    },
+    DownloadFinished {
+        download_id: String,
+        result: Result<SearchSelectDownloadResult, SearchSelectDownloadError>,
+    },
+    FolderListed {
+        username: String,
+        folder: String,
+        result: anyhow::Result<Vec<SearchCandidate>>,
+    },
+    BrowseFinished {
+        username: String,
+        result: anyhow::Result<ShareTree>,
+    },
+    RoomListFinished(anyhow::Result<RoomListPayload>),
+    WishlistFinished {
+        item: WishlistItem,
+        result: anyhow::Result<Vec<SearchCandidate>>,
+    },
+    SessionReturned(SessionClient),
+}
+
+/// Reads server pushes while the session idles, so the UI loop never waits
+/// on the socket itself.
+pub struct ReaderTask {
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<SessionReader>,
+}
+
+impl ReaderTask {
+    /// Stops forwarding and returns the read half. Reads are cancel-safe, so
+    /// a frame that was partly read stays buffered in the reader.
+    async fn stop(self) -> Option<SessionReader> {
+        let _ = self.stop.send(());
+        self.task.await.ok()
+    }
+}
+
+pub fn spawn_reader(events: UnboundedSender<TaskEvent>, mut reader: SessionReader) -> ReaderTask {
+    let (stop, mut stopped) = oneshot::channel();
+    let task = tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                biased;
+                _ = &mut stopped => break,
+                frame = reader.read_frame() => match frame {
+                    Ok(frame) => {
+                        let Ok(message) = decode_server_message(frame.code, &frame.payload) else {
+                            continue;
+                        };
+                        if events.send(TaskEvent::ServerPush(message)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        let _ = events.send(TaskEvent::ReaderFailed(err));
+                        break;
+                    }
+                },
+            }
+        }
+        reader
+    });
+    ReaderTask { stop, task }
+}
+
+/// A session lent to a task together with the reader task holding its read
+/// half; the task takes the read half back before it waits for replies.
+pub struct TaskSession {
+    client: SessionClient,
+    reader: Option<ReaderTask>,
+}
+
+impl TaskSession {
+    pub fn new(client: SessionClient, reader: Option<ReaderTask>) -> Self {
+        Self { client, reader }
+    }
+
+    async fn into_client(self) -> SessionClient {
+        let mut client = self.client;
+        if let Some(reader) = self.reader
+            && let Some(reader) = reader.stop().await
+        {
+            client.restore_reader(reader);
+        }
+        client
+    }
+}
+
+pub fn spawn_search_request(
+    events: UnboundedSender<TaskEvent>,
+    session: TaskSession,
+    token: u32,
+    scope: SearchScope,
+    query: String,
+) {
+    tokio::spawn(async move {
+        let mut client = session.into_client().await;
+        let result = client.search_scoped(token, &scope, &query).await;
+        let _ = events.send(TaskEvent::SearchSent {
+            token,
+            query,
+            result,
+        });
+        let _ = events.send(TaskEvent::SessionReturned(client));
+    });
+}
+
+pub fn spawn_search(
+    events: UnboundedSender<TaskEvent>,
+    session: TaskSession,
+    token: u32,
+    scope: SearchScope,
+    query: String,
+    mode: SearchMode,
+) {
+    tokio::spawn(async move {
+        let mut client = session.into_client().await;
+        let result = client
+            .search_collect_scoped_candidates(
+                token,
+                &scope,
+                &query,
+                Duration::from_secs(6),
+                32,
+                mode,
+                None,
+                "P",
+            )
+            .await;
+        let _ = events.send(TaskEvent::SearchFinished {
+            token,
+            query,
+            result,
+        });
+        let _ = events.send(TaskEvent::SessionReturned(client));
+    });
+}
+
+pub fn spawn_download(
+    events: UnboundedSender<TaskEvent>,
+    session: TaskSession,
+    download_id: String,
+    candidate: SearchCandidate,
+    request: CandidateDownloadRequest,
+) {
+    tokio::spawn(async move {
+        let mut client = session.into_client().await;
+        let prepared = client
+            .prepare_candidate_download(&candidate, &request)
+            .await;
+        // The transfer only talks to the peer, so the UI gets the session
+        // back before the file is read.
+        let _ = events.send(TaskEvent::SessionReturned(client));
+        let result = match prepared {
+            Ok(download) => {
+                let (progress_tx, mut progress_rx) = mpsc::unbounded_channel();
+                let forward_events = events.clone();
+                let forward_id = download_id.clone();
+                let forwarder = tokio::spawn(async move {
+                    while let Some(progress) = progress_rx.recv().await {
+                        let _ = forward_events.send(TaskEvent::DownloadProgress {
+                            download_id: forward_id.clone(),
+                            progress,
+                        });
+                    }
+                });
+                let result = with_transfer_progress(progress_tx, download.run()).await;
+                // The progress sender is dropped with the scope, so the
+                // forwarder drains what is left and stops before the result
+                // is sent.
+                let _ = forwarder.await;
+                result
+            }
+            Err(err) => Err(err),
+        };
+        let _ = events.send(TaskEvent::DownloadFinished {
+            download_id,
+            result,
+        });
+    });
+}
+
+pub fn spawn_folder_listing(
+    events: UnboundedSender<TaskEvent>,
+    session: TaskSession,
+    username: String,
+    folder: String,
+    token: u32,
+) {
+    tokio::spawn(async move {
+        let mut client = session.into_client().await;
+        let result = client
+            .get_folder_contents(&username, &folder, token, Duration::from_secs(10))
+            .await;
+        let _ = events.send(TaskEvent::FolderListed {
+            username,
+            folder,
+            result,
+        });
+        let _ = events.send(TaskEvent::SessionReturned(client));
+    });
+}
+
+pub fn spawn_browse(
+    events: UnboundedSender<TaskEvent>,
+    session: TaskSession,
+    username: String,
+    token: u32,
+    refresh: bool,
+) {
+    tokio::spawn(async move {
+        let mut client = session.into_client().await;
+        let result = client
+            .browse_user(&username, token, Duration::from_secs(20), refresh)
+            .await;
+        let _ = events.send(TaskEvent::BrowseFinished { username, result });
+        let _ = events.send(TaskEvent::SessionReturned(client));
+    });
+}
+
+pub fn spawn_room_list(events: UnboundedSender<TaskEvent>, session: TaskSession) {
+    tokio::spawn(async move {
+        let mut client = session.into_client().await;
+        let result = client.list_rooms(Duration::from_secs(8)).await;
+        let _ = events.send(TaskEvent::RoomListFinished(result));
+        let _ = events.send(TaskEvent::SessionReturned(client));
+    });
+}
+
+pub fn spawn_wishlist_search(
+    events: UnboundedSender<TaskEvent>,
+    session: TaskSession,
+    item: WishlistItem,
+    token: u32,
+) {
+    tokio::spawn(async move {
+        let mut client = session.into_client().await;
+        let result = client
+            .collect_wishlist_candidates(token, &item.query, Duration::from_secs(6), 32)
+            .await;
+        let _ = events.send(TaskEvent::WishlistFinished { item, result });
+        let _ = events.send(TaskEvent::SessionReturned(client));
+    });
+}
@@ -12,10 +12,18 @@ use ratatui::backend::CrosstermBackend;
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
-use ratatui::widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap};
+use ratatui::widgets::{
+    Block, Borders, Cell, Clear, Gauge, List, ListItem, ListState, Paragraph, Row, Table,
+    TableState, Tabs, Wrap,
+};
 
-use crate::app::{App, LoginField, PendingAction, UiPhase};
-use crate::state::PersistedDownloadStatus;
+use crate::app::{
+    App, InputMode, LoginField, PendingAction, UiPhase, format_bytes, transfer_progress_label,
+};
+use crate::searches::{SearchStatus, SortColumn};
+use crate::shares::ShareNode;
+use crate::state::{MainTab, PersistedDownloadEntry, PersistedDownloadStatus};
+use soul_core::{ChatDirection, ChatMessage, UserPresence};
 
 const COLOR_BG: Color = Color::Rgb(20, 18, 26);
 const COLOR_TEXT: Color = Color::Rgb(240, 236, 227);
@@ -36,6 +44,12 @@ pub async fn run(app: &mut App) -> Result<()> {
     let result = async {
         loop {
             app.attempt_auto_login_if_needed().await;
+            app.drain_task_events();
+            app.start_queued_download();
+            app.start_wishlist_if_due();
+            app.flush_message_acks().await;
+            app.finish_searches();
+            app.sync_presence().await;
             terminal.draw(|frame| draw(frame, app))?;
 
             if !event::poll(Duration::from_millis(120))? {
@@ -46,8 +60,17 @@ pub async fn run(app: &mut App) -> Result<()> {
                 match app.handle_key(key) {
                     PendingAction::None => {}
                     PendingAction::Login => app.login().await,
-                    PendingAction::Search => app.search().await,
-                    PendingAction::Download => app.download_selected().await,
+                    PendingAction::Search => app.search(),
+                    PendingAction::Download => app.download_selected(),
+                    PendingAction::DownloadFolder => app.download_folder(),
+                    PendingAction::ToggleBuddy => app.toggle_buddy().await,
+                    PendingAction::ListRooms => app.list_rooms(),
+                    PendingAction::JoinRoom => app.join_room().await,
+                    PendingAction::LeaveRoom => app.leave_active_room().await,
+                    PendingAction::SayRoom => app.say_in_active_room().await,
+                    PendingAction::SendPrivateMessage => app.send_private_message().await,
+                    PendingAction::BrowseUser => app.browse_user(),
+                    PendingAction::RefreshShares => app.refresh_shares(),
                     PendingAction::RunDiagnostics => app.run_diagnostics().await,
                     PendingAction::Quit => break,
                 }
@@ -84,6 +107,7 @@ fn draw_login_modal(frame: &mut ratatui::Frame<'_>, app: &App) {
     let popup = centered_rect(66, 56, frame.area());
     frame.render_widget(Clear, popup);
 
+    let passphrase_height = if app.store_locked() { 3 } else { 0 };
     let sections = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
@@ -91,6 +115,7 @@ fn draw_login_modal(frame: &mut ratatui::Frame<'_>, app: &App) {
             Constraint::Length(3),
             Constraint::Length(3),
             Constraint::Length(3),
+            Constraint::Length(passphrase_height),
             Constraint::Length(3),
             Constraint::Length(2),
             Constraint::Min(3),
@@ -140,13 +165,23 @@ fn draw_login_modal(frame: &mut ratatui::Frame<'_>, app: &App) {
         ),
         sections[3],
     );
+    if app.store_locked() {
+        frame.render_widget(
+            login_field(
+                "Store passphrase",
+                &app.passphrase_mask(),
+                app.active_login_field() == LoginField::Passphrase,
+            ),
+            sections[4],
+        );
+    }
 
     let help = Paragraph::new(
         "Tab/Shift+Tab focus | Enter login | g diagnostics | Esc clear error | q quit",
     )
     .style(Style::default().fg(COLOR_MUTED))
     .wrap(Wrap { trim: true });
-    frame.render_widget(help, sections[4]);
+    frame.render_widget(help, sections[5]);
 
     let error_line = app
         .login_error
@@ -159,7 +194,7 @@ fn draw_login_modal(frame: &mut ratatui::Frame<'_>, app: &App) {
     } else {
         Style::default().fg(COLOR_MUTED)
     };
-    frame.render_widget(Paragraph::new(error_line).style(error_style), sections[5]);
+    frame.render_widget(Paragraph::new(error_line).style(error_style), sections[6]);
 
     let log_items: Vec<ListItem> = app
         .logs
@@ -174,7 +209,7 @@ fn draw_login_modal(frame: &mut ratatui::Frame<'_>, app: &App) {
             .title("Recent Events")
             .border_style(Style::default().fg(COLOR_BORDER)),
     );
-    frame.render_widget(logs, sections[6]);
+    frame.render_widget(logs, sections[7]);
 }
 
 fn draw_main(frame: &mut ratatui::Frame<'_>, app: &App) {
@@ -182,8 +217,9 @@ fn draw_main(frame: &mut ratatui::Frame<'_>, app: &App) {
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Length(3),
-            Constraint::Min(8),
             Constraint::Length(3),
+            Constraint::Min(8),
+            Constraint::Length(4),
         ])
         .split(frame.area());
 
@@ -210,6 +246,25 @@ fn draw_main(frame: &mut ratatui::Frame<'_>, app: &App) {
             Style::default().fg(COLOR_MUTED),
         ),
         Span::raw("  "),
+        Span::styled(
+            format!("status={}", app.presence_label()),
+            Style::default().fg(COLOR_MUTED),
+        ),
+        Span::raw("  "),
+        Span::styled(
+            format!("wishlist={}", app.state.wishlist.len()),
+            Style::default().fg(COLOR_MUTED),
+        ),
+        Span::raw("  "),
+        Span::styled(
+            format!("task={}", app.busy_task().unwrap_or("idle")),
+            Style::default().fg(if app.busy_task().is_some() {
+                COLOR_ACCENT
+            } else {
+                COLOR_MUTED
+            }),
+        ),
+        Span::raw("  "),
         Span::styled(last_log, Style::default().fg(COLOR_MUTED)),
     ]))
     .block(
@@ -221,62 +276,306 @@ fn draw_main(frame: &mut ratatui::Frame<'_>, app: &App) {
     .style(Style::default().fg(COLOR_TEXT).bg(COLOR_BG));
     frame.render_widget(header, root[0]);
 
-    if app.downloads_visible() {
-        let body = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([Constraint::Percentage(68), Constraint::Percentage(32)])
-            .split(root[1]);
-        frame.render_widget(results_widget(app), body[0]);
-        frame.render_widget(downloads_widget(app), body[1]);
-    } else {
-        frame.render_widget(results_widget(app), root[1]);
+    let tab_titles: Vec<Line> = MainTab::ALL
+        .iter()
+        .enumerate()
+        .map(|(idx, tab)| {
+            let mut title = format!("{} {}", idx + 1, tab.label());
+            let unread = match tab {
+                MainTab::Chat => app.chats.total_unread(),
+                MainTab::Rooms => app.rooms.total_unread(),
+                _ => 0,
+            };
+            if unread > 0 {
+                title.push_str(&format!(" ({unread})"));
+            }
+            Line::from(title)
+        })
+        .collect();
+    let tabs = Tabs::new(tab_titles)
+        .select(app.active_tab().index())
+        .style(Style::default().fg(COLOR_MUTED))
+        .highlight_style(
+            Style::default()
+                .fg(COLOR_ACCENT_STRONG)
+                .add_modifier(Modifier::BOLD),
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(COLOR_BORDER)),
+        );
+    frame.render_widget(tabs, root[1]);
+
+    match app.active_tab() {
+        MainTab::Search => draw_search_tab(frame, app, root[2]),
+        MainTab::Transfers => draw_downloads(frame, app, root[2], transfers_widget(app)),
+        MainTab::Rooms => draw_rooms_tab(frame, app, root[2]),
+        MainTab::Chat => draw_chat_tab(frame, app, root[2]),
+        MainTab::Users => frame.render_widget(users_widget(app, true), root[2]),
+        MainTab::Shares => draw_shares_tab(frame, app, root[2]),
     }
 
-    let query_hint = "keys: /=edit query Enter=search d=download t=toggle downloads c=clear history l=login g=diagnostics q=quit";
+    let status_line = if app.input_mode == InputMode::EditingBuddy {
+        format!("Buddy: {}_", app.buddy_buffer)
+    } else if app.input_mode == InputMode::EditingRoomName {
+        format!("Join room: {}_", app.room_buffer)
+    } else if app.input_mode == InputMode::EditingFilter {
+        format!("Filter: {}_", app.filter_buffer)
+    } else if app.input_mode == InputMode::EditingBrowseUser {
+        format!("Browse user: {}_", app.browse_buffer)
+    } else if app.input_mode == InputMode::EditingChatUser {
+        format!("Message user: {}_", app.chat_buffer)
+    } else {
+        match app.active_tab() {
+            MainTab::Transfers => format!(
+                "Filter: {}  Shown: {}/{}",
+                app.state.ui.transfers_filter.label(),
+                app.visible_downloads().len(),
+                app.state.downloads.len()
+            ),
+            MainTab::Chat => format!(
+                "Conversations: {}  Unread: {}",
+                app.chats.threads().len(),
+                app.chats.total_unread()
+            ),
+            MainTab::Users => format!("Buddies: {}", app.state.buddies.len()),
+            MainTab::Shares => format!(
+                "Browsing: {}  Queued downloads: {}",
+                app.shares.username().unwrap_or("-"),
+                app.download_queue.len()
+            ),
+            MainTab::Rooms => format!(
+                "Joined: {}  Listed rooms: {}  Unread: {}",
+                app.rooms.joined.len(),
+                app.rooms.browser.len(),
+                app.rooms.total_unread()
+            ),
+            _ => format!(
+                "Query: {}  Target: {}",
+                app.query_for_display(),
+                app.target_for_display()
+            ),
+        }
+    };
     let footer = Paragraph::new(vec![
+        Line::from(Span::styled(status_line, Style::default().fg(COLOR_TEXT))),
         Line::from(Span::styled(
-            format!("Query: {}", app.query_for_display()),
-            Style::default().fg(COLOR_TEXT),
+            tab_key_hint(app.active_tab()),
+            Style::default().fg(COLOR_MUTED),
         )),
-        Line::from(Span::styled(query_hint, Style::default().fg(COLOR_MUTED))),
     ])
     .block(
         Block::default()
             .borders(Borders::ALL)
-            .title("Search")
+            .title(app.active_tab().label())
             .border_style(Style::default().fg(COLOR_ACCENT)),
     )
     .style(Style::default().bg(COLOR_BG));
-    frame.render_widget(footer, root[2]);
+    frame.render_widget(footer, root[3]);
+}
+
+fn tab_key_hint(tab: MainTab) -> &'static str {
+    match tab {
+        MainTab::Search => {
+            "keys: 1-6/[ ]=tabs /=edit query Tab=target e=edit target Enter=search Left/Right=search tabs f=filter s=sort r=reverse x=close search d=download D=download folder m=message user v=browse shares w=wishlist b=buddy u=users a=away t=toggle downloads c=clear history l=login g=diagnostics q=quit"
+        }
+        MainTab::Transfers => {
+            "keys: 1-6/[ ]=tabs Up/Down=select f=filter x=remove entry c=clear history a=away q=quit"
+        }
+        MainTab::Chat => {
+            "keys: 1-6/[ ]=tabs Left/Right=conversations Up/Down=select Enter=open/write i=write n=new conversation PgUp/PgDn=scroll x=close Esc=cancel q=quit"
+        }
+        MainTab::Users => {
+            "keys: 1-6/[ ]=tabs Up/Down=select b=add/remove buddy x=remove selected m=message v=browse shares a=away q=quit"
+        }
+        MainTab::Rooms => {
+            "keys: 1-6/[ ]=tabs Left/Right=room tabs Up/Down=select Enter=join/write i=write r=refresh list j=join by name PgUp/PgDn=scroll x=leave Esc=cancel q=quit"
+        }
+        MainTab::Shares => {
+            "keys: 1-6/[ ]=tabs n=browse user Up/Down=select Enter/Right=open folder Left=close/parent d=download file or folder r=refresh PgUp/PgDn=scroll Esc=cancel q=quit"
+        }
+    }
+}
+
+fn draw_search_tab(frame: &mut ratatui::Frame<'_>, app: &App, area: Rect) {
+    let users_visible = app.state.ui.users_visible;
+    if !app.downloads_visible() && !users_visible {
+        search_results(frame, app, area);
+        return;
+    }
+    let body = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(68), Constraint::Percentage(32)])
+        .split(area);
+    search_results(frame, app, body[0]);
+    match (app.downloads_visible(), users_visible) {
+        (true, true) => {
+            let side = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+                .split(body[1]);
+            draw_downloads(frame, app, side[0], downloads_widget(app));
+            frame.render_widget(users_widget(app, false), side[1]);
+        }
+        (true, false) => draw_downloads(frame, app, body[1], downloads_widget(app)),
+        _ => frame.render_widget(users_widget(app, false), body[1]),
+    }
+}
+
+/// Room tab strip over either the room browser or one joined room with its
+/// ticker line, history, input box and member sidebar.
+fn draw_rooms_tab(frame: &mut ratatui::Frame<'_>, app: &App, area: Rect) {
+    let split = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(5)])
+        .split(area);
+
+    let mut titles = vec![Line::from("Browser")];
+    titles.extend(app.rooms.joined.iter().map(|room| {
+        if room.unread > 0 {
+            Line::from(format!("{} ({})", room.name, room.unread))
+        } else {
+            Line::from(room.name.clone())
+        }
+    }));
+    let strip = Tabs::new(titles)
+        .select(app.rooms.active.map_or(0, |idx| idx + 1))
+        .style(Style::default().fg(COLOR_MUTED))
+        .highlight_style(
+            Style::default()
+                .fg(COLOR_ACCENT_STRONG)
+                .add_modifier(Modifier::BOLD),
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Rooms")
+                .border_style(Style::default().fg(COLOR_BORDER)),
+        );
+    frame.render_widget(strip, split[0]);
+
+    let Some(room) = app.rooms.active_room() else {
+        frame.render_widget(room_browser_widget(app), split[1]);
+        return;
+    };
+
+    let body = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Min(20), Constraint::Length(26)])
+        .split(split[1]);
+    let main = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1),
+            Constraint::Min(3),
+            Constraint::Length(3),
+        ])
+        .split(body[0]);
+
+    let ticker = if room.tickers.is_empty() {
+        Line::from(Span::styled(
+            "No tickers.",
+            Style::default().fg(COLOR_MUTED),
+        ))
+    } else {
+        let text = room
+            .tickers
+            .iter()
+            .map(|(username, ticker)| format!("{username}: {ticker}"))
+            .collect::<Vec<_>>()
+            .join(" | ");
+        Line::from(Span::styled(text, Style::default().fg(COLOR_ACCENT)))
+    };
+    frame.render_widget(Paragraph::new(ticker), main[0]);
+
+    let height = main[1].height.saturating_sub(2) as usize;
+    let end = room.history.len().saturating_sub(room.scroll);
+    let start = end.saturating_sub(height);
+    let lines: Vec<ListItem> = room.history[start..end]
+        .iter()
+        .map(|entry| ListItem::new(entry.to_text()))
+        .collect();
+    let title = if room.scroll > 0 {
+        format!("{} (scrolled {})", room.name, room.scroll)
+    } else {
+        room.name.clone()
+    };
+    frame.render_widget(
+        List::new(lines)
+            .style(Style::default().fg(COLOR_TEXT))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(title)
+                    .border_style(Style::default().fg(COLOR_BORDER)),
+            ),
+        main[1],
+    );
+
+    let input = if app.input_mode == InputMode::EditingRoomMessage {
+        Paragraph::new(format!("> {}_", app.room_buffer)).style(Style::default().fg(COLOR_TEXT))
+    } else {
+        Paragraph::new("Press Enter or i to write.").style(Style::default().fg(COLOR_MUTED))
+    };
+    frame.render_widget(
+        input.block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Message")
+                .border_style(Style::default().fg(COLOR_ACCENT)),
+        ),
+        main[2],
+    );
+
+    let members: Vec<ListItem> = room
+        .members
+        .iter()
+        .map(|member| {
+            if room.operators.contains(member) {
+                ListItem::new(format!("@{member}")).style(Style::default().fg(COLOR_ACCENT))
+            } else {
+                ListItem::new(member.clone())
+            }
+        })
+        .collect();
+    frame.render_widget(
+        List::new(members)
+            .style(Style::default().fg(COLOR_TEXT))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!("Members ({})", room.members.len()))
+                    .border_style(Style::default().fg(COLOR_BORDER)),
+            ),
+        body[1],
+    );
 }
 
-fn results_widget(app: &App) -> List<'static> {
-    let items: Vec<ListItem> = if app.results.is_empty() {
-        vec![ListItem::new("No search results yet.")]
+fn room_browser_widget(app: &App) -> List<'static> {
+    let items: Vec<ListItem> = if app.rooms.browser.is_empty() {
+        vec![ListItem::new(
+            "Press r to load the room list, or j to join by name.",
+        )]
     } else {
-        app.results
+        app.rooms
+            .browser
             .iter()
             .enumerate()
-            .map(|(idx, row)| {
-                let marker = if idx == app.selected_result { ">" } else { " " };
-                let style = if idx == app.selected_result {
+            .map(|(idx, (name, users))| {
+                let selected = idx == app.rooms.browser_selected;
+                let style = if selected {
                     Style::default()
                         .fg(COLOR_ACCENT)
                         .add_modifier(Modifier::BOLD)
                 } else {
                     Style::default().fg(COLOR_TEXT)
                 };
-                let text = format!(
-                    "{marker} [{}] {} | {} | {} bytes",
-                    match row.source {
-                        soul_core::SearchResultSource::ServerSummary => "summary",
-                        soul_core::SearchResultSource::DistributedPeer => "distributed",
-                    },
-                    row.username,
-                    row.file_path,
-                    row.file_size
-                );
-                ListItem::new(Line::from(Span::styled(text, style)))
+                let marker = if selected { "> " } else { "  " };
+                ListItem::new(Line::from(Span::styled(
+                    format!("{marker}{name} ({users})"),
+                    style,
+                )))
             })
             .collect()
     };
@@ -284,11 +583,328 @@ fn results_widget(app: &App) -> List<'static> {
     List::new(items).block(
         Block::default()
             .borders(Borders::ALL)
-            .title("Results")
-            .border_style(Style::default().fg(COLOR_BORDER)),
+            .title(format!("Room Browser ({})", app.rooms.browser.len()))
+            .border_style(Style::default().fg(COLOR_ACCENT)),
     )
 }
 
+fn placeholder_widget(title: &'static str, text: &'static str) -> Paragraph<'static> {
+    Paragraph::new(text)
+        .style(Style::default().fg(COLOR_MUTED))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title)
+                .border_style(Style::default().fg(COLOR_BORDER)),
+        )
+}
+
+/// Search tab strip over the filter bar and the results table of the active
+/// search.
+fn search_results(frame: &mut ratatui::Frame<'_>, app: &App, area: Rect) {
+    let split = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Length(3),
+            Constraint::Min(3),
+        ])
+        .split(area);
+
+    let titles: Vec<Line> = app
+        .searches
+        .tabs
+        .iter()
+        .map(|tab| {
+            let marker = match tab.status {
+                SearchStatus::Streaming => " …",
+                SearchStatus::Fallback => " ~",
+                SearchStatus::Done => "",
+            };
+            Line::from(format!("{} ({}){marker}", tab.query, tab.rows.len()))
+        })
+        .collect();
+    let strip = Tabs::new(titles)
+        .select(app.searches.active)
+        .style(Style::default().fg(COLOR_MUTED))
+        .highlight_style(
+            Style::default()
+                .fg(COLOR_ACCENT_STRONG)
+                .add_modifier(Modifier::BOLD),
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Searches")
+                .border_style(Style::default().fg(COLOR_BORDER)),
+        );
+    frame.render_widget(strip, split[0]);
+
+    let tab = app.searches.active_tab();
+    let editing = app.input_mode == InputMode::EditingFilter;
+    let filter_text = if editing {
+        format!("{}_", app.filter_buffer)
+    } else {
+        tab.map(|tab| tab.filter_text.clone()).unwrap_or_default()
+    };
+    let filter_bar = Paragraph::new(filter_text)
+        .style(Style::default().fg(COLOR_TEXT))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Filter (word -word ext: size: br: free)")
+                .border_style(Style::default().fg(if editing {
+                    COLOR_ACCENT
+                } else {
+                    COLOR_BORDER
+                })),
+        );
+    frame.render_widget(filter_bar, split[1]);
+
+    let Some(tab) = tab else {
+        frame.render_widget(
+            placeholder_widget("Results", "No search results yet."),
+            split[2],
+        );
+        return;
+    };
+    let rows = tab.visible_rows();
+    let arrow = if tab.descending { " ▼" } else { " ▲" };
+    let header_cells = [
+        (SortColumn::User, "User"),
+        (SortColumn::Path, "Path"),
+        (SortColumn::Size, "Size"),
+        (SortColumn::Bitrate, "Bitrate"),
+        (SortColumn::Speed, "Speed"),
+    ]
+    .into_iter()
+    .map(|(column, label)| {
+        if column == tab.sort {
+            Cell::from(format!("{label}{arrow}"))
+        } else {
+            Cell::from(label)
+        }
+    })
+    .chain([Cell::from("Slots")]);
+    let header = Row::new(header_cells).style(
+        Style::default()
+            .fg(COLOR_ACCENT)
+            .add_modifier(Modifier::BOLD),
+    );
+    // Each user/folder group gets a header row; file rows show only the
+    // file name under it.
+    let mut body: Vec<Row> = Vec::with_capacity(rows.len());
+    let mut selected = None;
+    for (idx, row) in rows.iter().enumerate() {
+        let starts_group = idx == 0 || !rows[idx - 1].same_group(row);
+        if starts_group {
+            let group: Vec<_> = rows[idx..]
+                .iter()
+                .take_while(|other| other.same_group(row))
+                .collect();
+            let size: u64 = group.iter().map(|other| other.file_size).sum();
+            let folder = if row.folder().is_empty() {
+                "(share root)"
+            } else {
+                row.folder()
+            };
+            body.push(
+                Row::new([
+                    Cell::from(row.username.clone()),
+                    Cell::from(format!(
+                        "{folder} ({} files, {})",
+                        group.len(),
+                        format_bytes(size)
+                    )),
+                ])
+                .style(Style::default().fg(COLOR_MUTED)),
+            );
+        }
+        if idx == tab.selected {
+            selected = Some(body.len());
+        }
+        let name = row.file_path[row.folder().len()..].trim_start_matches(['\\', '/']);
+        body.push(Row::new([
+            Cell::from(""),
+            Cell::from(format!("  {name}")),
+            Cell::from(format_bytes(row.file_size)),
+            Cell::from(
+                row.bitrate
+                    .map(|br| format!("{br} kbps"))
+                    .unwrap_or_default(),
+            ),
+            Cell::from(
+                row.speed
+                    .map(|speed| format!("{}/s", format_bytes(speed.into())))
+                    .unwrap_or_default(),
+            ),
+            Cell::from(match row.free_slot {
+                Some(true) => "free",
+                Some(false) => "queued",
+                None => "",
+            }),
+        ]));
+    }
+    let title = format!(
+        "{} → {} ({}/{}, sort: {})",
+        tab.query,
+        tab.target,
+        rows.len(),
+        tab.rows.len(),
+        tab.sort.label()
+    );
+    let table = Table::new(
+        body,
+        [
+            Constraint::Length(16),
+            Constraint::Min(20),
+            Constraint::Length(10),
+            Constraint::Length(9),
+            Constraint::Length(11),
+            Constraint::Length(7),
+        ],
+    )
+    .header(header)
+    .style(Style::default().fg(COLOR_TEXT))
+    .row_highlight_style(
+        Style::default()
+            .fg(COLOR_ACCENT)
+            .add_modifier(Modifier::BOLD),
+    )
+    .highlight_symbol(">")
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(Style::default().fg(COLOR_BORDER)),
+    );
+    let mut state = TableState::default().with_selected(selected);
+    frame.render_stateful_widget(table, split[2], &mut state);
+}
+
+/// The browsed share as an indented folder tree.
+fn draw_shares_tab(frame: &mut ratatui::Frame<'_>, app: &App, area: Rect) {
+    let Some(tree) = &app.shares.tree else {
+        frame.render_widget(
+            placeholder_widget(
+                "Shares",
+                "No browsed shares yet. Press n to browse a user, or v on a result or buddy.",
+            ),
+            area,
+        );
+        return;
+    };
+    let items: Vec<ListItem> = app
+        .shares
+        .visible_nodes()
+        .iter()
+        .map(|node| match node {
+            ShareNode::Dir {
+                dir,
+                depth,
+                expanded,
+            } => {
+                let marker = if *expanded { "▾" } else { "▸" };
+                ListItem::new(Line::from(vec![
+                    Span::raw("  ".repeat(*depth)),
+                    Span::styled(
+                        format!("{marker} {}/", dir.name),
+                        Style::default().fg(COLOR_ACCENT),
+                    ),
+                    Span::styled(
+                        format!(
+                            "  {} files, {}",
+                            dir.file_count(),
+                            format_bytes(dir.total_size())
+                        ),
+                        Style::default().fg(COLOR_MUTED),
+                    ),
+                ]))
+            }
+            ShareNode::File { file, depth } => ListItem::new(Line::from(vec![
+                Span::raw("  ".repeat(*depth + 1)),
+                Span::styled(file.name.clone(), Style::default().fg(COLOR_TEXT)),
+                Span::styled(
+                    format!("  {}", format_bytes(file.size)),
+                    Style::default().fg(COLOR_MUTED),
+                ),
+            ])),
+        })
+        .collect();
+    let title = format!(
+        "Shares of {} ({} files, {})",
+        tree.username,
+        tree.root.file_count(),
+        format_bytes(tree.root.total_size())
+    );
+    let selected = (!items.is_empty()).then_some(app.shares.selected);
+    let list = List::new(items)
+        .highlight_style(
+            Style::default()
+                .bg(COLOR_BORDER)
+                .add_modifier(Modifier::BOLD),
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title)
+                .border_style(Style::default().fg(COLOR_BORDER)),
+        );
+    let mut state = ListState::default().with_selected(selected);
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+/// Live gauges for running transfers above the download history.
+fn draw_downloads(frame: &mut ratatui::Frame<'_>, app: &App, area: Rect, history: List<'_>) {
+    let active: Vec<_> = app
+        .state
+        .downloads
+        .iter()
+        .filter_map(|entry| app.transfers.get(&entry.id).map(|meter| (entry, meter)))
+        .collect();
+    if active.is_empty() {
+        frame.render_widget(history, area);
+        return;
+    }
+
+    let split = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(active.len() as u16 * 2 + 2),
+            Constraint::Min(3),
+        ])
+        .split(area);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Active")
+        .border_style(Style::default().fg(COLOR_ACCENT));
+    let inner = block.inner(split[0]);
+    frame.render_widget(block, split[0]);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Constraint::Length(2); active.len()])
+        .split(inner);
+    for ((entry, meter), row) in active.iter().zip(rows.iter()) {
+        let lines = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Length(1)])
+            .split(*row);
+        frame.render_widget(
+            Paragraph::new(format!("{} | {}", entry.username, entry.file_path))
+                .style(Style::default().fg(COLOR_TEXT)),
+            lines[0],
+        );
+        frame.render_widget(
+            Gauge::default()
+                .gauge_style(Style::default().fg(COLOR_ACCENT).bg(COLOR_BORDER))
+                .ratio(meter.ratio().unwrap_or(0.0))
+                .label(transfer_progress_label(meter)),
+            lines[1],
+        );
+    }
+    frame.render_widget(history, split[1]);
+}
+
 fn downloads_widget(app: &App) -> List<'static> {
     let items: Vec<ListItem> = if app.state.downloads.is_empty() {
         vec![ListItem::new("No downloads in history.")]
@@ -298,29 +914,272 @@ fn downloads_widget(app: &App) -> List<'static> {
             .iter()
             .rev()
             .take(16)
-            .map(|entry| {
-                let (status_label, status_style) = match entry.status {
-                    PersistedDownloadStatus::Done => ("done", Style::default().fg(COLOR_SUCCESS)),
-                    PersistedDownloadStatus::Failed => ("failed", Style::default().fg(COLOR_ERROR)),
-                    PersistedDownloadStatus::InProgress => {
-                        ("in-progress", Style::default().fg(COLOR_ACCENT))
-                    }
-                    PersistedDownloadStatus::Interrupted => {
-                        ("interrupted", Style::default().fg(COLOR_MUTED))
+            .map(|entry| ListItem::new(download_line(entry, "")))
+            .collect()
+    };
+
+    List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Downloads")
+            .border_style(Style::default().fg(COLOR_ACCENT)),
+    )
+}
+
+fn transfers_widget(app: &App) -> List<'static> {
+    let visible = app.visible_downloads();
+    let items: Vec<ListItem> = if visible.is_empty() {
+        vec![ListItem::new("No downloads match this filter.")]
+    } else {
+        visible
+            .iter()
+            .enumerate()
+            .map(|(idx, entry)| {
+                let marker = if idx == app.tab_selection.transfer {
+                    "> "
+                } else {
+                    "  "
+                };
+                ListItem::new(download_line(entry, marker))
+            })
+            .collect()
+    };
+
+    List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(
+                "Downloads ({})",
+                app.state.ui.transfers_filter.label()
+            ))
+            .border_style(Style::default().fg(COLOR_ACCENT)),
+    )
+}
+
+fn download_line(entry: &PersistedDownloadEntry, marker: &'static str) -> Line<'static> {
+    let (status_label, status_style) = match entry.status {
+        PersistedDownloadStatus::Queued => ("queued", Style::default().fg(COLOR_MUTED)),
+        PersistedDownloadStatus::Done => ("done", Style::default().fg(COLOR_SUCCESS)),
+        PersistedDownloadStatus::Failed => ("failed", Style::default().fg(COLOR_ERROR)),
+        PersistedDownloadStatus::InProgress => ("in-progress", Style::default().fg(COLOR_ACCENT)),
+        PersistedDownloadStatus::Interrupted => ("interrupted", Style::default().fg(COLOR_MUTED)),
+    };
+    Line::from(vec![
+        Span::styled(marker, Style::default().fg(COLOR_ACCENT)),
+        Span::styled(
+            format!("[{status_label}] "),
+            status_style.add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(
+            format!(
+                "{} | {} | {} bytes",
+                entry.username, entry.file_path, entry.bytes
+            ),
+            Style::default().fg(COLOR_TEXT),
+        ),
+    ])
+}
+
+/// Conversation tab strip over either the conversation list or one open
+/// conversation with its message input.
+fn draw_chat_tab(frame: &mut ratatui::Frame<'_>, app: &App, area: Rect) {
+    let split = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(5)])
+        .split(area);
+
+    let mut titles = vec![Line::from("Conversations")];
+    titles.extend(app.conversations.open.iter().map(|username| {
+        let unread = app.chats.unread(username);
+        if unread > 0 {
+            Line::from(Span::styled(
+                format!("{username} ({unread})"),
+                Style::default().fg(COLOR_ACCENT_STRONG),
+            ))
+        } else {
+            Line::from(username.clone())
+        }
+    }));
+    let strip = Tabs::new(titles)
+        .select(app.conversations.active.map_or(0, |idx| idx + 1))
+        .style(Style::default().fg(COLOR_MUTED))
+        .highlight_style(
+            Style::default()
+                .fg(COLOR_ACCENT_STRONG)
+                .add_modifier(Modifier::BOLD),
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Chat")
+                .border_style(Style::default().fg(COLOR_BORDER)),
+        );
+    frame.render_widget(strip, split[0]);
+
+    let Some(username) = app.conversations.active_user() else {
+        frame.render_widget(chat_threads_widget(app), split[1]);
+        return;
+    };
+
+    let body = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(3)])
+        .split(split[1]);
+    let messages = app
+        .chats
+        .thread(username)
+        .map_or(&[][..], |thread| thread.messages.as_slice());
+    let height = body[0].height.saturating_sub(2) as usize;
+    let end = messages.len().saturating_sub(app.conversations.scroll);
+    let start = end.saturating_sub(height);
+    let own_name = app.state.username.as_str();
+    let items: Vec<ListItem> = if messages.is_empty() {
+        vec![ListItem::new("No messages yet.").style(Style::default().fg(COLOR_MUTED))]
+    } else {
+        messages[start..end]
+            .iter()
+            .map(|message| ListItem::new(private_message_line(message, username, own_name)))
+            .collect()
+    };
+    let title = if app.conversations.scroll > 0 {
+        format!("{username} (scrolled {})", app.conversations.scroll)
+    } else {
+        username.to_string()
+    };
+    frame.render_widget(
+        List::new(items).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title)
+                .border_style(Style::default().fg(COLOR_BORDER)),
+        ),
+        body[0],
+    );
+
+    let input = if app.input_mode == InputMode::EditingChatMessage {
+        Paragraph::new(format!("> {}_", app.chat_buffer)).style(Style::default().fg(COLOR_TEXT))
+    } else {
+        Paragraph::new("Press Enter or i to write.").style(Style::default().fg(COLOR_MUTED))
+    };
+    frame.render_widget(
+        input.block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Message")
+                .border_style(Style::default().fg(COLOR_ACCENT)),
+        ),
+        body[1],
+    );
+}
+
+fn private_message_line(message: &ChatMessage, peer: &str, own_name: &str) -> Line<'static> {
+    let time = format!(
+        "[{:02}:{:02}] ",
+        message.timestamp % 86_400 / 3_600,
+        message.timestamp % 3_600 / 60
+    );
+    let (sender, style) = match message.direction {
+        ChatDirection::Incoming => (peer, Style::default().fg(COLOR_ACCENT_STRONG)),
+        ChatDirection::Outgoing => (own_name, Style::default().fg(COLOR_SUCCESS)),
+    };
+    let mut spans = vec![
+        Span::styled(time, Style::default().fg(COLOR_MUTED)),
+        Span::styled(format!("<{sender}> "), style),
+        Span::styled(message.text.clone(), Style::default().fg(COLOR_TEXT)),
+    ];
+    if message.offline {
+        spans.push(Span::styled(" (offline)", Style::default().fg(COLOR_MUTED)));
+    }
+    Line::from(spans)
+}
+
+fn chat_threads_widget(app: &App) -> List<'static> {
+    let threads = app.chats.threads();
+    let items: Vec<ListItem> = if threads.is_empty() {
+        vec![ListItem::new("No conversations yet.")]
+    } else {
+        threads
+            .iter()
+            .enumerate()
+            .map(|(idx, thread)| {
+                let marker = if idx == app.tab_selection.chat {
+                    "> "
+                } else {
+                    "  "
+                };
+                let last = thread
+                    .messages
+                    .last()
+                    .map_or("", |message| message.text.as_str());
+                let style = if thread.unread > 0 {
+                    Style::default()
+                        .fg(COLOR_ACCENT_STRONG)
+                        .add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(COLOR_TEXT)
+                };
+                let unread = if thread.unread > 0 {
+                    format!(" ({} new)", thread.unread)
+                } else {
+                    String::new()
+                };
+                ListItem::new(Line::from(vec![
+                    Span::styled(marker, Style::default().fg(COLOR_ACCENT)),
+                    Span::styled(format!("{}{unread}", thread.username), style),
+                    Span::styled(format!(" | {last}"), Style::default().fg(COLOR_MUTED)),
+                ]))
+            })
+            .collect()
+    };
+
+    List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Conversations")
+            .border_style(Style::default().fg(COLOR_BORDER)),
+    )
+}
+
+/// Buddy list; `selectable` draws the Users tab cursor.
+fn users_widget(app: &App, selectable: bool) -> List<'static> {
+    let buddies = &app.state.buddies;
+    let items: Vec<ListItem> = if buddies.is_empty() {
+        vec![ListItem::new("No buddies. Press b to add one.")]
+    } else {
+        buddies
+            .buddies()
+            .iter()
+            .enumerate()
+            .map(|(idx, buddy)| {
+                let marker = match selectable {
+                    true if idx == app.tab_selection.user => "> ",
+                    true => "  ",
+                    false => "",
+                };
+                let status = buddies.status(&buddy.username);
+                let (label, style) = match status.and_then(|status| status.presence) {
+                    Some(UserPresence::Online) => ("online", Style::default().fg(COLOR_SUCCESS)),
+                    Some(UserPresence::Away) => ("away", Style::default().fg(COLOR_ACCENT)),
+                    Some(UserPresence::Offline) => ("offline", Style::default().fg(COLOR_MUTED)),
+                    None if status.and_then(|status| status.exists) == Some(false) => {
+                        ("missing", Style::default().fg(COLOR_ERROR))
                     }
+                    None => ("unknown", Style::default().fg(COLOR_MUTED)),
                 };
+                let mut text = buddy.username.clone();
+                if let Some(group) = &buddy.group {
+                    text.push_str(&format!(" ({group})"));
+                }
+                if let Some(stats) = status.and_then(|status| status.stats) {
+                    text.push_str(&format!(" | {} files", stats.files));
+                }
+                if !buddy.note.is_empty() {
+                    text.push_str(&format!(" | {}", buddy.note));
+                }
                 ListItem::new(Line::from(vec![
-                    Span::styled(
-                        format!("[{status_label}] "),
-                        status_style.add_modifier(Modifier::BOLD),
-                    ),
-                    Span::styled(
-                        format!(
-                            "{} | {} | {} bytes",
-                            entry.username, entry.file_path, entry.bytes
-                        ),
-                        Style::default().fg(COLOR_TEXT),
-                    ),
+                    Span::styled(marker, Style::default().fg(COLOR_ACCENT)),
+                    Span::styled(format!("[{label}] "), style.add_modifier(Modifier::BOLD)),
+                    Span::styled(text, Style::default().fg(COLOR_TEXT)),
                 ]))
             })
             .collect()
@@ -329,8 +1188,8 @@ fn downloads_widget(app: &App) -> List<'static> {
     List::new(items).block(
         Block::default()
             .borders(Borders::ALL)
-            .title("Downloads")
-            .border_style(Style::default().fg(COLOR_ACCENT)),
+            .title("Users")
+            .border_style(Style::default().fg(COLOR_BORDER)),
     )
 }
 
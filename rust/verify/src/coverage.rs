@@ -0,0 +1,448 @@
+use super::load_hex_lines;
+use anyhow::{Context, Result};
+use protocol::{
+    DISTRIBUTED_MESSAGE_CODES, Frame, PEER_INIT_MESSAGE_CODES, PEER_MESSAGE_CODES, ProtocolMessage,
+    SERVER_MESSAGE_CODES, decode_distributed_message, decode_peer_init_message,
+    decode_peer_message, decode_server_message, encode_distributed_message,
+    encode_peer_init_message, encode_peer_message, encode_server_message,
+    is_known_distributed_code, is_known_peer_code, is_known_peer_init_code, is_known_server_code,
+    is_opaque_server_control_code, samples,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoverageScope {
+    Server,
+    Peer,
+    Distributed,
+    PeerInit,
+}
+
+impl CoverageScope {
+    const ALL: [Self; 4] = [Self::Server, Self::Peer, Self::Distributed, Self::PeerInit];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Server => "server",
+            Self::Peer => "peer",
+            Self::Distributed => "distributed",
+            Self::PeerInit => "peer init",
+        }
+    }
+
+    fn codes(self) -> Vec<(&'static str, u32)> {
+        match self {
+            Self::Server => SERVER_MESSAGE_CODES.to_vec(),
+            Self::Peer => PEER_MESSAGE_CODES.to_vec(),
+            Self::Distributed => DISTRIBUTED_MESSAGE_CODES
+                .iter()
+                .map(|&(name, code)| (name, u32::from(code)))
+                .collect(),
+            Self::PeerInit => PEER_INIT_MESSAGE_CODES
+                .iter()
+                .map(|&(name, code)| (name, u32::from(code)))
+                .collect(),
+        }
+    }
+
+    fn is_known(self, code: u32) -> bool {
+        match self {
+            Self::Server => is_known_server_code(code),
+            Self::Peer => is_known_peer_code(code),
+            Self::Distributed => u8::try_from(code).is_ok_and(is_known_distributed_code),
+            Self::PeerInit => u8::try_from(code).is_ok_and(is_known_peer_init_code),
+        }
+    }
+
+    /// Decodes `payload` under `code` and returns the variant it produced.
+    fn decode_variant(self, code: u32, payload: &[u8]) -> Option<String> {
+        match self {
+            Self::Server => decode_server_message(code, payload)
+                .ok()
+                .map(|message| variant_name(&message)),
+            Self::Peer => decode_peer_message(code, payload)
+                .ok()
+                .map(|message| variant_name(&message)),
+            Self::Distributed => {
+                let code = u8::try_from(code).ok()?;
+                decode_distributed_message(code, payload)
+                    .ok()
+                    .map(|message| variant_name(&message))
+            }
+            Self::PeerInit => {
+                let mut body = vec![u8::try_from(code).ok()?];
+                body.extend_from_slice(payload);
+                decode_peer_init_message(&body)
+                    .ok()
+                    .map(|message| variant_name(&message))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DecodeCoverage {
+    Typed,
+    Opaque,
+    Missing,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolCoverageRow {
+    pub scope: CoverageScope,
+    pub message: String,
+    pub code: u32,
+    pub decode: DecodeCoverage,
+    pub variants: Vec<String>,
+    pub encode: bool,
+    pub roundtrip_test: bool,
+    pub capture_frames: usize,
+    pub capture_roundtrip: Option<bool>,
+    pub capture_runs: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolCoverageSummary {
+    pub total_messages: usize,
+    pub server_messages: usize,
+    pub peer_messages: usize,
+    pub distributed_messages: usize,
+    pub peer_init_messages: usize,
+    pub typed_decode: usize,
+    pub opaque_decode: usize,
+    pub missing_decode: usize,
+    pub encode: usize,
+    pub roundtrip_test: usize,
+    pub capture_evidence: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolCoverageReport {
+    pub summary: ProtocolCoverageSummary,
+    pub rows: Vec<ProtocolCoverageRow>,
+}
+
+/// A codec sample message, encoded once so it can be replayed under every
+/// code in its scope.
+#[derive(Debug, Clone)]
+struct EncodedSample {
+    scope: CoverageScope,
+    variant: String,
+    code: u32,
+    payload: Vec<u8>,
+    roundtrips: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct CaptureEvidence {
+    frames: usize,
+    roundtrip_failures: usize,
+    reencoded_same_code: bool,
+    variants: BTreeSet<String>,
+    runs: BTreeSet<String>,
+}
+
+pub fn protocol_coverage(captures_dir: impl AsRef<Path>) -> Result<ProtocolCoverageReport> {
+    let evidence = scan_capture_evidence(captures_dir.as_ref())?;
+    Ok(build_protocol_coverage(&encoded_samples(), &evidence))
+}
+
+pub fn write_protocol_coverage_report(
+    path: impl AsRef<Path>,
+    report: &ProtocolCoverageReport,
+) -> Result<()> {
+    let path = path.as_ref();
+    let json = serde_json::to_string_pretty(report).context("serialize coverage report")?;
+    fs::write(path, json).with_context(|| format!("write report: {}", path.display()))?;
+    Ok(())
+}
+
+pub fn render_protocol_coverage_markdown(report: &ProtocolCoverageReport) -> String {
+    let summary = &report.summary;
+    let mut out = String::new();
+    let _ = writeln!(out, "# Protocol Coverage");
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "Generated from `rust/protocol` and the redacted capture corpus by `soul-cli verify coverage`."
+    );
+    let _ = writeln!(out);
+    let _ = writeln!(out, "- Messages: `{}`", summary.total_messages);
+    let _ = writeln!(out, "- Server messages: `{}`", summary.server_messages);
+    let _ = writeln!(out, "- Peer messages: `{}`", summary.peer_messages);
+    let _ = writeln!(
+        out,
+        "- Distributed messages: `{}`",
+        summary.distributed_messages
+    );
+    let _ = writeln!(
+        out,
+        "- Peer init messages: `{}`",
+        summary.peer_init_messages
+    );
+    let _ = writeln!(out, "- Typed decode: `{}`", summary.typed_decode);
+    let _ = writeln!(out, "- Opaque decode: `{}`", summary.opaque_decode);
+    let _ = writeln!(out, "- Missing decode: `{}`", summary.missing_decode);
+    let _ = writeln!(out, "- Encode: `{}`", summary.encode);
+    let _ = writeln!(out, "- Round-trip tests: `{}`", summary.roundtrip_test);
+    let _ = writeln!(out, "- Capture evidence: `{}`", summary.capture_evidence);
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "| scope | code | message | decode | encode | roundtrip test | capture frames | capture roundtrip |"
+    );
+    let _ = writeln!(out, "|---|---:|---|---|---|---|---:|---|");
+    for row in &report.rows {
+        let capture_roundtrip = match row.capture_roundtrip {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "-",
+        };
+        let _ = writeln!(
+            out,
+            "| {} | {} | `{}` | {} | {} | {} | {} | {} |",
+            row.scope.label(),
+            row.code,
+            row.message,
+            match row.decode {
+                DecodeCoverage::Typed => "typed",
+                DecodeCoverage::Opaque => "opaque",
+                DecodeCoverage::Missing => "missing",
+            },
+            yes_no(row.encode),
+            yes_no(row.roundtrip_test),
+            row.capture_frames,
+            capture_roundtrip
+        );
+    }
+    out
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value { "yes" } else { "no" }
+}
+
+/// Variant name of a codec message, read from its externally tagged serde form.
+fn variant_name(message: &impl Serialize) -> String {
+    match serde_json::to_value(message) {
+        Ok(Value::Object(map)) => map.keys().next().cloned().unwrap_or_default(),
+        Ok(Value::String(name)) => name,
+        _ => String::new(),
+    }
+}
+
+fn encoded_samples() -> Vec<EncodedSample> {
+    let mut out = Vec::new();
+    for message in samples::protocol_messages() {
+        let (scope, frame, roundtrips) = match &message {
+            ProtocolMessage::Server(server) => {
+                let frame = encode_server_message(server);
+                let decoded = decode_server_message(frame.code, &frame.payload);
+                let roundtrips = decoded.is_ok_and(|decoded| &decoded == server);
+                (CoverageScope::Server, frame, roundtrips)
+            }
+            ProtocolMessage::Peer(peer) => {
+                let frame = encode_peer_message(peer);
+                let decoded = decode_peer_message(frame.code, &frame.payload);
+                let roundtrips = decoded.is_ok_and(|decoded| &decoded == peer);
+                (CoverageScope::Peer, frame, roundtrips)
+            }
+        };
+        let variant = match &message {
+            ProtocolMessage::Server(server) => variant_name(server),
+            ProtocolMessage::Peer(peer) => variant_name(peer),
+        };
+        out.push(EncodedSample {
+            scope,
+            variant,
+            code: frame.code,
+            payload: frame.payload,
+            roundtrips,
+        });
+    }
+    for message in samples::distributed_messages() {
+        let frame = encode_distributed_message(&message);
+        let decoded = decode_distributed_message(frame.code, &frame.payload);
+        out.push(EncodedSample {
+            scope: CoverageScope::Distributed,
+            variant: variant_name(&message),
+            code: u32::from(frame.code),
+            roundtrips: decoded.is_ok_and(|decoded| decoded == message),
+            payload: frame.payload,
+        });
+    }
+    for message in samples::peer_init_messages() {
+        let frame = encode_peer_init_message(&message);
+        let mut body = vec![frame.code];
+        body.extend_from_slice(&frame.payload);
+        let decoded = decode_peer_init_message(&body);
+        out.push(EncodedSample {
+            scope: CoverageScope::PeerInit,
+            variant: variant_name(&message),
+            code: u32::from(frame.code),
+            roundtrips: decoded.is_ok_and(|decoded| decoded == message),
+            payload: frame.payload,
+        });
+    }
+    out
+}
+
+fn build_protocol_coverage(
+    samples: &[EncodedSample],
+    evidence: &BTreeMap<(CoverageScope, u32), CaptureEvidence>,
+) -> ProtocolCoverageReport {
+    let mut rows = Vec::new();
+    for scope in CoverageScope::ALL {
+        let scope_samples: Vec<&EncodedSample> = samples
+            .iter()
+            .filter(|sample| sample.scope == scope)
+            .collect();
+        for (name, code) in scope.codes() {
+            let captured = evidence.get(&(scope, code));
+
+            // A variant belongs to a code when a sample's payload decodes back
+            // into that variant under it, which also credits alias codes.
+            let mut variants: BTreeSet<String> = scope_samples
+                .iter()
+                .filter(|sample| {
+                    scope.decode_variant(code, &sample.payload).as_ref() == Some(&sample.variant)
+                })
+                .map(|sample| sample.variant.clone())
+                .collect();
+            if let Some(captured) = captured {
+                variants.extend(captured.variants.iter().cloned());
+            }
+
+            let decode = if !scope.is_known(code) {
+                DecodeCoverage::Missing
+            } else if scope == CoverageScope::Server && is_opaque_server_control_code(code) {
+                DecodeCoverage::Opaque
+            } else {
+                DecodeCoverage::Typed
+            };
+            let encoded_here: Vec<&&EncodedSample> = scope_samples
+                .iter()
+                .filter(|sample| sample.code == code)
+                .collect();
+
+            rows.push(ProtocolCoverageRow {
+                scope,
+                message: name.to_string(),
+                code,
+                decode,
+                variants: variants.into_iter().collect(),
+                encode: !encoded_here.is_empty() || captured.is_some_and(|e| e.reencoded_same_code),
+                roundtrip_test: encoded_here.iter().any(|sample| sample.roundtrips),
+                capture_frames: captured.map(|e| e.frames).unwrap_or(0),
+                capture_roundtrip: captured.map(|e| e.roundtrip_failures == 0),
+                capture_runs: captured
+                    .map(|e| e.runs.iter().cloned().collect())
+                    .unwrap_or_default(),
+            });
+        }
+    }
+
+    rows.sort_by(|a, b| (a.scope, a.code, &a.message).cmp(&(b.scope, b.code, &b.message)));
+
+    let count_scope = |scope: CoverageScope| rows.iter().filter(|r| r.scope == scope).count();
+    let summary = ProtocolCoverageSummary {
+        total_messages: rows.len(),
+        server_messages: count_scope(CoverageScope::Server),
+        peer_messages: count_scope(CoverageScope::Peer),
+        distributed_messages: count_scope(CoverageScope::Distributed),
+        peer_init_messages: count_scope(CoverageScope::PeerInit),
+        typed_decode: rows
+            .iter()
+            .filter(|r| r.decode == DecodeCoverage::Typed)
+            .count(),
+        opaque_decode: rows
+            .iter()
+            .filter(|r| r.decode == DecodeCoverage::Opaque)
+            .count(),
+        missing_decode: rows
+            .iter()
+            .filter(|r| r.decode == DecodeCoverage::Missing)
+            .count(),
+        encode: rows.iter().filter(|r| r.encode).count(),
+        roundtrip_test: rows.iter().filter(|r| r.roundtrip_test).count(),
+        capture_evidence: rows.iter().filter(|r| r.capture_frames > 0).count(),
+    };
+
+    ProtocolCoverageReport { summary, rows }
+}
+
+fn scan_capture_evidence(
+    captures_dir: &Path,
+) -> Result<BTreeMap<(CoverageScope, u32), CaptureEvidence>> {
+    let mut evidence: BTreeMap<(CoverageScope, u32), CaptureEvidence> = BTreeMap::new();
+    if !captures_dir.exists() {
+        return Ok(evidence);
+    }
+
+    let mut run_dirs = Vec::new();
+    for entry in fs::read_dir(captures_dir)
+        .with_context(|| format!("read captures dir: {}", captures_dir.display()))?
+    {
+        let path = entry?.path();
+        if path.is_dir() {
+            run_dirs.push(path);
+        }
+    }
+    run_dirs.sort();
+
+    for run_dir in run_dirs {
+        let run_id = run_dir
+            .file_name()
+            .map(|v| v.to_string_lossy().to_string())
+            .unwrap_or_default();
+        for file in ["official_frames.hex", "neo_frames.hex"] {
+            let path = run_dir.join(file);
+            if !path.exists() {
+                continue;
+            }
+            for bytes in load_hex_lines(&path)? {
+                record_frame_evidence(&mut evidence, &run_id, &bytes);
+            }
+        }
+    }
+
+    Ok(evidence)
+}
+
+fn record_frame_evidence(
+    evidence: &mut BTreeMap<(CoverageScope, u32), CaptureEvidence>,
+    run_id: &str,
+    bytes: &[u8],
+) {
+    let Ok(frame) = Frame::decode(bytes) else {
+        return;
+    };
+
+    let server = decode_server_message(frame.code, &frame.payload)
+        .ok()
+        .map(|message| (variant_name(&message), encode_server_message(&message)));
+    let peer = decode_peer_message(frame.code, &frame.payload)
+        .ok()
+        .map(|message| (variant_name(&message), encode_peer_message(&message)));
+
+    for (scope, decoded) in [(CoverageScope::Server, server), (CoverageScope::Peer, peer)] {
+        let Some((variant, reencoded)) = decoded else {
+            continue;
+        };
+        let entry = evidence.entry((scope, frame.code)).or_default();
+        entry.frames += 1;
+        entry.variants.insert(variant);
+        entry.runs.insert(run_id.to_string());
+        entry.reencoded_same_code |= reencoded.code == frame.code;
+        if reencoded != frame {
+            entry.roundtrip_failures += 1;
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use super::decode_hex;
+use anyhow::{Result, bail};
+use protocol::{
+    PeerInitMessage, ProtocolMessage, ServerMessage, decode_peer_init_message, decode_peer_message,
+    decode_server_message, encode_peer_message, encode_server_message,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+const MAX_PARTIAL_FIELDS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameFraming {
+    Server,
+    Peer,
+    PeerInit,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InspectOptions {
+    pub framing: Option<FrameFraming>,
+    pub lenient: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PartialField {
+    pub offset: usize,
+    pub kind: String,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InspectedFrame {
+    pub offset: usize,
+    pub len: usize,
+    pub framing: Option<FrameFraming>,
+    pub code: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<ProtocolMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alternative: Option<ProtocolMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peer_init: Option<PeerInitMessage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub partial_fields: Vec<PartialField>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trailing_bytes: Option<String>,
+}
+
+impl InspectedFrame {
+    fn empty(offset: usize, len: usize) -> Self {
+        Self {
+            offset,
+            len,
+            framing: None,
+            code: None,
+            message: None,
+            alternative: None,
+            peer_init: None,
+            errors: Vec::new(),
+            partial_fields: Vec::new(),
+            trailing_bytes: None,
+        }
+    }
+}
+
+/// Parses hex text as pasted from logs or capture files: whitespace and `#`
+/// comment lines are ignored, so a whole `.hex` capture decodes as one stream.
+pub fn parse_hex_input(text: &str) -> Result<Vec<u8>> {
+    let clean: String = text
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .flat_map(|line| line.split_whitespace())
+        .map(|chunk| chunk.trim_start_matches("0x"))
+        .collect();
+    decode_hex(&clean)
+}
+
+pub fn looks_like_hex_text(bytes: &[u8]) -> bool {
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return false;
+    };
+    text.lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .all(|line| {
+            line.chars()
+                .all(|c| c.is_ascii_hexdigit() || c.is_whitespace() || c == 'x')
+        })
+        && text.chars().any(|c| c.is_ascii_hexdigit())
+}
+
+pub fn inspect_stream(bytes: &[u8], options: &InspectOptions) -> Result<Vec<InspectedFrame>> {
+    let mut frames = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let remaining = &bytes[offset..];
+        let declared = remaining
+            .get(..4)
+            .map(|len| u32::from_le_bytes(len.try_into().expect("u32 slice length")) as usize);
+        let body = declared.and_then(|declared| remaining.get(4..4 + declared));
+
+        let Some(body) = body else {
+            if !options.lenient {
+                bail!(
+                    "incomplete frame at offset {offset}: {} bytes left, declared length {}",
+                    remaining.len(),
+                    declared
+                        .map(|d| d.to_string())
+                        .unwrap_or_else(|| "-".into())
+                );
+            }
+            let mut frame = InspectedFrame::empty(offset, remaining.len());
+            frame.errors.push("incomplete frame".to_string());
+            frame.trailing_bytes = Some(hex::encode(remaining));
+            frames.push(frame);
+            break;
+        };
+
+        frames.push(inspect_body(offset, body, options)?);
+        offset += 4 + body.len();
+    }
+
+    Ok(frames)
+}
+
+fn inspect_body(offset: usize, body: &[u8], options: &InspectOptions) -> Result<InspectedFrame> {
+    let mut frame = InspectedFrame::empty(offset, 4 + body.len());
+
+    if options.framing != Some(FrameFraming::PeerInit) && body.len() >= 4 {
+        let code = u32::from_le_bytes(body[..4].try_into().expect("u32 slice length"));
+        let payload = &body[4..];
+        frame.code = Some(code);
+
+        let server_message = match options.framing {
+            None | Some(FrameFraming::Server) => match decode_server_message(code, payload) {
+                Ok(message) => Some(ProtocolMessage::Server(message)),
+                Err(err) => {
+                    frame.errors.push(format!("server: {err}"));
+                    None
+                }
+            },
+            _ => None,
+        };
+        let peer_message = match options.framing {
+            None | Some(FrameFraming::Peer) => match decode_peer_message(code, payload) {
+                Ok(message) => Some(ProtocolMessage::Peer(message)),
+                Err(err) => {
+                    frame.errors.push(format!("peer: {err}"));
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        // Opaque server control codes overlap peer codes; a typed peer decode
+        // is the more useful reading, mirroring the semantic comparator.
+        let server_is_opaque = matches!(
+            server_message,
+            Some(ProtocolMessage::Server(ServerMessage::OpaqueControl(_)))
+        );
+        let (primary, alternative) = match (server_message, peer_message) {
+            (Some(server), Some(peer)) if server_is_opaque => (Some(peer), Some(server)),
+            (Some(server), peer) => (Some(server), peer),
+            (None, peer) => (peer, None),
+        };
+
+        if let Some(primary) = primary {
+            frame.framing = Some(message_framing(&primary));
+            frame.trailing_bytes = trailing_after_reencode(&primary, payload);
+            frame.message = Some(primary);
+            frame.alternative = alternative;
+            frame.errors.clear();
+            return Ok(frame);
+        }
+    }
+
+    if matches!(options.framing, None | Some(FrameFraming::PeerInit)) {
+        match decode_peer_init_message(body) {
+            Ok(peer_init) => {
+                frame.framing = Some(FrameFraming::PeerInit);
+                frame.code = Some(u32::from(peer_init.code()));
+                frame.peer_init = Some(peer_init);
+                frame.errors.clear();
+                return Ok(frame);
+            }
+            Err(err) => frame.errors.push(format!("peer_init: {err}")),
+        }
+    }
+
+    if !options.lenient {
+        bail!(
+            "no decoder accepted frame at offset {offset} (code {}): {}; retry with lenient mode to see partial fields",
+            frame
+                .code
+                .map(|code| code.to_string())
+                .unwrap_or_else(|| "-".into()),
+            frame.errors.join("; ")
+        );
+    }
+
+    frame.framing = options.framing;
+    let payload = if options.framing == Some(FrameFraming::PeerInit) || body.len() < 4 {
+        body
+    } else {
+        &body[4..]
+    };
+    let (fields, consumed) = walk_partial_fields(payload);
+    frame.partial_fields = fields;
+    if consumed < payload.len() {
+        frame.trailing_bytes = Some(hex::encode(&payload[consumed..]));
+    }
+    Ok(frame)
+}
+
+/// Decoders that tolerate trailing bytes drop them silently; re-encoding the
+/// typed message reveals what was skipped.
+fn trailing_after_reencode(message: &ProtocolMessage, payload: &[u8]) -> Option<String> {
+    let reencoded = match message {
+        ProtocolMessage::Server(message) => encode_server_message(message).payload,
+        ProtocolMessage::Peer(message) => encode_peer_message(message).payload,
+    };
+    if payload.len() > reencoded.len() && payload.starts_with(&reencoded) {
+        Some(hex::encode(&payload[reencoded.len()..]))
+    } else {
+        None
+    }
+}
+
+/// Best-effort field walk over an unknown payload: length-prefixed printable
+/// strings where they fit, `u32` values otherwise.
+fn walk_partial_fields(payload: &[u8]) -> (Vec<PartialField>, usize) {
+    let mut fields = Vec::new();
+    let mut offset = 0;
+
+    while payload.len() - offset >= 4 && fields.len() < MAX_PARTIAL_FIELDS {
+        let value = u32::from_le_bytes(
+            payload[offset..offset + 4]
+                .try_into()
+                .expect("u32 slice length"),
+        );
+        let len = value as usize;
+        let string = payload
+            .get(offset + 4..offset + 4 + len)
+            .filter(|bytes| !bytes.is_empty())
+            .and_then(|bytes| std::str::from_utf8(bytes).ok())
+            .filter(|text| {
+                text.chars()
+                    .all(|c| !c.is_control() || c == '\n' || c == '\t')
+            });
+
+        match string {
+            Some(text) => {
+                fields.push(PartialField {
+                    offset,
+                    kind: "string".to_string(),
+                    value: json!(text),
+                });
+                offset += 4 + len;
+            }
+            None => {
+                fields.push(PartialField {
+                    offset,
+                    kind: "u32".to_string(),
+                    value: json!(value),
+                });
+                offset += 4;
+            }
+        }
+    }
+
+    (fields, offset)
+}
+
+fn message_framing(message: &ProtocolMessage) -> FrameFraming {
+    match message {
+        ProtocolMessage::Server(_) => FrameFraming::Server,
+        ProtocolMessage::Peer(_) => FrameFraming::Peer,
+    }
+}
@@ -1,11 +1,37 @@
-use anyhow::{Context, Result};
-use protocol::{Frame, ServerMessage, decode_peer_message, decode_server_message};
+use anyhow::{Context, Result, bail};
+use protocol::{
+    Frame, ServerMessage, decode_peer_init_message, decode_peer_message, decode_server_message,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
 use std::path::Path;
 
+mod coverage;
+mod inspect;
+mod render;
+mod schema;
+
+pub use coverage::{
+    CoverageScope, DecodeCoverage, ProtocolCoverageReport, ProtocolCoverageRow,
+    ProtocolCoverageSummary, protocol_coverage, render_protocol_coverage_markdown,
+    write_protocol_coverage_report,
+};
+pub use inspect::{
+    FrameFraming, InspectOptions, InspectedFrame, PartialField, inspect_stream,
+    looks_like_hex_text, parse_hex_input,
+};
+pub use render::{
+    CodeSummary, FieldDiff, ReportFormat, collect_field_diffs, message_name, render_capture_report,
+    write_rendered_capture_reports,
+};
+pub use schema::{
+    MessageSchema, SchemaConformance, SchemaConformanceCase, SchemaConformanceReport, SchemaField,
+    SchemaMessage, check_schema_conformance, check_schema_message, load_message_schema,
+    write_schema_conformance_report,
+};
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum ComparisonMode {
@@ -46,6 +72,132 @@ pub struct CaptureRunReport {
     pub frame_comparisons: Vec<CaptureFrameComparison>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureFrame {
+    pub connection: String,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IgnoreRule {
+    pub field: String,
+    pub code: Option<u32>,
+}
+
+impl IgnoreRule {
+    pub fn field(field: &str) -> Self {
+        Self {
+            field: field.to_owned(),
+            code: None,
+        }
+    }
+
+    /// Parses `field`, or `code:field` to scope the rule to one message code.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        let (code, field) = match spec.split_once(':') {
+            Some((code, field)) => {
+                let code = code
+                    .trim()
+                    .parse::<u32>()
+                    .with_context(|| format!("invalid message code in ignore rule {spec:?}"))?;
+                (Some(code), field.trim())
+            }
+            None => (None, spec),
+        };
+        if field.is_empty() {
+            bail!("ignore rule {spec:?} names no field");
+        }
+        Ok(Self {
+            field: field.to_owned(),
+            code,
+        })
+    }
+
+    pub fn volatile_defaults() -> Vec<Self> {
+        [
+            "token",
+            "search_token",
+            "timestamp",
+            "message_id",
+            "md5hash",
+        ]
+        .into_iter()
+        .map(Self::field)
+        .collect()
+    }
+
+    fn applies_to(&self, code: Option<u32>) -> bool {
+        self.code.is_none() || self.code == code
+    }
+
+    fn matches_path(&self, key: &str, path: &str) -> bool {
+        if self.field.contains('.') {
+            path == self.field || path.ends_with(&format!(".{}", self.field))
+        } else {
+            key == self.field
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AlignmentOptions {
+    pub ignore_rules: Vec<IgnoreRule>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlignedFrameKind {
+    Matched,
+    Modified,
+    Reordered,
+    Inserted,
+    Deleted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AlignedFrameEntry {
+    pub kind: AlignedFrameKind,
+    pub official_index: Option<usize>,
+    pub neo_index: Option<usize>,
+    pub code: Option<u32>,
+    pub semantic_first_diff_field: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ConnectionAlignmentReport {
+    pub connection: String,
+    pub official_frames: usize,
+    pub neo_frames: usize,
+    pub matched: usize,
+    pub modified: usize,
+    pub reordered: usize,
+    pub inserted: usize,
+    pub deleted: usize,
+    pub entries: Vec<AlignedFrameEntry>,
+}
+
+impl ConnectionAlignmentReport {
+    pub fn is_clean(&self) -> bool {
+        self.modified == 0 && self.reordered == 0 && self.inserted == 0 && self.deleted == 0
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CaptureAlignmentReport {
+    pub run_id: String,
+    pub ignore_rules: Vec<IgnoreRule>,
+    pub connections: Vec<ConnectionAlignmentReport>,
+}
+
+impl CaptureAlignmentReport {
+    pub fn is_clean(&self) -> bool {
+        self.connections
+            .iter()
+            .all(ConnectionAlignmentReport::is_clean)
+    }
+}
+
 pub fn load_hex_fixture(path: impl AsRef<Path>) -> Result<Vec<u8>> {
     let path_ref = path.as_ref();
     let raw = fs::read_to_string(path_ref)
@@ -110,6 +262,45 @@ pub fn load_hex_lines(path: impl AsRef<Path>) -> Result<Vec<Vec<u8>>> {
     Ok(out)
 }
 
+pub fn load_tagged_hex_lines(path: impl AsRef<Path>) -> Result<Vec<CaptureFrame>> {
+    let path_ref = path.as_ref();
+    let raw = fs::read_to_string(path_ref)
+        .with_context(|| format!("read hex lines: {}", path_ref.display()))?;
+
+    let mut connection = DEFAULT_CONNECTION.to_string();
+    let mut out = Vec::new();
+    for (line_no, line) in raw.lines().enumerate() {
+        let trimmed = line.trim();
+        if let Some(comment) = trimmed.strip_prefix('#') {
+            if let Some(tag) = comment.trim().strip_prefix("connection:") {
+                let tag = tag.trim();
+                connection = if tag.is_empty() {
+                    DEFAULT_CONNECTION.to_string()
+                } else {
+                    tag.to_string()
+                };
+            }
+            continue;
+        }
+        if trimmed.is_empty() {
+            continue;
+        }
+        let bytes = decode_hex(trimmed).with_context(|| {
+            format!(
+                "decode hex line {} from {}",
+                line_no + 1,
+                path_ref.display()
+            )
+        })?;
+        out.push(CaptureFrame {
+            connection: connection.clone(),
+            bytes,
+        });
+    }
+
+    Ok(out)
+}
+
 pub fn compare_capture_sequences(
     run_id: &str,
     official_frames: &[Vec<u8>],
@@ -192,6 +383,86 @@ pub fn compare_capture_run_with_mode(
     ))
 }
 
+pub fn align_capture_sequences(
+    run_id: &str,
+    official_frames: &[Vec<u8>],
+    neo_frames: &[Vec<u8>],
+    options: &AlignmentOptions,
+) -> CaptureAlignmentReport {
+    let tag = |bytes: &Vec<u8>| CaptureFrame {
+        connection: DEFAULT_CONNECTION.to_string(),
+        bytes: bytes.clone(),
+    };
+    let official: Vec<CaptureFrame> = official_frames.iter().map(tag).collect();
+    let neo: Vec<CaptureFrame> = neo_frames.iter().map(tag).collect();
+    align_tagged_capture_sequences(run_id, &official, &neo, options)
+}
+
+pub fn align_tagged_capture_sequences(
+    run_id: &str,
+    official_frames: &[CaptureFrame],
+    neo_frames: &[CaptureFrame],
+    options: &AlignmentOptions,
+) -> CaptureAlignmentReport {
+    let mut connections: Vec<String> = Vec::new();
+    for frame in official_frames.iter().chain(neo_frames) {
+        if !connections.contains(&frame.connection) {
+            connections.push(frame.connection.clone());
+        }
+    }
+
+    let connections = connections
+        .into_iter()
+        .map(|connection| {
+            let official: Vec<&[u8]> = official_frames
+                .iter()
+                .filter(|frame| frame.connection == connection)
+                .map(|frame| frame.bytes.as_slice())
+                .collect();
+            let neo: Vec<&[u8]> = neo_frames
+                .iter()
+                .filter(|frame| frame.connection == connection)
+                .map(|frame| frame.bytes.as_slice())
+                .collect();
+            align_connection(&connection, &official, &neo, options)
+        })
+        .collect();
+
+    CaptureAlignmentReport {
+        run_id: run_id.to_owned(),
+        ignore_rules: options.ignore_rules.clone(),
+        connections,
+    }
+}
+
+pub fn align_capture_run(
+    run_dir: impl AsRef<Path>,
+    options: &AlignmentOptions,
+) -> Result<CaptureAlignmentReport> {
+    let run_dir = run_dir.as_ref();
+    let run_id = run_dir
+        .file_name()
+        .map(|v| v.to_string_lossy().to_string())
+        .unwrap_or_else(|| "run".to_string());
+
+    let official = load_tagged_hex_lines(run_dir.join("official_frames.hex"))?;
+    let neo = load_tagged_hex_lines(run_dir.join("neo_frames.hex"))?;
+
+    Ok(align_tagged_capture_sequences(
+        &run_id, &official, &neo, options,
+    ))
+}
+
+pub fn write_alignment_report(
+    path: impl AsRef<Path>,
+    report: &CaptureAlignmentReport,
+) -> Result<()> {
+    let rendered = serde_json::to_string_pretty(report).context("serialize alignment report")?;
+    fs::write(path.as_ref(), rendered + "\n")
+        .with_context(|| format!("write alignment report: {}", path.as_ref().display()))?;
+    Ok(())
+}
+
 pub fn write_capture_report(path: impl AsRef<Path>, report: &CaptureRunReport) -> Result<()> {
     let rendered = serde_json::to_string_pretty(report).context("serialize capture report")?;
     fs::write(path.as_ref(), rendered + "\n")
@@ -199,6 +470,337 @@ pub fn write_capture_report(path: impl AsRef<Path>, report: &CaptureRunReport) -
     Ok(())
 }
 
+const DEFAULT_CONNECTION: &str = "default";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EditOp {
+    Keep(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+fn align_connection(
+    connection: &str,
+    official: &[&[u8]],
+    neo: &[&[u8]],
+    options: &AlignmentOptions,
+) -> ConnectionAlignmentReport {
+    let official_norm: Vec<Value> = official
+        .iter()
+        .map(|bytes| normalize_semantic_frame(bytes))
+        .collect();
+    let neo_norm: Vec<Value> = neo
+        .iter()
+        .map(|bytes| normalize_semantic_frame(bytes))
+        .collect();
+    let official_keys: Vec<Value> = official_norm
+        .iter()
+        .map(|value| strip_ignored_fields(value, &options.ignore_rules))
+        .collect();
+    let neo_keys: Vec<Value> = neo_norm
+        .iter()
+        .map(|value| strip_ignored_fields(value, &options.ignore_rules))
+        .collect();
+
+    let ops = lcs_edit_script(&official_keys, &neo_keys);
+
+    // Pair leftover deletes/inserts: identical frames elsewhere in the stream are reorders,
+    // same-code frames inside the same gap are modifications.
+    let mut pairs: HashMap<usize, (usize, AlignedFrameKind)> = HashMap::new();
+    let mut paired_neo: BTreeSet<usize> = BTreeSet::new();
+    let deletes: Vec<usize> = ops
+        .iter()
+        .filter_map(|op| match op {
+            EditOp::Delete(i) => Some(*i),
+            _ => None,
+        })
+        .collect();
+    let inserts: Vec<usize> = ops
+        .iter()
+        .filter_map(|op| match op {
+            EditOp::Insert(j) => Some(*j),
+            _ => None,
+        })
+        .collect();
+    for &i in &deletes {
+        if let Some(&j) = inserts
+            .iter()
+            .find(|&&j| !paired_neo.contains(&j) && official_keys[i] == neo_keys[j])
+        {
+            pairs.insert(i, (j, AlignedFrameKind::Reordered));
+            paired_neo.insert(j);
+        }
+    }
+
+    let mut gaps: Vec<(Vec<usize>, Vec<usize>)> = vec![(Vec::new(), Vec::new())];
+    for op in &ops {
+        match op {
+            EditOp::Keep(..) => gaps.push((Vec::new(), Vec::new())),
+            EditOp::Delete(i) => gaps.last_mut().expect("gap").0.push(*i),
+            EditOp::Insert(j) => gaps.last_mut().expect("gap").1.push(*j),
+        }
+    }
+    for (gap_deletes, gap_inserts) in gaps {
+        for i in gap_deletes {
+            if pairs.contains_key(&i) {
+                continue;
+            }
+            let code = frame_code(&official_norm[i]);
+            if let Some(&j) = gap_inserts
+                .iter()
+                .find(|&&j| !paired_neo.contains(&j) && frame_code(&neo_norm[j]) == code)
+            {
+                pairs.insert(i, (j, AlignedFrameKind::Modified));
+                paired_neo.insert(j);
+            }
+        }
+    }
+
+    let mut report = ConnectionAlignmentReport {
+        connection: connection.to_owned(),
+        official_frames: official.len(),
+        neo_frames: neo.len(),
+        matched: 0,
+        modified: 0,
+        reordered: 0,
+        inserted: 0,
+        deleted: 0,
+        entries: Vec::with_capacity(ops.len()),
+    };
+    for op in ops {
+        let entry = match op {
+            EditOp::Keep(i, j) => AlignedFrameEntry {
+                kind: AlignedFrameKind::Matched,
+                official_index: Some(i),
+                neo_index: Some(j),
+                code: frame_code(&official_norm[i]),
+                semantic_first_diff_field: None,
+            },
+            EditOp::Delete(i) => match pairs.get(&i) {
+                Some(&(j, kind)) => AlignedFrameEntry {
+                    kind,
+                    official_index: Some(i),
+                    neo_index: Some(j),
+                    code: frame_code(&official_norm[i]),
+                    semantic_first_diff_field: if kind == AlignedFrameKind::Modified {
+                        first_semantic_diff(&official_keys[i], &neo_keys[j], "semantic")
+                    } else {
+                        None
+                    },
+                },
+                None => AlignedFrameEntry {
+                    kind: AlignedFrameKind::Deleted,
+                    official_index: Some(i),
+                    neo_index: None,
+                    code: frame_code(&official_norm[i]),
+                    semantic_first_diff_field: None,
+                },
+            },
+            EditOp::Insert(j) => {
+                if paired_neo.contains(&j) {
+                    continue;
+                }
+                AlignedFrameEntry {
+                    kind: AlignedFrameKind::Inserted,
+                    official_index: None,
+                    neo_index: Some(j),
+                    code: frame_code(&neo_norm[j]),
+                    semantic_first_diff_field: None,
+                }
+            }
+        };
+        match entry.kind {
+            AlignedFrameKind::Matched => report.matched += 1,
+            AlignedFrameKind::Modified => report.modified += 1,
+            AlignedFrameKind::Reordered => report.reordered += 1,
+            AlignedFrameKind::Inserted => report.inserted += 1,
+            AlignedFrameKind::Deleted => report.deleted += 1,
+        }
+        report.entries.push(entry);
+    }
+
+    report
+}
+
+/// Edit script between two frame streams along a longest common subsequence.
+/// Matching prefix and suffix frames are anchored first; the middle is split
+/// with Hirschberg's method so memory stays linear in the stream length.
+fn lcs_edit_script(official: &[Value], neo: &[Value]) -> Vec<EditOp> {
+    let (official, neo) = intern_frames(official, neo);
+    let prefix = official
+        .iter()
+        .zip(&neo)
+        .take_while(|(left, right)| left == right)
+        .count();
+    let suffix = official[prefix..]
+        .iter()
+        .rev()
+        .zip(neo[prefix..].iter().rev())
+        .take_while(|(left, right)| left == right)
+        .count();
+
+    let mut ops: Vec<EditOp> = (0..prefix).map(|idx| EditOp::Keep(idx, idx)).collect();
+    hirschberg(
+        &official[prefix..official.len() - suffix],
+        &neo[prefix..neo.len() - suffix],
+        (prefix, prefix),
+        &mut ops,
+    );
+    ops.extend(
+        (0..suffix)
+            .map(|idx| EditOp::Keep(official.len() - suffix + idx, neo.len() - suffix + idx)),
+    );
+    ops
+}
+
+/// Sections up to this many table cells are diffed with a dense table.
+const DENSE_LCS_CELLS: usize = 1 << 16;
+
+/// Replaces each frame by an id shared by all equal frames, so the diff
+/// compares integers instead of JSON trees.
+fn intern_frames(official: &[Value], neo: &[Value]) -> (Vec<u32>, Vec<u32>) {
+    let mut ids: HashMap<String, u32> = HashMap::new();
+    let mut intern = |frames: &[Value]| -> Vec<u32> {
+        frames
+            .iter()
+            .map(|frame| {
+                let next = ids.len() as u32;
+                *ids.entry(frame.to_string()).or_insert(next)
+            })
+            .collect()
+    };
+    let official = intern(official);
+    let neo = intern(neo);
+    (official, neo)
+}
+
+fn hirschberg(official: &[u32], neo: &[u32], offset: (usize, usize), ops: &mut Vec<EditOp>) {
+    let (n, m) = (official.len(), neo.len());
+    if n <= 1 || (n + 1).saturating_mul(m + 1) <= DENSE_LCS_CELLS {
+        dense_edit_script(official, neo, offset, ops);
+        return;
+    }
+
+    let mid = n / 2;
+    let forward = lcs_lengths(official[..mid].iter(), neo.iter(), m);
+    let backward = lcs_lengths(official[mid..].iter().rev(), neo.iter().rev(), m);
+    let split = (0..=m)
+        .max_by_key(|&j| (forward[j] + backward[m - j], std::cmp::Reverse(j)))
+        .unwrap_or(0);
+
+    hirschberg(&official[..mid], &neo[..split], offset, ops);
+    hirschberg(
+        &official[mid..],
+        &neo[split..],
+        (offset.0 + mid, offset.1 + split),
+        ops,
+    );
+}
+
+/// Last row of the LCS table: entry `j` is the LCS length of `official` and
+/// the first `j` items of `neo`.
+fn lcs_lengths<'a>(
+    official: impl Iterator<Item = &'a u32>,
+    neo: impl Iterator<Item = &'a u32> + Clone,
+    m: usize,
+) -> Vec<u32> {
+    let mut prev = vec![0_u32; m + 1];
+    let mut row = vec![0_u32; m + 1];
+    for left in official {
+        for (j, right) in neo.clone().enumerate() {
+            row[j + 1] = if left == right {
+                prev[j] + 1
+            } else {
+                prev[j + 1].max(row[j])
+            };
+        }
+        std::mem::swap(&mut prev, &mut row);
+    }
+    prev
+}
+
+fn dense_edit_script(
+    official: &[u32],
+    neo: &[u32],
+    (official_offset, neo_offset): (usize, usize),
+    ops: &mut Vec<EditOp>,
+) {
+    let n = official.len();
+    let m = neo.len();
+    let mut table = vec![0_u32; (n + 1) * (m + 1)];
+    let at = |i: usize, j: usize| i * (m + 1) + j;
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            table[at(i, j)] = if official[i] == neo[j] {
+                table[at(i + 1, j + 1)] + 1
+            } else {
+                table[at(i + 1, j)].max(table[at(i, j + 1)])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if official[i] == neo[j] {
+            ops.push(EditOp::Keep(official_offset + i, neo_offset + j));
+            i += 1;
+            j += 1;
+        } else if table[at(i + 1, j)] >= table[at(i, j + 1)] {
+            ops.push(EditOp::Delete(official_offset + i));
+            i += 1;
+        } else {
+            ops.push(EditOp::Insert(neo_offset + j));
+            j += 1;
+        }
+    }
+    ops.extend((i..n).map(|idx| EditOp::Delete(official_offset + idx)));
+    ops.extend((j..m).map(|idx| EditOp::Insert(neo_offset + idx)));
+}
+
+fn frame_code(normalized: &Value) -> Option<u32> {
+    normalized
+        .get("code")
+        .and_then(Value::as_u64)
+        .map(|code| code as u32)
+}
+
+fn strip_ignored_fields(normalized: &Value, rules: &[IgnoreRule]) -> Value {
+    let code = frame_code(normalized);
+    let active: Vec<&IgnoreRule> = rules.iter().filter(|rule| rule.applies_to(code)).collect();
+    if active.is_empty() {
+        return normalized.clone();
+    }
+    strip_value(normalized, &active, "")
+}
+
+fn strip_value(value: &Value, rules: &[&IgnoreRule], path: &str) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter_map(|(key, child)| {
+                    let next = if path.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{path}.{key}")
+                    };
+                    if key != "code" && rules.iter().any(|rule| rule.matches_path(key, &next)) {
+                        return None;
+                    }
+                    Some((key.clone(), strip_value(child, rules, &next)))
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .enumerate()
+                .map(|(idx, item)| strip_value(item, rules, &format!("{path}[{idx}]")))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
 fn semantic_compare(official: &[u8], neo: &[u8]) -> (bool, Option<String>) {
     let official_norm = normalize_semantic_frame(official);
     let neo_norm = normalize_semantic_frame(neo);
@@ -217,6 +819,9 @@ fn normalize_semantic_frame(bytes: &[u8]) -> Value {
     let frame = match Frame::decode(bytes) {
         Ok(frame) => frame,
         Err(err) => {
+            if let Some(peer_init) = normalize_peer_init_frame(bytes) {
+                return peer_init;
+            }
             return json!({
                 "decode_error": err.to_string(),
                 "frame_md5": format!("{:x}", md5::compute(bytes)),
@@ -257,15 +862,29 @@ fn normalize_semantic_frame(bytes: &[u8]) -> Value {
                 "decoded_peer": peer,
             })
         }
-        (Err(_), Err(_)) => json!({
-            "code": frame.code,
-            "known": false,
-            "payload_len": frame.payload.len(),
-            "payload_md5": format!("{:x}", md5::compute(&frame.payload)),
+        (Err(_), Err(_)) => normalize_peer_init_frame(bytes).unwrap_or_else(|| {
+            json!({
+                "code": frame.code,
+                "known": false,
+                "payload_len": frame.payload.len(),
+                "payload_md5": format!("{:x}", md5::compute(&frame.payload)),
+            })
         }),
     }
 }
 
+/// Connection setup frames use a one-byte code, so they only get a reading
+/// once the four-byte server and peer decoders have both given up.
+fn normalize_peer_init_frame(bytes: &[u8]) -> Option<Value> {
+    let message = decode_peer_init_message(bytes.get(4..)?).ok()?;
+    Some(json!({
+        "code": message.code(),
+        "known": true,
+        "scope": "peer_init",
+        "decoded": message,
+    }))
+}
+
 fn first_semantic_diff(expected: &Value, actual: &Value, path: &str) -> Option<String> {
     match (expected, actual) {
         (Value::Object(left), Value::Object(right)) => {
@@ -325,7 +944,7 @@ fn first_diff_offset(expected: &[u8], actual: &[u8]) -> Option<usize> {
 
 fn decode_hex(input: &str) -> Result<Vec<u8>> {
     let clean = input.trim();
-    if clean.len() % 2 != 0 {
+    if !clean.len().is_multiple_of(2) {
         anyhow::bail!("hex string length must be even");
     }
 
@@ -349,7 +968,9 @@ mod tests {
     use protocol::{
         CODE_PM_TRANSFER_RESPONSE, CODE_SM_ADD_LIKE_TERM, CODE_SM_DOWNLOAD_SPEED,
         CODE_SM_GET_RECOMMENDATIONS, CODE_SM_GET_ROOM_TICKER, CODE_SM_GET_USER_STATUS,
-        CODE_SM_JOIN_ROOM, CODE_SM_MESSAGE_USER, CODE_SM_USER_JOINED_ROOM, PayloadWriter,
+        CODE_SM_HEARTBEAT, CODE_SM_JOIN_ROOM, CODE_SM_MESSAGE_USER, CODE_SM_USER_JOINED_ROOM,
+        PayloadWriter, PeerInitMessage, PeerInitPayload, PierceFirewallPayload, ProtocolMessage,
+        encode_peer_init_message,
     };
 
     fn transfer_response_frame_bytes(token: u32, allowed_raw: u32) -> Vec<u8> {
@@ -427,6 +1048,10 @@ mod tests {
         Frame::new(CODE_SM_GET_ROOM_TICKER, writer.into_inner()).encode()
     }
 
+    fn heartbeat_frame_bytes() -> Vec<u8> {
+        Frame::new(CODE_SM_HEARTBEAT, Vec::new()).encode()
+    }
+
     #[test]
     fn comparison_detects_diff() {
         let cmp = compare_fixture_hex("fixture", &[0x00, 0x01], &[0x00, 0x02]);
@@ -496,6 +1121,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn semantic_mode_decodes_peer_init_frames() {
+        let peer_init = |username: &str| {
+            encode_peer_init_message(&PeerInitMessage::PeerInit(PeerInitPayload {
+                username: username.to_string(),
+                connection_type: "P".to_string(),
+                token: 0,
+            }))
+            .encode()
+        };
+        let pierce =
+            encode_peer_init_message(&PeerInitMessage::PierceFirewall(PierceFirewallPayload {
+                token: 7,
+            }))
+            .encode();
+
+        let normalized = normalize_semantic_frame(&pierce);
+        assert_eq!(normalized["scope"], "peer_init");
+        assert_eq!(normalized["decoded"]["PierceFirewall"]["token"], 7);
+
+        let report = compare_capture_sequences_with_mode(
+            "run-peer-init",
+            &[peer_init("alice"), pierce.clone()],
+            &[peer_init("bob"), pierce],
+            ComparisonMode::Semantic,
+        );
+        assert_eq!(report.total_pairs, 2);
+        assert_eq!(report.matched_pairs, 1);
+        assert!(
+            report.frame_comparisons[0]
+                .semantic_first_diff_field
+                .as_deref()
+                .unwrap_or_default()
+                .contains("username")
+        );
+    }
+
     #[test]
     fn semantic_mode_reports_room_presence_field_diff() {
         let official = vec![room_presence_frame_bytes("nicotine", "alice")];
@@ -688,4 +1350,508 @@ mod tests {
                 .contains("decoded_")
         );
     }
+
+    #[test]
+    fn alignment_reports_inserted_heartbeat_without_cascading_mismatches() {
+        let official = vec![
+            server_speed_frame_bytes(1024),
+            room_presence_frame_bytes("nicotine", "alice"),
+            add_like_term_frame_bytes("idm"),
+        ];
+        let neo = vec![
+            server_speed_frame_bytes(1024),
+            heartbeat_frame_bytes(),
+            room_presence_frame_bytes("nicotine", "alice"),
+            add_like_term_frame_bytes("idm"),
+        ];
+        let report =
+            align_capture_sequences("run-align", &official, &neo, &AlignmentOptions::default());
+
+        assert_eq!(report.connections.len(), 1);
+        let connection = &report.connections[0];
+        assert_eq!(connection.matched, 3);
+        assert_eq!(connection.inserted, 1);
+        assert_eq!(connection.deleted, 0);
+        assert_eq!(connection.modified, 0);
+        let inserted = connection
+            .entries
+            .iter()
+            .find(|entry| entry.kind == AlignedFrameKind::Inserted)
+            .expect("inserted entry");
+        assert_eq!(inserted.neo_index, Some(1));
+        assert_eq!(inserted.code, Some(CODE_SM_HEARTBEAT));
+    }
+
+    #[test]
+    fn alignment_classifies_reordered_modified_and_deleted_frames() {
+        let official = vec![
+            room_presence_frame_bytes("nicotine", "alice"),
+            add_like_term_frame_bytes("idm"),
+            server_speed_frame_bytes(2048),
+            room_ticker_frame_bytes("nicotine", "alice", "Now playing A"),
+            user_status_frame_bytes("alice", 2, true),
+        ];
+        let neo = vec![
+            add_like_term_frame_bytes("idm"),
+            room_presence_frame_bytes("nicotine", "alice"),
+            server_speed_frame_bytes(1024),
+            room_ticker_frame_bytes("nicotine", "alice", "Now playing A"),
+        ];
+        let report =
+            align_capture_sequences("run-align", &official, &neo, &AlignmentOptions::default());
+        let connection = &report.connections[0];
+
+        assert_eq!(connection.reordered, 1);
+        assert_eq!(connection.modified, 1);
+        assert_eq!(connection.deleted, 1);
+        assert_eq!(connection.inserted, 0);
+        assert!(!report.is_clean());
+        let modified = connection
+            .entries
+            .iter()
+            .find(|entry| entry.kind == AlignedFrameKind::Modified)
+            .expect("modified entry");
+        assert_eq!(modified.code, Some(CODE_SM_DOWNLOAD_SPEED));
+        assert!(
+            modified
+                .semantic_first_diff_field
+                .as_deref()
+                .unwrap_or_default()
+                .contains("bytes_per_sec")
+        );
+    }
+
+    #[test]
+    fn long_streams_align_along_a_longest_common_subsequence() {
+        let official: Vec<Value> = (0..3_000)
+            .map(|idx| json!({ "code": 1, "n": idx }))
+            .collect();
+        let mut neo = official.clone();
+        neo.retain(|frame| frame["n"].as_u64().is_some_and(|n| n % 7 != 3));
+        for idx in (0..neo.len()).step_by(11).rev() {
+            neo.insert(idx, json!({ "code": 32, "n": idx }));
+        }
+
+        let ops = lcs_edit_script(&official, &neo);
+        let kept: Vec<(usize, usize)> = ops
+            .iter()
+            .filter_map(|op| match op {
+                EditOp::Keep(i, j) => Some((*i, *j)),
+                _ => None,
+            })
+            .collect();
+        let deleted = ops
+            .iter()
+            .filter(|op| matches!(op, EditOp::Delete(_)))
+            .count();
+        let inserted = ops
+            .iter()
+            .filter(|op| matches!(op, EditOp::Insert(_)))
+            .count();
+        assert_eq!(kept.len() + deleted, official.len());
+        assert_eq!(kept.len() + inserted, neo.len());
+        assert_eq!(deleted, (0..3_000).filter(|n| n % 7 == 3).count());
+        assert!(
+            kept.windows(2)
+                .all(|pair| pair[0].0 < pair[1].0 && pair[0].1 < pair[1].1)
+        );
+        assert!(kept.iter().all(|&(i, j)| official[i] == neo[j]));
+    }
+
+    #[test]
+    fn alignment_ignore_rules_skip_volatile_fields() {
+        let official = vec![private_message_incoming_frame_bytes(
+            91,
+            1_705_000_000,
+            "alice",
+            "hello",
+            true,
+        )];
+        let neo = vec![private_message_incoming_frame_bytes(
+            92,
+            1_705_000_042,
+            "alice",
+            "hello",
+            true,
+        )];
+
+        let strict = align_capture_sequences(
+            "run-volatile",
+            &official,
+            &neo,
+            &AlignmentOptions::default(),
+        );
+        assert_eq!(strict.connections[0].modified, 1);
+
+        let relaxed = align_capture_sequences(
+            "run-volatile",
+            &official,
+            &neo,
+            &AlignmentOptions {
+                ignore_rules: IgnoreRule::volatile_defaults(),
+            },
+        );
+        assert!(relaxed.is_clean());
+        assert_eq!(relaxed.connections[0].matched, 1);
+
+        let scoped = align_capture_sequences(
+            "run-volatile",
+            &official,
+            &neo,
+            &AlignmentOptions {
+                ignore_rules: vec![IgnoreRule {
+                    field: "message_id".to_string(),
+                    code: Some(CODE_SM_DOWNLOAD_SPEED),
+                }],
+            },
+        );
+        assert_eq!(scoped.connections[0].modified, 1);
+    }
+
+    #[test]
+    fn ignore_rule_parses_optional_code_scope() {
+        assert_eq!(
+            IgnoreRule::parse("token").expect("field rule"),
+            IgnoreRule::field("token")
+        );
+        assert_eq!(
+            IgnoreRule::parse("35:message_id").expect("scoped rule"),
+            IgnoreRule {
+                field: "message_id".to_string(),
+                code: Some(35),
+            }
+        );
+        assert!(IgnoreRule::parse("speed:message_id").is_err());
+        assert!(IgnoreRule::parse("35:").is_err());
+    }
+
+    #[test]
+    fn alignment_groups_frames_by_connection_tag() {
+        let path =
+            std::env::temp_dir().join(format!("nss-verify-tagged-{}.hex", std::process::id()));
+        let body = format!(
+            "{}\n# connection: peer-1\n{}\n# connection:\n{}\n",
+            hex::encode(server_speed_frame_bytes(1)),
+            hex::encode(transfer_response_frame_bytes(7, 1)),
+            hex::encode(heartbeat_frame_bytes())
+        );
+        fs::write(&path, body).expect("write tagged fixture");
+        let frames = load_tagged_hex_lines(&path).expect("load tagged frames");
+        let _ = fs::remove_file(&path);
+
+        let connections: Vec<&str> = frames.iter().map(|f| f.connection.as_str()).collect();
+        assert_eq!(connections, vec!["default", "peer-1", "default"]);
+
+        let neo = vec![frames[1].clone(), frames[0].clone(), frames[2].clone()];
+        let report = align_tagged_capture_sequences(
+            "run-tagged",
+            &frames,
+            &neo,
+            &AlignmentOptions::default(),
+        );
+        assert_eq!(report.connections.len(), 2);
+        assert!(report.is_clean());
+        assert_eq!(report.connections[0].connection, "default");
+        assert_eq!(report.connections[0].matched, 2);
+        assert_eq!(report.connections[1].matched, 1);
+    }
+
+    #[test]
+    fn markdown_report_renders_timeline_field_diffs_and_code_summary() {
+        let official = vec![
+            heartbeat_frame_bytes(),
+            server_speed_frame_bytes(2048),
+            transfer_response_frame_bytes(7, 1),
+        ];
+        let neo = vec![heartbeat_frame_bytes(), server_speed_frame_bytes(1024)];
+        let report = compare_capture_sequences_with_mode(
+            "run-md",
+            &official,
+            &neo,
+            ComparisonMode::Semantic,
+        );
+
+        let rendered = render_capture_report(&report, &official, &neo, ReportFormat::Markdown);
+
+        assert!(rendered.contains("# Capture verification: run-md"));
+        assert!(rendered.contains(&format!(
+            "| {CODE_SM_HEARTBEAT} | Heartbeat | 1 | 1 | 0 | 0 | 0 |"
+        )));
+        assert!(rendered.contains(&format!(
+            "| {CODE_SM_DOWNLOAD_SPEED} | DownloadSpeed | 1 | 0 | 1 | 0 | 0 |"
+        )));
+        assert!(rendered.contains("| 2 | TransferResponse"));
+        assert!(rendered.contains("official_only"));
+        assert!(rendered.contains("### Frame 1 (mismatch)"));
+        assert!(rendered.contains("2048"));
+        // 2048 vs 1024 differs in the second payload byte, offset 9.
+        assert!(rendered.contains("00000000  08 00 00 00 22 00 00 00 00 [08] 00 00"));
+        assert!(rendered.contains("00000000  08 00 00 00 22 00 00 00 00 [04] 00 00"));
+        assert!(!rendered.contains("### Frame 0"));
+    }
+
+    #[test]
+    fn html_report_marks_differing_bytes_and_escapes_text() {
+        let official = vec![server_speed_frame_bytes(2048)];
+        let neo = vec![server_speed_frame_bytes(1024)];
+        let report =
+            compare_capture_sequences_with_mode("<run>", &official, &neo, ComparisonMode::Semantic);
+
+        let rendered = render_capture_report(&report, &official, &neo, ReportFormat::Html);
+
+        assert!(rendered.starts_with("<!DOCTYPE html>"));
+        assert!(rendered.contains("Capture verification: &lt;run&gt;"));
+        assert!(rendered.contains("<mark>08</mark>"));
+        assert!(rendered.contains("<mark>04</mark>"));
+        assert!(rendered.contains("first diff offset: 9"));
+        assert!(rendered.contains("<tr class=\"mismatch\">"));
+    }
+
+    #[test]
+    fn report_timeline_follows_the_alignment_with_gap_rows() {
+        let official = vec![
+            heartbeat_frame_bytes(),
+            server_speed_frame_bytes(2048),
+            transfer_response_frame_bytes(7, 1),
+        ];
+        let neo = vec![heartbeat_frame_bytes(), transfer_response_frame_bytes(7, 1)];
+        let report =
+            compare_capture_sequences_with_mode("run-gap", &official, &neo, ComparisonMode::Bytes);
+
+        let rendered = render_capture_report(&report, &official, &neo, ReportFormat::Markdown);
+
+        assert!(rendered.contains("| 1 | DownloadSpeed"));
+        assert!(rendered.contains("| - | official_only |"));
+        assert!(rendered.contains("### Frame 1 (official_only)"));
+        // The dropped frame must not shift the transfer response into a mismatch.
+        assert!(rendered.contains(&format!(
+            "| {CODE_PM_TRANSFER_RESPONSE} | TransferResponse | 1 | 1 | 0 | 0 | 0 |"
+        )));
+        assert!(!rendered.contains("### Frame 2"));
+    }
+
+    #[test]
+    fn field_diffs_cover_every_differing_path() {
+        let official =
+            json!({"code": 1, "decoded": {"Search": {"token": 1, "query": "a", "list": [1, 2]}}});
+        let neo =
+            json!({"code": 1, "decoded": {"Search": {"token": 2, "query": "a", "list": [1]}}});
+
+        let diffs = collect_field_diffs(&official, &neo);
+        let paths: Vec<&str> = diffs.iter().map(|d| d.path.as_str()).collect();
+
+        assert_eq!(
+            paths,
+            vec![
+                "semantic.decoded.Search.list[1]",
+                "semantic.decoded.Search.token"
+            ]
+        );
+        assert_eq!(diffs[0].neo, None);
+        assert_eq!(message_name(&official), "Search");
+    }
+
+    #[test]
+    fn committed_protocol_coverage_matches_the_codec_and_captures() {
+        let repo_root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        let report = protocol_coverage(repo_root.join("captures/redacted")).expect("coverage");
+        let regenerate = "stale; regenerate with `soul-cli verify coverage` from the repo root";
+
+        let json_path = repo_root.join("docs/state/protocol-coverage.json");
+        let committed_json = fs::read_to_string(&json_path).expect("read committed json");
+        let fresh_json = serde_json::to_string_pretty(&report).expect("serialize coverage");
+        assert!(
+            committed_json == fresh_json,
+            "{} is {regenerate}",
+            json_path.display()
+        );
+
+        let md_path = repo_root.join("docs/state/protocol-coverage.md");
+        let committed_md = fs::read_to_string(&md_path).expect("read committed markdown");
+        assert!(
+            committed_md == render_protocol_coverage_markdown(&report),
+            "{} is {regenerate}",
+            md_path.display()
+        );
+    }
+
+    #[test]
+    fn protocol_coverage_enumerates_constants_and_capture_evidence() {
+        let captures_dir =
+            std::env::temp_dir().join(format!("nss-verify-coverage-{}", std::process::id()));
+        let run_dir = captures_dir.join("run-heartbeat");
+        fs::create_dir_all(&run_dir).expect("create run dir");
+        fs::write(
+            run_dir.join("official_frames.hex"),
+            format!("{}\n", hex::encode(heartbeat_frame_bytes())),
+        )
+        .expect("write frames");
+
+        let report = protocol_coverage(&captures_dir).expect("coverage");
+        let _ = fs::remove_dir_all(&captures_dir);
+
+        let summary = &report.summary;
+        assert_eq!(
+            summary.total_messages,
+            summary.server_messages
+                + summary.peer_messages
+                + summary.distributed_messages
+                + summary.peer_init_messages
+        );
+        let row = |scope: CoverageScope, message: &str| {
+            report
+                .rows
+                .iter()
+                .find(|row| row.scope == scope && row.message == message)
+                .unwrap_or_else(|| panic!("missing row {message}"))
+        };
+
+        let heartbeat = row(CoverageScope::Server, "SM_HEARTBEAT");
+        assert_eq!(heartbeat.code, CODE_SM_HEARTBEAT);
+        assert_eq!(heartbeat.decode, DecodeCoverage::Typed);
+        assert_eq!(heartbeat.variants, vec!["Heartbeat".to_string()]);
+        assert!(heartbeat.encode);
+        assert!(heartbeat.roundtrip_test);
+        assert_eq!(heartbeat.capture_frames, 1);
+        assert_eq!(heartbeat.capture_roundtrip, Some(true));
+        assert_eq!(heartbeat.capture_runs, vec!["run-heartbeat".to_string()]);
+
+        let alias = row(CoverageScope::Server, "SM_FILE_SEARCH_RESPONSE");
+        assert_eq!(alias.code, row(CoverageScope::Server, "SM_ROOM_LIST").code);
+        assert!(alias.encode);
+
+        // The alternate peer-message code is decoded but always re-encoded as 68.
+        assert!(!row(CoverageScope::Server, "SM_PEER_MESSAGE_ALT").encode);
+        assert_eq!(
+            row(CoverageScope::Server, "SM_PEER_MESSAGE_ALT").variants,
+            vec!["PeerMessage".to_string()]
+        );
+        assert_eq!(
+            row(CoverageScope::Peer, "PM_TRANSFER_RESPONSE").capture_frames,
+            0
+        );
+
+        let embedded = row(CoverageScope::Distributed, "DM_EMBEDDED_MESSAGE");
+        assert_eq!(embedded.decode, DecodeCoverage::Typed);
+        assert_eq!(embedded.variants, vec!["EmbeddedMessage".to_string()]);
+        assert!(embedded.roundtrip_test);
+        let peer_init = row(CoverageScope::PeerInit, "PI_PEER_INIT");
+        assert_eq!(peer_init.variants, vec!["PeerInit".to_string()]);
+        assert!(peer_init.encode && peer_init.roundtrip_test);
+    }
+
+    #[test]
+    fn inspect_stream_autodetects_server_peer_and_peer_init_framing() {
+        let mut stream = server_speed_frame_bytes(2048);
+        stream.extend(transfer_response_frame_bytes(9, 1));
+        stream.extend([5, 0, 0, 0, 0, 7, 0, 0, 0]);
+
+        let frames = inspect_stream(&stream, &InspectOptions::default()).expect("inspect");
+
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].framing, Some(FrameFraming::Server));
+        assert_eq!(frames[0].code, Some(CODE_SM_DOWNLOAD_SPEED));
+        assert!(matches!(
+            frames[0].message,
+            Some(ProtocolMessage::Server(ServerMessage::DownloadSpeed(_)))
+        ));
+        assert_eq!(frames[1].framing, Some(FrameFraming::Peer));
+        assert_eq!(frames[1].offset, 12);
+        assert_eq!(frames[2].framing, Some(FrameFraming::PeerInit));
+        assert_eq!(
+            frames[2].peer_init,
+            Some(PeerInitMessage::PierceFirewall(PierceFirewallPayload {
+                token: 7
+            }))
+        );
+    }
+
+    #[test]
+    fn inspect_lenient_mode_reports_partial_fields_and_trailing_bytes() {
+        let mut writer = PayloadWriter::new();
+        writer.write_string("alice");
+        writer.write_u32(42);
+        writer.write_raw_bytes(&[0xaa, 0xbb]);
+        let unknown = Frame::new(9_999, writer.into_inner()).encode();
+
+        let err = inspect_stream(&unknown, &InspectOptions::default())
+            .expect_err("strict mode rejects unknown code");
+        assert!(err.to_string().contains("code 9999"));
+
+        let options = InspectOptions {
+            framing: None,
+            lenient: true,
+        };
+        let mut stream = unknown.clone();
+        stream.extend([0x10, 0x00]);
+        let frames = inspect_stream(&stream, &options).expect("lenient inspect");
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].message, None);
+        assert_eq!(frames[0].partial_fields.len(), 2);
+        assert_eq!(frames[0].partial_fields[0].kind, "string");
+        assert_eq!(frames[0].partial_fields[0].value, json!("alice"));
+        assert_eq!(frames[0].partial_fields[1].value, json!(42));
+        assert_eq!(frames[0].trailing_bytes.as_deref(), Some("aabb"));
+        assert_eq!(frames[1].trailing_bytes.as_deref(), Some("1000"));
+    }
+
+    #[test]
+    fn parse_hex_input_skips_comments_and_whitespace() {
+        let text = "# connection: a\n08000000 22000000\n0x00080000\n";
+        let bytes = parse_hex_input(text).expect("parse hex");
+        assert_eq!(bytes, server_speed_frame_bytes(2048));
+        assert!(looks_like_hex_text(text.as_bytes()));
+        assert!(!looks_like_hex_text(&[0xff, 0x00]));
+    }
+
+    #[test]
+    fn schema_conformance_flags_field_order_disagreement() {
+        let field = |name: &str, ty: &str| SchemaField {
+            name: name.to_string(),
+            field_type: ty.to_string(),
+        };
+        let mut message = SchemaMessage {
+            scope: "server".to_string(),
+            code: CODE_SM_USER_JOINED_ROOM,
+            name: "SM_USER_JOINED_ROOM".to_string(),
+            payload: vec![field("room", "string"), field("username", "string")],
+            confidence: None,
+        };
+        assert_eq!(
+            check_schema_message(&message).result,
+            SchemaConformance::Conforms
+        );
+
+        message.payload.reverse();
+        let case = check_schema_message(&message);
+        assert!(case.is_disagreement());
+        assert!(matches!(
+            case.result,
+            SchemaConformance::FieldMismatch { ref field, .. } if field == "username"
+        ));
+    }
+
+    #[test]
+    fn message_schema_matches_codec_except_known_disagreements() {
+        // Schema entries whose layout the codec deliberately reads differently;
+        // remove an entry once schema or codec is reconciled.
+        const KNOWN_DISAGREEMENTS: &[&str] = &["SM_CONNECT_TO_PEER", "SM_PEER_MESSAGE"];
+
+        let schema = load_message_schema(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../analysis/protocol/message_schema.json"
+        ))
+        .expect("load schema");
+        let report = check_schema_conformance(&schema);
+
+        let disagreements: Vec<&str> = report
+            .cases
+            .iter()
+            .filter(|case| case.is_disagreement())
+            .map(|case| case.name.as_str())
+            .collect();
+        assert_eq!(disagreements, KNOWN_DISAGREEMENTS);
+        assert!(report.conforms > report.total / 2);
+    }
 }
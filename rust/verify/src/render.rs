@@ -0,0 +1,611 @@
+use super::{
+    AlignedFrameKind, AlignmentOptions, CaptureRunReport, ComparisonMode, align_capture_sequences,
+    first_diff_offset, normalize_semantic_frame,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const HEX_DUMP_ROW_BYTES: usize = 16;
+const HEX_DUMP_CONTEXT_ROWS: usize = 4;
+const MAX_FIELD_DIFFS: usize = 32;
+const MAX_VALUE_CHARS: usize = 120;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    Html,
+    Markdown,
+}
+
+impl ReportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Html => "html",
+            Self::Markdown => "md",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub path: String,
+    pub official: Option<Value>,
+    pub neo: Option<Value>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CodeSummary {
+    pub code: Option<u32>,
+    pub name: String,
+    pub pairs: usize,
+    pub matched: usize,
+    pub mismatched: usize,
+    pub official_only: usize,
+    pub neo_only: usize,
+}
+
+#[derive(Debug, Clone)]
+struct TimelineRow {
+    index: usize,
+    kind: AlignedFrameKind,
+    official: Option<FrameView>,
+    neo: Option<FrameView>,
+    matches: bool,
+    first_diff_offset: Option<usize>,
+    semantic_first_diff_field: Option<String>,
+    field_diffs: Vec<FieldDiff>,
+}
+
+#[derive(Debug, Clone)]
+struct FrameView {
+    code: Option<u32>,
+    name: String,
+    bytes: Vec<u8>,
+}
+
+/// Renders every requested format from one comparison and one alignment,
+/// writing each next to `stem` with the format's extension.
+pub fn write_rendered_capture_reports(
+    stem: impl AsRef<Path>,
+    report: &CaptureRunReport,
+    official_frames: &[Vec<u8>],
+    neo_frames: &[Vec<u8>],
+    formats: &[ReportFormat],
+) -> Result<Vec<PathBuf>> {
+    let stem = stem.as_ref();
+    let rows = build_timeline(report, official_frames, neo_frames);
+    let summaries = summarize_by_code(&rows);
+
+    let mut written = Vec::with_capacity(formats.len());
+    for &format in formats {
+        let path = stem.with_extension(format.extension());
+        let rendered = render_rows(report, &rows, &summaries, format);
+        fs::write(&path, rendered).with_context(|| format!("write report: {}", path.display()))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+pub fn render_capture_report(
+    report: &CaptureRunReport,
+    official_frames: &[Vec<u8>],
+    neo_frames: &[Vec<u8>],
+    format: ReportFormat,
+) -> String {
+    let rows = build_timeline(report, official_frames, neo_frames);
+    let summaries = summarize_by_code(&rows);
+    render_rows(report, &rows, &summaries, format)
+}
+
+fn render_rows(
+    report: &CaptureRunReport,
+    rows: &[TimelineRow],
+    summaries: &[CodeSummary],
+    format: ReportFormat,
+) -> String {
+    match format {
+        ReportFormat::Html => render_html(report, rows, summaries),
+        ReportFormat::Markdown => render_markdown(report, rows, summaries),
+    }
+}
+
+pub fn collect_field_diffs(official: &Value, neo: &Value) -> Vec<FieldDiff> {
+    let mut out = Vec::new();
+    collect_field_diffs_at(official, neo, "semantic", &mut out);
+    out
+}
+
+pub fn message_name(normalized: &Value) -> String {
+    let decoded = normalized
+        .get("decoded")
+        .or_else(|| normalized.get("decoded_server"));
+    match decoded {
+        Some(Value::Object(map)) => map
+            .keys()
+            .next()
+            .cloned()
+            .unwrap_or_else(|| "unknown".to_string()),
+        Some(Value::String(name)) => name.clone(),
+        _ if normalized.get("decode_error").is_some() => "undecodable".to_string(),
+        _ => "unknown".to_string(),
+    }
+}
+
+fn collect_field_diffs_at(official: &Value, neo: &Value, path: &str, out: &mut Vec<FieldDiff>) {
+    match (official, neo) {
+        (Value::Object(left), Value::Object(right)) => {
+            let mut keys = BTreeSet::new();
+            keys.extend(left.keys().cloned());
+            keys.extend(right.keys().cloned());
+            for key in keys {
+                let next = format!("{path}.{key}");
+                match (left.get(&key), right.get(&key)) {
+                    (Some(a), Some(b)) => collect_field_diffs_at(a, b, &next, out),
+                    (a, b) => out.push(FieldDiff {
+                        path: next,
+                        official: a.cloned(),
+                        neo: b.cloned(),
+                    }),
+                }
+            }
+        }
+        (Value::Array(left), Value::Array(right)) => {
+            let max = left.len().max(right.len());
+            for idx in 0..max {
+                let next = format!("{path}[{idx}]");
+                match (left.get(idx), right.get(idx)) {
+                    (Some(a), Some(b)) => collect_field_diffs_at(a, b, &next, out),
+                    (a, b) => out.push(FieldDiff {
+                        path: next,
+                        official: a.cloned(),
+                        neo: b.cloned(),
+                    }),
+                }
+            }
+        }
+        _ => {
+            if official != neo {
+                out.push(FieldDiff {
+                    path: path.to_string(),
+                    official: Some(official.clone()),
+                    neo: Some(neo.clone()),
+                });
+            }
+        }
+    }
+}
+
+/// Lays frames out along the capture alignment, so an inserted or dropped
+/// frame becomes a one-sided gap row instead of shifting every later pair.
+fn build_timeline(
+    report: &CaptureRunReport,
+    official_frames: &[Vec<u8>],
+    neo_frames: &[Vec<u8>],
+) -> Vec<TimelineRow> {
+    let alignment = align_capture_sequences(
+        &report.run_id,
+        official_frames,
+        neo_frames,
+        &AlignmentOptions::default(),
+    );
+    let entries = alignment
+        .connections
+        .into_iter()
+        .flat_map(|connection| connection.entries);
+
+    let mut rows = Vec::with_capacity(official_frames.len().max(neo_frames.len()));
+    for (index, entry) in entries.enumerate() {
+        let official = entry.official_index.and_then(|i| official_frames.get(i));
+        let neo = entry.neo_index.and_then(|i| neo_frames.get(i));
+        let official_norm = official.map(|bytes| normalize_semantic_frame(bytes));
+        let neo_norm = neo.map(|bytes| normalize_semantic_frame(bytes));
+
+        let field_diffs = match (&official_norm, &neo_norm) {
+            (Some(a), Some(b)) => collect_field_diffs(a, b),
+            _ => Vec::new(),
+        };
+        let matches = entry.kind == AlignedFrameKind::Matched
+            && (report.comparison_mode == ComparisonMode::Semantic || official == neo);
+
+        rows.push(TimelineRow {
+            index,
+            kind: entry.kind,
+            official: official.zip(official_norm.as_ref()).map(frame_view),
+            neo: neo.zip(neo_norm.as_ref()).map(frame_view),
+            matches,
+            first_diff_offset: match (official, neo) {
+                (Some(a), Some(b)) => first_diff_offset(a, b),
+                _ => None,
+            },
+            semantic_first_diff_field: entry.semantic_first_diff_field,
+            field_diffs,
+        });
+    }
+
+    rows
+}
+
+fn frame_view((bytes, normalized): (&Vec<u8>, &Value)) -> FrameView {
+    FrameView {
+        code: normalized
+            .get("code")
+            .and_then(Value::as_u64)
+            .and_then(|code| u32::try_from(code).ok()),
+        name: message_name(normalized),
+        bytes: bytes.clone(),
+    }
+}
+
+fn summarize_by_code(rows: &[TimelineRow]) -> Vec<CodeSummary> {
+    let mut by_code: BTreeMap<Option<u32>, CodeSummary> = BTreeMap::new();
+
+    for row in rows {
+        let primary = row.official.as_ref().or(row.neo.as_ref());
+        let Some(primary) = primary else {
+            continue;
+        };
+        let entry = by_code.entry(primary.code).or_insert_with(|| CodeSummary {
+            code: primary.code,
+            name: primary.name.clone(),
+            ..CodeSummary::default()
+        });
+
+        match (&row.official, &row.neo) {
+            (Some(_), Some(_)) => {
+                entry.pairs += 1;
+                if row.matches {
+                    entry.matched += 1;
+                } else {
+                    entry.mismatched += 1;
+                }
+            }
+            (Some(_), None) => entry.official_only += 1,
+            (None, Some(_)) => entry.neo_only += 1,
+            (None, None) => {}
+        }
+    }
+
+    by_code.into_values().collect()
+}
+
+fn row_status(row: &TimelineRow) -> &'static str {
+    match row.kind {
+        _ if row.matches => "match",
+        AlignedFrameKind::Matched | AlignedFrameKind::Modified => "mismatch",
+        AlignedFrameKind::Reordered => "reordered",
+        AlignedFrameKind::Deleted => "official_only",
+        AlignedFrameKind::Inserted => "neo_only",
+    }
+}
+
+fn frame_label(frame: Option<&FrameView>) -> String {
+    match frame {
+        Some(frame) => match frame.code {
+            Some(code) => format!("{} ({code}) {}B", frame.name, frame.bytes.len()),
+            None => format!("{} {}B", frame.name, frame.bytes.len()),
+        },
+        None => "-".to_string(),
+    }
+}
+
+fn code_label(code: Option<u32>) -> String {
+    code.map(|code| code.to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+fn value_label(value: Option<&Value>) -> String {
+    let Some(value) = value else {
+        return "(missing)".to_string();
+    };
+    let text = value.to_string();
+    if text.chars().count() <= MAX_VALUE_CHARS {
+        return text;
+    }
+    let truncated: String = text.chars().take(MAX_VALUE_CHARS).collect();
+    format!("{truncated}...")
+}
+
+/// Returns the row range of a hex dump to show, centred on the first diff.
+fn hex_dump_rows(len: usize, diff_offset: Option<usize>) -> (usize, usize) {
+    let total_rows = len.div_ceil(HEX_DUMP_ROW_BYTES);
+    match diff_offset {
+        Some(offset) => {
+            let diff_row = offset / HEX_DUMP_ROW_BYTES;
+            let start = diff_row.saturating_sub(HEX_DUMP_CONTEXT_ROWS);
+            let end = (diff_row + HEX_DUMP_CONTEXT_ROWS + 1).min(total_rows);
+            (start, end.max(start))
+        }
+        None => (0, total_rows.min(HEX_DUMP_CONTEXT_ROWS * 2 + 1)),
+    }
+}
+
+fn hex_dump_lines(
+    bytes: &[u8],
+    other: &[u8],
+    diff_offset: Option<usize>,
+    mark: impl Fn(&str) -> String,
+) -> Vec<String> {
+    let (start_row, end_row) = hex_dump_rows(bytes.len(), diff_offset);
+    let total_rows = bytes.len().div_ceil(HEX_DUMP_ROW_BYTES);
+    let mut lines = Vec::new();
+
+    if start_row > 0 {
+        lines.push("...".to_string());
+    }
+    for row in start_row..end_row {
+        let base = row * HEX_DUMP_ROW_BYTES;
+        let end = (base + HEX_DUMP_ROW_BYTES).min(bytes.len());
+        let mut line = format!("{base:08x} ");
+        for (offset, byte) in bytes.iter().enumerate().take(end).skip(base) {
+            let cell = format!("{byte:02x}");
+            line.push(' ');
+            if other.get(offset) != Some(byte) {
+                line.push_str(&mark(&cell));
+            } else {
+                line.push_str(&cell);
+            }
+        }
+        lines.push(line);
+    }
+    if end_row < total_rows {
+        lines.push("...".to_string());
+    }
+
+    lines
+}
+
+fn render_markdown(
+    report: &CaptureRunReport,
+    rows: &[TimelineRow],
+    summaries: &[CodeSummary],
+) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# Capture verification: {}", report.run_id);
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "mode={:?} pairs={} matched={} mismatched={} official_only={} neo_only={}",
+        report.comparison_mode,
+        report.total_pairs,
+        report.matched_pairs,
+        report.mismatched_pairs,
+        report.official_only,
+        report.neo_only
+    );
+    let _ = writeln!(out);
+
+    let _ = writeln!(out, "## Summary by code");
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "| code | message | pairs | matched | mismatched | official_only | neo_only |"
+    );
+    let _ = writeln!(out, "|---|---|---|---|---|---|---|");
+    for summary in summaries {
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | {} | {} | {} | {} |",
+            code_label(summary.code),
+            summary.name,
+            summary.pairs,
+            summary.matched,
+            summary.mismatched,
+            summary.official_only,
+            summary.neo_only
+        );
+    }
+    let _ = writeln!(out);
+
+    let _ = writeln!(out, "## Timeline");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "| # | official | neo | status | first diff |");
+    let _ = writeln!(out, "|---|---|---|---|---|");
+    for row in rows {
+        let first_diff = match (&row.semantic_first_diff_field, row.first_diff_offset) {
+            (Some(field), Some(offset)) => format!("`{field}` @ {offset}"),
+            (Some(field), None) => format!("`{field}`"),
+            (None, Some(offset)) => format!("@ {offset}"),
+            (None, None) => String::new(),
+        };
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | {} | {} |",
+            row.index,
+            frame_label(row.official.as_ref()),
+            frame_label(row.neo.as_ref()),
+            row_status(row),
+            first_diff
+        );
+    }
+
+    for row in rows.iter().filter(|row| !row.matches) {
+        let _ = writeln!(out);
+        let _ = writeln!(out, "### Frame {} ({})", row.index, row_status(row));
+        let _ = writeln!(out);
+
+        if !row.field_diffs.is_empty() {
+            let _ = writeln!(out, "| field | official | neo |");
+            let _ = writeln!(out, "|---|---|---|");
+            for diff in row.field_diffs.iter().take(MAX_FIELD_DIFFS) {
+                let _ = writeln!(
+                    out,
+                    "| `{}` | `{}` | `{}` |",
+                    diff.path,
+                    value_label(diff.official.as_ref()).replace('|', "\\|"),
+                    value_label(diff.neo.as_ref()).replace('|', "\\|")
+                );
+            }
+            if row.field_diffs.len() > MAX_FIELD_DIFFS {
+                let _ = writeln!(
+                    out,
+                    "| ... | {} more | |",
+                    row.field_diffs.len() - MAX_FIELD_DIFFS
+                );
+            }
+            let _ = writeln!(out);
+        }
+
+        let empty = Vec::new();
+        let official_bytes = row.official.as_ref().map(|f| &f.bytes).unwrap_or(&empty);
+        let neo_bytes = row.neo.as_ref().map(|f| &f.bytes).unwrap_or(&empty);
+        for (label, bytes, other) in [
+            ("official", official_bytes, neo_bytes),
+            ("neo", neo_bytes, official_bytes),
+        ] {
+            if bytes.is_empty() {
+                continue;
+            }
+            let _ = writeln!(out, "{label}:");
+            let _ = writeln!(out);
+            let _ = writeln!(out, "```");
+            for line in hex_dump_lines(bytes, other, row.first_diff_offset, |cell| {
+                format!("[{cell}]")
+            }) {
+                let _ = writeln!(out, "{line}");
+            }
+            let _ = writeln!(out, "```");
+            let _ = writeln!(out);
+        }
+    }
+
+    out
+}
+
+fn render_html(
+    report: &CaptureRunReport,
+    rows: &[TimelineRow],
+    summaries: &[CodeSummary],
+) -> String {
+    let mut out = String::new();
+    let title = format!("Capture verification: {}", escape_html(&report.run_id));
+    let _ = writeln!(out, "<!DOCTYPE html>");
+    let _ = writeln!(
+        out,
+        "<html><head><meta charset=\"utf-8\"><title>{title}</title>"
+    );
+    let _ = writeln!(
+        out,
+        "<style>body{{font-family:sans-serif}}table{{border-collapse:collapse}}\
+td,th{{border:1px solid #ccc;padding:2px 6px;vertical-align:top}}\
+tr.mismatch,tr.reordered,tr.official_only,tr.neo_only{{background:#fde8e8}}\
+pre{{margin:0}}mark{{background:#f7c948}}</style>"
+    );
+    let _ = writeln!(out, "</head><body>");
+    let _ = writeln!(out, "<h1>{title}</h1>");
+    let _ = writeln!(
+        out,
+        "<p>mode={:?} pairs={} matched={} mismatched={} official_only={} neo_only={}</p>",
+        report.comparison_mode,
+        report.total_pairs,
+        report.matched_pairs,
+        report.mismatched_pairs,
+        report.official_only,
+        report.neo_only
+    );
+
+    let _ = writeln!(out, "<h2>Summary by code</h2>");
+    let _ = writeln!(
+        out,
+        "<table><tr><th>code</th><th>message</th><th>pairs</th><th>matched</th>\
+<th>mismatched</th><th>official_only</th><th>neo_only</th></tr>"
+    );
+    for summary in summaries {
+        let _ = writeln!(
+            out,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            code_label(summary.code),
+            escape_html(&summary.name),
+            summary.pairs,
+            summary.matched,
+            summary.mismatched,
+            summary.official_only,
+            summary.neo_only
+        );
+    }
+    let _ = writeln!(out, "</table>");
+
+    let _ = writeln!(out, "<h2>Timeline</h2>");
+    let _ = writeln!(
+        out,
+        "<table><tr><th>#</th><th>official</th><th>neo</th><th>status</th><th>details</th></tr>"
+    );
+    for row in rows {
+        let status = row_status(row);
+        let _ = write!(
+            out,
+            "<tr class=\"{status}\"><td>{}</td><td>{}</td><td>{}</td><td>{status}</td><td>",
+            row.index,
+            escape_html(&frame_label(row.official.as_ref())),
+            escape_html(&frame_label(row.neo.as_ref()))
+        );
+        if !row.matches {
+            render_html_row_details(&mut out, row);
+        }
+        let _ = writeln!(out, "</td></tr>");
+    }
+    let _ = writeln!(out, "</table>");
+    let _ = writeln!(out, "</body></html>");
+
+    out
+}
+
+fn render_html_row_details(out: &mut String, row: &TimelineRow) {
+    if let Some(offset) = row.first_diff_offset {
+        let _ = write!(out, "<div>first diff offset: {offset}</div>");
+    }
+    if !row.field_diffs.is_empty() {
+        let _ = write!(
+            out,
+            "<table><tr><th>field</th><th>official</th><th>neo</th></tr>"
+        );
+        for diff in row.field_diffs.iter().take(MAX_FIELD_DIFFS) {
+            let _ = write!(
+                out,
+                "<tr><td><code>{}</code></td><td><code>{}</code></td><td><code>{}</code></td></tr>",
+                escape_html(&diff.path),
+                escape_html(&value_label(diff.official.as_ref())),
+                escape_html(&value_label(diff.neo.as_ref()))
+            );
+        }
+        if row.field_diffs.len() > MAX_FIELD_DIFFS {
+            let _ = write!(
+                out,
+                "<tr><td colspan=\"3\">{} more</td></tr>",
+                row.field_diffs.len() - MAX_FIELD_DIFFS
+            );
+        }
+        let _ = write!(out, "</table>");
+    }
+
+    let empty = Vec::new();
+    let official_bytes = row.official.as_ref().map(|f| &f.bytes).unwrap_or(&empty);
+    let neo_bytes = row.neo.as_ref().map(|f| &f.bytes).unwrap_or(&empty);
+    let _ = write!(out, "<table><tr><th>official</th><th>neo</th></tr><tr>");
+    for (bytes, other) in [(official_bytes, neo_bytes), (neo_bytes, official_bytes)] {
+        let lines = hex_dump_lines(bytes, other, row.first_diff_offset, |cell| {
+            format!("<mark>{cell}</mark>")
+        });
+        let _ = write!(out, "<td><pre>{}</pre></td>", lines.join("\n"));
+    }
+    let _ = write!(out, "</tr></table>");
+}
+
+fn escape_html(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for ch in input.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            other => out.push(other),
+        }
+    }
+    out
+}
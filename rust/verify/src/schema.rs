@@ -0,0 +1,393 @@
+use super::first_diff_offset;
+use anyhow::{Context, Result};
+use protocol::{
+    PayloadWriter, decode_peer_message, decode_server_message, encode_peer_message,
+    encode_server_message,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageSchema {
+    pub version: u32,
+    pub messages: Vec<SchemaMessage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaMessage {
+    pub scope: String,
+    pub code: u32,
+    pub name: String,
+    pub payload: Vec<SchemaField>,
+    #[serde(default)]
+    pub confidence: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SchemaConformance {
+    Conforms,
+    Skipped {
+        reason: String,
+    },
+    DecodeFailed {
+        error: String,
+    },
+    ReencodeMismatch {
+        first_diff_offset: Option<usize>,
+        synthesized_len: usize,
+        reencoded_len: usize,
+    },
+    FieldMismatch {
+        field: String,
+        expected: Value,
+        actual: Value,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaConformanceCase {
+    pub scope: String,
+    pub code: u32,
+    pub name: String,
+    pub payload_hex: String,
+    pub decoded: Option<Value>,
+    pub unmatched_fields: Vec<String>,
+    pub result: SchemaConformance,
+}
+
+impl SchemaConformanceCase {
+    pub fn is_disagreement(&self) -> bool {
+        !matches!(
+            self.result,
+            SchemaConformance::Conforms | SchemaConformance::Skipped { .. }
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaConformanceReport {
+    pub schema_version: u32,
+    pub total: usize,
+    pub conforms: usize,
+    pub skipped: usize,
+    pub disagreements: usize,
+    pub cases: Vec<SchemaConformanceCase>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expectation {
+    Value(Value),
+    Unchecked,
+}
+
+struct SynthesizedPayload {
+    bytes: Vec<u8>,
+    expectations: Vec<(String, Expectation)>,
+}
+
+pub fn load_message_schema(path: impl AsRef<Path>) -> Result<MessageSchema> {
+    let path = path.as_ref();
+    let raw =
+        fs::read_to_string(path).with_context(|| format!("read schema: {}", path.display()))?;
+    serde_json::from_str(&raw).with_context(|| format!("parse schema: {}", path.display()))
+}
+
+pub fn check_schema_conformance(schema: &MessageSchema) -> SchemaConformanceReport {
+    let cases: Vec<SchemaConformanceCase> =
+        schema.messages.iter().map(check_schema_message).collect();
+    let conforms = cases
+        .iter()
+        .filter(|case| case.result == SchemaConformance::Conforms)
+        .count();
+    let skipped = cases
+        .iter()
+        .filter(|case| matches!(case.result, SchemaConformance::Skipped { .. }))
+        .count();
+
+    SchemaConformanceReport {
+        schema_version: schema.version,
+        total: cases.len(),
+        conforms,
+        skipped,
+        disagreements: cases.len() - conforms - skipped,
+        cases,
+    }
+}
+
+pub fn write_schema_conformance_report(
+    path: impl AsRef<Path>,
+    report: &SchemaConformanceReport,
+) -> Result<()> {
+    let path = path.as_ref();
+    let json = serde_json::to_string_pretty(report).context("serialize schema report")?;
+    fs::write(path, json).with_context(|| format!("write report: {}", path.display()))?;
+    Ok(())
+}
+
+pub fn check_schema_message(message: &SchemaMessage) -> SchemaConformanceCase {
+    let mut case = SchemaConformanceCase {
+        scope: message.scope.clone(),
+        code: message.code,
+        name: message.name.clone(),
+        payload_hex: String::new(),
+        decoded: None,
+        unmatched_fields: Vec::new(),
+        result: SchemaConformance::Conforms,
+    };
+
+    if message.payload.is_empty() {
+        case.result = SchemaConformance::Skipped {
+            reason: "schema has no payload layout".to_string(),
+        };
+        return case;
+    }
+
+    let SynthesizedPayload {
+        bytes: payload,
+        expectations,
+    } = match synthesize_payload(&message.payload) {
+        Ok(synthesized) => synthesized,
+        Err(reason) => {
+            case.result = SchemaConformance::Skipped { reason };
+            return case;
+        }
+    };
+    case.payload_hex = hex::encode(&payload);
+
+    let decoded = match message.scope.as_str() {
+        "server" => decode_server_message(message.code, &payload).map(|decoded| {
+            (
+                serde_json::to_value(&decoded).unwrap_or(Value::Null),
+                encode_server_message(&decoded),
+            )
+        }),
+        "peer" => decode_peer_message(message.code, &payload).map(|decoded| {
+            (
+                serde_json::to_value(&decoded).unwrap_or(Value::Null),
+                encode_peer_message(&decoded),
+            )
+        }),
+        other => {
+            case.result = SchemaConformance::Skipped {
+                reason: format!("unknown scope {other}"),
+            };
+            return case;
+        }
+    };
+
+    let (decoded, reencoded) = match decoded {
+        Ok(decoded) => decoded,
+        Err(err) => {
+            case.result = SchemaConformance::DecodeFailed {
+                error: err.to_string(),
+            };
+            return case;
+        }
+    };
+    case.decoded = Some(decoded.clone());
+
+    for (field, expectation) in &expectations {
+        let Some(actual) = locate_field(&decoded, field) else {
+            case.unmatched_fields.push(field.clone());
+            continue;
+        };
+        if let Expectation::Value(expected) = expectation
+            && !values_agree(expected, actual)
+        {
+            case.result = SchemaConformance::FieldMismatch {
+                field: field.clone(),
+                expected: expected.clone(),
+                actual: actual.clone(),
+            };
+            return case;
+        }
+    }
+
+    if reencoded.code != message.code || reencoded.payload != payload {
+        case.result = SchemaConformance::ReencodeMismatch {
+            first_diff_offset: first_diff_offset(&payload, &reencoded.payload),
+            synthesized_len: payload.len(),
+            reencoded_len: reencoded.payload.len(),
+        };
+    }
+
+    case
+}
+
+/// Builds one example payload in schema field order. Count fields are set to
+/// one so that the flattened `entry.*` fields that follow form one element.
+fn synthesize_payload(fields: &[SchemaField]) -> std::result::Result<SynthesizedPayload, String> {
+    let mut writer = PayloadWriter::new();
+    let mut expectations = Vec::new();
+    let mut previous_was_count = false;
+
+    for (idx, field) in fields.iter().enumerate() {
+        let leaf = field.name.rsplit('.').next().unwrap_or(&field.name);
+        let is_count = leaf.ends_with("count");
+        let number = 100 + idx as u32;
+        let text = format!("{leaf}-{idx}");
+
+        let expectation = match field.field_type.as_str() {
+            "u32" | "optional_u32" if is_count => {
+                writer.write_u32(1);
+                Expectation::Value(json!(1))
+            }
+            "u32" | "optional_u32" => {
+                writer.write_u32(number);
+                Expectation::Value(json!(number))
+            }
+            "i32" => {
+                let value = -(number as i32);
+                writer.write_u32(value as u32);
+                Expectation::Value(json!(value))
+            }
+            "u16" => {
+                writer.write_raw_bytes(&(number as u16).to_le_bytes());
+                Expectation::Value(json!(number))
+            }
+            "u8" => {
+                writer.write_u8(number as u8);
+                Expectation::Value(json!(number as u8))
+            }
+            "u64" => {
+                let value = u64::from(number) << 32 | u64::from(number);
+                writer.write_u64(value);
+                Expectation::Value(json!(value))
+            }
+            "bool_u8" => {
+                writer.write_u8(1);
+                Expectation::Value(json!(true))
+            }
+            "bool_u32" => {
+                writer.write_u32(1);
+                Expectation::Value(json!(true))
+            }
+            "enum_u32" => {
+                writer.write_u32(0);
+                Expectation::Unchecked
+            }
+            "ipv4_u32_le" | "ipv4_u32_reversed" => {
+                writer.write_raw_bytes(&[10, 20, 30, 40]);
+                Expectation::Unchecked
+            }
+            "string" | "optional_string" => {
+                writer.write_string(&text);
+                Expectation::Value(json!(text))
+            }
+            "array<string>" => {
+                if !previous_was_count {
+                    writer.write_u32(1);
+                }
+                writer.write_string(&text);
+                Expectation::Value(json!([text]))
+            }
+            "bytes_len_prefixed" => {
+                writer.write_bytes(text.as_bytes());
+                Expectation::Unchecked
+            }
+            // Metadata-only entries describe the fields that follow them.
+            "enum" => continue,
+            ty if ty.starts_with("array<") => {
+                if !previous_was_count {
+                    writer.write_u32(1);
+                }
+                Expectation::Unchecked
+            }
+            "bytes_raw" if leaf == "extension_reserved_bytes" => continue,
+            "bytes_raw" if idx + 1 == fields.len() => {
+                writer.write_raw_bytes(text.as_bytes());
+                Expectation::Unchecked
+            }
+            other => {
+                return Err(format!(
+                    "cannot synthesize field {} of type {other}",
+                    field.name
+                ));
+            }
+        };
+
+        previous_was_count = is_count && field.field_type.ends_with("u32");
+        expectations.push((field.name.clone(), expectation));
+    }
+
+    Ok(SynthesizedPayload {
+        bytes: writer.into_inner(),
+        expectations,
+    })
+}
+
+/// Maps a schema field name onto the decoded message. Flattened `prefix.leaf`
+/// names and `prefix_count` fields resolve against the list whose key starts
+/// with `prefix`; plain names resolve against the message body first.
+fn locate_field<'a>(decoded: &'a Value, field: &str) -> Option<&'a Value> {
+    let body = match decoded {
+        Value::Object(map) if map.len() == 1 => map.values().next().unwrap_or(decoded),
+        other => other,
+    };
+
+    if let Some((prefix, leaf)) = field.split_once('.') {
+        return find_list(body, prefix)
+            .and_then(|items| items.first())
+            .and_then(|item| find_field(item, leaf))
+            .or_else(|| find_in_lists(body, leaf))
+            .or_else(|| find_field(body, leaf));
+    }
+    if let Some(prefix) = field.strip_suffix("_count")
+        && let Some(list) = find_list_value(body, prefix)
+    {
+        return Some(list);
+    }
+    body.get(field).or_else(|| find_field(body, field))
+}
+
+fn find_list<'a>(value: &'a Value, prefix: &str) -> Option<&'a Vec<Value>> {
+    find_list_value(value, prefix).and_then(Value::as_array)
+}
+
+fn find_list_value<'a>(value: &'a Value, prefix: &str) -> Option<&'a Value> {
+    match value {
+        Value::Object(map) => map
+            .iter()
+            .find(|(key, v)| key.starts_with(prefix) && v.is_array())
+            .map(|(_, v)| v)
+            .or_else(|| map.values().find_map(|v| find_list_value(v, prefix))),
+        _ => None,
+    }
+}
+
+fn find_in_lists<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    match value {
+        Value::Object(map) => map.values().find_map(|v| find_in_lists(v, key)),
+        Value::Array(items) => items.first().and_then(|item| find_field(item, key)),
+        _ => None,
+    }
+}
+
+fn find_field<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    match value {
+        Value::Object(map) => map
+            .get(key)
+            .or_else(|| map.values().find_map(|v| find_field(v, key))),
+        Value::Array(items) => items.iter().find_map(|v| find_field(v, key)),
+        _ => None,
+    }
+}
+
+fn values_agree(expected: &Value, actual: &Value) -> bool {
+    match (expected, actual) {
+        // Counts may be modelled as the length of a decoded list.
+        (Value::Number(_), Value::Array(items)) => expected.as_u64() == Some(items.len() as u64),
+        (Value::Bool(flag), Value::Number(n)) => n.as_u64() == Some(u64::from(*flag)),
+        _ => expected == actual,
+    }
+}
@@ -3,15 +3,11 @@ from __future__ import annotations
 import argparse
 import csv
 import json
-import re
 from collections import Counter
 from datetime import datetime, timezone
 from pathlib import Path
 
 
-CONST_RE = re.compile(r"pub const CODE_(SM_[A-Z0-9_]+|PM_[A-Z0-9_]+): u32 = (\d+);")
-
-
 def now_iso() -> str:
     return datetime.now(timezone.utc).replace(microsecond=0).isoformat()
 
@@ -37,10 +33,12 @@ def read_message_map(path: Path) -> dict[str, dict[str, str]]:
 
 
 def read_protocol_constants(path: Path) -> dict[str, int]:
-    data = path.read_text(encoding="utf-8")
+    """Implemented server/peer codes, taken from `soul-cli verify coverage` output."""
+    report = json.loads(path.read_text(encoding="utf-8"))
     constants: dict[str, int] = {}
-    for name, code in CONST_RE.findall(data):
-        constants[name] = int(code)
+    for row in report["rows"]:
+        if row["scope"] in ("server", "peer") and row["decode"] != "missing":
+            constants[row["message"]] = int(row["code"])
     return constants
 
 
@@ -161,6 +159,7 @@ def render_markdown(rows: list[dict[str, str]]) -> str:
             "## Regeneration",
             "",
             "```bash",
+            "cargo run --manifest-path rust/Cargo.toml -p soul-cli -- verify coverage",
             "python3 tools/protocol/generate_protocol_matrix.py",
             "```",
         ]
@@ -203,7 +202,7 @@ def main() -> int:
     parser = argparse.ArgumentParser(description="Generate protocol coverage matrix markdown")
     parser.add_argument("--message-names", default="evidence/reverse/message_name_strings.txt")
     parser.add_argument("--message-map", default="analysis/ghidra/maps/message_map.csv")
-    parser.add_argument("--protocol-lib", default="rust/protocol/src/lib.rs")
+    parser.add_argument("--protocol-coverage", default="docs/state/protocol-coverage.json")
     parser.add_argument("--out", default="docs/state/protocol-matrix.md")
     parser.add_argument("--out-json", default="docs/state/protocol-matrix.json")
     args = parser.parse_args()
@@ -211,14 +210,14 @@ def main() -> int:
     repo_root = Path(__file__).resolve().parent.parent.parent
     names_path = (repo_root / args.message_names).resolve()
     map_path = (repo_root / args.message_map).resolve()
-    protocol_path = (repo_root / args.protocol_lib).resolve()
+    coverage_path = (repo_root / args.protocol_coverage).resolve()
     out_path = (repo_root / args.out).resolve()
     out_json_path = (repo_root / args.out_json).resolve()
 
     rows = build_rows(
         known_names=read_message_names(names_path),
         message_map=read_message_map(map_path),
-        protocol_constants=read_protocol_constants(protocol_path),
+        protocol_constants=read_protocol_constants(coverage_path),
     )
     out_path.parent.mkdir(parents=True, exist_ok=True)
     out_path.write_text(render_markdown(rows), encoding="utf-8")